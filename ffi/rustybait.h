@@ -0,0 +1,43 @@
+/* C API of the rustybait chess engine, linked as librustybait_ffi, see ffi/src/lib.rs */
+#ifndef RUSTYBAIT_H
+#define RUSTYBAIT_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct RustybaitEngine RustybaitEngine;
+
+/* The engine in the start position, freed with rustybait_free */
+RustybaitEngine *rustybait_new(void);
+void rustybait_free(RustybaitEngine *engine);
+
+/* Functions returning an int give 0 on success and -1 on failure, described by
+ * rustybait_last_error */
+
+/* Sets the position from a FEN, or "startpos" */
+int rustybait_set_fen(RustybaitEngine *engine, const char *fen);
+/* Plays a move in UCI notation, e.g. "e2e4" or "a7a8q" */
+int rustybait_push_move(RustybaitEngine *engine, const char *uci_move);
+/* The position as a FEN */
+const char *rustybait_fen(RustybaitEngine *engine);
+
+/* Searches the position until one of the limits is reached, 0 meaning no limit, at
+ * least one of them must be given. Fails when the game is over. */
+int rustybait_search(RustybaitEngine *engine, uint64_t move_time_ms, uint64_t nodes, uint8_t depth);
+/* Best move of the last search in UCI notation, empty before the first one */
+const char *rustybait_best_move(const RustybaitEngine *engine);
+/* Score of the last search as in UCI, "cp <centipawns>" or "mate <moves>" */
+const char *rustybait_score(const RustybaitEngine *engine);
+
+const char *rustybait_last_error(const RustybaitEngine *engine);
+
+/* Strings returned are owned by the engine and valid until its next call */
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
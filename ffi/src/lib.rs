@@ -0,0 +1,238 @@
+use nohash_hasher::BuildNoHashHasher;
+use rustybait::{
+    chess::score::{self, Score},
+    constants::{DEFAULT_CONTEMPT, TT_CAPACITY},
+    search::{self, SearchLimits, TranspositionTable},
+    wdl, Game, Move,
+};
+use std::{
+    collections::HashMap,
+    ffi::{c_char, c_int, CStr, CString},
+    fmt::Display,
+    sync::atomic::AtomicBool,
+    time::Duration,
+};
+
+// The engine for C, C++ or C# programs such as GUIs, declared in rustybait.h and built with
+//
+//     cargo build --release
+//
+// into librustybait_ffi.so (.dll, .dylib) and the static librustybait_ffi.a.
+// Searches run on the thread calling them until they reach their limits.
+
+pub struct RustybaitEngine {
+    game: Game,
+    table: TranspositionTable,
+    contempt: Score,
+    best_move: CString,
+    score: CString,
+    fen: CString,
+    last_error: CString,
+}
+
+impl RustybaitEngine {
+    fn fail(&mut self, err: impl Display) -> c_int {
+        self.last_error = to_c_string(err);
+        -1
+    }
+}
+
+/// Strings given to C can't contain null bytes, which none of ours do
+fn to_c_string(text: impl Display) -> CString {
+    CString::new(text.to_string().replace('\0', "")).unwrap()
+}
+
+/// # Safety
+/// `text` is null or a valid C string
+unsafe fn read_str<'a>(text: *const c_char) -> Result<&'a str, &'static str> {
+    if text.is_null() {
+        return Err("Null string");
+    }
+    CStr::from_ptr(text).to_str().map_err(|_| "Invalid UTF-8")
+}
+
+#[no_mangle]
+pub extern "C" fn rustybait_new() -> *mut RustybaitEngine {
+    Box::into_raw(Box::new(RustybaitEngine {
+        game: Game::default(),
+        table: HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildNoHashHasher::default()),
+        contempt: DEFAULT_CONTEMPT,
+        best_move: CString::default(),
+        score: CString::default(),
+        fen: CString::default(),
+        last_error: CString::default(),
+    }))
+}
+
+/// # Safety
+/// `engine` is null or comes from `rustybait_new` and isn't used anymore
+#[no_mangle]
+pub unsafe extern "C" fn rustybait_free(engine: *mut RustybaitEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// # Safety
+/// `engine` comes from `rustybait_new`, `fen` is null or a valid C string
+#[no_mangle]
+pub unsafe extern "C" fn rustybait_set_fen(
+    engine: *mut RustybaitEngine,
+    fen: *const c_char,
+) -> c_int {
+    let engine = &mut *engine;
+    let fen = match read_str(fen) {
+        Ok(fen) => fen,
+        Err(err) => return engine.fail(err),
+    };
+
+    let game = match fen {
+        "startpos" => Ok(Game::default()),
+        fen => Game::new(fen),
+    };
+    match game {
+        Ok(game) => {
+            engine.game = game;
+            0
+        }
+        Err(err) => engine.fail(format!("Invalid FEN string: {}", err)),
+    }
+}
+
+/// # Safety
+/// `engine` comes from `rustybait_new`, `uci_move` is null or a valid C string
+#[no_mangle]
+pub unsafe extern "C" fn rustybait_push_move(
+    engine: *mut RustybaitEngine,
+    uci_move: *const c_char,
+) -> c_int {
+    let engine = &mut *engine;
+    let uci_move = match read_str(uci_move) {
+        Ok(uci_move) => uci_move,
+        Err(err) => return engine.fail(err),
+    };
+
+    match Move::from_uci_notation(uci_move, &engine.game) {
+        Ok(_move) => {
+            engine.game.push_history(_move);
+            0
+        }
+        Err(err) => engine.fail(err),
+    }
+}
+
+/// # Safety
+/// `engine` comes from `rustybait_new`
+#[no_mangle]
+pub unsafe extern "C" fn rustybait_fen(engine: *mut RustybaitEngine) -> *const c_char {
+    let engine = &mut *engine;
+    engine.fen = to_c_string(engine.game.to_fen());
+    engine.fen.as_ptr()
+}
+
+/// # Safety
+/// `engine` comes from `rustybait_new`
+#[no_mangle]
+pub unsafe extern "C" fn rustybait_search(
+    engine: *mut RustybaitEngine,
+    move_time_ms: u64,
+    nodes: u64,
+    depth: u8,
+) -> c_int {
+    let engine = &mut *engine;
+    if move_time_ms == 0 && nodes == 0 && depth == 0 {
+        return engine.fail("The search must be limited by time, nodes or depth");
+    }
+
+    let mut limits = SearchLimits::new();
+    if move_time_ms != 0 {
+        limits = limits.move_time(Duration::from_millis(move_time_ms));
+    }
+    if nodes != 0 {
+        limits = limits.nodes(nodes);
+    }
+    if depth != 0 {
+        limits = limits.depth(depth);
+    }
+
+    let result = search::search(
+        &engine.game,
+        &limits,
+        &mut engine.table,
+        &AtomicBool::new(true),
+        engine.contempt,
+        |_| (),
+    );
+    match result {
+        Some((best_move, best_score, _)) => {
+            engine.best_move = to_c_string(best_move.uci_notation(&engine.game));
+            engine.score = to_c_string(score::to_uci(wdl::normalize(best_score, &engine.game)));
+            0
+        }
+        None => engine.fail("No legal move, the game is over"),
+    }
+}
+
+/// # Safety
+/// `engine` comes from `rustybait_new`
+#[no_mangle]
+pub unsafe extern "C" fn rustybait_best_move(engine: *const RustybaitEngine) -> *const c_char {
+    (*engine).best_move.as_ptr()
+}
+
+/// # Safety
+/// `engine` comes from `rustybait_new`
+#[no_mangle]
+pub unsafe extern "C" fn rustybait_score(engine: *const RustybaitEngine) -> *const c_char {
+    (*engine).score.as_ptr()
+}
+
+/// # Safety
+/// `engine` comes from `rustybait_new`
+#[no_mangle]
+pub unsafe extern "C" fn rustybait_last_error(engine: *const RustybaitEngine) -> *const c_char {
+    (*engine).last_error.as_ptr()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    unsafe fn string(text: *const c_char) -> String {
+        CStr::from_ptr(text).to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn c_api() {
+        unsafe {
+            let engine = rustybait_new();
+            let fen = CString::new("6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1").unwrap();
+            assert_eq!(rustybait_set_fen(engine, fen.as_ptr()), 0);
+            assert_eq!(rustybait_search(engine, 0, 0, 0), -1);
+            assert_eq!(rustybait_search(engine, 0, 0, 3), 0);
+            assert_eq!(string(rustybait_best_move(engine)), "e1e8");
+            assert_eq!(string(rustybait_score(engine)), "mate 1");
+
+            let mate = CString::new("e1e8").unwrap();
+            assert_eq!(rustybait_push_move(engine, mate.as_ptr()), 0);
+            assert_eq!(
+                string(rustybait_fen(engine)),
+                "4R1k1/5ppp/8/8/8/8/8/6K1 b - - 1 1"
+            );
+            assert_eq!(rustybait_search(engine, 100, 0, 0), -1);
+            assert_eq!(
+                string(rustybait_last_error(engine)),
+                "No legal move, the game is over"
+            );
+
+            let illegal = CString::new("e8e1").unwrap();
+            assert_eq!(rustybait_push_move(engine, illegal.as_ptr()), -1);
+            assert_eq!(rustybait_set_fen(engine, std::ptr::null()), -1);
+            let invalid = CString::new("8/8 w").unwrap();
+            assert_eq!(rustybait_set_fen(engine, invalid.as_ptr()), -1);
+            assert!(string(rustybait_last_error(engine)).starts_with("Invalid FEN string"));
+
+            rustybait_free(engine);
+        }
+    }
+}
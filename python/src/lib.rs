@@ -0,0 +1,281 @@
+use nohash_hasher::BuildNoHashHasher;
+use pyo3::{exceptions::PyValueError, prelude::*};
+use rustybait_engine::{
+    chess::{
+        move_struct::UciMoveError,
+        score::{self, Score},
+    },
+    constants::{DEFAULT_CONTEMPT, TT_CAPACITY},
+    search::{self, SearchInfo, SearchLimits, TranspositionTable},
+    wdl::Wdl,
+    Move, Player,
+};
+use std::{collections::HashMap, sync::atomic::AtomicBool, time::Duration};
+
+// The engine for Python, e.g. to analyze or label positions of datasets, installed with
+//
+//     pip install ./python
+//
+// which builds it with maturin. Searches run on the thread calling them until they reach
+// their limits.
+
+/// A game from a FEN, the start position by default, and the moves played since
+#[pyclass(unsendable, skip_from_py_object)]
+#[derive(Clone)]
+struct Game {
+    game: rustybait_engine::Game,
+}
+
+#[pymethods]
+impl Game {
+    #[new]
+    #[pyo3(signature = (fen = None))]
+    fn new(fen: Option<&str>) -> PyResult<Self> {
+        let game = match fen {
+            Some(fen) => rustybait_engine::Game::new(fen)
+                .map_err(|err| PyValueError::new_err(format!("Invalid FEN string: {}", err)))?,
+            None => rustybait_engine::Game::default(),
+        };
+        Ok(Self { game })
+    }
+
+    /// Plays a legal move, in UCI notation or SAN
+    fn push(&mut self, _move: &str) -> PyResult<()> {
+        let parsed = match Move::from_uci_notation(_move, &self.game) {
+            Err(UciMoveError::Unparseable(_)) => {
+                Move::from_san(_move, &self.game).map_err(|err| err.to_string())
+            }
+            parsed => parsed.map_err(|err| err.to_string()),
+        };
+        self.game
+            .push_history(parsed.map_err(PyValueError::new_err)?);
+        Ok(())
+    }
+
+    fn fen(&self) -> String {
+        self.game.to_fen()
+    }
+
+    /// The legal moves in UCI notation
+    fn legal_moves(&self) -> Vec<String> {
+        self.game
+            .legal_moves()
+            .iter()
+            .map(|_move| _move.uci_notation(&self.game))
+            .collect()
+    }
+
+    /// The moves played since the game was created, in UCI notation
+    fn history(&self) -> Vec<String> {
+        self.game
+            .history()
+            .iter()
+            .map(|_move| _move.uci_notation(&self.game))
+            .collect()
+    }
+
+    /// "white" or "black"
+    fn turn(&self) -> &'static str {
+        match self.game.player() {
+            Player::White => "white",
+            Player::Black => "black",
+        }
+    }
+
+    fn is_check(&self) -> bool {
+        self.game.is_check()
+    }
+
+    /// How the game ended, e.g. "checkmate" or "threefold repetition", None if it goes on
+    fn outcome(&self) -> Option<&'static str> {
+        let outcome = self.game.outcome();
+        outcome.is_over().then(|| outcome.description())
+    }
+
+    /// Result as written in PGN: "1-0", "0-1", "1/2-1/2" or "*"
+    fn result(&self) -> &'static str {
+        self.game.result().as_str()
+    }
+
+    /// Static evaluation in centipawns, from the point of view of white
+    fn evaluate(&self) -> Score {
+        self.game.evaluate()
+    }
+
+    fn copy(&self) -> Self {
+        self.clone()
+    }
+
+    fn __str__(&self) -> String {
+        self.game.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Game('{}')", self.game.to_fen())
+    }
+}
+
+/// Result of a search, from the point of view of the player to move
+#[pyclass(get_all)]
+struct SearchResult {
+    /// In UCI notation, None when the game is over
+    best_move: Option<String>,
+    /// Centipawns normalized so that 100 means a 50% chance of winning, None for mates
+    score: Option<i32>,
+    /// Moves to mate, negative when getting mated
+    mate: Option<i32>,
+    /// Win, draw and loss probabilities in permille
+    wdl: (u32, u32, u32),
+    depth: u8,
+    nodes: u64,
+    /// Best line in UCI notation
+    pv: Vec<String>,
+}
+
+#[pymethods]
+impl SearchResult {
+    fn __repr__(&self) -> String {
+        fn or_none(value: Option<impl std::fmt::Display>) -> String {
+            value.map_or_else(|| String::from("None"), |value| value.to_string())
+        }
+
+        let best_move = self
+            .best_move
+            .as_ref()
+            .map(|best_move| format!("'{}'", best_move));
+        format!(
+            "SearchResult(best_move={}, score={}, mate={}, depth={})",
+            or_none(best_move),
+            or_none(self.score),
+            or_none(self.mate),
+            self.depth
+        )
+    }
+}
+
+/// The search with its transposition table, which is kept between searches
+#[pyclass]
+struct Engine {
+    table: TranspositionTable,
+    contempt: Score,
+}
+
+#[pymethods]
+impl Engine {
+    #[new]
+    #[pyo3(signature = (contempt = DEFAULT_CONTEMPT))]
+    fn new(contempt: Score) -> Self {
+        Self {
+            table: HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildNoHashHasher::default()),
+            contempt,
+        }
+    }
+
+    /// Searches the game until one of the limits is reached: `movetime` in ms, `nodes`
+    /// or `depth` in half-moves, at least one of which must be given
+    #[pyo3(signature = (game, movetime = None, nodes = None, depth = None))]
+    fn search(
+        &mut self,
+        game: &Game,
+        movetime: Option<u64>,
+        nodes: Option<u64>,
+        depth: Option<u8>,
+    ) -> PyResult<SearchResult> {
+        if movetime.is_none() && nodes.is_none() && depth.is_none() {
+            return Err(PyValueError::new_err(
+                "The search must be limited by movetime, nodes or depth",
+            ));
+        }
+
+        let mut limits = SearchLimits::new();
+        if let Some(movetime) = movetime {
+            limits = limits.move_time(Duration::from_millis(movetime));
+        }
+        if let Some(nodes) = nodes {
+            limits = limits.nodes(nodes);
+        }
+        if let Some(depth) = depth {
+            limits = limits.depth(depth);
+        }
+
+        let game = &game.game;
+        let mut last_info = None;
+        let best_move = search::search(
+            game,
+            &limits,
+            &mut self.table,
+            &AtomicBool::new(true),
+            self.contempt,
+            |info| last_info = Some(info.clone()),
+        );
+
+        // Without legal moves the search ends before its first depth
+        let info = last_info.unwrap_or_else(|| SearchInfo {
+            depth: 0,
+            multipv: 1,
+            score: 0,
+            wdl: Wdl::new(0, game),
+            nodes: 0,
+            pv: Vec::new(),
+        });
+        let is_mate = score::is_mate(info.score);
+        Ok(SearchResult {
+            best_move: best_move.map(|(best_move, _, _)| best_move.uci_notation(game)),
+            score: (!is_mate).then_some(info.score),
+            mate: is_mate.then(|| score::mate_moves(info.score)),
+            wdl: (info.wdl.win, info.wdl.draw, info.wdl.loss),
+            depth: info.depth,
+            nodes: info.nodes,
+            pv: info
+                .pv
+                .iter()
+                .map(|_move| _move.uci_notation(game))
+                .collect(),
+        })
+    }
+
+    /// Forgets what was learned from the previous positions
+    fn clear(&mut self) {
+        self.table.clear();
+    }
+}
+
+#[pymodule]
+fn rustybait(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<Game>()?;
+    module.add_class::<Engine>()?;
+    module.add_class::<SearchResult>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_and_search() {
+        let mut game = Game::new(None).unwrap();
+        assert_eq!(game.legal_moves().len(), 20);
+        game.push("e4").unwrap();
+        game.push("e7e5").unwrap();
+        assert!(game.push("e2e4").is_err());
+        assert_eq!(game.history(), ["e2e4", "e7e5"]);
+        assert_eq!(game.turn(), "white");
+        assert!(Game::new(Some("8/8 w")).is_err());
+
+        let mut engine = Engine::new(DEFAULT_CONTEMPT);
+        let game = Game::new(Some("6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1")).unwrap();
+        assert!(engine.search(&game, None, None, None).is_err());
+        let result = engine.search(&game, None, None, Some(3)).unwrap();
+        assert_eq!(result.best_move.as_deref(), Some("e1e8"));
+        assert_eq!((result.score, result.mate), (None, Some(1)));
+        assert_eq!(result.pv[0], "e1e8");
+
+        let mut mated = game.copy();
+        mated.push("Re8#").unwrap();
+        assert_eq!(mated.outcome(), Some("checkmate"));
+        assert_eq!(mated.result(), "1-0");
+        let result = engine.search(&mated, Some(100), None, None).unwrap();
+        assert_eq!(result.best_move, None);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    chess::{score, Game, Score},
+    constants::{DEFAULT_CONTEMPT, TT_CAPACITY},
+    engine::{Engine, EngineEvent},
+    json::{self, Message},
+    search::{search, SearchInfo, SearchLimits, TranspositionTable},
+    uci,
+    wdl::Wdl,
+};
+use anyhow::Context;
+use nohash_hasher::BuildNoHashHasher;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io::stdin,
+    sync::{atomic::AtomicBool, mpsc::Receiver},
+    thread,
+    time::Duration,
+};
+
+/// Result of a depth of the search, as printed for the analysis
+#[derive(Serialize)]
+pub struct JsonReport {
+    depth: u8,
+    /// Rank of the line, 1 for the best move
+    multipv: u8,
+    #[serde(serialize_with = "score::serialize")]
+    score: Score,
+    wdl: Wdl,
+    /// Positions visited by this search
+    nodes: u64,
+    /// Best line in UCI notation, starting with the best move
+    pv: Vec<String>,
+}
+
+impl JsonReport {
+    /// `game` is the position searched, in which the moves are written
+    pub fn new(info: &SearchInfo, game: &Game) -> Self {
+        Self {
+            depth: info.depth,
+            multipv: info.multipv,
+            score: info.score,
+            wdl: info.wdl,
+            nodes: info.nodes,
+            pv: info
+                .pv
+                .iter()
+                .map(|_move| _move.uci_notation(game))
+                .collect(),
+        }
+    }
+}
+
+/// Searches the position for `millis` and prints everything as JSON, one object per line:
+/// the position, the result of every depth and finally the best move, which is null
+/// when the game is over
+pub fn analyze(game: &Game, millis: u64) {
+    json::print(&Message::Position(game));
+
+    let mut cache: TranspositionTable =
+        HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildNoHashHasher::default());
+
+    let best_move = search(
+        game,
+        &SearchLimits::new().move_time(Duration::from_millis(millis)),
+        &mut cache,
+        &AtomicBool::new(true),
+        DEFAULT_CONTEMPT,
+        |info| json::print(&Message::Info(JsonReport::new(info, game))),
+    )
+    .map(|(best_move, _, _)| best_move.uci_notation(game));
+
+    json::print(&Message::BestMove {
+        bestmove: best_move,
+    });
+}
+
+/// Searches `game` until stdin is closed, printing the same JSON as `analyze` for the
+/// `lines` best moves of every depth
+///
+/// Every line read from stdin is another position, given like the arguments of the UCI
+/// `position` command, e.g. `startpos moves e2e4 e7e5`. The search of the previous one
+/// ends with its best move and the search of the new one starts right away.
+pub fn analyze_continuously(mut game: Game, lines: u8) -> anyhow::Result<()> {
+    let (mut engine, events) = Engine::new();
+    engine.set_option("MultiPV", &lines.to_string())?;
+    let mut input = stdin().lines();
+
+    // The printer of each position hands the events back for the next one
+    let mut events = Some(events);
+    loop {
+        json::print(&Message::Position(&game));
+        engine.set_position(game.clone())?;
+        engine.start_search(SearchLimits::new().infinite())?;
+        let printer = spawn_printer(events.take().unwrap(), game);
+
+        let next = loop {
+            let Some(line) = input.next() else {
+                break None;
+            };
+            let line = line.context("Failed to read line from stdin")?;
+            let line = line.trim();
+            let line = line.strip_prefix("position").unwrap_or(line);
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match uci::parse_position(&mut line.split_ascii_whitespace(), &engine) {
+                Ok(next) => break Some(next),
+                Err(err) => json::print(&Message::Error {
+                    message: &err.to_string(),
+                }),
+            }
+        };
+
+        engine.stop();
+        events = Some(printer.join().unwrap());
+        match next {
+            Some(next) => game = next,
+            None => return Ok(()),
+        }
+    }
+}
+
+/// Prints the events of the search of `game` until its best move
+fn spawn_printer(
+    events: Receiver<EngineEvent>,
+    game: Game,
+) -> thread::JoinHandle<Receiver<EngineEvent>> {
+    thread::spawn(move || {
+        for event in events.iter() {
+            match event {
+                EngineEvent::Info(info) => {
+                    json::print(&Message::Info(JsonReport::new(&info, &game)));
+                }
+                EngineEvent::BestMove(best_move) => {
+                    let bestmove = best_move.map(|best_move| best_move.uci_notation(&game));
+                    json::print(&Message::BestMove { bestmove });
+                    break;
+                }
+            }
+        }
+        events
+    })
+}
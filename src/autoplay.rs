@@ -1,45 +1,190 @@
 use crate::{
-    chess::Game,
-    constants::TT_CAPACITY,
-    search::{get_best_move_until_stop, TranspositionTable},
+    chess::{
+        pgn::{Annotation, GameResult},
+        score::Score,
+        Game,
+    },
+    constants::{DEFAULT_CONTEMPT, TT_CAPACITY},
+    engine_match::{Adjudication, Adjudicator, PlayedGame, Termination},
+    openings::{OpeningSettings, Openings},
+    search::{search, SearchLimits, TranspositionTable},
+    uci, wdl,
 };
-use arrayvec::ArrayVec;
 use nohash_hasher::BuildNoHashHasher;
 use std::{
     collections::HashMap,
-    sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
-        Arc,
-    },
-    time::Duration,
+    sync::atomic::AtomicBool,
+    time::{Duration, Instant},
 };
 
-pub fn autoplay(millis: u64) {
-    let mut game = Game::default();
+/// Settings of games of the engine against itself
+pub struct AutoplaySettings {
+    /// Where the games start from
+    pub openings: OpeningSettings,
+    /// Games played one after the other
+    pub games: usize,
+    /// Time of every move in ms, unless there is a time control
+    pub millis: u64,
+    /// Time of each side at the start of the game and added after each of their moves,
+    /// in ms, as read by `engine_match::parse_time_control`
+    pub time_control: Option<(u64, u64)>,
+    /// Moves of each side after which the game is stopped unfinished
+    pub max_moves: Option<usize>,
+    /// How the game is decided from the scores before it is over
+    pub adjudication: Adjudication,
+}
+
+/// Plays games of the engine against itself, printing every position with the search
+/// of its move, and after each game how it ended and the game as PGN, with the
+/// evaluation and depth of each move
+pub fn autoplay(settings: &AutoplaySettings) -> anyhow::Result<()> {
+    let mut openings = Openings::new(&settings.openings)?;
+
+    for round in 1..=settings.games {
+        let (played, annotations) = play_game(settings, openings.next_game(), true);
+
+        println!("{}", &played.game);
+        println!(
+            "Result: {} ({})",
+            played.result.as_str(),
+            played.termination.description()
+        );
+        let mut pgn =
+            played
+                .game
+                .to_pgn_game("RustyBait", "RustyBait", played.result, &annotations);
+        pgn.set_tag("Round", &round.to_string());
+        pgn.set_tag("Termination", played.termination.tag());
+        println!("{}", pgn);
+    }
+
+    Ok(())
+}
+
+fn play_game(
+    settings: &AutoplaySettings,
+    mut game: Game,
+    verbose: bool,
+) -> (PlayedGame, Vec<Annotation>) {
     let mut cache: TranspositionTable =
         HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildNoHashHasher::default());
+    let output = uci::Output::stdout();
+    // Remaining time of white and black with a time control, in ms
+    let mut clocks = settings
+        .time_control
+        .map_or([0, 0], |(time, _)| [time as i64, time as i64]);
 
-    loop {
-        let mut moves = ArrayVec::new();
-        game.get_moves(&mut moves, true);
-        println!("{}", game.get_pgn());
-        println!("{}", &game);
+    let mut adjudicator = Adjudicator::new(settings.adjudication);
+    let mut annotations = Vec::new();
 
-        let search_is_running = Arc::new(AtomicBool::new(true));
+    let (result, termination) = loop {
+        let outcome = game.outcome();
+        if let Some(termination) = Termination::of(outcome) {
+            break (outcome.result(), termination);
+        }
+        if settings
+            .max_moves
+            .is_some_and(|max_moves| game.history().len() >= 2 * max_moves)
+        {
+            break (GameResult::Unfinished, Termination::MaxLength);
+        }
+
+        if verbose {
+            println!("{}", &game);
+        }
 
-        std::thread::spawn({
-            let search_is_running = search_is_running.clone();
-            move || {
-                std::thread::sleep(Duration::from_millis(millis));
-                search_is_running.store(false, Relaxed);
+        let player = game.player();
+        let limits = match settings.time_control {
+            Some((_, increment)) => SearchLimits::new().clock(
+                player,
+                Duration::from_millis(clocks[player.as_index()].max(0) as u64),
+                Duration::from_millis(increment),
+            ),
+            None => SearchLimits::new().move_time(Duration::from_millis(settings.millis)),
+        };
+
+        let start = Instant::now();
+        let (next_move, score, depth) = search(
+            &game,
+            &limits,
+            &mut cache,
+            &AtomicBool::new(true),
+            DEFAULT_CONTEMPT,
+            |info| {
+                if verbose {
+                    uci::print_info(&output, info, &game, false);
+                }
+            },
+        )
+        .expect("The game goes on, so there is a legal move");
+
+        if let Some((_, increment)) = settings.time_control {
+            let clock = &mut clocks[player.as_index()];
+            *clock -= start.elapsed().as_millis() as i64;
+            if *clock < 0 {
+                break (
+                    GameResult::win(player.the_other()),
+                    Termination::TimeForfeit,
+                );
             }
+            *clock += increment as i64;
+        }
+
+        // Scaled like the scores reported to GUIs, which the thresholds are meant for
+        let score = wdl::normalize(score, &game);
+        annotations.push(Annotation {
+            score: score * player as Score,
+            depth,
         });
+        let adjudicated = adjudicator.update(&game, score);
+        game.push_history(next_move);
 
-        let next_move = match get_best_move_until_stop(&game, &mut cache, &search_is_running, None)
-        {
-            Some(_move) => _move,
-            None => break,
+        if let Some(result) = adjudicated {
+            break (result, Termination::Adjudication);
+        }
+    };
+
+    let played = PlayedGame {
+        game,
+        result,
+        termination,
+    };
+    (played, annotations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn termination() {
+        let settings = AutoplaySettings {
+            openings: OpeningSettings::default(),
+            games: 1,
+            millis: 10,
+            time_control: None,
+            max_moves: Some(2),
+            adjudication: Adjudication::default(),
         };
-        game.push_history(next_move);
+        let game = Game::new("4k3/8/8/8/8/8/8/R3K3 w Q - 0 30").unwrap();
+        let (played, annotations) = play_game(&settings, game, false);
+        assert_eq!(played.termination, Termination::MaxLength);
+        assert_eq!(played.result, GameResult::Unfinished);
+        assert_eq!(played.game.history().len(), 4);
+        assert_eq!(annotations.len(), 4);
+
+        // Mate in one
+        let settings = AutoplaySettings {
+            openings: OpeningSettings::default(),
+            games: 1,
+            millis: 0,
+            time_control: Some((1000, 10)),
+            max_moves: None,
+            adjudication: Adjudication::default(),
+        };
+        let game = Game::new("6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1").unwrap();
+        let (played, _) = play_game(&settings, game, false);
+        assert_eq!(played.termination, Termination::Checkmate);
+        assert_eq!(played.result, GameResult::WhiteWins);
     }
 }
@@ -1,66 +1,50 @@
 use crate::{
-    chess::{move_struct::Move, Game},
-    constants::{TESTING_GAME, TT_CAPACITY},
-    search::{get_best_move_entry, TranspositionTable},
+    chess::Game,
+    constants::{DEFAULT_CONTEMPT, TT_CAPACITY},
+    json::{self, Message},
+    search::{self, get_best_move_entry, TranspositionTable},
 };
 use std::{
     collections::HashMap, hash::BuildHasherDefault, sync::atomic::AtomicBool, time::Instant,
 };
 
-pub fn run_simple_benchmark(depth: u8, steps: u8) {
-    let mut moves = TESTING_GAME.split_ascii_whitespace();
-
-    let mut game = Game::default();
-    let mut durations = vec![];
-
-    let is_running = AtomicBool::new(true);
-
-    let mut cache: TranspositionTable =
-        HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildHasherDefault::default());
-    'outer: loop {
-        let now = Instant::now();
-
-        let mut history = [0; 64 * 12];
-
-        get_best_move_entry(game.clone(), &is_running, depth, &mut cache, &mut history).unwrap();
-
-        durations.push(now.elapsed());
-
-        for _ in 0..steps {
-            let Some(_move) = moves.next() else {
-                break 'outer;
-            };
-
-            game.push(Move::from_uci_notation(_move, &game).unwrap());
-        }
-    }
-
-    let product = durations.iter().fold(1.0, |acc, x| acc * x.as_secs_f64());
-    let geo_mean = product.powf(1.0 / durations.len() as f64);
-
-    println!(
-        "Depth: {}, Steps: {}
-Geometric Mean: {:.2} ms",
-        depth,
-        steps,
-        geo_mean * 1000.0
-    );
-}
-
-pub fn run_iterative_benchmark(depth: u8, steps: u8) {
-    let mut moves = TESTING_GAME.split_ascii_whitespace();
-
-    let mut game = Game::default();
-    let mut durations = vec![];
-
+/// Positions searched by the benchmark: openings, middlegames with both kings castled or
+/// not, and endgames, taken from the benchmark of Stockfish
+const POSITIONS: [&str; 20] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 10",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 11",
+    "4rrk1/pp1n3p/3q2pQ/2p1pb2/2PP4/2P3N1/P2B2PP/4RRK1 b - - 7 19",
+    "rq3rk1/ppp2ppp/1bnpb3/3N2B1/3NP3/7P/PPPQ1PP1/2KR3R w - - 7 14",
+    "r1bq1r1k/1pp1n1pp/1p1p4/4p2Q/4Pp2/1BNP4/PPP2PPP/3R1RK1 w - - 2 14",
+    "r3r1k1/2p2ppp/p1p1bn2/8/1q2P3/2NPQN2/PPP3PP/R4RK1 b - - 2 15",
+    "r1bbk1nr/pp3p1p/2n5/1N4p1/2Np1B2/8/PPP2PPP/2KR1B1R w kq - 0 13",
+    "r1bq1rk1/ppp1nppp/4n3/3p3Q/3P4/1BP1B3/PP1N2PP/R4RK1 w - - 1 16",
+    "4r1k1/r1q2ppp/ppp2n2/4P3/5Rb1/1N1BQ3/PPP3PP/R5K1 w - - 1 17",
+    "2rqkb1r/ppp2p2/2npb1p1/1N1Nn2p/2P1PP2/8/PP2B1PP/R1BQK2R b KQ - 0 11",
+    "r1bq1r1k/b1p1npp1/p2p3p/1p6/3PP3/1B2NN2/PP3PPP/R2Q1RK1 w - - 1 16",
+    "3r1rk1/p5pp/bpp1pp2/8/q1PP1P2/b3P3/P2NQRPP/1R2B1K1 b - - 6 22",
+    "r1q2rk1/2p1bppp/2Pp4/p6b/Q1PNp3/4B3/PP1R1PPP/2K4R w - - 2 18",
+    "4k2r/1pb2ppp/1p2p3/1R1p4/3P4/2r1PN2/P4PPP/1R4K1 b - - 3 22",
+    "3q2k1/pb3p1p/4pbp1/2r5/PpN2N2/1P2P2P/5PP1/Q2R2K1 b - - 4 26",
+    "6k1/6p1/6Pp/ppp5/3pn2P/1P3K2/1PP2P2/3N4 b - - 0 1",
+    "3b4/5kp1/1p1p1p1p/pP1PpP1P/P1P1P3/3KN3/8/8 w - - 0 1",
+    "8/8/8/8/5kp1/P7/8/1K1N4 w - - 0 1",
+    "8/8/8/5N2/8/p7/8/2NK3k w - - 0 1",
+];
+
+/// Searches every position from depth 1 to `depth` with an empty table and returns the
+/// number of nodes, which only changes when the search itself does
+fn search_positions(depth: u8) -> u64 {
     let is_running = AtomicBool::new(true);
-
     let mut cache: TranspositionTable =
         HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildHasherDefault::default());
-    'outer: loop {
-        let now = Instant::now();
+    let start_nodes = search::nodes();
 
+    for fen in POSITIONS {
+        let game = Game::new(fen).unwrap();
         let mut history = [0; 64 * 12];
+        cache.clear();
 
         for iter_depth in 1..=depth {
             get_best_move_entry(
@@ -69,29 +53,53 @@ pub fn run_iterative_benchmark(depth: u8, steps: u8) {
                 iter_depth,
                 &mut cache,
                 &mut history,
+                DEFAULT_CONTEMPT,
+                &[],
             )
             .unwrap();
         }
+    }
 
-        durations.push(now.elapsed());
-
-        for _ in 0..steps {
-            let Some(_move) = moves.next() else {
-                break 'outer;
-            };
+    search::nodes() - start_nodes
+}
 
-            game.push(Move::from_uci_notation(_move, &game).unwrap());
-        }
+/// Searches a fixed set of positions to a fixed depth, used to measure the speed of the
+/// engine and for PGO optimization
+///
+/// The last line is the number of nodes, a signature which tells whether a change of the
+/// engine changes its search or only its speed.
+pub fn run_benchmark(depth: u8) {
+    let now = Instant::now();
+    let nodes = search_positions(depth);
+    let elapsed = now.elapsed();
+
+    let nps = (nodes as f64 / elapsed.as_secs_f64()) as u64;
+
+    if json::is_enabled() {
+        json::print(&Message::Bench {
+            depth,
+            positions: POSITIONS.len(),
+            time_ms: elapsed.as_millis(),
+            nps,
+            nodes,
+        });
+        return;
     }
 
-    let product = durations.iter().fold(1.0, |acc, x| acc * x.as_secs_f64());
-    let geo_mean = product.powf(1.0 / durations.len() as f64);
+    println!("Depth: {}, Positions: {}", depth, POSITIONS.len());
+    println!("Time: {} ms", elapsed.as_millis());
+    println!("Nodes per second: {}", nps);
+    println!("Nodes: {}", nodes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!(
-        "Depth: {}, Steps: {}
-Geometric Mean: {:.2} ms",
-        depth,
-        steps,
-        geo_mean * 1000.0
-    );
+    #[test]
+    fn signature_is_deterministic() {
+        let nodes = search_positions(3);
+        assert!(nodes > 0);
+        assert_eq!(search_positions(3), nodes);
+    }
 }
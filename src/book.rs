@@ -0,0 +1,272 @@
+use crate::chess::{
+    move_struct::{Move, MoveKind, MAX_MOVES},
+    piece::PieceType,
+    position::Position,
+    Game,
+};
+use anyhow::{bail, Context};
+use arrayvec::ArrayVec;
+use memmap2::Mmap;
+use std::{fs::File, ops::Deref};
+
+// Polyglot opening books: http://hgm.nubati.net/book_format.html
+
+/// Bytes of an entry: the key, the move, its weight and the learning data, big-endian
+const ENTRY_SIZE: usize = 16;
+
+/// An opening book in the Polyglot format, entries are sorted by the key of their position
+///
+/// The file is mapped into memory, so that books of hundreds of MB only load the pages
+/// touched by the binary search of the positions.
+pub struct Book {
+    data: BookData,
+}
+
+/// Bytes of a book, mapped from its file or owned by the book
+enum BookData {
+    Mapped(Mmap),
+    Owned(Vec<u8>),
+}
+
+impl Deref for BookData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            BookData::Mapped(map) => map,
+            BookData::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// A move of the book with how often it should be played compared to the others
+pub struct BookEntry {
+    pub _move: Move,
+    pub weight: u16,
+}
+
+impl Book {
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to read {}", path))?;
+        // SAFETY: The file must not be truncated or written while it is mapped, which books
+        // aren't while they are played
+        let map = unsafe { Mmap::map(&file) }.with_context(|| format!("Failed to map {}", path))?;
+        Self::new(BookData::Mapped(map)).with_context(|| format!("Invalid book {}", path))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> anyhow::Result<Self> {
+        Self::new(BookData::Owned(data))
+    }
+
+    fn new(data: BookData) -> anyhow::Result<Self> {
+        if !data.len().is_multiple_of(ENTRY_SIZE) {
+            bail!("The size isn't a multiple of {} bytes", ENTRY_SIZE);
+        }
+
+        Ok(Self { data })
+    }
+
+    fn key_at(&self, index: usize) -> u64 {
+        let start = index * ENTRY_SIZE;
+        u64::from_be_bytes(self.data[start..start + 8].try_into().unwrap())
+    }
+
+    /// The entries of the position, moves which aren't legal in it are left out
+    pub fn probe(&self, game: &Game) -> Vec<BookEntry> {
+        // Books are written for standard chess, the castling rights have no key for other rooks
+        if game.is_chess960() {
+            return Vec::new();
+        }
+
+        let key = game.polyglot_key();
+        let count = self.data.len() / ENTRY_SIZE;
+
+        // First entry with this key
+        let (mut low, mut high) = (0, count);
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.key_at(middle) < key {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+
+        let moves = game.legal_moves();
+
+        let mut entries = Vec::new();
+        for index in (low..count).take_while(|&index| self.key_at(index) == key) {
+            let start = index * ENTRY_SIZE;
+            let raw_move = u16::from_be_bytes([self.data[start + 8], self.data[start + 9]]);
+            let weight = u16::from_be_bytes([self.data[start + 10], self.data[start + 11]]);
+
+            if let Some(_move) = decode_move(raw_move, game, &moves) {
+                entries.push(BookEntry { _move, weight });
+            }
+        }
+
+        entries
+    }
+
+    /// Chooses one of the moves of the position with a probability proportional to its
+    /// weight, `random` is any random number
+    ///
+    /// `randomness` from 0 to 100 tells how far from the best moves of the book the choice
+    /// may go: moves whose weight is below `100 - randomness` percent of the highest one are
+    /// left out, so 0 only plays the moves with the highest weight and 100 plays all of them.
+    pub fn pick(&self, game: &Game, randomness: u8, random: u64) -> Option<Move> {
+        let mut entries = self.probe(game);
+        let best = entries.iter().map(|entry| entry.weight).max()?;
+        let kept = 100 - randomness.min(100) as u64;
+        entries.retain(|entry| entry.weight as u64 * 100 >= best as u64 * kept);
+
+        let total: u64 = entries.iter().map(|entry| entry.weight as u64).sum();
+        if total == 0 {
+            return None;
+        }
+
+        let mut target = random % total;
+        for entry in entries {
+            if target < entry.weight as u64 {
+                return Some(entry._move);
+            }
+            target -= entry.weight as u64;
+        }
+
+        None
+    }
+}
+
+/// Finds the legal move written in the Polyglot encoding: the destination in the lowest
+/// 6 bits, then the start and the promotion. Castling is written as the king taking its rook.
+fn decode_move(raw_move: u16, game: &Game, moves: &ArrayVec<Move, MAX_MOVES>) -> Option<Move> {
+    let square = |bits: u16| Position::new_assert((bits >> 3 & 0b111) as i8, (bits & 0b111) as i8);
+    let end = square(raw_move);
+    let start = square(raw_move >> 6);
+    let promotion = match raw_move >> 12 & 0b111 {
+        0 => None,
+        1 => Some(PieceType::Knight),
+        2 => Some(PieceType::Bishop),
+        3 => Some(PieceType::Rook),
+        4 => Some(PieceType::Queen),
+        _ => return None,
+    };
+
+    moves.iter().copied().find(|_move| {
+        let target = match _move.kind() {
+            MoveKind::Castling(side) => {
+                let (_, _, old_rook, _) = game.castling_squares(game.player(), side);
+                old_rook
+            }
+            _ => _move.end(),
+        };
+        let new_piece = match _move.kind() {
+            MoveKind::Promotion(new_piece) => Some(new_piece),
+            _ => None,
+        };
+
+        _move.start() == start && target == end && new_piece == promotion
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: u64, raw_move: u16, weight: u16) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ENTRY_SIZE);
+        bytes.extend(key.to_be_bytes());
+        bytes.extend(raw_move.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend(0u32.to_be_bytes());
+        bytes
+    }
+
+    #[test]
+    fn probe() {
+        let game = Game::default();
+        let key = game.polyglot_key();
+
+        // e2e4, d2d4 and a move of another position around them, sorted by key
+        let e2e4 = 12 << 6 | 28;
+        let d2d4 = 11 << 6 | 27;
+        let data = [
+            entry(key - 1, e2e4, 10),
+            entry(key, e2e4, 3),
+            entry(key, d2d4, 1),
+            entry(key, 0, 5),
+            entry(key + 1, d2d4, 10),
+        ]
+        .concat();
+        let book = Book::from_bytes(data).unwrap();
+
+        // The last entry of the position isn't a legal move
+        let entries = book.probe(&game);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]._move.uci_notation(&game), "e2e4");
+        assert_eq!(entries[1]._move.uci_notation(&game), "d2d4");
+
+        assert_eq!(
+            book.pick(&game, 100, 2).unwrap().uci_notation(&game),
+            "e2e4"
+        );
+        assert_eq!(
+            book.pick(&game, 100, 3).unwrap().uci_notation(&game),
+            "d2d4"
+        );
+        assert_eq!(
+            book.pick(&game, 100, 4).unwrap().uci_notation(&game),
+            "e2e4"
+        );
+
+        // d2d4 has a third of the weight of e2e4
+        for random in 0..4 {
+            assert_eq!(
+                book.pick(&game, 0, random).unwrap().uci_notation(&game),
+                "e2e4"
+            );
+            assert_eq!(
+                book.pick(&game, 60, random).unwrap().uci_notation(&game),
+                "e2e4"
+            );
+        }
+        assert_eq!(book.pick(&game, 70, 3).unwrap().uci_notation(&game), "d2d4");
+
+        let game = Game::new("8/8/8/8/8/8/8/K6k w - - 0 1").unwrap();
+        assert!(book.pick(&game, 100, 0).is_none());
+
+        assert!(Book::from_bytes(vec![0; ENTRY_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn open() {
+        let game = Game::default();
+        let path = std::env::temp_dir().join(format!("rustybait-book-{}.bin", std::process::id()));
+        std::fs::write(&path, entry(game.polyglot_key(), 12 << 6 | 28, 1)).unwrap();
+
+        let book = Book::open(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let entries = book.unwrap().probe(&game);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]._move.uci_notation(&game), "e2e4");
+
+        assert!(Book::open(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn moves() {
+        let game = Game::new("r3k3/1P6/8/8/8/8/8/R3K2R w KQq - 0 1").unwrap();
+        let mut moves = ArrayVec::new();
+        game.get_moves(&mut moves);
+
+        // e1h1, e1a1, b7a8 promoting to a knight
+        for (raw_move, uci) in [
+            (4 << 6 | 7, "e1g1"),
+            (4 << 6, "e1c1"),
+            (1 << 12 | 49 << 6 | 56, "b7a8n"),
+        ] {
+            let _move = decode_move(raw_move, &game, &moves).unwrap();
+            assert_eq!(_move.uci_notation(&game), uci);
+        }
+    }
+}
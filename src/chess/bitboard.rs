@@ -0,0 +1,368 @@
+use super::piece::PieceType;
+use super::position::Position;
+use super::unchecked;
+use super::Player;
+use alloc::{boxed::Box, vec, vec::Vec};
+use once_cell::race::OnceBox;
+
+// Attacks of sliding pieces looked up in tables indexed by magic multiplication
+// Based on: https://www.chessprogramming.org/Magic_Bitboards
+
+/// One bit per square, indexed by Position::as_usize
+pub type Bitboard = u64;
+
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(0, 1), (0, -1), (1, 0), (-1, 0)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(1, 1), (-1, -1), (1, -1), (-1, 1)];
+
+#[inline]
+pub fn bit(position: Position) -> Bitboard {
+    1 << position.as_usize()
+}
+
+/// Iterates over the positions of the set bits, from a1 to h8
+#[inline]
+pub fn positions(mut bitboard: Bitboard) -> impl Iterator<Item = Position> {
+    core::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+
+        let index = bitboard.trailing_zeros() as i8;
+        bitboard &= bitboard - 1;
+
+        // SAFETY: The index of a bit of a u64 is always in 0..64
+        Some(unsafe { Position::new_unsafe(index / 8, index % 8) })
+    })
+}
+
+/// Attacks of a piece which jumps by the given deltas, for every square
+const fn leaper_attacks<const N: usize>(deltas: [(i8, i8); N]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+
+    let mut square = 0;
+    while square < 64 {
+        let mut index = 0;
+        while index < N {
+            let row = square as i8 / 8 + deltas[index].0;
+            let col = square as i8 % 8 + deltas[index].1;
+            if row >= 0 && row < 8 && col >= 0 && col < 8 {
+                table[square] |= 1 << (row * 8 + col);
+            }
+            index += 1;
+        }
+        square += 1;
+    }
+
+    table
+}
+
+static KNIGHT_ATTACKS: [Bitboard; 64] = leaper_attacks([
+    (1, 2),
+    (2, 1),
+    (-1, -2),
+    (-2, -1),
+    (1, -2),
+    (-2, 1),
+    (-1, 2),
+    (2, -1),
+]);
+
+static KING_ATTACKS: [Bitboard; 64] = leaper_attacks([
+    (0, 1),
+    (0, -1),
+    (1, 0),
+    (-1, 0),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+]);
+
+/// Indexed by Player::as_index
+static PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    leaper_attacks([(1, 1), (1, -1)]),
+    leaper_attacks([(-1, 1), (-1, -1)]),
+];
+
+#[inline]
+pub fn knight_attacks(position: Position) -> Bitboard {
+    KNIGHT_ATTACKS[position.as_usize()]
+}
+
+#[inline]
+pub fn king_attacks(position: Position) -> Bitboard {
+    KING_ATTACKS[position.as_usize()]
+}
+
+/// Squares attacked by a pawn of `player`
+#[inline]
+pub fn pawn_attacks(player: Player, position: Position) -> Bitboard {
+    PAWN_ATTACKS[player.as_index()][position.as_usize()]
+}
+
+/// Squares strictly between two positions on the same row, column or diagonal,
+/// empty if they aren't aligned
+#[inline]
+pub fn between(first: Position, second: Position) -> Bitboard {
+    let (first_bit, second_bit) = (bit(first), bit(second));
+
+    if rook_attacks(first, 0) & second_bit != 0 {
+        rook_attacks(first, second_bit) & rook_attacks(second, first_bit)
+    } else if bishop_attacks(first, 0) & second_bit != 0 {
+        bishop_attacks(first, second_bit) & bishop_attacks(second, first_bit)
+    } else {
+        0
+    }
+}
+
+/// Squares attacked by a rook, the first occupied square of every ray is included
+#[inline]
+pub fn rook_attacks(position: Position, occupied: Bitboard) -> Bitboard {
+    let attacks = sliding_attacks();
+    attacks.lookup(&attacks.rook[position.as_usize()], occupied)
+}
+
+/// Squares attacked by a bishop, the first occupied square of every ray is included
+#[inline]
+pub fn bishop_attacks(position: Position, occupied: Bitboard) -> Bitboard {
+    let attacks = sliding_attacks();
+    attacks.lookup(&attacks.bishop[position.as_usize()], occupied)
+}
+
+/// Squares attacked by a rook, bishop or queen, empty for the other pieces
+#[inline]
+pub fn slider_attacks(piece_type: PieceType, position: Position, occupied: Bitboard) -> Bitboard {
+    match piece_type {
+        PieceType::Rook => rook_attacks(position, occupied),
+        PieceType::Bishop => bishop_attacks(position, occupied),
+        PieceType::Queen => rook_attacks(position, occupied) | bishop_attacks(position, occupied),
+        _ => 0,
+    }
+}
+
+/// Everything needed to find the attacks from one square in the shared table
+#[derive(Clone, Copy, Default)]
+struct Magic {
+    /// Squares whose occupancy changes the attacks, the edges of the rays are left out
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+struct SlidingAttacks {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    table: Vec<Bitboard>,
+}
+
+impl SlidingAttacks {
+    #[inline]
+    fn lookup(&self, magic: &Magic, occupied: Bitboard) -> Bitboard {
+        let index = ((occupied & magic.mask).wrapping_mul(magic.magic) >> magic.shift) as usize;
+
+        // SAFETY: The table has room for every index of every magic, since the multiplication
+        // is shifted down to the number of bits reserved for the square
+        unsafe { *unchecked::get(&self.table, magic.offset + index) }
+    }
+}
+
+static SLIDING_ATTACKS: OnceBox<SlidingAttacks> = OnceBox::new();
+
+/// The magics are searched for on the first call
+#[inline]
+fn sliding_attacks() -> &'static SlidingAttacks {
+    SLIDING_ATTACKS.get_or_init(|| Box::new(generate()))
+}
+
+/// Attacks found by walking the rays, used to fill the tables
+fn ray_attacks(square: usize, occupied: Bitboard, directions: &[(i8, i8); 4]) -> Bitboard {
+    let start = Position::new_assert(square as i8 / 8, square as i8 % 8);
+    let mut attacks = 0;
+
+    for &direction in directions {
+        let mut position = start;
+        while let Some(next) = position.add(direction) {
+            attacks |= bit(next);
+            if occupied & bit(next) != 0 {
+                break;
+            }
+            position = next;
+        }
+    }
+
+    attacks
+}
+
+fn relevant_mask(square: usize, directions: &[(i8, i8); 4]) -> Bitboard {
+    let start = Position::new_assert(square as i8 / 8, square as i8 % 8);
+    let mut mask = 0;
+
+    for &direction in directions {
+        let mut position = start;
+        while let Some(next) = position.add(direction) {
+            // The last square of a ray is attacked whether it is occupied or not
+            if next.add(direction).is_none() {
+                break;
+            }
+            mask |= bit(next);
+            position = next;
+        }
+    }
+
+    mask
+}
+
+/// Seeds for every row of the board which find magics after few attempts, from Stockfish
+const SEEDS: [u64; 8] = [728, 10316, 55013, 32803, 12281, 15100, 16645, 255];
+
+/// Xorshift generator, a fixed seed makes the search for magics deterministic
+struct Prng(u64);
+
+impl Prng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(2685821657736338717)
+    }
+
+    /// Numbers with few bits set make good magic candidates
+    fn sparse(&mut self) -> u64 {
+        self.next() & self.next() & self.next()
+    }
+}
+
+fn generate() -> SlidingAttacks {
+    let mut table = Vec::new();
+
+    let mut find_magics = |directions: &[(i8, i8); 4]| {
+        let mut magics = [Magic::default(); 64];
+
+        for (square, magic) in magics.iter_mut().enumerate() {
+            let mask = relevant_mask(square, directions);
+            let bits = mask.count_ones();
+
+            // Enumerate every subset of the mask with the carry-rippler trick
+            let mut occupancies = Vec::with_capacity(1 << bits);
+            let mut subset: Bitboard = 0;
+            loop {
+                occupancies.push((subset, ray_attacks(square, subset, directions)));
+                subset = subset.wrapping_sub(mask) & mask;
+                if subset == 0 {
+                    break;
+                }
+            }
+
+            magic.mask = mask;
+            magic.shift = 64 - bits;
+            magic.offset = table.len();
+            table.resize(table.len() + (1 << bits), 0);
+
+            // Every occupancy has to land on an entry which is either free
+            // or already holds the same attacks, entries are marked with
+            // the attempt which used them so they don't have to be cleared
+            let mut prng = Prng(SEEDS[square / 8]);
+            let mut used = vec![0u32; 1 << bits];
+            let mut attempt = 0;
+            'search: loop {
+                magic.magic = prng.sparse();
+                if (mask.wrapping_mul(magic.magic) >> 56).count_ones() < 6 {
+                    continue;
+                }
+
+                attempt += 1;
+                for &(occupied, attacks) in &occupancies {
+                    let index = (occupied.wrapping_mul(magic.magic) >> magic.shift) as usize;
+                    let entry = &mut table[magic.offset + index];
+
+                    if used[index] != attempt {
+                        used[index] = attempt;
+                        *entry = attacks;
+                    } else if *entry != attacks {
+                        continue 'search;
+                    }
+                }
+
+                break;
+            }
+        }
+
+        magics
+    };
+
+    let rook = find_magics(&ROOK_DIRECTIONS);
+    let bishop = find_magics(&BISHOP_DIRECTIONS);
+
+    SlidingAttacks {
+        rook,
+        bishop,
+        table,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lookup_matches_rays() {
+        let mut prng = Prng(42);
+
+        for _ in 0..1000 {
+            let occupied = prng.sparse() | prng.sparse();
+
+            for square in 0..64 {
+                let position = Position::new_assert(square as i8 / 8, square as i8 % 8);
+                assert_eq!(
+                    rook_attacks(position, occupied),
+                    ray_attacks(square, occupied, &ROOK_DIRECTIONS)
+                );
+                assert_eq!(
+                    bishop_attacks(position, occupied),
+                    ray_attacks(square, occupied, &BISHOP_DIRECTIONS)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn leaper_and_between() {
+        let a1 = Position::new_assert(0, 0);
+        let e4 = Position::new_assert(3, 4);
+        let h7 = Position::new_assert(6, 7);
+
+        assert_eq!(knight_attacks(a1).count_ones(), 2);
+        assert_eq!(king_attacks(e4).count_ones(), 8);
+        assert_eq!(
+            pawn_attacks(Player::White, e4),
+            bit(Position::new_assert(4, 3)) | bit(Position::new_assert(4, 5))
+        );
+        assert_eq!(pawn_attacks(Player::Black, a1), 0);
+
+        assert_eq!(
+            between(e4, h7),
+            bit(Position::new_assert(4, 5)) | bit(Position::new_assert(5, 6))
+        );
+        assert_eq!(between(a1, Position::new_assert(0, 3)).count_ones(), 2);
+        assert_eq!(between(a1, e4), 0);
+        assert_eq!(between(e4, Position::new_assert(4, 4)), 0);
+    }
+
+    #[test]
+    fn iterate_positions() {
+        let bitboard = bit(Position::new_assert(0, 0))
+            | bit(Position::new_assert(3, 5))
+            | bit(Position::new_assert(7, 7));
+
+        assert_eq!(
+            positions(bitboard).collect::<Vec<_>>(),
+            [
+                Position::new_assert(0, 0),
+                Position::new_assert(3, 5),
+                Position::new_assert(7, 7)
+            ]
+        );
+        assert_eq!(positions(0).count(), 0);
+    }
+}
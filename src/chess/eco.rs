@@ -0,0 +1,702 @@
+use super::move_struct::Move;
+use super::{Game, START_FEN};
+use alloc::{boxed::Box, collections::BTreeMap};
+use once_cell::race::OnceBox;
+
+// Encyclopaedia of Chess Openings classification
+// Based on: https://www.chessprogramming.org/ECO
+
+/// Code, name and moves from the start position of the classified openings. More specific
+/// lines come after the ones they continue, and the first name reached by a position wins.
+const OPENINGS: &[(&str, &str, &str)] = &[
+    // Flank openings
+    ("A00", "Polish Opening", "b4"),
+    ("A00", "Grob Opening", "g4"),
+    ("A00", "Van't Kruijs Opening", "e3"),
+    ("A00", "Mieses Opening", "d3"),
+    ("A00", "Hungarian Opening", "g3"),
+    ("A00", "Saragossa Opening", "c3"),
+    ("A00", "Clemenz Opening", "h3"),
+    ("A00", "Barnes Opening", "f3"),
+    ("A00", "Barnes Opening: Fool's Mate", "f3 e5 g4 Qh4#"),
+    ("A00", "Anderssen's Opening", "a3"),
+    ("A00", "Van Geet Opening", "Nc3"),
+    ("A01", "Nimzo-Larsen Attack", "b3"),
+    ("A02", "Bird Opening", "f4"),
+    ("A03", "Bird Opening: Dutch Variation", "f4 d5"),
+    ("A02", "Bird Opening: From's Gambit", "f4 e5"),
+    ("A04", "Zukertort Opening", "Nf3"),
+    ("A04", "Zukertort Opening: Sicilian Invitation", "Nf3 c5"),
+    ("A05", "Zukertort Opening", "Nf3 Nf6"),
+    ("A06", "Zukertort Opening", "Nf3 d5"),
+    ("A07", "King's Indian Attack", "Nf3 d5 g3"),
+    ("A09", "Réti Opening", "Nf3 d5 c4"),
+    ("A09", "Réti Opening: Accepted", "Nf3 d5 c4 dxc4"),
+    // English
+    ("A10", "English Opening", "c4"),
+    ("A10", "English Opening: Anglo-Dutch Defense", "c4 f5"),
+    (
+        "A11",
+        "English Opening: Caro-Kann Defensive System",
+        "c4 c6",
+    ),
+    ("A13", "English Opening: Agincourt Defense", "c4 e6"),
+    ("A15", "English Opening: Anglo-Indian Defense", "c4 Nf6"),
+    (
+        "A16",
+        "English Opening: Anglo-Indian Defense, Queen's Knight Variation",
+        "c4 Nf6 Nc3",
+    ),
+    ("A20", "English Opening: King's English Variation", "c4 e5"),
+    (
+        "A21",
+        "English Opening: King's English Variation, Reversed Sicilian",
+        "c4 e5 Nc3",
+    ),
+    (
+        "A22",
+        "English Opening: King's English Variation, Two Knights Variation",
+        "c4 e5 Nc3 Nf6",
+    ),
+    (
+        "A25",
+        "English Opening: King's English Variation, Closed System",
+        "c4 e5 Nc3 Nc6 g3",
+    ),
+    ("A30", "English Opening: Symmetrical Variation", "c4 c5"),
+    (
+        "A34",
+        "English Opening: Symmetrical Variation, Normal Variation",
+        "c4 c5 Nc3",
+    ),
+    // Queen's pawn games
+    ("A40", "Queen's Pawn Game", "d4"),
+    ("A40", "Englund Gambit", "d4 e5"),
+    ("A40", "Horwitz Defense", "d4 e6"),
+    ("A40", "Modern Defense", "d4 g6"),
+    ("A41", "Old Indian Defense", "d4 d6"),
+    ("A43", "Benoni Defense: Old Benoni", "d4 c5"),
+    ("A45", "Indian Defense", "d4 Nf6"),
+    ("A45", "Trompowsky Attack", "d4 Nf6 Bg5"),
+    ("A46", "Indian Defense: Knights Variation", "d4 Nf6 Nf3"),
+    ("A46", "London System", "d4 Nf6 Nf3 e6 Bf4"),
+    ("A48", "East Indian Defense", "d4 Nf6 Nf3 g6"),
+    ("A48", "London System", "d4 Nf6 Nf3 g6 Bf4"),
+    ("A50", "Indian Defense: Normal Variation", "d4 Nf6 c4"),
+    ("A51", "Indian Defense: Budapest Defense", "d4 Nf6 c4 e5"),
+    (
+        "A52",
+        "Indian Defense: Budapest Defense",
+        "d4 Nf6 c4 e5 dxe5 Ng4",
+    ),
+    ("A53", "Old Indian Defense", "d4 Nf6 c4 d6"),
+    ("A56", "Benoni Defense", "d4 Nf6 c4 c5"),
+    ("A57", "Benko Gambit", "d4 Nf6 c4 c5 d5 b5"),
+    (
+        "A60",
+        "Benoni Defense: Modern Variation",
+        "d4 Nf6 c4 c5 d5 e6",
+    ),
+    ("A80", "Dutch Defense", "d4 f5"),
+    ("A83", "Dutch Defense: Staunton Gambit", "d4 f5 e4"),
+    ("A84", "Dutch Defense", "d4 f5 c4"),
+    (
+        "A87",
+        "Dutch Defense: Leningrad Variation",
+        "d4 f5 c4 Nf6 g3 g6 Bg2 Bg7 Nf3",
+    ),
+    (
+        "A90",
+        "Dutch Defense: Classical Variation",
+        "d4 f5 c4 Nf6 g3 e6 Bg2",
+    ),
+    // Semi-open games
+    ("B00", "King's Pawn Game", "e4"),
+    ("B00", "Nimzowitsch Defense", "e4 Nc6"),
+    ("B00", "Owen Defense", "e4 b6"),
+    ("B00", "St. George Defense", "e4 a6"),
+    ("B01", "Scandinavian Defense", "e4 d5"),
+    (
+        "B01",
+        "Scandinavian Defense: Mieses-Kotroc Variation",
+        "e4 d5 exd5 Qxd5",
+    ),
+    (
+        "B01",
+        "Scandinavian Defense: Main Line",
+        "e4 d5 exd5 Qxd5 Nc3 Qa5",
+    ),
+    (
+        "B01",
+        "Scandinavian Defense: Modern Variation",
+        "e4 d5 exd5 Nf6",
+    ),
+    ("B02", "Alekhine Defense", "e4 Nf6"),
+    ("B03", "Alekhine Defense", "e4 Nf6 e5 Nd5 d4"),
+    (
+        "B04",
+        "Alekhine Defense: Modern Variation",
+        "e4 Nf6 e5 Nd5 d4 d6 Nf3",
+    ),
+    ("B06", "Modern Defense", "e4 g6"),
+    ("B06", "Modern Defense: Standard Line", "e4 g6 d4 Bg7"),
+    ("B07", "Pirc Defense", "e4 d6 d4 Nf6"),
+    (
+        "B08",
+        "Pirc Defense: Classical Variation",
+        "e4 d6 d4 Nf6 Nc3 g6 Nf3",
+    ),
+    (
+        "B09",
+        "Pirc Defense: Austrian Attack",
+        "e4 d6 d4 Nf6 Nc3 g6 f4",
+    ),
+    ("B10", "Caro-Kann Defense", "e4 c6"),
+    (
+        "B10",
+        "Caro-Kann Defense: Two Knights Attack",
+        "e4 c6 Nc3 d5 Nf3",
+    ),
+    ("B12", "Caro-Kann Defense", "e4 c6 d4 d5"),
+    (
+        "B12",
+        "Caro-Kann Defense: Advance Variation",
+        "e4 c6 d4 d5 e5",
+    ),
+    (
+        "B13",
+        "Caro-Kann Defense: Exchange Variation",
+        "e4 c6 d4 d5 exd5 cxd5",
+    ),
+    (
+        "B14",
+        "Caro-Kann Defense: Panov Attack",
+        "e4 c6 d4 d5 exd5 cxd5 c4 Nf6 Nc3",
+    ),
+    ("B15", "Caro-Kann Defense", "e4 c6 d4 d5 Nc3"),
+    (
+        "B15",
+        "Caro-Kann Defense: Main Line",
+        "e4 c6 d4 d5 Nc3 dxe4 Nxe4",
+    ),
+    (
+        "B17",
+        "Caro-Kann Defense: Karpov Variation",
+        "e4 c6 d4 d5 Nc3 dxe4 Nxe4 Nd7",
+    ),
+    (
+        "B18",
+        "Caro-Kann Defense: Classical Variation",
+        "e4 c6 d4 d5 Nc3 dxe4 Nxe4 Bf5",
+    ),
+    ("B20", "Sicilian Defense", "e4 c5"),
+    ("B20", "Sicilian Defense: Bowdler Attack", "e4 c5 Bc4"),
+    ("B20", "Sicilian Defense: Wing Gambit", "e4 c5 b4"),
+    (
+        "B21",
+        "Sicilian Defense: Smith-Morra Gambit",
+        "e4 c5 d4 cxd4 c3",
+    ),
+    ("B22", "Sicilian Defense: Alapin Variation", "e4 c5 c3"),
+    ("B23", "Sicilian Defense: Closed", "e4 c5 Nc3"),
+    (
+        "B23",
+        "Sicilian Defense: Grand Prix Attack",
+        "e4 c5 Nc3 Nc6 f4",
+    ),
+    ("B27", "Sicilian Defense", "e4 c5 Nf3"),
+    (
+        "B27",
+        "Sicilian Defense: Hyperaccelerated Dragon",
+        "e4 c5 Nf3 g6",
+    ),
+    ("B30", "Sicilian Defense: Old Sicilian", "e4 c5 Nf3 Nc6"),
+    (
+        "B30",
+        "Sicilian Defense: Rossolimo Variation",
+        "e4 c5 Nf3 Nc6 Bb5",
+    ),
+    (
+        "B32",
+        "Sicilian Defense: Open",
+        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4",
+    ),
+    (
+        "B33",
+        "Sicilian Defense: Four Knights Variation",
+        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 Nf6 Nc3",
+    ),
+    (
+        "B33",
+        "Sicilian Defense: Lasker-Pelikan Variation",
+        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 Nf6 Nc3 e5",
+    ),
+    (
+        "B34",
+        "Sicilian Defense: Accelerated Dragon",
+        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 g6",
+    ),
+    ("B40", "Sicilian Defense: French Variation", "e4 c5 Nf3 e6"),
+    (
+        "B41",
+        "Sicilian Defense: Kan Variation",
+        "e4 c5 Nf3 e6 d4 cxd4 Nxd4 a6",
+    ),
+    (
+        "B44",
+        "Sicilian Defense: Taimanov Variation",
+        "e4 c5 Nf3 e6 d4 cxd4 Nxd4 Nc6",
+    ),
+    ("B50", "Sicilian Defense: Modern Variations", "e4 c5 Nf3 d6"),
+    (
+        "B51",
+        "Sicilian Defense: Moscow Variation",
+        "e4 c5 Nf3 d6 Bb5+",
+    ),
+    (
+        "B53",
+        "Sicilian Defense: Modern Variations, Main Line",
+        "e4 c5 Nf3 d6 d4 cxd4",
+    ),
+    ("B54", "Sicilian Defense: Open", "e4 c5 Nf3 d6 d4 cxd4 Nxd4"),
+    (
+        "B56",
+        "Sicilian Defense: Classical Variation",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3",
+    ),
+    (
+        "B58",
+        "Sicilian Defense: Classical Variation",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 Nc6",
+    ),
+    (
+        "B70",
+        "Sicilian Defense: Dragon Variation",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 g6",
+    ),
+    (
+        "B80",
+        "Sicilian Defense: Scheveningen Variation",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 e6",
+    ),
+    (
+        "B90",
+        "Sicilian Defense: Najdorf Variation",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6",
+    ),
+    (
+        "B90",
+        "Sicilian Defense: Najdorf Variation, English Attack",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6 Be3",
+    ),
+    (
+        "B94",
+        "Sicilian Defense: Najdorf Variation",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6 Bg5",
+    ),
+    // French
+    ("C00", "French Defense", "e4 e6"),
+    ("C00", "French Defense: Knight Variation", "e4 e6 Nf3"),
+    (
+        "C01",
+        "French Defense: Exchange Variation",
+        "e4 e6 d4 d5 exd5 exd5",
+    ),
+    ("C02", "French Defense: Advance Variation", "e4 e6 d4 d5 e5"),
+    (
+        "C03",
+        "French Defense: Tarrasch Variation",
+        "e4 e6 d4 d5 Nd2",
+    ),
+    (
+        "C10",
+        "French Defense: Paulsen Variation",
+        "e4 e6 d4 d5 Nc3",
+    ),
+    (
+        "C10",
+        "French Defense: Rubinstein Variation",
+        "e4 e6 d4 d5 Nc3 dxe4",
+    ),
+    (
+        "C11",
+        "French Defense: Classical Variation",
+        "e4 e6 d4 d5 Nc3 Nf6",
+    ),
+    (
+        "C15",
+        "French Defense: Winawer Variation",
+        "e4 e6 d4 d5 Nc3 Bb4",
+    ),
+    // Open games
+    ("C20", "King's Pawn Game", "e4 e5"),
+    ("C20", "Center Game", "e4 e5 d4"),
+    ("C22", "Center Game Accepted", "e4 e5 d4 exd4 Qxd4"),
+    ("C21", "Danish Gambit", "e4 e5 d4 exd4 c3"),
+    ("C23", "Bishop's Opening", "e4 e5 Bc4"),
+    ("C24", "Bishop's Opening: Berlin Defense", "e4 e5 Bc4 Nf6"),
+    ("C25", "Vienna Game", "e4 e5 Nc3"),
+    ("C26", "Vienna Game: Falkbeer Variation", "e4 e5 Nc3 Nf6"),
+    ("C29", "Vienna Game: Vienna Gambit", "e4 e5 Nc3 Nf6 f4"),
+    ("C30", "King's Gambit", "e4 e5 f4"),
+    (
+        "C31",
+        "King's Gambit Declined: Falkbeer Countergambit",
+        "e4 e5 f4 d5",
+    ),
+    ("C33", "King's Gambit Accepted", "e4 e5 f4 exf4"),
+    (
+        "C34",
+        "King's Gambit Accepted: King's Knight's Gambit",
+        "e4 e5 f4 exf4 Nf3",
+    ),
+    ("C40", "King's Knight Opening", "e4 e5 Nf3"),
+    ("C40", "Latvian Gambit", "e4 e5 Nf3 f5"),
+    ("C40", "Elephant Gambit", "e4 e5 Nf3 d5"),
+    ("C41", "Philidor Defense", "e4 e5 Nf3 d6"),
+    ("C42", "Petrov's Defense", "e4 e5 Nf3 Nf6"),
+    (
+        "C42",
+        "Petrov's Defense: Classical Attack",
+        "e4 e5 Nf3 Nf6 Nxe5 d6 Nf3 Nxe4 d4",
+    ),
+    (
+        "C43",
+        "Petrov's Defense: Steinitz Attack",
+        "e4 e5 Nf3 Nf6 d4",
+    ),
+    (
+        "C44",
+        "King's Knight Opening: Normal Variation",
+        "e4 e5 Nf3 Nc6",
+    ),
+    ("C44", "Ponziani Opening", "e4 e5 Nf3 Nc6 c3"),
+    ("C44", "Scotch Game", "e4 e5 Nf3 Nc6 d4"),
+    ("C44", "Scotch Gambit", "e4 e5 Nf3 Nc6 d4 exd4 Bc4"),
+    ("C45", "Scotch Game", "e4 e5 Nf3 Nc6 d4 exd4 Nxd4"),
+    ("C46", "Three Knights Opening", "e4 e5 Nf3 Nc6 Nc3"),
+    ("C47", "Four Knights Game", "e4 e5 Nf3 Nc6 Nc3 Nf6"),
+    (
+        "C47",
+        "Four Knights Game: Scotch Variation",
+        "e4 e5 Nf3 Nc6 Nc3 Nf6 d4",
+    ),
+    (
+        "C48",
+        "Four Knights Game: Spanish Variation",
+        "e4 e5 Nf3 Nc6 Nc3 Nf6 Bb5",
+    ),
+    ("C50", "Italian Game", "e4 e5 Nf3 Nc6 Bc4"),
+    ("C50", "Italian Game: Giuoco Piano", "e4 e5 Nf3 Nc6 Bc4 Bc5"),
+    (
+        "C50",
+        "Italian Game: Giuoco Pianissimo",
+        "e4 e5 Nf3 Nc6 Bc4 Bc5 d3",
+    ),
+    (
+        "C51",
+        "Italian Game: Evans Gambit",
+        "e4 e5 Nf3 Nc6 Bc4 Bc5 b4",
+    ),
+    (
+        "C53",
+        "Italian Game: Classical Variation",
+        "e4 e5 Nf3 Nc6 Bc4 Bc5 c3",
+    ),
+    (
+        "C55",
+        "Italian Game: Two Knights Defense",
+        "e4 e5 Nf3 Nc6 Bc4 Nf6",
+    ),
+    (
+        "C57",
+        "Italian Game: Two Knights Defense, Fried Liver Attack",
+        "e4 e5 Nf3 Nc6 Bc4 Nf6 Ng5 d5 exd5 Nxd5 Nxf7",
+    ),
+    (
+        "C57",
+        "Italian Game: Two Knights Defense, Traxler Counterattack",
+        "e4 e5 Nf3 Nc6 Bc4 Nf6 Ng5 Bc5",
+    ),
+    (
+        "C58",
+        "Italian Game: Two Knights Defense, Knight Attack",
+        "e4 e5 Nf3 Nc6 Bc4 Nf6 Ng5 d5 exd5 Na5",
+    ),
+    ("C60", "Ruy Lopez", "e4 e5 Nf3 Nc6 Bb5"),
+    ("C62", "Ruy Lopez: Steinitz Defense", "e4 e5 Nf3 Nc6 Bb5 d6"),
+    (
+        "C64",
+        "Ruy Lopez: Classical Variation",
+        "e4 e5 Nf3 Nc6 Bb5 Bc5",
+    ),
+    ("C65", "Ruy Lopez: Berlin Defense", "e4 e5 Nf3 Nc6 Bb5 Nf6"),
+    (
+        "C67",
+        "Ruy Lopez: Berlin Defense, Rio Gambit Accepted",
+        "e4 e5 Nf3 Nc6 Bb5 Nf6 O-O Nxe4",
+    ),
+    (
+        "C68",
+        "Ruy Lopez: Exchange Variation",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Bxc6",
+    ),
+    (
+        "C70",
+        "Ruy Lopez: Morphy Defense",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4",
+    ),
+    (
+        "C78",
+        "Ruy Lopez: Morphy Defense",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O",
+    ),
+    (
+        "C80",
+        "Ruy Lopez: Open",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Nxe4",
+    ),
+    (
+        "C84",
+        "Ruy Lopez: Closed",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7",
+    ),
+    (
+        "C88",
+        "Ruy Lopez: Closed",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7 Re1 b5 Bb3",
+    ),
+    (
+        "C89",
+        "Ruy Lopez: Marshall Attack",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7 Re1 b5 Bb3 O-O c3 d5",
+    ),
+    // Closed games
+    ("D00", "Queen's Pawn Game", "d4 d5"),
+    ("D00", "Blackmar-Diemer Gambit", "d4 d5 e4"),
+    (
+        "D00",
+        "Queen's Pawn Game: Accelerated London System",
+        "d4 d5 Bf4",
+    ),
+    ("D02", "Queen's Pawn Game: Zukertort Variation", "d4 d5 Nf3"),
+    ("D02", "London System", "d4 d5 Nf3 Nf6 Bf4"),
+    ("D05", "Colle System", "d4 d5 Nf3 Nf6 e3"),
+    ("D06", "Queen's Gambit", "d4 d5 c4"),
+    (
+        "D07",
+        "Queen's Gambit Declined: Chigorin Defense",
+        "d4 d5 c4 Nc6",
+    ),
+    (
+        "D08",
+        "Queen's Gambit Declined: Albin Countergambit",
+        "d4 d5 c4 e5",
+    ),
+    ("D10", "Slav Defense", "d4 d5 c4 c6"),
+    ("D11", "Slav Defense: Modern Line", "d4 d5 c4 c6 Nf3"),
+    (
+        "D15",
+        "Slav Defense: Three Knights Variation",
+        "d4 d5 c4 c6 Nf3 Nf6 Nc3",
+    ),
+    (
+        "D16",
+        "Slav Defense: Alapin Variation",
+        "d4 d5 c4 c6 Nf3 Nf6 Nc3 dxc4 a4",
+    ),
+    ("D20", "Queen's Gambit Accepted", "d4 d5 c4 dxc4"),
+    ("D30", "Queen's Gambit Declined", "d4 d5 c4 e6"),
+    (
+        "D31",
+        "Queen's Gambit Declined: Queen's Knight Variation",
+        "d4 d5 c4 e6 Nc3",
+    ),
+    ("D32", "Tarrasch Defense", "d4 d5 c4 e6 Nc3 c5"),
+    (
+        "D35",
+        "Queen's Gambit Declined: Normal Defense",
+        "d4 d5 c4 e6 Nc3 Nf6",
+    ),
+    (
+        "D35",
+        "Queen's Gambit Declined: Exchange Variation",
+        "d4 d5 c4 e6 Nc3 Nf6 cxd5 exd5",
+    ),
+    (
+        "D37",
+        "Queen's Gambit Declined: Three Knights Variation",
+        "d4 d5 c4 e6 Nc3 Nf6 Nf3",
+    ),
+    ("D43", "Semi-Slav Defense", "d4 d5 c4 e6 Nc3 Nf6 Nf3 c6"),
+    (
+        "D45",
+        "Semi-Slav Defense: Normal Variation",
+        "d4 d5 c4 e6 Nc3 Nf6 Nf3 c6 e3",
+    ),
+    (
+        "D50",
+        "Queen's Gambit Declined: Modern Variation",
+        "d4 d5 c4 e6 Nc3 Nf6 Bg5",
+    ),
+    ("D70", "Neo-Grünfeld Defense", "d4 Nf6 c4 g6 f3 d5"),
+    ("D80", "Grünfeld Defense", "d4 Nf6 c4 g6 Nc3 d5"),
+    (
+        "D85",
+        "Grünfeld Defense: Exchange Variation",
+        "d4 Nf6 c4 g6 Nc3 d5 cxd5 Nxd5",
+    ),
+    (
+        "D90",
+        "Grünfeld Defense: Three Knights Variation",
+        "d4 Nf6 c4 g6 Nc3 d5 Nf3",
+    ),
+    // Indian defenses
+    ("E00", "Indian Defense: East Indian Defense", "d4 Nf6 c4 e6"),
+    ("E00", "Catalan Opening", "d4 Nf6 c4 e6 g3"),
+    (
+        "E10",
+        "Indian Defense: Anti-Nimzo-Indian",
+        "d4 Nf6 c4 e6 Nf3",
+    ),
+    ("E11", "Bogo-Indian Defense", "d4 Nf6 c4 e6 Nf3 Bb4+"),
+    ("E12", "Queen's Indian Defense", "d4 Nf6 c4 e6 Nf3 b6"),
+    ("E20", "Nimzo-Indian Defense", "d4 Nf6 c4 e6 Nc3 Bb4"),
+    (
+        "E32",
+        "Nimzo-Indian Defense: Classical Variation",
+        "d4 Nf6 c4 e6 Nc3 Bb4 Qc2",
+    ),
+    (
+        "E40",
+        "Nimzo-Indian Defense: Normal Variation",
+        "d4 Nf6 c4 e6 Nc3 Bb4 e3",
+    ),
+    ("E60", "King's Indian Defense", "d4 Nf6 c4 g6"),
+    ("E61", "King's Indian Defense", "d4 Nf6 c4 g6 Nc3 Bg7"),
+    (
+        "E70",
+        "King's Indian Defense: Normal Variation",
+        "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6",
+    ),
+    (
+        "E76",
+        "King's Indian Defense: Four Pawns Attack",
+        "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 f4",
+    ),
+    (
+        "E80",
+        "King's Indian Defense: Sämisch Variation",
+        "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 f3",
+    ),
+    (
+        "E90",
+        "King's Indian Defense: Normal Variation",
+        "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 Nf3",
+    ),
+    (
+        "E91",
+        "King's Indian Defense: Orthodox Variation",
+        "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 Nf3 O-O Be2",
+    ),
+    (
+        "E97",
+        "King's Indian Defense: Orthodox Variation, Classical System",
+        "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6 Nf3 O-O Be2 e5 O-O Nc6",
+    ),
+];
+
+/// An opening from the ECO classification
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Opening {
+    /// ECO code, from A00 to E99
+    pub eco: &'static str,
+    pub name: &'static str,
+}
+
+/// Index in `OPENINGS` of the opening reached by each position hash
+static TABLE: OnceBox<BTreeMap<u64, usize>> = OnceBox::new();
+
+fn generate() -> BTreeMap<u64, usize> {
+    let mut table = BTreeMap::new();
+    for (index, &(_, _, moves)) in OPENINGS.iter().enumerate() {
+        let mut game = Game::new(START_FEN).unwrap();
+        for san in moves.split_ascii_whitespace() {
+            let _move = Move::from_san(san, &game).expect("Invalid move in the ECO table");
+            game.push_history(_move);
+        }
+        table.entry(game.hash()).or_insert(index);
+    }
+    table
+}
+
+impl Game {
+    /// The most specific opening reached during the game, also by transposition.
+    /// The table is generated on the first call.
+    pub fn opening(&self) -> Option<Opening> {
+        if self.is_chess960() {
+            return None;
+        }
+
+        let table = TABLE.get_or_init(|| Box::new(generate()));
+        self.hash_history
+            .iter()
+            .chain([&self.hash])
+            .rev()
+            .find_map(|hash| table.get(hash))
+            .map(|&index| Opening {
+                eco: OPENINGS[index].0,
+                name: OPENINGS[index].1,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opening(moves: &str) -> Option<Opening> {
+        let mut game = Game::new(START_FEN).unwrap();
+        for san in moves.split_ascii_whitespace() {
+            let _move = Move::from_san(san, &game).unwrap();
+            game.push_history(_move);
+        }
+        game.opening()
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            TABLE.get_or_init(|| Box::new(generate())).len(),
+            OPENINGS.len()
+        );
+        assert!(OPENINGS.iter().all(
+            |(eco, _, _)| eco.len() == 3 && ('A'..='E').contains(&eco.chars().next().unwrap())
+        ));
+    }
+
+    #[test]
+    fn classification() {
+        assert_eq!(opening(""), None);
+        assert_eq!(
+            opening("e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6 h3"),
+            Some(Opening {
+                eco: "B90",
+                name: "Sicilian Defense: Najdorf Variation"
+            })
+        );
+        assert_eq!(opening("e4 e5 Nf3 Nc6 Bb5 a6 Ba4").unwrap().eco, "C70");
+
+        // Transpositions reach the same opening
+        assert_eq!(
+            opening("Nf3 Nf6 c4 g6 Nc3 Bg7 d4 O-O e4 d6 Be2")
+                .unwrap()
+                .eco,
+            "E91"
+        );
+
+        // The last known position is kept once the game leaves the table
+        assert_eq!(opening("d4 d5 c4 e6 a3 a6").unwrap().eco, "D30");
+    }
+}
@@ -0,0 +1,161 @@
+use super::evaluation::{EvalTerm, Evaluation};
+use super::kpk;
+use super::params::EvalParams;
+use super::piece::PieceType;
+use super::position::Position;
+use super::{Game, Player, Score};
+
+/// Endgames with a dedicated evaluation, the strong player owns the pieces
+/// in the name and the weak player is left with its king
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum KnownEndgame {
+    /// King and pawn versus king, looked up in the bitbase
+    Kpk,
+    /// King and queen versus king
+    Kqk,
+    /// King and rook versus king
+    Krk,
+    /// King, bishop and knight versus king, mate is only possible in a corner
+    /// of the same color as the bishop
+    Kbnk,
+}
+
+impl KnownEndgame {
+    /// Recognizes the endgame by the material signature, with `strong` as the player
+    /// owning the pieces
+    pub fn recognize(game: &Game, strong: Player) -> Option<Self> {
+        if game.material(strong.the_other()) != 0 {
+            return None;
+        }
+
+        let signature = [
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+            PieceType::Pawn,
+        ]
+        .map(|piece_type| game.piece_count(strong, piece_type));
+
+        match signature {
+            [0, 0, 0, 0, 1] => Some(Self::Kpk),
+            [1, 0, 0, 0, 0] => Some(Self::Kqk),
+            [0, 1, 0, 0, 0] => Some(Self::Krk),
+            [0, 0, 1, 1, 0] => Some(Self::Kbnk),
+            _ => None,
+        }
+    }
+
+    pub fn evaluate(self, game: &Game, evaluation: &mut Evaluation, strong: Player) {
+        let weak = strong.the_other();
+        let strong_king = game.get_king_position(strong);
+        let weak_king = game.get_king_position(weak);
+
+        match self {
+            Self::Kpk => {
+                let is_win = kpk::probe(
+                    strong,
+                    game.player(),
+                    strong_king,
+                    weak_king,
+                    game.find_piece(strong, PieceType::Pawn),
+                );
+
+                if is_win {
+                    evaluation.add(EvalTerm::Endgame, strong, game.params.kpk_win_bonus);
+                } else {
+                    evaluation.set_scale(strong, 0);
+                    evaluation.set_scale(weak, 0);
+                }
+            }
+            Self::Kqk | Self::Krk => {
+                evaluation.add(
+                    EvalTerm::Endgame,
+                    strong,
+                    mop_up_score(&game.params, strong_king, weak_king),
+                );
+            }
+            Self::Kbnk => {
+                let is_light = game.find_piece(strong, PieceType::Bishop).is_light();
+
+                // Driving the king to the edge is not enough, it has to go to the right corner
+                let corner_distance = [(0, 0), (0, 7), (7, 0), (7, 7)]
+                    .map(|(row, col)| Position::new_assert(row, col))
+                    .into_iter()
+                    .filter(|corner| corner.is_light() == is_light)
+                    .map(|corner| corner.distance(weak_king))
+                    .min()
+                    .unwrap_or_default() as Score;
+
+                evaluation.add(
+                    EvalTerm::Endgame,
+                    strong,
+                    (7 - corner_distance) * game.params.kbnk_corner_bonus
+                        + king_proximity(strong_king, weak_king)
+                            * game.params.mop_up_king_proximity_bonus,
+                );
+            }
+        }
+    }
+}
+
+/// Manhattan distance between the position and the closest of the four center squares
+pub fn center_distance(position: Position) -> Score {
+    let row_distance = i8::max(3 - position.row(), position.row() - 4);
+    let col_distance = i8::max(3 - position.col(), position.col() - 4);
+    (row_distance + col_distance) as Score
+}
+
+/// How much closer than the maximum Manhattan distance of 14 the kings are
+pub fn king_proximity(first: Position, second: Position) -> Score {
+    let distance = i8::abs(first.row() - second.row()) + i8::abs(first.col() - second.col());
+    (14 - distance) as Score
+}
+
+/// Rewards driving the losing king to the edge of the board, with the winning king
+/// coming closer to it, which is how every mate against a lone king is delivered
+pub fn mop_up_score(params: &EvalParams, strong_king: Position, weak_king: Position) -> Score {
+    center_distance(weak_king) * params.mop_up_center_distance_bonus
+        + king_proximity(strong_king, weak_king) * params.mop_up_king_proximity_bonus
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recognize_endgames() {
+        let recognize = |fen: &str| {
+            let game = Game::new(fen).unwrap();
+            (
+                KnownEndgame::recognize(&game, Player::White),
+                KnownEndgame::recognize(&game, Player::Black),
+            )
+        };
+
+        assert_eq!(
+            recognize("8/8/4k3/8/8/4K3/4P3/8 w - - 0 1"),
+            (Some(KnownEndgame::Kpk), None)
+        );
+        assert_eq!(
+            recognize("8/8/4k3/8/8/4K3/8/q7 w - - 0 1"),
+            (None, Some(KnownEndgame::Kqk))
+        );
+        assert_eq!(
+            recognize("8/8/4k3/8/8/4K3/8/3BN3 w - - 0 1"),
+            (Some(KnownEndgame::Kbnk), None)
+        );
+        assert_eq!(recognize("8/8/4k3/8/8/4K3/4P3/3R4 w - - 0 1"), (None, None));
+        assert_eq!(recognize("8/8/4kp2/8/8/4K3/8/3R4 w - - 0 1"), (None, None));
+    }
+
+    #[test]
+    fn kbnk_right_corner() {
+        // The bishop on d1 is light squared, so black has to be mated on a8 or h1
+        let right = Game::new("k7/8/1K6/8/8/8/8/3BN3 w - - 0 1").unwrap();
+        let wrong = Game::new("7k/8/6K1/8/8/8/8/3BN3 w - - 0 1").unwrap();
+
+        let endgame = |game: &Game| game.evaluation().get(EvalTerm::Endgame, Player::White);
+        assert!(endgame(&right) > endgame(&wrong));
+    }
+}
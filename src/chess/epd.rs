@@ -0,0 +1,293 @@
+use super::move_struct::Move;
+use super::score::Score;
+use super::Game;
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use anyhow::{bail, Context};
+use core::fmt;
+
+// Extended Position Description: https://www.chessprogramming.org/Extended_Position_Description
+
+/// A position with its operations, e.g. `bm Qd1+; id "WAC.001";`
+///
+/// The counters of the position are read from the `hmvc` and `fmvn` operations, or from
+/// the last two fields of a FEN, which some files use instead of the four fields of an EPD.
+pub struct Epd {
+    pub game: Game,
+    /// Operand of `id`, the name of the position
+    pub id: Option<String>,
+    /// Operands of `bm`, any of them solves the position
+    pub best_moves: Vec<Move>,
+    /// Operands of `am`, the moves to avoid
+    pub avoid_moves: Vec<Move>,
+    /// Operand of `ce`, the score of the position for the player to move in centipawns
+    pub centipawn_evaluation: Option<Score>,
+    /// The other operations in the order they were written, with the operands unquoted
+    pub operations: Vec<(String, Vec<String>)>,
+}
+
+impl Epd {
+    pub fn new(game: Game) -> Self {
+        Self {
+            game,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            centipawn_evaluation: None,
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn parse(line: &str) -> anyhow::Result<Self> {
+        let mut rest = line.trim();
+        let mut fields = Vec::with_capacity(6);
+        for _ in 0..4 {
+            let (field, remainder) = split_field(rest);
+            if field.is_empty() {
+                bail!("Expected 4 fields before the operations");
+            }
+            fields.push(field);
+            rest = remainder;
+        }
+
+        // Counters written like in a FEN
+        let (halfmove_clock, remainder) = split_field(rest);
+        if halfmove_clock.parse::<u16>().is_ok() {
+            let (fullmove_number, remainder) = split_field(remainder);
+            if fullmove_number.parse::<usize>().is_ok() {
+                fields.extend([halfmove_clock, fullmove_number]);
+                rest = remainder;
+            }
+        }
+
+        let operations = parse_operations(rest)?;
+
+        for (opcode, operands) in &operations {
+            match (opcode.as_str(), operands.as_slice()) {
+                ("hmvc", [value]) if fields.len() == 4 => fields.push(value),
+                ("fmvn", [value]) if fields.len() == 5 => fields.push(value),
+                ("hmvc" | "fmvn", _) => bail!("Invalid {} operation", opcode),
+                _ => (),
+            }
+        }
+        if fields.len() == 5 {
+            bail!("Missing fmvn operation after hmvc");
+        }
+
+        let mut epd = Self::new(Game::new(&fields.join(" "))?);
+
+        for (opcode, operands) in operations {
+            match opcode.as_str() {
+                "bm" | "am" => {
+                    let mut moves = Vec::with_capacity(operands.len());
+                    for operand in &operands {
+                        let _move = Move::from_san(operand, &epd.game)
+                            .ok()
+                            .or_else(|| Move::from_uci_notation(operand, &epd.game).ok())
+                            .with_context(|| format!("Invalid move {} in {}", operand, opcode))?;
+                        moves.push(_move);
+                    }
+                    if opcode == "bm" {
+                        epd.best_moves = moves;
+                    } else {
+                        epd.avoid_moves = moves;
+                    }
+                }
+                "ce" => {
+                    let [value] = operands.as_slice() else {
+                        bail!("Invalid ce operation");
+                    };
+                    epd.centipawn_evaluation =
+                        Some(value.parse().context("Invalid centipawn evaluation")?);
+                }
+                "id" => {
+                    let [value] = operands.as_slice() else {
+                        bail!("Invalid id operation");
+                    };
+                    epd.id = Some(value.clone());
+                }
+                "hmvc" | "fmvn" => (),
+                _ => epd.operations.push((opcode, operands)),
+            }
+        }
+
+        Ok(epd)
+    }
+
+    /// First operand of the operation, for the ones without a field of their own
+    pub fn operation(&self, opcode: &str) -> Option<&str> {
+        self.operations
+            .iter()
+            .find(|(name, _)| name == opcode)
+            .and_then(|(_, operands)| operands.first())
+            .map(String::as_str)
+    }
+}
+
+/// The first field and the text after it
+fn split_field(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+    let end = text.find(char::is_whitespace).unwrap_or(text.len());
+    (&text[..end], &text[end..])
+}
+
+/// Splits the operations after the position, each one is an opcode followed by operands
+/// and ended by a semicolon. The semicolon of the last operation may be left out.
+fn parse_operations(text: &str) -> anyhow::Result<Vec<(String, Vec<String>)>> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    loop {
+        match chars.next() {
+            None | Some(';') => {
+                if !words.is_empty() {
+                    let opcode = words.remove(0);
+                    operations.push((opcode, core::mem::take(&mut words)));
+                }
+                if chars.peek().is_none() {
+                    break;
+                }
+            }
+            Some(character) if character.is_whitespace() => (),
+            Some('"') => {
+                let mut word = String::new();
+                loop {
+                    match chars.next().context("Unterminated string operand")? {
+                        '"' => break,
+                        '\\' => word.push(chars.next().context("Unterminated string operand")?),
+                        character => word.push(character),
+                    }
+                }
+                words.push(word);
+            }
+            Some(character) => {
+                let mut word = String::from(character);
+                while let Some(character) =
+                    chars.next_if(|character| !character.is_whitespace() && *character != ';')
+                {
+                    word.push(character);
+                }
+                words.push(word);
+            }
+        }
+    }
+
+    Ok(operations)
+}
+
+/// Writes an operation, quoting operands which are strings or which couldn't be read back otherwise
+fn write_operation(f: &mut fmt::Formatter<'_>, opcode: &str, operands: &[String]) -> fmt::Result {
+    // Comments are c0 to c9
+    let is_string = opcode == "id"
+        || (opcode.len() == 2
+            && opcode.starts_with('c')
+            && opcode.ends_with(|c: char| c.is_ascii_digit()));
+
+    write!(f, " {}", opcode)?;
+    for operand in operands {
+        if is_string || operand.is_empty() || operand.contains([' ', ';', '"']) {
+            let operand = operand.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(f, " \"{}\"", operand)?;
+        } else {
+            write!(f, " {}", operand)?;
+        }
+    }
+    write!(f, ";")
+}
+
+/// Four fields of the position, then the counters and the operations
+impl fmt::Display for Epd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fen = self.game.to_fen();
+        let fields: Vec<&str> = fen.split_ascii_whitespace().collect();
+        write!(f, "{}", fields[..4].join(" "))?;
+        write_operation(f, "hmvc", &[fields[4].to_string()])?;
+        write_operation(f, "fmvn", &[fields[5].to_string()])?;
+
+        for (opcode, moves) in [("bm", &self.best_moves), ("am", &self.avoid_moves)] {
+            if !moves.is_empty() {
+                let sans: Vec<String> =
+                    moves.iter().map(|_move| _move.to_san(&self.game)).collect();
+                write_operation(f, opcode, &sans)?;
+            }
+        }
+        if let Some(score) = self.centipawn_evaluation {
+            write_operation(f, "ce", &[score.to_string()])?;
+        }
+        if let Some(id) = &self.id {
+            write_operation(f, "id", core::slice::from_ref(id))?;
+        }
+        for (opcode, operands) in &self.operations {
+            write_operation(f, opcode, operands)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let epd = Epd::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(epd.id.as_deref(), Some("WAC.001"));
+        assert_eq!(epd.best_moves.len(), 1);
+        assert_eq!(epd.best_moves[0].uci_notation(&epd.game), "g3g6");
+        assert!(epd.avoid_moves.is_empty());
+        assert_eq!(
+            epd.game.to_fen(),
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1"
+        );
+
+        // Counters can be operations or FEN fields, strings may contain semicolons
+        let epd = Epd::parse(
+            "4k3/8/8/8/8/8/4P3/4K3 w - - am Kd1 Kf1; ce -35; hmvc 7; fmvn 40; c0 \"a; b\"",
+        )
+        .unwrap();
+        assert_eq!(epd.game.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 w - - 7 40");
+        assert_eq!(epd.avoid_moves.len(), 2);
+        assert_eq!(epd.centipawn_evaluation, Some(-35));
+        assert_eq!(epd.operation("c0"), Some("a; b"));
+
+        let epd = Epd::parse("4k3/8/8/8/8/8/4P3/4K3 b - - 3 52 c9 \"1-0\";").unwrap();
+        assert_eq!(epd.game.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 b - - 3 52");
+        assert_eq!(epd.operation("c9"), Some("1-0"));
+
+        assert!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w -").is_err());
+        assert!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - bm Ke8;").is_err());
+        assert!(Epd::parse("4k3/8/8/8/8/8/4P3/4K3 w - - id \"open;").is_err());
+    }
+
+    #[test]
+    fn write() {
+        let mut epd = Epd::new(Game::default());
+        epd.best_moves = vec![
+            Move::from_uci_notation("e2e4", &epd.game).unwrap(),
+            Move::from_uci_notation("d2d4", &epd.game).unwrap(),
+        ];
+        epd.centipawn_evaluation = Some(20);
+        epd.id = Some(String::from("Start \"1\""));
+        epd.operations
+            .push((String::from("c9"), vec![String::from("1/2-1/2")]));
+
+        let line = epd.to_string();
+        assert_eq!(
+            line,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - hmvc 0; fmvn 1; \
+             bm e4 d4; ce 20; id \"Start \\\"1\\\"\"; c9 \"1/2-1/2\";"
+        );
+
+        let read = Epd::parse(&line).unwrap();
+        assert_eq!(read.to_string(), line);
+        assert_eq!(read.id, epd.id);
+    }
+}
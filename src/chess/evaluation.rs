@@ -0,0 +1,1147 @@
+use super::bitboard;
+use super::endgame::{mop_up_score, KnownEndgame};
+use super::params::EvalParams;
+use super::piece::PieceType;
+use super::position::Position;
+use super::scores::PIECE_VALUES;
+use super::unchecked;
+use super::{Game, GamePhase, Player, Score, FIFTY_MOVE_LIMIT};
+use alloc::format;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EvalTerm {
+    Material,
+    PieceSquare,
+    Pawns,
+    Pieces,
+    KingSafety,
+    Mobility,
+    Space,
+    Threats,
+    Endgame,
+}
+
+impl EvalTerm {
+    pub const ALL: [Self; 9] = [
+        Self::Material,
+        Self::PieceSquare,
+        Self::Pawns,
+        Self::Pieces,
+        Self::KingSafety,
+        Self::Mobility,
+        Self::Space,
+        Self::Threats,
+        Self::Endgame,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Material => "Material",
+            Self::PieceSquare => "Piece-square",
+            Self::Pawns => "Pawns",
+            Self::Pieces => "Pieces",
+            Self::KingSafety => "King safety",
+            Self::Mobility => "Mobility",
+            Self::Space => "Space",
+            Self::Threats => "Threats",
+            Self::Endgame => "Endgame",
+        }
+    }
+}
+
+/// Scale factor which leaves the evaluation unchanged
+pub const SCALE_NORMAL: Score = 64;
+
+/// Static evaluation of a position split by term and by player
+///
+/// Every value is stored from the point of view of the player it belongs to
+#[derive(Clone, Copy, Debug)]
+pub struct Evaluation {
+    terms: [[Score; 2]; EvalTerm::ALL.len()],
+    /// The sum of the terms is multiplied by scale / SCALE_NORMAL, which pulls the
+    /// evaluation toward a draw in drawish endgames. There is one factor per player,
+    /// only the one of the player who is ahead is used.
+    scale: [Score; 2],
+    /// The evaluation shrinks toward a draw as the fifty-move rule gets closer
+    halfmove_clock: Score,
+}
+
+impl Default for Evaluation {
+    fn default() -> Self {
+        Self {
+            terms: Default::default(),
+            scale: [SCALE_NORMAL; 2],
+            halfmove_clock: 0,
+        }
+    }
+}
+
+impl Evaluation {
+    #[inline]
+    pub(super) fn add(&mut self, term: EvalTerm, player: Player, value: Score) {
+        self.terms[term as usize][player.as_index()] += value;
+    }
+
+    pub fn get(&self, term: EvalTerm, player: Player) -> Score {
+        self.terms[term as usize][player.as_index()]
+    }
+
+    /// Value of a single term from white's point of view
+    pub fn term_total(&self, term: EvalTerm) -> Score {
+        self.get(term, Player::White) - self.get(term, Player::Black)
+    }
+
+    /// Scale factor applied to the total
+    pub fn scale(&self) -> Score {
+        self.scale_for(self.sum())
+    }
+
+    fn scale_for(&self, score: Score) -> Score {
+        let player = if score >= 0 {
+            Player::White
+        } else {
+            Player::Black
+        };
+        self.scale[player.as_index()]
+    }
+
+    pub(super) fn set_scale(&mut self, player: Player, scale: Score) {
+        let current = &mut self.scale[player.as_index()];
+        *current = scale.min(*current);
+    }
+
+    /// Sum of all terms from white's point of view, before scaling
+    pub fn sum(&self) -> Score {
+        EvalTerm::ALL
+            .iter()
+            .map(|&term| self.term_total(term))
+            .sum()
+    }
+
+    fn scaled(&self, score: Score) -> Score {
+        let score = score * self.scale_for(score) / SCALE_NORMAL;
+        let limit = FIFTY_MOVE_LIMIT as Score;
+        score * (limit - self.halfmove_clock) / limit
+    }
+
+    /// Value of the whole evaluation from white's point of view
+    pub fn total(&self) -> Score {
+        self.scaled(self.sum())
+    }
+
+    /// Share of the sum kept by `total`, after the scale factor and the fifty-move rule
+    pub fn scale_ratio(&self) -> f64 {
+        let limit = FIFTY_MOVE_LIMIT as f64;
+        self.scale() as f64 / SCALE_NORMAL as f64 * (limit - self.halfmove_clock as f64) / limit
+    }
+}
+
+impl core::fmt::Display for Evaluation {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        writeln!(
+            f,
+            "{:<14}|{:>8} |{:>8} |{:>8}",
+            "Term", "White", "Black", "Total"
+        )?;
+        writeln!(f, "{:-<14}+{:-<9}+{:-<9}+{:-<9}", "", "", "", "")?;
+        for term in EvalTerm::ALL {
+            writeln!(
+                f,
+                "{:<14}|{:>8} |{:>8} |{:>8}",
+                term.name(),
+                self.get(term, Player::White),
+                self.get(term, Player::Black),
+                self.term_total(term)
+            )?;
+        }
+        writeln!(f, "{:-<14}+{:-<9}+{:-<9}+{:-<9}", "", "", "", "")?;
+        if self.halfmove_clock != 0 {
+            let limit = FIFTY_MOVE_LIMIT as Score;
+            writeln!(
+                f,
+                "{:<14}|{:>8} |{:>8} |{:>8}",
+                "Fifty-move",
+                "",
+                "",
+                format!("{}/{}", limit - self.halfmove_clock, limit)
+            )?;
+        }
+        if self.scale() != SCALE_NORMAL {
+            writeln!(
+                f,
+                "{:<14}|{:>8} |{:>8} |{:>8}",
+                "Scale factor",
+                "",
+                "",
+                format!("{}/{}", self.scale(), SCALE_NORMAL)
+            )?;
+        }
+        writeln!(
+            f,
+            "{:<14}|{:>8} |{:>8} |{:>8}",
+            "Final",
+            "",
+            "",
+            self.total()
+        )?;
+        write!(
+            f,
+            "(scores are in centipawns, the total is from white's side)"
+        )
+    }
+}
+
+/// Rows occupied by the pawns of every file, as a bitmask per file and per player
+type PawnRows = [[u8; 8]; 2];
+
+/// Squares attacked by the pawns of every player, one bit per square
+type PawnAttacks = [u64; 2];
+
+/// Number of pieces of every type, per player
+type PieceCounts = [[u8; 6]; 2];
+
+/// Bitmask of the rows in front of `row`, from the point of view of `player`
+fn rows_ahead(player: Player, row: i8) -> u8 {
+    match player {
+        Player::White => !((2u16 << row) - 1) as u8,
+        Player::Black => ((1u16 << row) - 1) as u8,
+    }
+}
+
+/// Information gathered by a single scan of the board, shared by the evaluation terms
+struct BoardSummary {
+    pawn_rows: PawnRows,
+    pawn_attacks: PawnAttacks,
+}
+
+/// When the cheap part of the evaluation is further than this outside of the alpha-beta
+/// window, the expensive terms are assumed not to bring it back inside
+const LAZY_EVAL_MARGIN: Score = 350;
+
+impl Game {
+    /// Static evaluation of the position from white's point of view
+    ///
+    /// Material and piece-square scores are kept up to date by push/pop,
+    /// the other terms are computed from scratch
+    pub fn evaluate(&self) -> Score {
+        let mut evaluation = Evaluation::default();
+        let summary = self.evaluate_core(&mut evaluation);
+        self.evaluate_expensive(&mut evaluation, &summary);
+        evaluation.scaled(self.score + evaluation.sum())
+    }
+
+    /// Static evaluation of the position from the point of view of the player to move,
+    /// which skips the expensive terms when the rest of the evaluation is far enough
+    /// outside of the window, since the search would cut the node off either way
+    pub fn evaluate_lazy(&self, alpha: Score, beta: Score) -> Score {
+        let player = self.current_player as Score;
+
+        let mut evaluation = Evaluation::default();
+        let summary = self.evaluate_core(&mut evaluation);
+        let score = evaluation.scaled(self.score + evaluation.sum()) * player;
+
+        if score + LAZY_EVAL_MARGIN <= alpha || score - LAZY_EVAL_MARGIN >= beta {
+            return score;
+        }
+
+        self.evaluate_expensive(&mut evaluation, &summary);
+        evaluation.scaled(self.score + evaluation.sum()) * player
+    }
+
+    /// Same as evaluate, but keeps every term separate, which is useful for debugging
+    pub fn evaluation(&self) -> Evaluation {
+        let mut evaluation = Evaluation::default();
+
+        for row in 0..8 {
+            for col in 0..8 {
+                let position = Position::new_assert(row, col);
+                if let Some(piece) = self.get_position(position) {
+                    let score = self.past_scores[position.as_usize()] * piece.owner as Score;
+                    let value = PIECE_VALUES[piece.piece_type as usize];
+
+                    // The king can never be captured, so its value is left out of the material
+                    if piece.piece_type != PieceType::King {
+                        evaluation.add(EvalTerm::Material, piece.owner, value);
+                    }
+                    evaluation.add(EvalTerm::PieceSquare, piece.owner, score - value);
+                }
+            }
+        }
+
+        let summary = self.evaluate_core(&mut evaluation);
+        self.evaluate_expensive(&mut evaluation, &summary);
+
+        evaluation
+    }
+
+    /// Adds the terms which aren't maintained incrementally, but only need
+    /// a single scan of the board, including the endgame scale factors
+    fn evaluate_core(&self, evaluation: &mut Evaluation) -> BoardSummary {
+        evaluation.halfmove_clock = self.halfmove_clock().min(FIFTY_MOVE_LIMIT) as Score;
+
+        let mut summary = BoardSummary {
+            pawn_rows: [[0; 8]; 2],
+            pawn_attacks: [0; 2],
+        };
+
+        for player in [Player::White, Player::Black] {
+            let owner = player.as_index();
+            for position in bitboard::positions(self.pieces_of(player, PieceType::Pawn)) {
+                summary.pawn_rows[owner][position.col() as usize] |= 1 << position.row();
+                summary.pawn_attacks[owner] |= bitboard::pawn_attacks(player, position);
+            }
+        }
+
+        self.evaluate_endgame(evaluation, &summary.pawn_rows);
+        self.evaluate_pawns(evaluation, &summary.pawn_rows);
+        self.evaluate_pieces(evaluation, &summary);
+
+        if self.phase != GamePhase::Endgame {
+            self.evaluate_king_safety(evaluation, &summary.pawn_rows);
+            self.evaluate_pawn_storm(evaluation, &summary.pawn_rows);
+        }
+
+        summary
+    }
+
+    /// Adds the terms which need the squares attacked by every piece
+    fn evaluate_expensive(&self, evaluation: &mut Evaluation, summary: &BoardSummary) {
+        self.evaluate_mobility(evaluation);
+        self.evaluate_threats(evaluation, &summary.pawn_attacks);
+
+        if self.phase != GamePhase::Endgame {
+            evaluate_space(
+                evaluation,
+                &self.params,
+                &self.piece_counts,
+                &summary.pawn_rows,
+                &summary.pawn_attacks,
+            );
+        }
+    }
+
+    /// Recognizes endgames whose outcome is known, or which are hard to win
+    fn evaluate_endgame(&self, evaluation: &mut Evaluation, pawn_rows: &PawnRows) {
+        for strong in [Player::White, Player::Black] {
+            if let Some(endgame) = KnownEndgame::recognize(self, strong) {
+                endgame.evaluate(self, evaluation, strong);
+            } else {
+                self.evaluate_scaling(evaluation, strong, pawn_rows);
+                self.evaluate_mop_up(evaluation, strong);
+            }
+        }
+    }
+
+    /// Helps converting won endgames without pawns, where the material score alone
+    /// gives no clue on how to make progress
+    fn evaluate_mop_up(&self, evaluation: &mut Evaluation, strong: Player) {
+        let weak = strong.the_other();
+        let pawn_count =
+            self.piece_count(strong, PieceType::Pawn) + self.piece_count(weak, PieceType::Pawn);
+        let advantage = self.non_pawn_material(strong) - self.non_pawn_material(weak);
+
+        if pawn_count != 0 || advantage < PIECE_VALUES[PieceType::Rook as usize] {
+            return;
+        }
+
+        evaluation.add(
+            EvalTerm::Endgame,
+            strong,
+            mop_up_score(
+                &self.params,
+                self.get_king_position(strong),
+                self.get_king_position(weak),
+            ),
+        );
+    }
+
+    /// Lowers the scale factor of `strong` in endgames it will have trouble winning
+    fn evaluate_scaling(&self, evaluation: &mut Evaluation, strong: Player, pawn_rows: &PawnRows) {
+        let weak = strong.the_other();
+        let strong_counts = &self.piece_counts[strong.as_index()];
+        let weak_counts = &self.piece_counts[weak.as_index()];
+        let strong_material = self.non_pawn_material(strong);
+        let weak_material = self.non_pawn_material(weak);
+        let strong_pawns = strong_counts[PieceType::Pawn as usize];
+        let bishop_value = PIECE_VALUES[PieceType::Bishop as usize];
+        let rook_value = PIECE_VALUES[PieceType::Rook as usize];
+
+        // Without pawns, at least a rook more is needed to win
+        if strong_pawns == 0 && strong_material - weak_material <= bishop_value {
+            let scale = if strong_material < rook_value {
+                0
+            } else if weak_material <= bishop_value {
+                self.params.pawnless_against_minor_scale
+            } else {
+                self.params.pawnless_scale
+            };
+            evaluation.set_scale(strong, scale);
+        }
+
+        // Two knights can't force mate against a bare king
+        let knight_value = PIECE_VALUES[PieceType::Knight as usize];
+        if strong_pawns == 0 && weak_material == 0 && strong_material == 2 * knight_value {
+            evaluation.set_scale(strong, 0);
+        }
+
+        // Opposite-colored bishops
+        if strong_counts[PieceType::Bishop as usize] == 1
+            && weak_counts[PieceType::Bishop as usize] == 1
+            && self.find_piece(strong, PieceType::Bishop).is_light()
+                != self.find_piece(weak, PieceType::Bishop).is_light()
+        {
+            if strong_material == bishop_value && weak_material == bishop_value {
+                evaluation.set_scale(strong, self.params.opposite_bishops_scale);
+            } else {
+                evaluation.set_scale(strong, self.params.opposite_bishops_with_pieces_scale);
+            }
+        }
+
+        // Pawns on a single rook file, with no bishop or a bishop which doesn't control
+        // the promotion square, can't win against a king which holds the corner
+        let has_only_bishop =
+            strong_material == bishop_value && strong_counts[PieceType::Bishop as usize] == 1;
+        if weak_material == 0 && strong_pawns > 0 && (strong_material == 0 || has_only_bishop) {
+            let promotion_row = match strong {
+                Player::White => 7,
+                Player::Black => 0,
+            };
+
+            for col in [0, 7] {
+                if pawn_rows[strong.as_index()][col as usize].count_ones() as u8 != strong_pawns {
+                    continue;
+                }
+
+                let promotion = Position::new_assert(promotion_row, col);
+                let is_wrong_bishop = !has_only_bishop
+                    || self.find_piece(strong, PieceType::Bishop).is_light()
+                        != promotion.is_light();
+
+                if is_wrong_bishop && self.get_king_position(weak).distance(promotion) <= 1 {
+                    evaluation.set_scale(strong, 0);
+                }
+            }
+        }
+
+        // Rook and pawn against rook, with the defending king in front of the pawn
+        if strong_material == rook_value
+            && strong_counts[PieceType::Rook as usize] == 1
+            && strong_pawns == 1
+            && weak_material == rook_value
+            && weak_counts[PieceType::Rook as usize] == 1
+            && weak_counts[PieceType::Pawn as usize] == 0
+        {
+            let pawn = self.find_piece(strong, PieceType::Pawn);
+            let weak_king = self.get_king_position(weak);
+            let is_ahead = match strong {
+                Player::White => weak_king.row() > pawn.row(),
+                Player::Black => weak_king.row() < pawn.row(),
+            };
+
+            if weak_king.col() == pawn.col() && is_ahead {
+                evaluation.set_scale(strong, self.params.blocked_rook_endgame_scale);
+            }
+        }
+    }
+
+    /// Returns the position of a piece which is known to be on the board
+    pub(super) fn find_piece(&self, owner: Player, piece_type: PieceType) -> Position {
+        bitboard::positions(self.pieces_of(owner, piece_type))
+            .next()
+            .expect("The piece should be on the board")
+    }
+
+    fn evaluate_pawns(&self, evaluation: &mut Evaluation, pawn_rows: &PawnRows) {
+        for player in [Player::White, Player::Black] {
+            let own_pawns = &pawn_rows[player.as_index()];
+            let enemy_pawns = &pawn_rows[player.the_other().as_index()];
+
+            for col in 0..8 {
+                let file = own_pawns[col];
+                if file == 0 {
+                    continue;
+                }
+
+                let pawn_count = file.count_ones() as Score;
+                if pawn_count > 1 {
+                    evaluation.add(
+                        EvalTerm::Pawns,
+                        player,
+                        -(pawn_count - 1) * self.params.doubled_pawn_penalty,
+                    );
+                }
+
+                let left = if col > 0 { own_pawns[col - 1] } else { 0 };
+                let right = if col < 7 { own_pawns[col + 1] } else { 0 };
+                if left == 0 && right == 0 {
+                    evaluation.add(
+                        EvalTerm::Pawns,
+                        player,
+                        -pawn_count * self.params.isolated_pawn_penalty,
+                    );
+                }
+
+                // Rows of pawns which could stop or capture a pawn on this file,
+                // a pawn behind another pawn of the same color isn't considered passed
+                let mut blockers = enemy_pawns[col] | file;
+                if col > 0 {
+                    blockers |= enemy_pawns[col - 1];
+                }
+                if col < 7 {
+                    blockers |= enemy_pawns[col + 1];
+                }
+
+                for row in 0..8 {
+                    if file & (1 << row) == 0 {
+                        continue;
+                    }
+
+                    let relative_row = match player {
+                        Player::White => row,
+                        Player::Black => 7 - row,
+                    };
+
+                    if blockers & rows_ahead(player, row as i8) == 0 {
+                        evaluation.add(
+                            EvalTerm::Pawns,
+                            player,
+                            self.params.passed_pawn_bonus[relative_row],
+                        );
+                    }
+
+                    // Pawns side by side form a phalanx, pawns diagonally behind support it
+                    let is_phalanx = (left | right) & (1 << row) != 0;
+                    let behind = row as i8 - player as i8;
+                    let supporters = if (0..8).contains(&behind) {
+                        (left & (1 << behind) != 0) as Score + (right & (1 << behind) != 0) as Score
+                    } else {
+                        0
+                    };
+
+                    if is_phalanx || supporters > 0 {
+                        evaluation.add(
+                            EvalTerm::Pawns,
+                            player,
+                            self.params.connected_pawn_bonus[relative_row]
+                                + supporters * self.params.supported_pawn_bonus,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Penalizes pieces which can hardly take part in the game: knights stuck in a corner
+    /// of the opponent's side, bishops cut off by enemy pawns, bishops hemmed in
+    /// by their own pawns and pieces pinned to their king
+    fn evaluate_pieces(&self, evaluation: &mut Evaluation, summary: &BoardSummary) {
+        for player in [Player::White, Player::Black] {
+            let pawns = self.pieces(PieceType::Pawn);
+            let pinned = self
+                .pins(player)
+                .iter()
+                .filter(|pin| pawns & bitboard::bit(pin.pinned) == 0)
+                .count() as Score;
+            evaluation.add(
+                EvalTerm::Pieces,
+                player,
+                -pinned * self.params.pinned_piece_penalty,
+            );
+        }
+
+        for row in 0..8 {
+            for col in 0..8 {
+                let position = Position::new_assert(row, col);
+                let Some(piece) = self.get_position(position) else {
+                    continue;
+                };
+
+                let player = piece.owner;
+                let enemy = player.the_other();
+                let relative_row = match player {
+                    Player::White => row,
+                    Player::Black => 7 - row,
+                };
+                let is_rim = col == 0 || col == 7;
+
+                match piece.piece_type {
+                    PieceType::Knight if is_rim && relative_row == 7 => {
+                        let mut escapes = 0;
+                        piece.for_each_attack(self, position, |square| {
+                            let is_covered = summary.pawn_attacks[enemy.as_index()]
+                                & (1 << square.as_usize())
+                                != 0
+                                || self.get_position(square).is_some_and(|other| {
+                                    other.owner == player || other.piece_type == PieceType::Pawn
+                                });
+
+                            if !is_covered {
+                                escapes += 1;
+                            }
+                        });
+
+                        if escapes == 0 {
+                            evaluation.add(
+                                EvalTerm::Pieces,
+                                player,
+                                -self.params.trapped_knight_penalty,
+                            );
+                        }
+                    }
+                    PieceType::Bishop => {
+                        // Bxa7 b6, the pawn blocks the only way out and can't be taken
+                        if is_rim && relative_row == 6 {
+                            let inward = if col == 0 { 1 } else { -1 };
+                            let blocker = position.add((-(player as i8), inward));
+
+                            if blocker.is_some_and(|blocker| {
+                                self.get_position(blocker).is_some_and(|other| {
+                                    other.owner == enemy && other.piece_type == PieceType::Pawn
+                                }) && summary.pawn_attacks[enemy.as_index()]
+                                    & (1 << blocker.as_usize())
+                                    != 0
+                            }) {
+                                evaluation.add(
+                                    EvalTerm::Pieces,
+                                    player,
+                                    -self.params.trapped_bishop_penalty,
+                                );
+                            }
+                        }
+
+                        let mut pawns = 0;
+                        for (pawn_col, file) in
+                            summary.pawn_rows[player.as_index()].iter().enumerate()
+                        {
+                            for pawn_row in 0..8 {
+                                if file & (1 << pawn_row) == 0 {
+                                    continue;
+                                }
+
+                                let pawn = Position::new_assert(pawn_row, pawn_col as i8);
+                                if pawn.is_light() != position.is_light() {
+                                    continue;
+                                }
+
+                                pawns += 1;
+                                if pawn
+                                    .add((player as i8, 0))
+                                    .is_some_and(|front| self.get_position(front).is_some())
+                                {
+                                    pawns += 1;
+                                }
+                            }
+                        }
+
+                        evaluation.add(
+                            EvalTerm::Pieces,
+                            player,
+                            -pawns * self.params.bad_bishop_penalty,
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn evaluate_king_safety(&self, evaluation: &mut Evaluation, pawn_rows: &PawnRows) {
+        for player in [Player::White, Player::Black] {
+            let king = self.get_king_position(player);
+            let own_pawns = &pawn_rows[player.as_index()];
+
+            let relative_row = match player {
+                Player::White => king.row(),
+                Player::Black => 7 - king.row(),
+            };
+
+            // A king that left its first two rows doesn't have a pawn shield to speak of
+            if relative_row > 1 {
+                continue;
+            }
+
+            for col in (king.col() - 1).max(0)..=(king.col() + 1).min(7) {
+                let file = own_pawns[col as usize];
+
+                if file == 0 {
+                    evaluation.add(
+                        EvalTerm::KingSafety,
+                        player,
+                        -self.params.open_file_near_king_penalty,
+                    );
+                }
+
+                let shield_row = |distance: i8| king.row() + distance * player as i8;
+
+                let has_pawn_at = |row: i8| (0..8).contains(&row) && file & (1 << row) != 0;
+
+                if has_pawn_at(shield_row(1)) {
+                    continue;
+                } else if has_pawn_at(shield_row(2)) {
+                    evaluation.add(
+                        EvalTerm::KingSafety,
+                        player,
+                        -self.params.advanced_shield_pawn_penalty,
+                    );
+                } else {
+                    evaluation.add(
+                        EvalTerm::KingSafety,
+                        player,
+                        -self.params.missing_shield_pawn_penalty,
+                    );
+                }
+            }
+        }
+    }
+
+    /// With the kings on opposite wings, both players can throw their pawns at the other
+    /// king without weakening their own, so every advanced pawn in front of a king is
+    /// a danger to it
+    fn evaluate_pawn_storm(&self, evaluation: &mut Evaluation, pawn_rows: &PawnRows) {
+        // Kings in the center, on the d and e files, aren't castled on either wing
+        let wing = |player: Player| match self.get_king_position(player).col() {
+            0..=2 => Some(0),
+            5..=7 => Some(1),
+            _ => None,
+        };
+
+        let (Some(white_wing), Some(black_wing)) = (wing(Player::White), wing(Player::Black))
+        else {
+            return;
+        };
+        if white_wing == black_wing {
+            return;
+        }
+
+        for player in [Player::White, Player::Black] {
+            let king = self.get_king_position(player);
+            let attacker = player.the_other();
+            let enemy_pawns = &pawn_rows[attacker.as_index()];
+
+            for col in (king.col() - 1).max(0)..=(king.col() + 1).min(7) {
+                let file = enemy_pawns[col as usize];
+                if file == 0 {
+                    continue;
+                }
+
+                let relative_row = match attacker {
+                    Player::White => 7 - file.leading_zeros(),
+                    Player::Black => 7 - file.trailing_zeros(),
+                };
+
+                evaluation.add(
+                    EvalTerm::KingSafety,
+                    player,
+                    -self.params.pawn_storm_penalty[relative_row as usize],
+                );
+            }
+        }
+    }
+
+    fn evaluate_mobility(&self, evaluation: &mut Evaluation) {
+        for position in bitboard::positions(self.occupied()) {
+            // SAFETY: The occupancy matches the board
+            let piece = unsafe { unchecked::unwrap(self.get_position(position)) };
+
+            let bonus = self.params.mobility_bonus[piece.piece_type as usize];
+            if bonus == 0 {
+                continue;
+            }
+
+            let mut count = 0;
+            piece.for_each_attack(self, position, |square| {
+                if !self
+                    .get_position(square)
+                    .is_some_and(|other| other.owner == piece.owner)
+                {
+                    count += 1;
+                }
+            });
+
+            let baseline = self.params.mobility_baseline[piece.piece_type as usize];
+            evaluation.add(EvalTerm::Mobility, piece.owner, (count - baseline) * bonus);
+        }
+    }
+
+    /// Rewards attacks which may win material: pieces attacked by pawns or by less
+    /// valuable pieces, and pieces which are attacked more times than they are defended
+    fn evaluate_threats(&self, evaluation: &mut Evaluation, pawn_attacks: &PawnAttacks) {
+        let occupied = self.occupied();
+
+        for position in bitboard::positions(occupied & !self.pieces(PieceType::King)) {
+            // SAFETY: The occupancy matches the board
+            let piece = unsafe { unchecked::unwrap(self.get_position(position)) };
+            let attacker = piece.owner.the_other();
+
+            // Batteries aren't counted, only the first piece on every ray attacks
+            let attackers_to = self.attackers_to(position, occupied);
+            let attackers = attackers_to & self.occupied_by(attacker);
+            let defenders = attackers_to & self.occupied_by(piece.owner);
+
+            let value = PIECE_VALUES[piece.piece_type as usize];
+            for square in bitboard::positions(attackers & !self.pieces(PieceType::Pawn)) {
+                // SAFETY: The attackers are pieces on the board
+                let attacking_piece = unsafe { unchecked::unwrap(self.get_position(square)) };
+                if PIECE_VALUES[attacking_piece.piece_type as usize] < value {
+                    evaluation.add(
+                        EvalTerm::Threats,
+                        attacker,
+                        self.params.threat_by_lesser_piece_bonus,
+                    );
+                }
+            }
+
+            if piece.piece_type != PieceType::Pawn
+                && pawn_attacks[attacker.as_index()] & bitboard::bit(position) != 0
+            {
+                evaluation.add(
+                    EvalTerm::Threats,
+                    attacker,
+                    self.params.threat_by_pawn_bonus,
+                );
+            }
+
+            if attackers != 0 && defenders == 0 {
+                evaluation.add(EvalTerm::Threats, attacker, self.params.hanging_piece_bonus);
+            } else if attackers.count_ones() > defenders.count_ones() {
+                evaluation.add(
+                    EvalTerm::Threats,
+                    attacker,
+                    self.params.underdefended_piece_bonus,
+                );
+            }
+        }
+    }
+}
+
+/// Rewards the squares a player controls behind its pawn chain, which give room to
+/// maneuver the pieces, more so when there are many pieces that need that room
+fn evaluate_space(
+    evaluation: &mut Evaluation,
+    params: &EvalParams,
+    piece_counts: &PieceCounts,
+    pawn_rows: &PawnRows,
+    pawn_attacks: &PawnAttacks,
+) {
+    for player in [Player::White, Player::Black] {
+        let counts = &piece_counts[player.as_index()];
+        let own_pawns = &pawn_rows[player.as_index()];
+        let enemy_pawn_attacks = pawn_attacks[player.the_other().as_index()];
+
+        let pieces = [
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+        ]
+        .iter()
+        .map(|&piece_type| counts[piece_type as usize] as Score)
+        .sum::<Score>();
+
+        let mut squares = 0;
+
+        for col in 2..6 {
+            let file = own_pawns[col as usize];
+
+            for relative_row in 1..6 {
+                let row = match player {
+                    Player::White => relative_row,
+                    Player::Black => 7 - relative_row,
+                };
+                let position = Position::new_assert(row, col);
+
+                let is_behind_pawn = file & rows_ahead(player, row) != 0;
+                let is_safe =
+                    file & (1 << row) == 0 && enemy_pawn_attacks & (1 << position.as_usize()) == 0;
+
+                if is_behind_pawn && is_safe {
+                    squares += if relative_row >= 4 { 2 } else { 1 };
+                }
+            }
+        }
+
+        evaluation.add(
+            EvalTerm::Space,
+            player,
+            squares * pieces * params.space_bonus / 16,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chess::{move_struct::Move, scores::*},
+        constants::TESTING_GAME,
+    };
+
+    #[test]
+    fn startpos_is_balanced() {
+        let game = Game::default();
+        let evaluation = game.evaluation();
+
+        for term in EvalTerm::ALL {
+            assert_eq!(evaluation.term_total(term), 0);
+        }
+    }
+
+    #[test]
+    fn breakdown_matches_evaluate() {
+        let mut game = Game::default();
+
+        for _move in TESTING_GAME.split_ascii_whitespace() {
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            game.push(_move);
+
+            assert_eq!(game.evaluation().total(), game.evaluate());
+        }
+    }
+
+    #[test]
+    fn mirrored_evaluation_is_opposite() {
+        let mut game = Game::default();
+
+        for _move in TESTING_GAME.split_ascii_whitespace() {
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            game.push_history(_move);
+
+            let evaluation = game.evaluation();
+            let mirrored = game.mirrored().evaluation();
+            for term in EvalTerm::ALL {
+                for player in [Player::White, Player::Black] {
+                    assert_eq!(
+                        evaluation.get(term, player),
+                        mirrored.get(term, player.the_other()),
+                        "{} of {:?} in {}",
+                        term.name(),
+                        player,
+                        game.to_fen()
+                    );
+                }
+            }
+            assert_eq!(evaluation.total(), -mirrored.total());
+        }
+    }
+
+    #[test]
+    fn kpk_endgames() {
+        // Black to move can't stop the pawn
+        let game = Game::new("8/8/8/8/8/1k6/6P1/6K1 b - - 0 1").unwrap();
+        assert_eq!(
+            game.evaluation().get(EvalTerm::Endgame, Player::White),
+            KPK_WIN_BONUS
+        );
+        assert!(game.evaluate() > KPK_WIN_BONUS);
+
+        // The defending king holds the opposition in front of the pawn
+        let game = Game::new("8/8/4k3/8/4K3/4P3/8/8 w - - 0 1").unwrap();
+        assert_eq!(game.evaluation().scale(), 0);
+        assert_eq!(game.evaluate(), 0);
+    }
+
+    #[test]
+    fn endgame_scaling() {
+        // A single minor piece can't win
+        let game = Game::new("8/8/4k3/8/8/3NK3/8/8 w - - 0 1").unwrap();
+        assert_eq!(game.evaluate(), 0);
+
+        // Opposite-colored bishops
+        let game = Game::new("8/4k3/2b5/8/3PP3/4B3/4K3/8 w - - 0 1").unwrap();
+        assert_eq!(game.evaluation().scale(), OPPOSITE_BISHOPS_SCALE);
+
+        // Rook pawn with a bishop of the wrong color
+        let game = Game::new("k7/8/8/P7/8/8/8/2B1K3 w - - 0 1").unwrap();
+        assert_eq!(game.evaluate(), 0);
+        let game = Game::new("k7/8/8/P7/8/8/8/3BK3 w - - 0 1").unwrap();
+        assert!(game.evaluate() > 0);
+
+        // Defending king in front of the pawn in a rook endgame
+        let game = Game::new("r2k4/8/8/3P4/8/3K4/8/7R b - - 0 1").unwrap();
+        assert_eq!(game.evaluation().scale(), BLOCKED_ROOK_ENDGAME_SCALE);
+
+        // Two knights can't force mate, with black being the side ahead
+        let game = Game::new("8/8/4k3/3nn3/8/4K3/8/8 w - - 0 1").unwrap();
+        assert_eq!(game.evaluate(), 0);
+    }
+
+    #[test]
+    fn lazy_evaluation() {
+        let mut game = Game::default();
+
+        for _move in TESTING_GAME.split_ascii_whitespace() {
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            game.push(_move);
+
+            let player = game.player() as Score;
+            let score = game.evaluate() * player;
+
+            // Inside of the window the evaluation is complete
+            assert_eq!(game.evaluate_lazy(score - 1, score + 1), score);
+
+            // Far outside of it, the expensive terms are skipped
+            let lazy = game.evaluate_lazy(score + 1000, score + 1001);
+            assert!(lazy + LAZY_EVAL_MARGIN <= score + 1000);
+        }
+    }
+
+    #[test]
+    fn halfmove_clock_scaling() {
+        let fresh = Game::new("4k3/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+        let halfway = Game::new("4k3/8/8/8/8/8/8/4K2R w - - 50 1").unwrap();
+        let limit = Game::new("4k3/8/8/8/8/8/8/4K2R w - - 100 1").unwrap();
+
+        assert_eq!(halfway.evaluate(), fresh.evaluate() / 2);
+        assert_eq!(limit.evaluate(), 0);
+    }
+
+    #[test]
+    fn mop_up() {
+        // The black king is better off in the center, away from the white king
+        let center = Game::new("8/8/8/3k4/8/8/8/KQ6 w - - 0 1").unwrap();
+        let edge = Game::new("3k4/8/8/8/8/8/8/KQ6 w - - 0 1").unwrap();
+        let near = Game::new("3k4/8/3K4/8/8/8/8/1Q6 w - - 0 1").unwrap();
+
+        let mop_up = |game: &Game| game.evaluation().get(EvalTerm::Endgame, Player::White);
+        assert!(mop_up(&center) < mop_up(&edge));
+        assert!(mop_up(&edge) < mop_up(&near));
+
+        // Not applied with pawns on the board
+        let game = Game::new("3k4/8/8/8/8/8/P7/KQ6 w - - 0 1").unwrap();
+        assert_eq!(mop_up(&game), 0);
+    }
+
+    #[test]
+    fn space() {
+        // Closed King's Indian, white's pawn chain reaches further
+        let game =
+            Game::new("r1bq1rk1/pppn1pbp/3p1np1/3Pp3/2P1P3/2N2N2/PP2BPPP/R1BQK2R b KQ - 0 8")
+                .unwrap();
+        let evaluation = game.evaluation();
+
+        assert!(evaluation.get(EvalTerm::Space, Player::White) > 0);
+        assert!(
+            evaluation.get(EvalTerm::Space, Player::White)
+                > evaluation.get(EvalTerm::Space, Player::Black)
+        );
+    }
+
+    #[test]
+    fn threats() {
+        // The e5 pawn attacks the knight on f6, which is defended by the queen
+        let game =
+            Game::new("rnbqkb1r/pppp1ppp/5n2/4P3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 3").unwrap();
+        let evaluation = game.evaluation();
+        assert_eq!(
+            evaluation.get(EvalTerm::Threats, Player::White),
+            THREAT_BY_PAWN_BONUS
+        );
+        assert_eq!(evaluation.get(EvalTerm::Threats, Player::Black), 0);
+
+        // The knight on c3 attacks the undefended rook on d5
+        let game = Game::new("4k3/8/8/3r4/8/2N5/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.evaluation().get(EvalTerm::Threats, Player::White),
+            THREAT_BY_LESSER_PIECE_BONUS + HANGING_PIECE_BONUS
+        );
+
+        // The knight on d5 is attacked by the knight and the bishop, but only defended
+        // by the pawn on e6, while it attacks the undefended knight on c3
+        let game = Game::new("4k3/8/4p3/3n4/8/1BN5/8/4K3 w - - 0 1").unwrap();
+        let evaluation = game.evaluation();
+        assert_eq!(
+            evaluation.get(EvalTerm::Threats, Player::White),
+            UNDERDEFENDED_PIECE_BONUS
+        );
+        assert_eq!(
+            evaluation.get(EvalTerm::Threats, Player::Black),
+            HANGING_PIECE_BONUS
+        );
+    }
+
+    #[test]
+    fn connected_pawns() {
+        // The pawns on d5 and e5 form a phalanx supported by c4, the h-pawn is on its own
+        let game = Game::new("4k3/8/8/3PP3/2P5/8/7P/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.evaluation().get(EvalTerm::Pawns, Player::White),
+            PASSED_PAWN_BONUS[4] * 2
+                + PASSED_PAWN_BONUS[3]
+                + PASSED_PAWN_BONUS[1]
+                + 2 * CONNECTED_PAWN_BONUS[4]
+                + SUPPORTED_PAWN_BONUS
+                - ISOLATED_PAWN_PENALTY
+        );
+    }
+
+    #[test]
+    fn pawn_storm() {
+        // The white pawns on g4 and h4 march towards the black king on g8,
+        // while the black pawns in front of the white king haven't moved yet
+        let storm = Game::new("r4rk1/ppp2ppp/8/8/6PP/8/PPP2P2/2KR3R w - - 0 1").unwrap();
+        let quiet = Game::new("r4rk1/ppp2ppp/8/8/8/8/PPP2PPP/2KR3R w - - 0 1").unwrap();
+
+        assert_eq!(
+            storm.evaluation().get(EvalTerm::KingSafety, Player::Black),
+            quiet.evaluation().get(EvalTerm::KingSafety, Player::Black) - 2 * PAWN_STORM_PENALTY[3]
+        );
+        assert_eq!(
+            storm.evaluation().get(EvalTerm::KingSafety, Player::White),
+            quiet.evaluation().get(EvalTerm::KingSafety, Player::White)
+        );
+
+        // Not applied with both kings on the same wing
+        let same_wing = Game::new("r4rk1/ppp2ppp/8/8/6PP/8/PPP2P2/R4RK1 w - - 0 1").unwrap();
+        let same_wing_quiet = Game::new("r4rk1/ppp2ppp/8/8/8/8/PPP2PPP/R4RK1 w - - 0 1").unwrap();
+        assert_eq!(
+            same_wing
+                .evaluation()
+                .get(EvalTerm::KingSafety, Player::Black),
+            same_wing_quiet
+                .evaluation()
+                .get(EvalTerm::KingSafety, Player::Black)
+        );
+    }
+
+    #[test]
+    fn trapped_pieces() {
+        // The knight on a8 can only go to b6 and c7, both covered by black pawns
+        let game = Game::new("N3k3/p1p5/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.evaluation().get(EvalTerm::Pieces, Player::White),
+            -TRAPPED_KNIGHT_PENALTY
+        );
+
+        // The bishop on a7 is cut off by the pawn on b6, which is defended by c7
+        let game = Game::new("4k3/B1p5/1p6/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.evaluation().get(EvalTerm::Pieces, Player::White),
+            -TRAPPED_BISHOP_PENALTY
+        );
+        let game = Game::new("4k3/B7/1p6/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(game.evaluation().get(EvalTerm::Pieces, Player::White), 0);
+
+        // The dark-squared bishop on c1 is behind pawns on d4 and e3, the first one blocked
+        let game = Game::new("4k3/8/8/3p4/3P4/4P3/8/2B1K3 w - - 0 1").unwrap();
+        assert_eq!(
+            game.evaluation().get(EvalTerm::Pieces, Player::White),
+            -3 * BAD_BISHOP_PENALTY
+        );
+    }
+
+    #[test]
+    fn pawn_structure() {
+        // White has doubled isolated pawns on the e file, black has a passed pawn on a6
+        let game = Game::new("4k3/8/p7/8/4P3/4P3/8/4K3 w - - 0 1").unwrap();
+        let evaluation = game.evaluation();
+
+        assert_eq!(
+            evaluation.get(EvalTerm::Pawns, Player::White),
+            -DOUBLED_PAWN_PENALTY - 2 * ISOLATED_PAWN_PENALTY + PASSED_PAWN_BONUS[3]
+        );
+        assert_eq!(
+            evaluation.get(EvalTerm::Pawns, Player::Black),
+            -ISOLATED_PAWN_PENALTY + PASSED_PAWN_BONUS[2]
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use super::Player;
+use alloc::string::String;
+use core::fmt;
+
+/// Reasons a FEN is rejected by `Game::new`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum FenError {
+    MissingBoard,
+    /// The board must have 8 ranks separated by `/`
+    InvalidRankCount(usize),
+    /// Rank from 1 to 8 whose squares don't add up to 8
+    InvalidRankLength(i8),
+    InvalidPiece(char),
+    PawnOnLastRank,
+    /// Every player must have exactly one king
+    InvalidKingCount(Player),
+    InvalidPlayer(String),
+    InvalidCastlingRight(char),
+    /// The king or the rook of a castling right isn't on its first rank
+    MissingCastlingPieces(char),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    /// The king of the player who just moved can't be attacked
+    OpponentInCheck,
+    /// More pawns and promoted pieces than the 8 pawns a player starts with
+    TooManyPieces(Player),
+    /// No move gives check with more than two pieces at once
+    TooManyCheckers,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingBoard => write!(f, "Missing board"),
+            Self::InvalidRankCount(count) => write!(f, "Expected 8 ranks, found {}", count),
+            Self::InvalidRankLength(rank) => write!(f, "Rank {} doesn't have 8 squares", rank),
+            Self::InvalidPiece(piece) => write!(f, "Invalid piece: {}", piece),
+            Self::PawnOnLastRank => write!(f, "Pawns can't be on the first or last rank"),
+            Self::InvalidKingCount(player) => write!(f, "{:?} must have exactly one king", player),
+            Self::InvalidPlayer(player) => write!(f, "Invalid player: {}", player),
+            Self::InvalidCastlingRight(right) => write!(f, "Invalid castling right: {}", right),
+            Self::MissingCastlingPieces(right) => write!(
+                f,
+                "Castling right {} without its king and rook on the first rank",
+                right
+            ),
+            Self::InvalidEnPassant(square) => write!(f, "Invalid en passant square: {}", square),
+            Self::InvalidHalfmoveClock(clock) => write!(f, "Invalid halfmove clock: {}", clock),
+            Self::InvalidFullmoveNumber(number) => {
+                write!(f, "Invalid fullmove number: {}", number)
+            }
+            Self::OpponentInCheck => write!(f, "The player who just moved is in check"),
+            Self::TooManyPieces(player) => {
+                write!(
+                    f,
+                    "{:?} has more pieces than promotions can explain",
+                    player
+                )
+            }
+            Self::TooManyCheckers => write!(f, "The king is checked by more than two pieces"),
+        }
+    }
+}
+
+impl core::error::Error for FenError {}
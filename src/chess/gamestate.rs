@@ -1,18 +1,45 @@
-use super::zobrist;
+use super::bitboard::Bitboard;
+use super::move_struct::Move;
+use super::piece::Piece;
+use super::{unchecked, zobrist, Player};
+
+/// The side of the king the rook is on, short castling is towards the h file
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
+pub enum CastlingSide {
+    Short,
+    Long,
+}
+
+impl CastlingSide {
+    pub const ALL: [Self; 2] = [Self::Short, Self::Long];
+}
 
 /// Information about the state of the game at a moment in time that can't be derived easily
 /// Because of that, we hold it in a stack in the ChessGame struct
 #[derive(Clone, Copy, Debug)]
 pub struct GameState {
     /// First 4 bits represent en passant
-    /// The last 4 bits of castling_rights indicate castling rights
+    /// The last 4 bits indicate castling rights, short then long, white then black
     bitfield: u8,
+    /// Half-moves since the last capture or pawn move, it isn't part of the hash
+    halfmove_clock: u16,
+    /// Pieces giving check to the player to move, it follows from the board
+    checkers: Bitboard,
+    /// The move which led to this state, None at the start of the game and after a null move
+    last_move: Option<Move>,
+    /// Piece taken by that move, which isn't stored in the move
+    captured_piece: Option<Piece>,
 }
 
 impl GameState {
     #[inline]
     pub fn hash(self) -> u64 {
-        unsafe { *zobrist::STATE.get_unchecked(self.bitfield as usize) }
+        unsafe { *unchecked::get(&zobrist::STATE, self.bitfield as usize) }
     }
 
     #[inline]
@@ -26,63 +53,69 @@ impl GameState {
     }
 
     #[inline]
-    pub const fn white_king_castling(self) -> bool {
-        (self.bitfield & (1 << 4)) != 0
+    pub const fn halfmove_clock(self) -> u16 {
+        self.halfmove_clock
+    }
+
+    #[inline]
+    pub fn set_halfmove_clock(&mut self, value: u16) {
+        self.halfmove_clock = value;
     }
 
     #[inline]
-    pub fn set_white_king_castling_false(&mut self) {
-        self.bitfield &= !(1 << 4);
+    pub const fn checkers(self) -> Bitboard {
+        self.checkers
     }
 
     #[inline]
-    pub fn set_white_king_castling_true(&mut self) {
-        self.bitfield |= 1 << 4;
+    pub fn set_checkers(&mut self, checkers: Bitboard) {
+        self.checkers = checkers;
     }
 
     #[inline]
-    pub const fn white_queen_castling(self) -> bool {
-        (self.bitfield & (1 << 5)) != 0
+    pub const fn last_move(self) -> Option<Move> {
+        self.last_move
     }
 
     #[inline]
-    pub fn set_white_queen_castling_false(&mut self) {
-        self.bitfield &= !(1 << 5);
+    pub fn set_last_move(&mut self, last_move: Option<Move>) {
+        self.last_move = last_move;
     }
 
     #[inline]
-    pub fn set_white_queen_castling_true(&mut self) {
-        self.bitfield |= 1 << 5;
+    pub const fn captured_piece(self) -> Option<Piece> {
+        self.captured_piece
     }
 
     #[inline]
-    pub const fn black_king_castling(self) -> bool {
-        (self.bitfield & (1 << 6)) != 0
+    pub fn set_captured_piece(&mut self, captured_piece: Option<Piece>) {
+        self.captured_piece = captured_piece;
     }
 
     #[inline]
-    pub fn set_black_king_castling_false(&mut self) {
-        self.bitfield &= !(1 << 6);
+    fn castling_bit(player: Player, side: CastlingSide) -> u8 {
+        1 << (4 + 2 * player.as_index() + side as usize)
     }
 
     #[inline]
-    pub fn set_black_king_castling_true(&mut self) {
-        self.bitfield |= 1 << 6;
+    pub fn castling(self, player: Player, side: CastlingSide) -> bool {
+        self.bitfield & Self::castling_bit(player, side) != 0
     }
 
     #[inline]
-    pub const fn black_queen_castling(self) -> bool {
-        (self.bitfield & (1 << 7)) != 0
+    pub fn set_castling(&mut self, player: Player, side: CastlingSide) {
+        self.bitfield |= Self::castling_bit(player, side);
     }
 
     #[inline]
-    pub fn set_black_queen_castling_false(&mut self) {
-        self.bitfield &= !(1 << 7);
+    pub fn clear_castling(&mut self, player: Player, side: CastlingSide) {
+        self.bitfield &= !Self::castling_bit(player, side);
     }
 
+    /// Clears both castling rights of `player`, once the king moves
     #[inline]
-    pub fn set_black_queen_castling_true(&mut self) {
-        self.bitfield |= 1 << 7;
+    pub fn clear_all_castling(&mut self, player: Player) {
+        self.bitfield &= !(0b11 << (4 + 2 * player.as_index()));
     }
 }
 
@@ -92,6 +125,10 @@ impl Default for GameState {
         Self {
             // 8 Represents no en passant square
             bitfield: 8,
+            halfmove_clock: 0,
+            checkers: 0,
+            last_move: None,
+            captured_piece: None,
         }
     }
 }
@@ -0,0 +1,303 @@
+use super::position::Position;
+use super::Player;
+use alloc::{boxed::Box, vec, vec::Vec};
+use once_cell::race::OnceBox;
+
+// Bitbase for king and pawn versus king endgames, generated by retrograde analysis
+// Based on: https://www.chessprogramming.org/KPK
+
+/// The attacking side is normalized to white with its pawn on files a-d and rows 2-7,
+/// so every position is indexed by side to move, both kings and one of 24 pawn squares
+const SIZE: usize = 2 * 64 * 64 * 24;
+
+const INVALID: u8 = 0;
+const UNKNOWN: u8 = 1;
+const DRAW: u8 = 2;
+const WIN: u8 = 4;
+
+static BITBASE: OnceBox<Vec<u64>> = OnceBox::new();
+
+/// Returns whether white wins with the given pieces, assuming the pawn is on files a-d
+/// and the position is legal. The bitbase is generated on the first call.
+fn probe_normalized(
+    white_to_move: bool,
+    white_king: usize,
+    black_king: usize,
+    pawn: usize,
+) -> bool {
+    let bitbase = BITBASE.get_or_init(|| Box::new(generate()));
+    let index = index(white_to_move, white_king, black_king, pawn);
+    bitbase[index / 64] & (1 << (index % 64)) != 0
+}
+
+/// Returns whether the side owning the pawn wins
+///
+/// `strong` is the player owning the pawn, `to_move` is the player to move
+pub fn probe(
+    strong: Player,
+    to_move: Player,
+    strong_king: Position,
+    weak_king: Position,
+    pawn: Position,
+) -> bool {
+    // Flip the board vertically if the pawn is black, so that it always goes up
+    let row = |position: Position| match strong {
+        Player::White => position.row(),
+        Player::Black => 7 - position.row(),
+    };
+
+    // Mirror the board horizontally so that the pawn is always on files a-d
+    let col = |position: Position| {
+        if pawn.col() >= 4 {
+            7 - position.col()
+        } else {
+            position.col()
+        }
+    };
+
+    let square = |position: Position| (row(position) * 8 + col(position)) as usize;
+
+    probe_normalized(
+        to_move == strong,
+        square(strong_king),
+        square(weak_king),
+        square(pawn),
+    )
+}
+
+#[inline]
+fn index(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> usize {
+    let (pawn_row, pawn_col) = (pawn / 8, pawn % 8);
+    debug_assert!((1..7).contains(&pawn_row) && pawn_col < 4);
+
+    white_to_move as usize
+        | black_king << 1
+        | white_king << 7
+        | pawn_col << 13
+        | (6 - pawn_row) << 15
+}
+
+fn distance(a: usize, b: usize) -> usize {
+    let row_distance = (a / 8).abs_diff(b / 8);
+    let col_distance = (a % 8).abs_diff(b % 8);
+    row_distance.max(col_distance)
+}
+
+fn king_moves(square: usize) -> impl Iterator<Item = usize> {
+    let position = Position::new_assert((square / 8) as i8, (square % 8) as i8);
+    [
+        (0, 1),
+        (0, -1),
+        (1, 0),
+        (-1, 0),
+        (1, 1),
+        (1, -1),
+        (-1, 1),
+        (-1, -1),
+    ]
+    .into_iter()
+    .filter_map(move |delta| position.add(delta))
+    .map(Position::as_usize)
+}
+
+/// Whether a white pawn on `pawn` attacks `square`
+fn pawn_attacks(pawn: usize, square: usize) -> bool {
+    square / 8 == pawn / 8 + 1 && (square % 8).abs_diff(pawn % 8) == 1
+}
+
+fn initial_result(white_to_move: bool, white_king: usize, black_king: usize, pawn: usize) -> u8 {
+    if distance(white_king, black_king) <= 1 || white_king == pawn || black_king == pawn {
+        return INVALID;
+    }
+
+    if white_to_move {
+        // Black can't be in check while white is to move
+        if pawn_attacks(pawn, black_king) {
+            return INVALID;
+        }
+
+        // The pawn promotes and can't be captured right away
+        let promotion = pawn + 8;
+        if pawn / 8 == 6
+            && white_king != promotion
+            && (distance(black_king, promotion) > 1 || distance(white_king, promotion) == 1)
+        {
+            return WIN;
+        }
+    } else {
+        // The pawn is captured
+        if distance(black_king, pawn) == 1 && distance(white_king, pawn) > 1 {
+            return DRAW;
+        }
+
+        // Stalemate
+        if king_moves(black_king).all(|square| {
+            distance(square, white_king) <= 1 || pawn_attacks(pawn, square) || square == pawn
+        }) {
+            return DRAW;
+        }
+    }
+
+    UNKNOWN
+}
+
+/// Combines the results of all the moves available in a position
+fn classify(
+    db: &[u8],
+    white_to_move: bool,
+    white_king: usize,
+    black_king: usize,
+    pawn: usize,
+) -> u8 {
+    let mut result = INVALID;
+
+    if white_to_move {
+        for square in king_moves(white_king) {
+            result |= db[index(false, square, black_king, pawn)];
+        }
+
+        if pawn / 8 < 6 {
+            result |= db[index(false, white_king, black_king, pawn + 8)];
+        }
+
+        // Double push, the square in front of the pawn must be empty
+        let front = pawn + 8;
+        if pawn / 8 == 1 && front != white_king && front != black_king {
+            result |= db[index(false, white_king, black_king, pawn + 16)];
+        }
+
+        if result & WIN != 0 {
+            WIN
+        } else if result & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            DRAW
+        }
+    } else {
+        for square in king_moves(black_king) {
+            result |= db[index(true, white_king, square, pawn)];
+        }
+
+        if result & DRAW != 0 {
+            DRAW
+        } else if result & UNKNOWN != 0 {
+            UNKNOWN
+        } else {
+            WIN
+        }
+    }
+}
+
+fn generate() -> Vec<u64> {
+    let mut db = vec![INVALID; SIZE];
+    let mut positions = Vec::with_capacity(SIZE);
+
+    for white_to_move in [false, true] {
+        for white_king in 0..64 {
+            for black_king in 0..64 {
+                for pawn_row in 1..7 {
+                    for pawn_col in 0..4 {
+                        let pawn = pawn_row * 8 + pawn_col;
+                        let result = initial_result(white_to_move, white_king, black_king, pawn);
+                        db[index(white_to_move, white_king, black_king, pawn)] = result;
+
+                        if result == UNKNOWN {
+                            positions.push((white_to_move, white_king, black_king, pawn));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // Keep resolving positions until nothing changes
+    let mut changed = true;
+    while changed {
+        changed = false;
+
+        for &(white_to_move, white_king, black_king, pawn) in &positions {
+            let index = index(white_to_move, white_king, black_king, pawn);
+            if db[index] != UNKNOWN {
+                continue;
+            }
+
+            let result = classify(&db, white_to_move, white_king, black_king, pawn);
+            if result != UNKNOWN {
+                db[index] = result;
+                changed = true;
+            }
+        }
+    }
+
+    // Every position that couldn't be resolved is a draw
+    let mut bitbase = vec![0u64; SIZE / 64];
+    for (index, &result) in db.iter().enumerate() {
+        if result == WIN {
+            bitbase[index / 64] |= 1 << (index % 64);
+        }
+    }
+
+    bitbase
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe_squares(strong: Player, to_move: Player, squares: [(i8, i8); 3]) -> bool {
+        let [strong_king, weak_king, pawn] =
+            squares.map(|(row, col)| Position::new_assert(row, col));
+        probe(strong, to_move, strong_king, weak_king, pawn)
+    }
+
+    #[test]
+    fn known_positions() {
+        // Opposition in front of the pawn, whoever moves loses the opposition
+        assert!(probe_squares(
+            Player::White,
+            Player::Black,
+            [(5, 4), (7, 4), (4, 4)]
+        ));
+        assert!(!probe_squares(
+            Player::White,
+            Player::White,
+            [(3, 4), (5, 4), (2, 4)]
+        ));
+
+        // Rook pawns with the defending king in the corner are always drawn
+        assert!(!probe_squares(
+            Player::White,
+            Player::White,
+            [(5, 1), (7, 0), (4, 0)]
+        ));
+        assert!(!probe_squares(
+            Player::White,
+            Player::White,
+            [(1, 6), (7, 7), (5, 7)]
+        ));
+
+        // The defending king is too far away from the pawn
+        assert!(probe_squares(
+            Player::White,
+            Player::White,
+            [(0, 0), (7, 7), (3, 0)]
+        ));
+        assert!(!probe_squares(
+            Player::White,
+            Player::Black,
+            [(0, 0), (4, 4), (3, 0)]
+        ));
+
+        // Same positions with colors reversed
+        assert!(probe_squares(
+            Player::Black,
+            Player::White,
+            [(2, 4), (0, 4), (3, 4)]
+        ));
+        assert!(!probe_squares(
+            Player::Black,
+            Player::Black,
+            [(4, 4), (2, 4), (5, 4)]
+        ));
+    }
+}
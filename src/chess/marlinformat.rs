@@ -0,0 +1,243 @@
+use super::pgn::GameResult;
+use super::piece::{Piece, PieceType};
+use super::position::Position;
+use super::score::Score;
+use super::{CastlingSide, Game, Player};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use anyhow::{bail, Context};
+
+// Marlinformat, the packed positions read by the marlinflow and bullet trainers
+// Based on: https://github.com/jnlt3/marlinflow
+
+/// Bytes of a record: the occupied squares, a nibble for each piece, the player to move
+/// with the en passant square, the counters, the score and the result
+pub const RECORD_SIZE: usize = 32;
+
+/// Nibble of a rook which can still castle, the other pieces are numbered from the pawn
+/// to the king and the fourth bit is set for black
+const UNMOVED_ROOK: u8 = 6;
+const BLACK: u8 = 8;
+
+/// En passant square of a record without one
+const NO_EN_PASSANT: u8 = 64;
+
+fn piece_code(piece_type: PieceType) -> u8 {
+    match piece_type {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 1,
+        PieceType::Bishop => 2,
+        PieceType::Rook => 3,
+        PieceType::Queen => 4,
+        PieceType::King => 5,
+    }
+}
+
+/// Packs a position with its score from the point of view of white and the result
+/// of the game it was played in, which must be over
+pub fn pack(game: &Game, score: Score, result: GameResult) -> [u8; RECORD_SIZE] {
+    let mut record = [0; RECORD_SIZE];
+
+    let mut castling_rooks = Vec::with_capacity(4);
+    for player in [Player::White, Player::Black] {
+        for side in [CastlingSide::Short, CastlingSide::Long] {
+            if game.state().castling(player, side) {
+                let (_, _, rook, _) = game.castling_squares(player, side);
+                castling_rooks.push(rook);
+            }
+        }
+    }
+
+    let mut occupancy: u64 = 0;
+    let mut index = 0;
+    for square in 0..64 {
+        let position = Position::from_usize(square);
+        let Some(piece) = game.get_position(position) else {
+            continue;
+        };
+        occupancy |= 1 << square;
+
+        let mut code = if castling_rooks.contains(&position) {
+            UNMOVED_ROOK
+        } else {
+            piece_code(piece.piece_type)
+        };
+        if piece.owner == Player::Black {
+            code |= BLACK;
+        }
+        record[8 + index / 2] |= code << (4 * (index % 2));
+        index += 1;
+    }
+    record[..8].copy_from_slice(&occupancy.to_le_bytes());
+
+    let en_passant = match game.state().en_passant() {
+        col @ 0..=7 => {
+            let row = match game.player() {
+                Player::White => 5,
+                Player::Black => 2,
+            };
+            Position::new_assert(row, col).as_usize() as u8
+        }
+        _ => NO_EN_PASSANT,
+    };
+    let black_to_move = match game.player() {
+        Player::White => 0,
+        Player::Black => 1 << 7,
+    };
+    record[24] = black_to_move | en_passant;
+    // The format has a single byte for it, a longer clock is a draw anyway
+    record[25] = game.halfmove_clock().min(u8::MAX as u16) as u8;
+    record[26..28].copy_from_slice(&(game.fullmove_number() as u16).to_le_bytes());
+
+    let score = score.clamp(i16::MIN as Score, i16::MAX as Score) as i16;
+    record[28..30].copy_from_slice(&score.to_le_bytes());
+    record[30] = match result {
+        GameResult::BlackWins => 0,
+        GameResult::Draw => 1,
+        GameResult::WhiteWins => 2,
+        GameResult::Unfinished => panic!("Only positions of finished games can be packed"),
+    };
+
+    record
+}
+
+/// Reads a record written by `pack`, or by another program using the format
+pub fn unpack(record: &[u8; RECORD_SIZE]) -> anyhow::Result<(Game, Score, GameResult)> {
+    let occupancy = u64::from_le_bytes(record[..8].try_into().unwrap());
+    if occupancy.count_ones() > 32 {
+        bail!("More than 32 pieces");
+    }
+
+    let mut board = [None; 64];
+    let mut castling = String::new();
+    for (index, square) in (0..64)
+        .filter(|square| occupancy & (1 << square) != 0)
+        .enumerate()
+    {
+        let code = (record[8 + index / 2] >> (4 * (index % 2))) & 0b1111;
+        let owner = if code & BLACK != 0 {
+            Player::Black
+        } else {
+            Player::White
+        };
+        let piece_type = match code & !BLACK {
+            0 => PieceType::Pawn,
+            1 => PieceType::Knight,
+            2 => PieceType::Bishop,
+            3 | UNMOVED_ROOK => PieceType::Rook,
+            4 => PieceType::Queen,
+            5 => PieceType::King,
+            _ => bail!("Invalid piece {}", code),
+        };
+        board[square] = Some(Piece { piece_type, owner });
+
+        // Castling rights are written with the files of the rooks, as in Shredder-FEN
+        if code & !BLACK == UNMOVED_ROOK {
+            let file = (b'A' + (square % 8) as u8) as char;
+            castling.push(match owner {
+                Player::White => file,
+                Player::Black => file.to_ascii_lowercase(),
+            });
+        }
+    }
+
+    let mut fen = String::new();
+    for row in (0..8).rev() {
+        let mut empty = 0;
+        for col in 0..8 {
+            match board[row * 8 + col] {
+                Some(piece) => {
+                    if empty > 0 {
+                        fen.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    fen.push(piece.as_char_ascii());
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            fen.push_str(&empty.to_string());
+        }
+        if row > 0 {
+            fen.push('/');
+        }
+    }
+
+    let player = if record[24] & (1 << 7) != 0 { 'b' } else { 'w' };
+    let en_passant = match record[24] & 0b0111_1111 {
+        NO_EN_PASSANT => String::from("-"),
+        square @ 0..=63 => {
+            let position = Position::from_usize(square as usize);
+            format!(
+                "{}{}",
+                (b'a' + position.col() as u8) as char,
+                position.row() + 1
+            )
+        }
+        square => bail!("Invalid en passant square {}", square),
+    };
+    if castling.is_empty() {
+        castling.push('-');
+    }
+    let fullmove_number = u16::from_le_bytes([record[26], record[27]]);
+    let fen = format!(
+        "{} {} {} {} {} {}",
+        fen, player, castling, en_passant, record[25], fullmove_number
+    );
+    let game = Game::new(&fen).with_context(|| format!("Invalid position {}", fen))?;
+
+    let score = i16::from_le_bytes([record[28], record[29]]) as Score;
+    let result = match record[30] {
+        0 => GameResult::BlackWins,
+        1 => GameResult::Draw,
+        2 => GameResult::WhiteWins,
+        result => bail!("Invalid result {}", result),
+    };
+
+    Ok((game, score, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::START_FEN;
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 11 48",
+            "1r2k2r/8/8/8/8/8/8/R3K1R1 b Gk - 0 1",
+        ] {
+            let game = Game::new(fen).unwrap();
+            let record = pack(&game, -123, GameResult::Draw);
+            let (unpacked, score, result) = unpack(&record).unwrap();
+
+            assert_eq!(unpacked.to_fen(), game.to_fen());
+            assert_eq!(unpacked.hash(), game.hash());
+            assert_eq!(score, -123);
+            assert_eq!(result, GameResult::Draw);
+        }
+    }
+
+    #[test]
+    fn layout() {
+        let game = Game::default();
+        let record = pack(&game, 40_000, GameResult::WhiteWins);
+
+        assert_eq!(record[..8], 0xFFFF_0000_0000_FFFFu64.to_le_bytes());
+        // White rook that can castle then knight, black pawns on the third row of bytes
+        assert_eq!(record[8], UNMOVED_ROOK | (1 << 4));
+        assert_eq!(record[16], BLACK | (BLACK << 4));
+        assert_eq!(record[24], NO_EN_PASSANT);
+        assert_eq!(record[26..28], [1, 0]);
+        assert_eq!(i16::from_le_bytes([record[28], record[29]]), i16::MAX);
+        assert_eq!(record[30], 2);
+    }
+}
@@ -1,21 +1,56 @@
+pub mod bitboard;
+pub mod evaluation;
 pub mod move_struct;
+pub mod params;
 pub mod zobrist;
 
-mod gamestate;
-mod piece;
-mod position;
-mod scores;
+pub mod piece;
+pub mod position;
+pub mod score;
+pub mod scores;
 
-use anyhow::{bail, Context};
+mod eco;
+mod endgame;
+pub mod epd;
+mod fen;
+mod gamestate;
+mod kpk;
+pub mod marlinformat;
+mod outcome;
+pub mod pgn;
+mod polyglot;
+#[cfg(test)]
+mod properties;
+mod san;
+#[cfg(any(test, feature = "verify-incremental"))]
+mod snapshot;
+mod unchecked;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use arrayvec::ArrayVec;
+use bitboard::Bitboard;
 use gamestate::GameState;
-use move_struct::Move;
+use move_struct::{Move, MoveKind, MAX_MOVES};
+use params::EvalParams;
 use piece::{Piece, PieceType};
 use position::Position;
-use scores::ENDGAME_THRESHOLD;
-use std::cell::Cell;
+use scores::{ENDGAME_THRESHOLD, PIECE_VALUES};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+pub use fen::FenError;
+pub use gamestate::CastlingSide;
+pub use outcome::Outcome;
+pub use san::SanMoveError;
+pub use score::Score;
 
-pub type Score = i16;
+/// Number of half-moves without captures or pawn moves after which the game is drawn
+pub const FIFTY_MOVE_LIMIT: u16 = 100;
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub enum GamePhase {
@@ -25,6 +60,11 @@ pub enum GamePhase {
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Player {
     White = 1,
     Black = -1,
@@ -37,16 +77,52 @@ pub struct Game {
     move_stack: Vec<Move>,
     phase: GamePhase,
     hash: u64,
+    /// Value of the pieces of every player, without the king
+    material: [Score; 2],
+    /// Number of pieces of every type, for every player
+    piece_counts: [[u8; 6]; 2],
+    /// Squares occupied by the pieces of every player
+    occupied: [Bitboard; 2],
+    /// Squares occupied by every type of piece, of both players
+    pieces: [Bitboard; 6],
     board: [Option<Piece>; 64],
     past_scores: [Score; 64],
     past_hashes: [u64; 64],
-    /// Cells are used here in order to allow the changing of the scores
-    /// depending on the game's state, e.g. for the endgame
-    ///
-    /// WARNING: The order of the scores must match the order of the pieces
-    piece_scores: [Cell<&'static [i16; 64]>; 6],
+    /// Hashes of the positions before every move, used to detect repetitions
+    hash_history: Vec<u64>,
+    /// Weights of the evaluation, `params::default` unless others are given when the game
+    /// is created, shared with its clones
+    params: Arc<EvalParams>,
+    /// The position before every move, which `pop` must restore exactly
+    #[cfg(feature = "verify-incremental")]
+    snapshots: Vec<snapshot::Snapshot>,
     king_positions: [Position; 2],
-    state: ArrayVec<GameState, 512>,
+    /// Columns the king of every player starts on, which only differ from the e file in Chess960
+    king_start_cols: [i8; 2],
+    /// Columns of the short and long castling rooks of every player, indexed by CastlingSide
+    rook_start_cols: [[i8; 2]; 2],
+    /// Castling moves are written as the king taking its own rook, as expected by Chess960 GUIs
+    chess960: bool,
+    /// Half-moves played before the position the game was created from,
+    /// according to the fullmove number of its FEN
+    initial_ply: usize,
+    state: Vec<GameState>,
+}
+
+/// A piece which can't leave the line between its king and an enemy slider
+/// without exposing the king
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Pin {
+    pub pinned: Position,
+    /// Squares the pinned piece may still go to, up to and including the pinning slider
+    pub ray: Bitboard,
+}
+
+/// Restrictions on the moves of the player to move other than the king
+struct LegalityMasks {
+    /// Squares which capture or block the only checking piece, all of them out of check
+    check_mask: Bitboard,
+    pins: ArrayVec<Pin, 8>,
 }
 
 impl Player {
@@ -56,97 +132,147 @@ impl Player {
             Self::Black => Self::White,
         }
     }
+
+    /// Row the pieces of the player start on
+    #[inline]
+    pub fn first_row(self) -> i8 {
+        match self {
+            Self::White => 0,
+            Self::Black => 7,
+        }
+    }
+
+    /// Index used by arrays holding a value for each player
+    #[inline]
+    pub fn as_index(self) -> usize {
+        match self {
+            Self::White => 0,
+            Self::Black => 1,
+        }
+    }
 }
 
+/// The standard starting position
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
 impl Default for Game {
     fn default() -> Self {
-        Game::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap()
+        Game::new(START_FEN).unwrap()
     }
 }
 
 impl Game {
-    pub fn new(fen: &str) -> anyhow::Result<Self> {
+    /// Reads a position in Forsyth-Edwards Notation
+    ///
+    /// Only the board is required, the fields after it default to white to move, no castling
+    /// rights, no en passant square and the counters of a new game. Anything after the sixth
+    /// field is ignored.
+    pub fn new(fen: &str) -> Result<Self, FenError> {
+        Self::with_params(fen, params::default())
+    }
+
+    /// Reads a position like `new`, evaluated with `params` instead of the defaults
+    pub fn with_params(fen: &str, params: Arc<EvalParams>) -> Result<Self, FenError> {
         let mut terms = fen.split_ascii_whitespace();
 
-        let mut hash = 0;
         let mut score = 0;
+        let mut material = [0; 2];
+        let mut piece_counts = [[0; 6]; 2];
+        let mut occupied = [0; 2];
+        let mut piece_bitboards = [0; 6];
 
         let mut board = [None; 64];
         let mut past_scores = [0; 64];
-        let mut past_hashes = [0; 64];
-        let mut white_king_pos = None;
-        let mut black_king_pos = None;
-        let piece_scores: [Cell<&[i16; 64]>; 6] = [
-            Cell::new(&scores::QUEEN_SCORES),
-            Cell::new(&scores::ROOK_SCORES),
-            Cell::new(&scores::BISHOP_SCORES),
-            Cell::new(&scores::KNIGHT_SCORES),
-            Cell::new(&scores::PAWN_SCORES),
-            Cell::new(&scores::KING_SCORES_MIDDLE),
-        ];
-
-        let Some(pieces) = terms.next() else {
-            bail!("Missing board");
-        };
+        let mut past_hashes = [zobrist::EMPTY_PLACE; 64];
+        let mut king_positions = [None; 2];
 
-        let mut row = 7;
-        let mut col = 0;
+        let ranks = terms.next().ok_or(FenError::MissingBoard)?;
+        let rank_count = ranks.split('/').count();
+        if rank_count != 8 {
+            return Err(FenError::InvalidRankCount(rank_count));
+        }
 
-        for character in pieces.chars() {
-            match character {
-                '/' => {
-                    if row == 0 {
-                        bail!("Too many rows");
-                    }
-                    col = 0;
-                    row -= 1;
-                }
-                piece if piece.is_ascii_alphabetic() => {
-                    if col == 8 {
-                        bail!("Too many columns");
+        for (row, rank) in (0..8).rev().zip(ranks.split('/')) {
+            let mut col = 0;
+
+            for character in rank.chars() {
+                if let Some(empty_count) = character.to_digit(10).filter(|count| *count > 0) {
+                    col += empty_count as i8;
+                } else {
+                    let piece = Piece::from_char_ascii(character)
+                        .ok_or(FenError::InvalidPiece(character))?;
+                    let Some(position) = Position::new(row, col) else {
+                        return Err(FenError::InvalidRankLength(row + 1));
+                    };
+
+                    if piece.piece_type == PieceType::Pawn && (row == 0 || row == 7) {
+                        return Err(FenError::PawnOnLastRank);
                     }
-                    let piece = Piece::from_char_ascii(piece).with_context(|| "Invalid piece")?;
                     if piece.piece_type == PieceType::King {
-                        match piece.owner {
-                            Player::White => white_king_pos = Some(Position::new_assert(row, col)),
-                            Player::Black => black_king_pos = Some(Position::new_assert(row, col)),
+                        if king_positions[piece.owner.as_index()].is_some() {
+                            return Err(FenError::InvalidKingCount(piece.owner));
                         }
+                        king_positions[piece.owner.as_index()] = Some(position);
                     }
-                    let position = Position::new_assert(row, col);
+
                     board[position.as_usize()] = Some(piece);
-                    past_scores[position.as_usize()] = piece.score(position, &piece_scores);
+                    past_scores[position.as_usize()] =
+                        piece.score(position, &params, GamePhase::Opening);
                     score += past_scores[position.as_usize()];
+                    piece_counts[piece.owner.as_index()][piece.piece_type as usize] += 1;
+                    occupied[piece.owner.as_index()] |= bitboard::bit(position);
+                    piece_bitboards[piece.piece_type as usize] |= bitboard::bit(position);
+                    if piece.piece_type != PieceType::King {
+                        material[piece.owner.as_index()] += PIECE_VALUES[piece.piece_type as usize];
+                    }
                     past_hashes[position.as_usize()] = piece.hash(position);
-                    hash ^= past_hashes[position.as_usize()];
 
                     col += 1;
                 }
-                empty_count if character.is_ascii_digit() => {
-                    let count = (empty_count as u8 - b'0') as i8;
-                    for i in 0..count {
-                        let position = Position::new_assert(row, col + i);
-                        past_hashes[position.as_usize()] = zobrist::EMPTY_PLACE;
-                        hash ^= past_hashes[position.as_usize()];
-                    }
 
-                    col += count;
+                if col > 8 {
+                    return Err(FenError::InvalidRankLength(row + 1));
                 }
-                _ => bail!("Unknown character met"),
             }
-        }
 
-        if row != 0 || col != 8 {
-            bail!("Invalid board size");
+            if col != 8 {
+                return Err(FenError::InvalidRankLength(row + 1));
+            }
         }
 
-        let Some(next_player) = terms.next() else {
-            bail!("Missing player");
+        let [Some(white_king_pos), Some(black_king_pos)] = king_positions else {
+            let player = match king_positions[0] {
+                None => Player::White,
+                Some(_) => Player::Black,
+            };
+            return Err(FenError::InvalidKingCount(player));
         };
 
-        let current_player = match next_player.chars().next().unwrap() {
-            'w' => Player::White,
-            'b' => Player::Black,
-            _ => bail!("Invalid player"),
+        // Every piece beyond the ones of the starting position is a promoted pawn
+        for player in [Player::White, Player::Black] {
+            let counts: [u8; 6] = piece_counts[player.as_index()];
+            let pawns = counts[PieceType::Pawn as usize];
+            let promoted: u8 = [
+                (PieceType::Queen, 1),
+                (PieceType::Rook, 2),
+                (PieceType::Bishop, 2),
+                (PieceType::Knight, 2),
+            ]
+            .iter()
+            .map(|&(piece_type, initial)| counts[piece_type as usize].saturating_sub(initial))
+            .sum();
+
+            if pawns + promoted > 8 {
+                return Err(FenError::TooManyPieces(player));
+            }
+        }
+
+        let mut hash = past_hashes.iter().fold(0, |hash, place| hash ^ place);
+
+        let current_player = match terms.next().unwrap_or("w") {
+            "w" => Player::White,
+            "b" => Player::Black,
+            player => return Err(FenError::InvalidPlayer(player.to_string())),
         };
 
         if current_player == Player::Black {
@@ -155,55 +281,161 @@ impl Game {
 
         let mut state = GameState::default();
 
-        let Some(castling_rights) = terms.next() else {
-            bail!("Missing castling rights");
-        };
+        let mut king_start_cols = [4; 2];
+        let mut rook_start_cols = [[7, 0]; 2];
 
-        for right in castling_rights.chars() {
-            match right {
-                'K' => state.set_white_king_castling_true(),
-                'Q' => state.set_white_queen_castling_true(),
-                'k' => state.set_black_king_castling_true(),
-                'q' => state.set_black_queen_castling_true(),
-                '-' => continue,
-                _ => bail!("Invalid castling right"),
+        // Both the standard letters and the files of the rooks (Shredder-FEN) are accepted,
+        // the standard letters stand for the outermost rook on their side (X-FEN)
+        for right in terms.next().unwrap_or("-").chars() {
+            if right == '-' {
+                continue;
             }
-        }
 
-        let Some(en_passant) = terms.next() else {
-            bail!("Missing en passant");
-        };
+            let (player, king) = if right.is_ascii_uppercase() {
+                (Player::White, white_king_pos)
+            } else {
+                (Player::Black, black_king_pos)
+            };
+            let row = player.first_row();
+
+            let is_rook = |&col: &i8| {
+                board[Position::new_assert(row, col).as_usize()]
+                    == Some(Piece {
+                        piece_type: PieceType::Rook,
+                        owner: player,
+                    })
+            };
+
+            let (side, rook_col) = match right.to_ascii_lowercase() {
+                'k' => (CastlingSide::Short, (king.col() + 1..8).rev().find(is_rook)),
+                'q' => (CastlingSide::Long, (0..king.col()).find(is_rook)),
+                file @ 'a'..='h' => {
+                    let col = (file as u8 - b'a') as i8;
+                    let side = if col > king.col() {
+                        CastlingSide::Short
+                    } else {
+                        CastlingSide::Long
+                    };
+                    (side, Some(col).filter(is_rook))
+                }
+                _ => return Err(FenError::InvalidCastlingRight(right)),
+            };
 
+            let Some(rook_col) = rook_col.filter(|_| king.row() == row) else {
+                return Err(FenError::MissingCastlingPieces(right));
+            };
+
+            king_start_cols[player.as_index()] = king.col();
+            rook_start_cols[player.as_index()][side as usize] = rook_col;
+            state.set_castling(player, side);
+        }
+
+        let en_passant = terms.next().unwrap_or("-");
         if en_passant != "-" {
-            let col = en_passant.chars().nth(0).unwrap();
-            state.set_en_passant(((col as u8) - b'a') as i8);
-            if !(0..8).contains(&state.en_passant()) {
-                bail!("Invalid en passant square");
+            let invalid_en_passant = || FenError::InvalidEnPassant(en_passant.to_string());
+
+            // The target is the square the pawn skipped, behind the pawn as seen by the
+            // player to move, and both it and the square the pawn came from must be empty
+            let mut chars = en_passant.bytes();
+            let (Some(col), Some(row), None) = (chars.next(), chars.next(), chars.next()) else {
+                return Err(invalid_en_passant());
+            };
+            let col = col.wrapping_sub(b'a') as i8;
+            let target_row = match current_player {
+                Player::White => 5,
+                Player::Black => 2,
+            };
+            let Some(target) = Position::new(row.wrapping_sub(b'1') as i8, col)
+                .filter(|target| target.row() == target_row)
+            else {
+                return Err(invalid_en_passant());
+            };
+            let pawn = Position::new_assert(target.row() - current_player as i8, col);
+            let origin = Position::new_assert(target.row() + current_player as i8, col);
+
+            if board[pawn.as_usize()]
+                != Some(Piece {
+                    piece_type: PieceType::Pawn,
+                    owner: current_player.the_other(),
+                })
+                || board[target.as_usize()].is_some()
+                || board[origin.as_usize()].is_some()
+            {
+                return Err(invalid_en_passant());
+            }
+
+            // As in push, the square is only kept if a pawn is there to capture, which keeps
+            // the hash the same as when the position is reached by playing the moves
+            let can_capture = [-1, 1].into_iter().any(|delta| {
+                pawn.add((0, delta)).is_some_and(|position| {
+                    board[position.as_usize()]
+                        == Some(Piece {
+                            piece_type: PieceType::Pawn,
+                            owner: current_player,
+                        })
+                })
+            });
+            if can_capture {
+                state.set_en_passant(col);
             }
         }
 
-        let Some(white_king_pos) = white_king_pos else {
-            bail!("White king not found");
-        };
+        if let Some(halfmove_clock) = terms.next() {
+            let halfmove_clock = halfmove_clock
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(halfmove_clock.to_string()))?;
+            state.set_halfmove_clock(halfmove_clock);
+        }
 
-        let Some(black_king_pos) = black_king_pos else {
-            bail!("Black king not found");
+        let fullmove_number = match terms.next() {
+            // Far more than any game lasts, and small enough to count plies from it
+            Some(fullmove_number) => fullmove_number
+                .parse::<u16>()
+                .ok()
+                .filter(|&number| number >= 1)
+                .ok_or_else(|| FenError::InvalidFullmoveNumber(fullmove_number.to_string()))?
+                as usize,
+            None => 1,
         };
+        let initial_ply = 2 * (fullmove_number - 1) + (current_player == Player::Black) as usize;
 
         let mut game = Self {
             board,
             move_stack: Vec::with_capacity(1000),
             king_positions: [white_king_pos, black_king_pos],
+            king_start_cols,
+            rook_start_cols,
+            chess960: false,
+            initial_ply,
             current_player,
             score,
             hash,
-            state: ArrayVec::new(),
+            material,
+            piece_counts,
+            occupied,
+            pieces: piece_bitboards,
+            state: Vec::with_capacity(1000),
             past_scores,
             past_hashes,
-            piece_scores,
+            hash_history: Vec::with_capacity(1000),
+            params,
+            #[cfg(feature = "verify-incremental")]
+            snapshots: Vec::new(),
             phase: GamePhase::Opening,
         };
 
+        let opponent = current_player.the_other();
+        if game.is_targeted(game.get_king_position(opponent), opponent) {
+            return Err(FenError::OpponentInCheck);
+        }
+
+        // Every move uncovers at most one slider besides checking with the moved piece
+        let checkers = game.compute_checkers();
+        if checkers.count_ones() > 2 {
+            return Err(FenError::TooManyCheckers);
+        }
+
+        state.set_checkers(checkers);
         game.state.push(state);
         game.hash ^= state.hash();
         game.update_phase();
@@ -211,14 +443,46 @@ impl Game {
         Ok(game)
     }
 
+    /// Positions from the one read from the FEN to the current one, counted from 1
+    // There is always the state of the first position
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
         self.state.len()
     }
 
+    /// Starts at 1 and is incremented after every move of black, as in the FEN
+    pub fn fullmove_number(&self) -> usize {
+        (self.initial_ply + self.len() - 1) / 2 + 1
+    }
+
+    /// Zobrist hash of the position, which is also its key in the transposition table
     pub fn hash(&self) -> u64 {
         self.hash
     }
 
+    /// Whether the current position occurred before, with the same player to move
+    pub fn is_repetition(&self) -> bool {
+        // Positions before the last capture or pawn move can't occur again
+        self.hash_history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock() as usize)
+            .skip(1)
+            .step_by(2)
+            .any(|&hash| hash == self.hash)
+    }
+
+    /// Half-moves since the last capture or pawn move
+    pub fn halfmove_clock(&self) -> u16 {
+        self.state().halfmove_clock()
+    }
+
+    /// Whether the game is drawn by the fifty-move rule
+    pub fn is_fifty_move_draw(&self) -> bool {
+        self.halfmove_clock() >= FIFTY_MOVE_LIMIT
+    }
+
+    /// The player to move
     pub fn player(&self) -> Player {
         self.current_player
     }
@@ -227,37 +491,97 @@ impl Game {
         self.score
     }
 
-    pub fn move_stack(&self) -> &[Move] {
-        &self.move_stack
+    /// The phase only moves forward, once the endgame begins it stays there,
+    /// even if the moves leading to it are taken back
+    pub fn phase(&self) -> GamePhase {
+        self.phase
+    }
+
+    /// Value of the pieces of `player`, without the king
+    pub fn material(&self, player: Player) -> Score {
+        self.material[player.as_index()]
+    }
+
+    /// Value of the pieces of `player`, without the pawns and the king
+    pub fn non_pawn_material(&self, player: Player) -> Score {
+        self.material(player)
+            - self.piece_count(player, PieceType::Pawn) as Score
+                * PIECE_VALUES[PieceType::Pawn as usize]
+    }
+
+    /// Number of pieces of the given type owned by `player`
+    pub fn piece_count(&self, player: Player, piece_type: PieceType) -> u8 {
+        self.piece_counts[player.as_index()][piece_type as usize]
     }
 
     pub fn get_position(&self, position: Position) -> Option<Piece> {
         // SAFETY: position is always valid
-        unsafe { *self.board.get_unchecked(position.as_usize()) }
+        unsafe { *unchecked::get(&self.board, position.as_usize()) }
+    }
+
+    /// Squares occupied by a piece of either player
+    #[inline]
+    pub fn occupied(&self) -> Bitboard {
+        self.occupied[0] | self.occupied[1]
+    }
+
+    /// Squares occupied by the pieces of `player`
+    #[inline]
+    pub fn occupied_by(&self, player: Player) -> Bitboard {
+        self.occupied[player.as_index()]
+    }
+
+    /// Squares occupied by a piece of `piece_type` of either player
+    #[inline]
+    pub fn pieces(&self, piece_type: PieceType) -> Bitboard {
+        self.pieces[piece_type as usize]
+    }
+
+    /// Squares occupied by a piece of `piece_type` owned by `player`
+    #[inline]
+    pub fn pieces_of(&self, player: Player, piece_type: PieceType) -> Bitboard {
+        self.pieces(piece_type) & self.occupied_by(player)
     }
 
     pub fn state(&self) -> GameState {
         // SAFETY: There should always be a valid state
-        unsafe { *self.state.last().unwrap_unchecked() }
+        unsafe { *unchecked::unwrap(self.state.last()) }
     }
 
     fn set_position(&mut self, position: Position, new_place: Option<Piece>) {
         // SAFETY: position is always valid
         let (place, place_score, place_hash) = unsafe {
             (
-                self.board.get_unchecked_mut(position.as_usize()),
-                self.past_scores.get_unchecked_mut(position.as_usize()),
-                self.past_hashes.get_unchecked_mut(position.as_usize()),
+                unchecked::get_mut(&mut self.board, position.as_usize()),
+                unchecked::get_mut(&mut self.past_scores, position.as_usize()),
+                unchecked::get_mut(&mut self.past_hashes, position.as_usize()),
             )
         };
 
         self.hash ^= *place_hash;
         self.score -= *place_score;
 
+        if let Some(piece) = *place {
+            self.piece_counts[piece.owner.as_index()][piece.piece_type as usize] -= 1;
+            self.occupied[piece.owner.as_index()] ^= bitboard::bit(position);
+            self.pieces[piece.piece_type as usize] ^= bitboard::bit(position);
+            if piece.piece_type != PieceType::King {
+                self.material[piece.owner.as_index()] -= PIECE_VALUES[piece.piece_type as usize];
+            }
+        }
+        if let Some(piece) = new_place {
+            self.piece_counts[piece.owner.as_index()][piece.piece_type as usize] += 1;
+            self.occupied[piece.owner.as_index()] ^= bitboard::bit(position);
+            self.pieces[piece.piece_type as usize] ^= bitboard::bit(position);
+            if piece.piece_type != PieceType::King {
+                self.material[piece.owner.as_index()] += PIECE_VALUES[piece.piece_type as usize];
+            }
+        }
+
         *place = new_place;
 
         *place_score = place
-            .map(|piece| piece.score(position, &self.piece_scores))
+            .map(|piece| piece.score(position, &self.params, self.phase))
             .unwrap_or(0);
         *place_hash = place
             .map(|piece| piece.hash(position))
@@ -281,64 +605,53 @@ impl Game {
         }
     }
 
+    /// Plays a move of the game, which is kept for the PGN and may change the phase
+    ///
+    /// The search uses `push` instead, which only makes the move.
     pub fn push_history(&mut self, _move: Move) {
         self.move_stack.push(_move);
         self.update_phase();
         self.push(_move);
+
+        // Moves played in the game are few enough to check the hash in every debug build,
+        // unlike the ones tried by the search
+        debug_assert_eq!(self.hash, self.compute_hash(), "Incremental hash mismatch");
     }
 
+    /// Makes a legal move, which `pop` undoes
     pub fn push(&mut self, _move: Move) {
+        #[cfg(feature = "verify-incremental")]
+        self.snapshots.push(snapshot::Snapshot::of(self));
+        self.hash_history.push(self.hash);
         let mut state = self.state();
         state.set_en_passant(8);
-        match _move {
-            Move::Normal {
-                piece,
-                start,
-                end,
-                captured_piece,
-            } => {
+        if _move.resets_halfmove_clock(self) {
+            state.set_halfmove_clock(0);
+        } else {
+            state.set_halfmove_clock(state.halfmove_clock() + 1);
+        }
+
+        let player = self.current_player;
+        let (start, end) = (_move.start(), _move.end());
+        let captured_piece = _move.captured_piece(self);
+        state.set_last_move(Some(_move));
+        state.set_captured_piece(captured_piece);
+
+        match _move.kind() {
+            MoveKind::Normal => {
+                let piece = _move.piece(self);
                 self.set_position(start, None);
                 self.set_position(end, Some(piece));
 
                 if piece.piece_type == PieceType::King {
-                    self.set_king_position(self.current_player, end);
-                    match self.current_player {
-                        Player::White => {
-                            state.set_white_king_castling_false();
-                            state.set_white_queen_castling_false();
-                        }
-                        Player::Black => {
-                            state.set_black_king_castling_false();
-                            state.set_black_queen_castling_false();
-                        }
-                    }
+                    self.set_king_position(player, end);
+                    state.clear_all_castling(player);
                 } else if piece.piece_type == PieceType::Rook {
-                    match start {
-                        Position::WHITE_QUEEN_ROOK => state.set_white_queen_castling_false(),
-                        Position::WHITE_KING_ROOK => state.set_white_king_castling_false(),
-                        Position::BLACK_QUEEN_ROOK => state.set_black_queen_castling_false(),
-                        Position::BLACK_KING_ROOK => state.set_black_king_castling_false(),
-                        _ => (),
-                    }
+                    self.clear_rook_castling(&mut state, start);
                 }
 
-                if captured_piece.is_some_and(|piece| {
-                    piece.piece_type == PieceType::Rook && piece.owner == Player::White
-                }) {
-                    match end {
-                        Position::WHITE_QUEEN_ROOK => state.set_white_queen_castling_false(),
-                        Position::WHITE_KING_ROOK => state.set_white_king_castling_false(),
-                        _ => (),
-                    }
-                }
-                if captured_piece.is_some_and(|piece| {
-                    piece.piece_type == PieceType::Rook && piece.owner == Player::Black
-                }) {
-                    match end {
-                        Position::BLACK_QUEEN_ROOK => state.set_black_queen_castling_false(),
-                        Position::BLACK_KING_ROOK => state.set_black_king_castling_false(),
-                        _ => (),
-                    }
+                if captured_piece.is_some() {
+                    self.clear_rook_castling(&mut state, end);
                 }
                 if piece.piece_type == PieceType::Pawn && i8::abs(end.row() - start.row()) == 2 {
                     // Check if there are enemy pawns that could capture en passant
@@ -365,162 +678,100 @@ impl Game {
                     }
                 }
             }
-            Move::Promotion {
-                owner,
-                start,
-                end,
-                new_piece,
-                captured_piece,
-            } => {
+            MoveKind::Promotion(new_piece) => {
                 self.set_position(start, None);
                 self.set_position(
                     end,
                     Some(Piece {
-                        owner,
+                        owner: player,
                         piece_type: new_piece,
                     }),
                 );
 
-                if captured_piece.is_some_and(|piece| {
-                    piece.piece_type == PieceType::Rook && piece.owner == Player::White
-                }) {
-                    match end {
-                        Position::WHITE_QUEEN_ROOK => state.set_white_queen_castling_false(),
-                        Position::WHITE_KING_ROOK => state.set_white_king_castling_false(),
-                        _ => (),
-                    }
-                }
-                if captured_piece.is_some_and(|piece| {
-                    piece.piece_type == PieceType::Rook && piece.owner == Player::Black
-                }) {
-                    match end {
-                        Position::BLACK_QUEEN_ROOK => state.set_black_queen_castling_false(),
-                        Position::BLACK_KING_ROOK => state.set_black_king_castling_false(),
-                        _ => (),
-                    }
+                if captured_piece.is_some() {
+                    self.clear_rook_castling(&mut state, end);
                 }
             }
-            Move::EnPassant {
-                owner,
-                start_col,
-                end_col,
-            } => {
-                let (old_pawn, new_pawn, taken_pawn) = match owner {
-                    Player::White => (
-                        Position::new_assert(4, start_col),
-                        Position::new_assert(5, end_col),
-                        Position::new_assert(4, end_col),
-                    ),
-                    Player::Black => (
-                        Position::new_assert(3, start_col),
-                        Position::new_assert(2, end_col),
-                        Position::new_assert(3, end_col),
-                    ),
-                };
-                self.set_position(taken_pawn, None);
-                self.set_position(old_pawn, None);
+            MoveKind::EnPassant => {
+                // The taken pawn is next to the starting square, on the file of the destination
+                self.set_position(Position::new_assert(start.row(), end.col()), None);
+                self.set_position(start, None);
                 self.set_position(
-                    new_pawn,
+                    end,
                     Some(Piece {
                         piece_type: PieceType::Pawn,
-                        owner,
+                        owner: player,
                     }),
                 );
             }
-            Move::CastlingLong { owner } => {
-                let row = match owner {
-                    Player::White => 0,
-                    Player::Black => 7,
-                };
-                let (old_king, new_king, old_rook, new_rook) = (
-                    Position::new_assert(row, 4),
-                    Position::new_assert(row, 2),
-                    Position::new_assert(row, 0),
-                    Position::new_assert(row, 3),
-                );
-
-                self.set_position(old_rook, None);
-                self.set_position(old_king, None);
-                self.set_position(
-                    new_rook,
-                    Some(Piece {
-                        piece_type: PieceType::Rook,
-                        owner,
-                    }),
-                );
-
-                self.set_position(
-                    new_king,
-                    Some(Piece {
-                        piece_type: PieceType::King,
-                        owner,
-                    }),
-                );
-                self.set_king_position(self.current_player, new_king);
-                match self.current_player {
-                    Player::White => {
-                        state.set_white_king_castling_false();
-                        state.set_white_queen_castling_false();
-                    }
-                    Player::Black => {
-                        state.set_black_king_castling_false();
-                        state.set_black_queen_castling_false();
-                    }
-                }
+            MoveKind::Castling(side) => {
+                self.push_castling(player, side);
+                state.clear_all_castling(player);
             }
-            Move::CastlingShort { owner } => {
-                let row = match owner {
-                    Player::White => 0,
-                    Player::Black => 7,
-                };
-                let (old_king, new_king, old_rook, new_rook) = (
-                    Position::new_assert(row, 4),
-                    Position::new_assert(row, 6),
-                    Position::new_assert(row, 7),
-                    Position::new_assert(row, 5),
-                );
+        };
+        self.current_player = self.current_player.the_other();
+        state.set_checkers(self.compute_checkers());
+        self.hash ^= zobrist::BLACK_TO_MOVE;
+        self.hash ^= self.state().hash();
+        self.state.push(state);
+        self.hash ^= self.state().hash();
 
-                self.set_position(old_rook, None);
-                self.set_position(old_king, None);
-                self.set_position(
-                    new_rook,
-                    Some(Piece {
-                        piece_type: PieceType::Rook,
-                        owner,
-                    }),
-                );
+        #[cfg(feature = "verify-incremental")]
+        self.verify_incremental_state();
+    }
 
-                self.set_position(
-                    new_king,
-                    Some(Piece {
-                        piece_type: PieceType::King,
-                        owner,
-                    }),
-                );
+    /// Passes the turn to the opponent without moving, as if the player could skip a move
+    ///
+    /// The halfmove clock is reset, positions from before a null move aren't repetitions.
+    pub fn push_null(&mut self) {
+        #[cfg(feature = "verify-incremental")]
+        self.snapshots.push(snapshot::Snapshot::of(self));
+        self.hash_history.push(self.hash);
+        let mut state = self.state();
+        state.set_en_passant(8);
+        state.set_halfmove_clock(0);
+        // Null moves are only made out of check, so the opponent can't be in check either
+        state.set_checkers(0);
+        state.set_last_move(None);
+        state.set_captured_piece(None);
 
-                self.set_king_position(self.current_player, new_king);
-                match self.current_player {
-                    Player::White => {
-                        state.set_white_king_castling_false();
-                        state.set_white_queen_castling_false();
-                    }
-                    Player::Black => {
-                        state.set_black_king_castling_false();
-                        state.set_black_queen_castling_false();
-                    }
-                }
-            }
-        };
         self.current_player = self.current_player.the_other();
         self.hash ^= zobrist::BLACK_TO_MOVE;
-        self.hash ^= self.state().hash(); // SAFETY: The game will not be longer than 512 moves
-        unsafe {
-            self.state.push_unchecked(state);
-        }
         self.hash ^= self.state().hash();
+        self.state.push(state);
+        self.hash ^= self.state().hash();
+
+        #[cfg(feature = "verify-incremental")]
+        self.verify_incremental_state();
+    }
+
+    /// Undoes `push_null`
+    pub fn pop_null(&mut self) {
+        debug_assert!(
+            self.state().last_move().is_none(),
+            "The last move isn't a null move"
+        );
+        self.hash_history.pop();
+        self.hash ^= self.state().hash();
+        self.state.truncate(self.len().saturating_sub(1));
+        self.hash ^= self.state().hash();
+        self.hash ^= zobrist::BLACK_TO_MOVE;
+        self.current_player = self.current_player.the_other();
+
+        #[cfg(feature = "verify-incremental")]
+        {
+            let snapshot = self.snapshots.pop().expect("No null move to undo");
+            snapshot.assert_restored(self, "Null move not undone");
+            self.verify_incremental_state();
+        }
     }
 
-    pub fn pop(&mut self, _move: Move) {
+    /// Undoes the last move made with `push`, which is kept along with what it changed
+    pub fn pop(&mut self) {
+        let state = self.state();
+        let _move = state.last_move().expect("No move to undo");
+        let captured_piece = state.captured_piece();
+        self.hash_history.pop();
         self.hash ^= self.state().hash();
         // self.state.pop() without verification for being empty
         self.state.truncate(self.len().saturating_sub(1));
@@ -528,374 +779,513 @@ impl Game {
         self.hash ^= zobrist::BLACK_TO_MOVE;
         self.current_player = self.current_player.the_other();
 
-        match _move {
-            Move::Normal {
-                piece,
-                start,
-                end,
-                captured_piece,
-            } => {
+        let player = self.current_player;
+        let (start, end) = (_move.start(), _move.end());
+
+        match _move.kind() {
+            MoveKind::Normal => {
+                // SAFETY: The move was played, so its piece is on the destination
+                let piece = unsafe { unchecked::unwrap(self.get_position(end)) };
                 self.set_position(start, Some(piece));
                 self.set_position(end, captured_piece);
 
                 if piece.piece_type == PieceType::King {
-                    self.set_king_position(self.current_player, start);
+                    self.set_king_position(player, start);
                 }
             }
-            Move::Promotion {
-                owner,
-                start,
-                end,
-                captured_piece,
-                ..
-            } => {
+            MoveKind::Promotion(_) => {
                 self.set_position(
                     start,
                     Some(Piece {
                         piece_type: PieceType::Pawn,
-                        owner,
+                        owner: player,
                     }),
                 );
                 self.set_position(end, captured_piece);
             }
-            Move::EnPassant {
-                owner,
-                start_col,
-                end_col,
-            } => {
-                let (old_pawn, new_pawn, taken_pawn) = match owner {
-                    Player::White => (
-                        Position::new_assert(4, start_col),
-                        Position::new_assert(5, end_col),
-                        Position::new_assert(4, end_col),
-                    ),
-                    Player::Black => (
-                        Position::new_assert(3, start_col),
-                        Position::new_assert(2, end_col),
-                        Position::new_assert(3, end_col),
-                    ),
-                };
-
-                self.set_position(new_pawn, None);
-                self.set_position(
-                    taken_pawn,
-                    Some(Piece {
-                        piece_type: PieceType::Pawn,
-                        owner: owner.the_other(),
-                    }),
-                );
+            MoveKind::EnPassant => {
+                self.set_position(end, None);
+                self.set_position(Position::new_assert(start.row(), end.col()), captured_piece);
                 self.set_position(
-                    old_pawn,
+                    start,
                     Some(Piece {
                         piece_type: PieceType::Pawn,
-                        owner,
+                        owner: player,
                     }),
                 );
             }
-            Move::CastlingLong { owner } => {
-                let row = match owner {
-                    Player::White => 0,
-                    Player::Black => 7,
-                };
-                let (old_king, new_king, old_rook, new_rook) = (
-                    Position::new_assert(row, 4),
-                    Position::new_assert(row, 2),
-                    Position::new_assert(row, 0),
-                    Position::new_assert(row, 3),
-                );
+            MoveKind::Castling(side) => self.pop_castling(player, side),
+        };
 
-                self.set_position(new_rook, None);
-                self.set_position(new_king, None);
-                self.set_position(
-                    old_rook,
-                    Some(Piece {
-                        piece_type: PieceType::Rook,
-                        owner,
-                    }),
-                );
+        // The position is the same as before the move, not only consistent with itself
+        #[cfg(feature = "verify-incremental")]
+        {
+            let snapshot = self.snapshots.pop().expect("No move to undo");
+            snapshot.assert_restored(self, "Move not undone");
+            self.verify_incremental_state();
+        }
+    }
 
-                self.set_position(
-                    old_king,
-                    Some(Piece {
-                        piece_type: PieceType::King,
-                        owner,
-                    }),
-                );
+    /// Squares of the king and the rook before and after castling, in this order
+    ///
+    /// In Chess960 some of them may be the same, so both pieces are removed before
+    /// either of them is placed again.
+    pub fn castling_squares(
+        &self,
+        player: Player,
+        side: CastlingSide,
+    ) -> (Position, Position, Position, Position) {
+        let row = player.first_row();
+        let (king_col, rook_col) = match side {
+            CastlingSide::Short => (6, 5),
+            CastlingSide::Long => (2, 3),
+        };
 
-                self.set_king_position(owner, old_king);
-            }
-            Move::CastlingShort { owner } => {
-                let row = match owner {
-                    Player::White => 0,
-                    Player::Black => 7,
-                };
-                let (old_king, new_king, old_rook, new_rook) = (
-                    Position::new_assert(row, 4),
-                    Position::new_assert(row, 6),
-                    Position::new_assert(row, 7),
-                    Position::new_assert(row, 5),
-                );
+        (
+            Position::new_assert(row, self.king_start_cols[player.as_index()]),
+            Position::new_assert(row, king_col),
+            Position::new_assert(row, self.rook_start_cols[player.as_index()][side as usize]),
+            Position::new_assert(row, rook_col),
+        )
+    }
 
-                self.set_position(new_rook, None);
-                self.set_position(new_king, None);
-                self.set_position(
-                    old_rook,
-                    Some(Piece {
-                        piece_type: PieceType::Rook,
-                        owner,
-                    }),
-                );
+    fn push_castling(&mut self, owner: Player, side: CastlingSide) {
+        let (old_king, new_king, old_rook, new_rook) = self.castling_squares(owner, side);
 
-                self.set_position(
-                    old_king,
-                    Some(Piece {
-                        piece_type: PieceType::King,
-                        owner,
-                    }),
-                );
+        self.set_position(old_rook, None);
+        self.set_position(old_king, None);
+        self.set_position(
+            new_rook,
+            Some(Piece {
+                piece_type: PieceType::Rook,
+                owner,
+            }),
+        );
+        self.set_position(
+            new_king,
+            Some(Piece {
+                piece_type: PieceType::King,
+                owner,
+            }),
+        );
 
-                self.set_king_position(owner, old_king);
-            }
-        };
+        self.set_king_position(owner, new_king);
     }
 
-    fn is_endgame(&self) -> bool {
-        let mut total_piece_score: u32 = 0;
+    fn pop_castling(&mut self, owner: Player, side: CastlingSide) {
+        let (old_king, new_king, old_rook, new_rook) = self.castling_squares(owner, side);
 
-        for row in 0..8 {
-            for col in 0..8 {
-                let position = Position::new_assert(row, col);
-                if let Some(piece) = self.get_position(position) {
-                    total_piece_score +=
-                        piece.score(position, &self.piece_scores).unsigned_abs() as u32;
+        self.set_position(new_rook, None);
+        self.set_position(new_king, None);
+        self.set_position(
+            old_rook,
+            Some(Piece {
+                piece_type: PieceType::Rook,
+                owner,
+            }),
+        );
+        self.set_position(
+            old_king,
+            Some(Piece {
+                piece_type: PieceType::King,
+                owner,
+            }),
+        );
+
+        self.set_king_position(owner, old_king);
+    }
+
+    /// Castling rights are lost once the rook moves or is captured, `position` is the square
+    /// the rook leaves or the square of the captured piece
+    fn clear_rook_castling(&self, state: &mut GameState, position: Position) {
+        for player in [Player::White, Player::Black] {
+            if position.row() != player.first_row() {
+                continue;
+            }
+            for side in CastlingSide::ALL {
+                if self.rook_start_cols[player.as_index()][side as usize] == position.col() {
+                    state.clear_castling(player, side);
                 }
             }
         }
-
-        total_piece_score < 2 * ENDGAME_THRESHOLD // because we are counting both sides
     }
 
-    pub fn update_phase(&mut self) {
-        if self.is_endgame() {
-            self.piece_scores[PieceType::King as usize].set(&scores::KING_SCORES_END);
-            self.phase = GamePhase::Endgame;
-        }
+    /// Castling moves are written in UCI as the king taking its own rook
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.chess960 = chess960;
     }
 
-    pub fn king_exists(&self, player: Player) -> bool {
-        self.get_position(self.get_king_position(player))
-            .is_some_and(|piece| piece.piece_type == PieceType::King)
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
     }
 
-    /// `moves` will be cleared by this function to be sure it has room for all moves
-    pub fn get_moves(&mut self, moves: &mut ArrayVec<Move, 256>, verify_king: bool) {
-        moves.clear();
-        if !self.king_exists(self.current_player) {
-            // no available moves;
-            return;
-        }
+    /// Zobrist hash of the position computed from scratch: the pieces on every square,
+    /// the castling rights and en passant file, and the player to move
+    fn compute_hash(&self) -> u64 {
+        let mut hash = self.state().hash();
 
-        let mut push = |_move| {
-            // SAFETY: The number of possible moves on the board at any given time
-            // will never exceed the arrays capacity (256)
-            unsafe {
-                moves.push_unchecked(_move);
-            }
-        };
+        if self.current_player == Player::Black {
+            hash ^= zobrist::BLACK_TO_MOVE;
+        }
 
         for row in 0..8 {
             for col in 0..8 {
-                let pos = Position::new_assert(row, col);
-                if let Some(piece) = self.get_position(pos) {
-                    if piece.owner == self.current_player {
-                        piece.get_moves(&mut push, self, pos);
-                    }
-                }
+                let position = Position::new_assert(row, col);
+                hash ^= match self.get_position(position) {
+                    Some(piece) => piece.hash(position),
+                    None => zobrist::EMPTY_PLACE,
+                };
             }
         }
 
-        // If verify_king then remove moves which put the king in check (invalid moves)
-        // We remove invalid moves by overwriting them with the following valid moves
-        if verify_king {
-            let player = self.current_player;
-            let king_position = self.get_king_position(player);
-            let is_king_targeted = self.is_targeted(king_position, player);
-            let mut keep_index = 0;
-            for index in 0..moves.len() {
-                let _move = moves[index];
-
-                if !is_king_targeted {
-                    if let Move::Normal { start, .. } = _move {
-                        let delta_col = start.col() - king_position.col();
-                        let delta_row = start.row() - king_position.row();
-                        if delta_col != 0 && delta_row != 0 && delta_col.abs() != delta_row.abs() {
-                            moves[keep_index] = _move;
-                            keep_index += 1;
-                            continue;
-                        }
-                    }
-                }
+        hash
+    }
+
+    /// Recomputes everything push and pop update incrementally and panics on any difference
+    ///
+    /// It scans the whole board, so it is only done after every move with the
+    /// `verify-incremental` feature, which slows down the engine a lot, and by the fuzzing
+    /// harness
+    #[cfg(any(test, feature = "verify-incremental", feature = "fuzzing"))]
+    pub fn verify_incremental_state(&self) {
+        let mut score = 0;
+        let mut material = [0; 2];
+        let mut piece_counts = [[0; 6]; 2];
+        let mut occupied = [0; 2];
+        let mut pieces = [0; 6];
 
-                self.push(_move);
-                let condition = !self.is_targeted(self.get_king_position(player), player);
-                self.pop(_move);
-                if condition {
-                    moves[keep_index] = _move;
-                    keep_index += 1;
+        for row in 0..8 {
+            for col in 0..8 {
+                let position = Position::new_assert(row, col);
+                if let Some(piece) = self.get_position(position) {
+                    score += piece.score(position, &self.params, self.phase);
+                    piece_counts[piece.owner.as_index()][piece.piece_type as usize] += 1;
+                    occupied[piece.owner.as_index()] |= bitboard::bit(position);
+                    pieces[piece.piece_type as usize] |= bitboard::bit(position);
+                    if piece.piece_type != PieceType::King {
+                        material[piece.owner.as_index()] += PIECE_VALUES[piece.piece_type as usize];
+                    } else {
+                        assert_eq!(self.get_king_position(piece.owner), position);
+                    }
                 }
             }
-
-            moves.truncate(keep_index);
         }
+
+        assert_eq!(self.score, score, "Incremental score mismatch");
+        assert_eq!(self.hash, self.compute_hash(), "Incremental hash mismatch");
+        assert_eq!(self.material, material, "Incremental material mismatch");
+        assert_eq!(
+            self.piece_counts, piece_counts,
+            "Incremental piece counts mismatch"
+        );
+        assert_eq!(self.occupied, occupied, "Incremental occupancy mismatch");
+        assert_eq!(self.pieces, pieces, "Incremental piece bitboards mismatch");
+        assert_eq!(
+            self.checkers(),
+            self.compute_checkers(),
+            "Incremental checkers mismatch"
+        );
     }
 
-    /// Returns if player's position is targeted by enemy pieces
+    fn is_endgame(&self) -> bool {
+        // Both sides are counted
+        self.material(Player::White) + self.material(Player::Black) < 2 * ENDGAME_THRESHOLD
+    }
+
+    pub fn update_phase(&mut self) {
+        if self.phase != GamePhase::Endgame && self.is_endgame() {
+            self.phase = GamePhase::Endgame;
+
+            // The kings are scored again with the new table
+            for player in [Player::White, Player::Black] {
+                let position = self.get_king_position(player);
+                self.set_position(position, self.get_position(position));
+            }
+        }
+    }
+
+    /// The legal moves of the player to move, empty when the game ended by checkmate
+    /// or stalemate
     ///
-    /// This function is ONLY used for testing castling rights and if a king is in check
+    /// The moves are grouped by the square of the moving piece, from a1 to h8, and the same
+    /// position always gives them in the same order. Nothing else is guaranteed about it:
+    /// the order has nothing to do with how good the moves are, the search sorts them itself.
+    pub fn legal_moves(&self) -> ArrayVec<Move, MAX_MOVES> {
+        let mut moves = ArrayVec::new();
+        self.get_moves(&mut moves);
+        moves
+    }
+
+    /// Same as `legal_moves`, filling a list owned by the caller, which the search reuses
     ///
-    /// Thus I considered it unnecessary to verify if the square is targeted by a king,
-    /// since I already verify that moves don't put kings near each other and a king blocking
-    /// a castling move is so unlikely I don't want to waste time on it.
-    pub fn is_targeted(&self, position: Position, player: Player) -> bool {
-        // Verifiy for kings
-        for delta in [
-            (1, 0),
-            (0, 1),
-            (-1, 0),
-            (0, -1),
-            (1, 1),
-            (-1, 1),
-            (1, -1),
-            (-1, -1),
-        ] {
-            if let Some(new_pos) = position.add(delta) {
-                if self.get_position(new_pos).is_some_and(|piece| {
-                    piece.owner != player && piece.piece_type == PieceType::King
-                }) {
-                    return true;
-                }
+    /// `moves` will be cleared by this function to be sure it has room for all moves.
+    /// Only legal moves are generated: when in check the other pieces may only capture
+    /// the checking piece or block it, pinned pieces stay on the line between their king
+    /// and the pinning piece, and the king doesn't move to attacked squares.
+    pub fn get_moves(&self, moves: &mut ArrayVec<Move, MAX_MOVES>) {
+        moves.clear();
+
+        let masks = self.legality_masks();
+
+        // Illegal moves are dropped right away, so the list never holds more than the
+        // legal moves, which are at most 218
+        let mut push = |_move| {
+            if self.is_legal_with(_move, &masks) {
+                moves.push(_move);
             }
+        };
+
+        let player = self.current_player;
+        for position in bitboard::positions(self.occupied_by(player)) {
+            // SAFETY: The occupancy matches the board
+            let piece = unsafe { unchecked::unwrap(self.get_position(position)) };
+            piece.get_moves(&mut push, self, position);
         }
+    }
 
-        // Verify for knights
-        for delta in [
-            (1, 2),
-            (2, 1),
-            (-1, -2),
-            (-2, -1),
-            (1, -2),
-            (-2, 1),
-            (-1, 2),
-            (2, -1),
-        ] {
-            if let Some(new_pos) = position.add(delta) {
-                if self.get_position(new_pos).is_some_and(|piece| {
-                    piece.owner != player && piece.piece_type == PieceType::Knight
-                }) {
-                    return true;
-                }
-            }
+    /// Whether a move, which may come from outside like the transposition table or UCI,
+    /// can be played in this position
+    ///
+    /// Only the moves of the piece on the starting square are generated.
+    pub fn is_legal(&self, _move: Move) -> bool {
+        let player = self.current_player;
+        let start = _move.start();
+
+        let Some(piece) = self.get_position(start) else {
+            return false;
+        };
+        if piece.owner != player {
+            return false;
         }
 
-        // Verify for pawns
-        match player {
-            Player::White => {
-                if let Some(new_pos) = position.add((1, 1)) {
-                    if self.get_position(new_pos).is_some_and(|piece| {
-                        piece.owner != player && piece.piece_type == PieceType::Pawn
-                    }) {
-                        return true;
-                    }
-                }
-                if let Some(new_pos) = position.add((1, -1)) {
-                    if self.get_position(new_pos).is_some_and(|piece| {
-                        piece.owner != player && piece.piece_type == PieceType::Pawn
-                    }) {
-                        return true;
-                    }
-                }
+        let mut is_generated = false;
+        piece.get_moves(|generated| is_generated |= generated == _move, self, start);
+
+        is_generated && self.is_legal_with(_move, &self.legality_masks())
+    }
+
+    /// Whether a legal move puts the opponent in check, computed without making it
+    pub fn gives_check(&self, _move: Move) -> bool {
+        let player = self.current_player;
+        let king_position = self.get_king_position(player.the_other());
+
+        // Squares emptied by the move, and the piece which may attack the king from its new square
+        let (start, end) = (_move.start(), _move.end());
+        let (emptied, end, piece_type, king_end) = match _move.kind() {
+            MoveKind::Normal => (bitboard::bit(start), end, _move.piece(self).piece_type, 0),
+            MoveKind::Promotion(new_piece) => (bitboard::bit(start), end, new_piece, 0),
+            MoveKind::EnPassant => {
+                let taken = Position::new_assert(start.row(), end.col());
+                let emptied = bitboard::bit(start) | bitboard::bit(taken);
+                (emptied, end, PieceType::Pawn, 0)
             }
-            Player::Black => {
-                if let Some(new_pos) = position.add((-1, 1)) {
-                    if self.get_position(new_pos).is_some_and(|piece| {
-                        piece.owner != player && piece.piece_type == PieceType::Pawn
-                    }) {
-                        return true;
-                    }
-                }
-                if let Some(new_pos) = position.add((-1, -1)) {
-                    if self.get_position(new_pos).is_some_and(|piece| {
-                        piece.owner != player && piece.piece_type == PieceType::Pawn
-                    }) {
-                        return true;
-                    }
-                }
+            MoveKind::Castling(side) => {
+                let (old_king, new_king, old_rook, new_rook) = self.castling_squares(player, side);
+                let emptied = bitboard::bit(old_king) | bitboard::bit(old_rook);
+                (emptied, new_rook, PieceType::Rook, bitboard::bit(new_king))
             }
         };
+        let occupied = (self.occupied() & !emptied) | bitboard::bit(end) | king_end;
 
-        // Helpful macro
-        macro_rules! search_enemies_loops {
-            ( $piece_type1:expr, $piece_type2:expr, $( $x:expr ),* ) => {
-                $(
-                for delta in $x {
-                    if let Some(new_pos) = position.add(delta) {
-                        if let Some(piece) = self.get_position(new_pos)  {
-                            if piece.owner != player &&
-                                (piece.piece_type == $piece_type1 || piece.piece_type == $piece_type2) {
-                                return true
-                            }
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                )*
-            };
+        let direct = match piece_type {
+            PieceType::Pawn => bitboard::pawn_attacks(player, end),
+            PieceType::Knight => bitboard::knight_attacks(end),
+            PieceType::King => 0,
+            _ => bitboard::slider_attacks(piece_type, end, occupied),
+        };
+        if direct & bitboard::bit(king_position) != 0 {
+            return true;
         }
 
-        // Verify lines for rooks/queens
-        search_enemies_loops![
-            PieceType::Rook,
-            PieceType::Queen,
-            (1..).map(|x| (0, x)),
-            (1..).map(|x| (0, -x)),
-            (1..).map(|x| (x, 0)),
-            (1..).map(|x| (-x, 0))
-        ];
+        // Sliders behind the squares the move emptied
+        let own = self.occupied_by(player) & !emptied;
+        let queens = self.pieces(PieceType::Queen);
+        let rooks = (self.pieces(PieceType::Rook) | queens) & own;
+        let bishops = (self.pieces(PieceType::Bishop) | queens) & own;
+        (bitboard::rook_attacks(king_position, occupied) & rooks)
+            | (bitboard::bishop_attacks(king_position, occupied) & bishops)
+            != 0
+    }
 
-        // Verify diagonals for bishops/queens
-        search_enemies_loops![
-            PieceType::Bishop,
-            PieceType::Queen,
-            (1..).map(|x| (x, x)),
-            (1..).map(|x| (-x, -x)),
-            (1..).map(|x| (x, -x)),
-            (1..).map(|x| (-x, x))
-        ];
+    /// The squares pieces of the player to move are restricted to by checks and pins
+    fn legality_masks(&self) -> LegalityMasks {
+        let player = self.current_player;
+        let king_position = self.get_king_position(player);
+        let checkers = self.checkers();
+
+        // Squares where the other pieces may go, which capture or block the only checking piece
+        let check_mask = match checkers.count_ones() {
+            0 => !0,
+            1 => bitboard::positions(checkers).fold(checkers, |mask, checker| {
+                mask | bitboard::between(king_position, checker)
+            }),
+            _ => 0,
+        };
+
+        LegalityMasks {
+            check_mask,
+            pins: self.pins(player),
+        }
+    }
+
+    /// Pieces of `player` pinned to their king by enemy sliders
+    pub fn pins(&self, player: Player) -> ArrayVec<Pin, 8> {
+        let king_position = self.get_king_position(player);
+        let occupied = self.occupied();
+        let own = self.occupied_by(player);
+        let enemies = self.occupied_by(player.the_other());
+
+        // Enemy sliders aimed at the king through at most one own piece
+        let rooks = (self.pieces(PieceType::Rook) | self.pieces(PieceType::Queen)) & enemies;
+        let bishops = (self.pieces(PieceType::Bishop) | self.pieces(PieceType::Queen)) & enemies;
+        let snipers = (bitboard::rook_attacks(king_position, enemies) & rooks)
+            | (bitboard::bishop_attacks(king_position, enemies) & bishops);
+
+        let mut pins = ArrayVec::new();
+        for sniper in bitboard::positions(snipers) {
+            let line = bitboard::between(king_position, sniper);
+            let blockers = line & occupied;
+            if blockers.count_ones() == 1 && blockers & own != 0 {
+                pins.extend(bitboard::positions(blockers).map(|pinned| Pin {
+                    pinned,
+                    ray: line | bitboard::bit(sniper),
+                }));
+            }
+        }
 
-        false
+        pins
     }
 
-    pub fn get_pgn(&self) -> String {
-        let moves: Vec<_> = self.move_stack.iter().map(Move::pgn_notation).collect();
+    /// Whether a move generated for the player to move doesn't leave their king in check
+    fn is_legal_with(&self, _move: Move, masks: &LegalityMasks) -> bool {
+        let player = self.current_player;
+        let king_position = self.get_king_position(player);
+        let occupied = self.occupied();
+        let enemies = self.occupied_by(player.the_other());
+
+        let (start, end) = (_move.start(), _move.end());
+
+        match _move.kind() {
+            MoveKind::Normal if start == king_position => {
+                // The king is removed so it doesn't hide squares behind it from sliders
+                self.attackers_to(end, occupied ^ bitboard::bit(king_position)) & enemies == 0
+            }
+            MoveKind::Normal | MoveKind::Promotion(_) => {
+                masks.check_mask & bitboard::bit(end) != 0
+                    && masks
+                        .pins
+                        .iter()
+                        .all(|pin| pin.pinned != start || pin.ray & bitboard::bit(end) != 0)
+            }
+            // Castling is only generated out of check and over squares which aren't attacked
+            MoveKind::Castling(_) => true,
+            // Two pieces leave the row of the king, which the pins don't account for,
+            // so the attacks are computed again on the board after the capture
+            MoveKind::EnPassant => {
+                let taken = bitboard::bit(Position::new_assert(start.row(), end.col()));
+
+                let occupied = (occupied ^ bitboard::bit(start) ^ taken) | bitboard::bit(end);
+                self.attackers_to(king_position, occupied) & enemies & !taken == 0
+            }
+        }
+    }
+
+    /// Pieces of both players which attack `position`, with the given occupancy
+    /// deciding which squares block the sliders
+    pub fn attackers_to(&self, position: Position, occupied: Bitboard) -> Bitboard {
+        let pawns = self.pieces(PieceType::Pawn);
+        let queens = self.pieces(PieceType::Queen);
+
+        (bitboard::pawn_attacks(Player::White, position) & pawns & self.occupied_by(Player::Black))
+            | (bitboard::pawn_attacks(Player::Black, position)
+                & pawns
+                & self.occupied_by(Player::White))
+            | (bitboard::knight_attacks(position) & self.pieces(PieceType::Knight))
+            | (bitboard::king_attacks(position) & self.pieces(PieceType::King))
+            | (bitboard::rook_attacks(position, occupied) & (self.pieces(PieceType::Rook) | queens))
+            | (bitboard::bishop_attacks(position, occupied)
+                & (self.pieces(PieceType::Bishop) | queens))
+    }
+
+    /// Returns if player's position is targeted by enemy pieces
+    pub fn is_targeted(&self, position: Position, player: Player) -> bool {
+        self.attackers_to(position, self.occupied()) & self.occupied_by(player.the_other()) != 0
+    }
+
+    /// Returns if the king of the player to move is attacked
+    #[inline]
+    pub fn is_check(&self) -> bool {
+        self.checkers() != 0
+    }
+
+    /// Enemy pieces attacking the king of the player to move, kept up to date by push and pop
+    #[inline]
+    pub fn checkers(&self) -> Bitboard {
+        self.state().checkers()
+    }
+
+    fn compute_checkers(&self) -> Bitboard {
+        let player = self.current_player;
+        self.attackers_to(self.get_king_position(player), self.occupied())
+            & self.occupied_by(player.the_other())
+    }
+
+    /// Moves played with `push_history` since the game was created
+    pub fn history(&self) -> &[Move] {
+        &self.move_stack
+    }
+
+    /// Key of the position in the Polyglot format of opening books, the same in every
+    /// program, unlike `hash` which is only known to this engine
+    pub fn polyglot_key(&self) -> u64 {
+        polyglot::key(self)
+    }
+
+    /// Parameters of the evaluation, which the game keeps from its creation
+    pub fn params(&self) -> &EvalParams {
+        &self.params
+    }
+
+    /// The position the game was created from, before the moves played with `push_history`
+    pub fn initial_position(&self) -> Self {
+        let mut game = self.clone();
+        for _ in &self.move_stack {
+            game.pop();
+        }
+        game.move_stack.clear();
+        game
+    }
+
+    /// Moves played since the game was created, in the movetext format of PGN
+    pub fn pgn_movetext(&self) -> String {
+        // Every move is written from the position before it, so the moves are played
+        // again from the initial position
+        let mut game = self.initial_position();
 
         let mut s = String::new();
 
-        for (i, _move) in moves.iter().enumerate() {
-            if i % 2 == 0 {
-                s.push_str((i / 2 + 1).to_string().as_str());
-                s.push('.');
-                s.push(' ');
+        for (index, &_move) in self.move_stack.iter().enumerate() {
+            match game.player() {
+                Player::White => s.push_str(&format!("{}. ", game.fullmove_number())),
+                Player::Black if index == 0 => {
+                    s.push_str(&format!("{}... ", game.fullmove_number()))
+                }
+                Player::Black => (),
             }
-            s.push_str(_move.as_str());
+            s.push_str(&_move.to_san(&game));
             s.push(' ');
+
+            game.push(_move);
         }
 
         s
     }
 
-    pub fn fen(&self) -> String {
+    /// Forsyth-Edwards Notation of the position, which `Game::new` reads back
+    ///
+    /// Castling rights are written as X-FEN, so they are standard unless
+    /// a Chess960 position has two rooks on the same side of the king.
+    pub fn to_fen(&self) -> String {
         let mut result = String::new();
 
         // Add board state
@@ -937,22 +1327,37 @@ impl Game {
         let state = self.state();
         let mut has_castling = false;
 
-        if state.white_king_castling() {
-            result.push('K');
-            has_castling = true;
-        }
-        if state.white_queen_castling() {
-            result.push('Q');
-            has_castling = true;
-        }
-        if state.black_king_castling() {
-            result.push('k');
-            has_castling = true;
-        }
-        if state.black_queen_castling() {
-            result.push('q');
-            has_castling = true;
+        for player in [Player::White, Player::Black] {
+            let row = player.first_row();
+            let is_rook = |col| {
+                self.get_position(Position::new_assert(row, col))
+                    == Some(Piece {
+                        piece_type: PieceType::Rook,
+                        owner: player,
+                    })
+            };
+
+            for side in CastlingSide::ALL {
+                if !state.castling(player, side) {
+                    continue;
+                }
+                has_castling = true;
+
+                // The standard letters are kept unless another rook is further out (X-FEN)
+                let rook_col = self.rook_start_cols[player.as_index()][side as usize];
+                let right = match side {
+                    CastlingSide::Short if !(rook_col + 1..8).any(is_rook) => 'K',
+                    CastlingSide::Long if !(0..rook_col).any(is_rook) => 'Q',
+                    _ => (b'A' + rook_col as u8) as char,
+                };
+
+                result.push(match player {
+                    Player::White => right,
+                    Player::Black => right.to_ascii_lowercase(),
+                });
+            }
         }
+
         if !has_castling {
             result.push('-');
         }
@@ -970,25 +1375,117 @@ impl Game {
             result.push('-');
         }
 
-        // TODO: Track halfmove clock
+        // Add halfmove clock
         result.push(' ');
-        result.push('0');
+        result.push_str(&state.halfmove_clock().to_string());
 
         // Add fullmove number
         result.push(' ');
-        result.push_str(&((self.move_stack.len() / 2) + 1).to_string());
+        result.push_str(&self.fullmove_number().to_string());
 
         result
     }
+
+    /// The same position with the colors swapped and the board turned upside down,
+    /// which should have the opposite evaluation. The moves played aren't kept.
+    pub fn mirrored(&self) -> Self {
+        let fen = self.to_fen();
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let [board, player, castling, en_passant, halfmove_clock, fullmove_number] = fields[..]
+        else {
+            unreachable!("to_fen writes the six fields");
+        };
+
+        let board = board
+            .split('/')
+            .rev()
+            .map(swap_case)
+            .collect::<Vec<_>>()
+            .join("/");
+        let player = if player == "w" { "b" } else { "w" };
+        // The rights of white come first
+        let castling = swap_case(castling);
+        let (mut castling, black): (String, String) =
+            castling.chars().partition(char::is_ascii_uppercase);
+        castling.push_str(&black);
+        let en_passant: String = en_passant
+            .chars()
+            .map(|character| match character {
+                '3' => '6',
+                '6' => '3',
+                _ => character,
+            })
+            .collect();
+
+        self.with_transformed_fen(&[
+            &board,
+            player,
+            &castling,
+            &en_passant,
+            halfmove_clock,
+            fullmove_number,
+        ])
+    }
+
+    /// The same position with the files in reverse order, from h to a. Castling rights
+    /// are dropped, since castling isn't symmetric. The moves played aren't kept.
+    pub fn flipped(&self) -> Self {
+        let fen = self.to_fen();
+        let fields: Vec<&str> = fen.split(' ').collect();
+        let [board, player, _, en_passant, halfmove_clock, fullmove_number] = fields[..] else {
+            unreachable!("to_fen writes the six fields");
+        };
+
+        let board = board
+            .split('/')
+            .map(|rank| rank.chars().rev().collect::<String>())
+            .collect::<Vec<_>>()
+            .join("/");
+        let en_passant: String = en_passant
+            .chars()
+            .map(|character| match character {
+                'a'..='h' => (b'h' - (character as u8 - b'a')) as char,
+                _ => character,
+            })
+            .collect();
+
+        self.with_transformed_fen(&[
+            &board,
+            player,
+            "-",
+            &en_passant,
+            halfmove_clock,
+            fullmove_number,
+        ])
+    }
+
+    fn with_transformed_fen(&self, fields: &[&str]) -> Self {
+        let mut game = Self::with_params(&fields.join(" "), self.params.clone())
+            .expect("transformed FEN is valid");
+        game.set_chess960(self.chess960);
+        game
+    }
 }
 
-impl std::fmt::Display for Game {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+fn swap_case(s: &str) -> String {
+    s.chars()
+        .map(|character| {
+            if character.is_ascii_uppercase() {
+                character.to_ascii_lowercase()
+            } else {
+                character.to_ascii_uppercase()
+            }
+        })
+        .collect()
+}
+
+impl core::fmt::Display for Game {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         writeln!(f)?;
 
         writeln!(f, "Hash: {:X}", self.hash)?;
-        writeln!(f, "Fen: {}", self.fen())?;
-        writeln!(f, "PGN: {}", self.get_pgn())?;
+        writeln!(f, "Fen: {}", self.to_fen())?;
+        writeln!(f, "PGN: {}", self.pgn_movetext())?;
         writeln!(f)?;
 
         for i in (0..8).rev() {
@@ -1009,18 +1506,89 @@ impl std::fmt::Display for Game {
     }
 }
 
-#[cfg(test)]
+/// The position for scripts and web pages, with its legal moves in UCI notation
+/// and whether the game is over, and the game leading to it: the FEN it started from
+/// and the moves played since, also in UCI notation
+#[cfg(feature = "serde")]
+impl Serialize for Game {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Json {
+            fen: String,
+            hash: String,
+            player: Player,
+            check: bool,
+            moves: Vec<String>,
+            result: &'static str,
+            opening: Option<eco::Opening>,
+            start: String,
+            chess960: bool,
+            history: Vec<String>,
+        }
 
+        let moves = self.legal_moves();
+
+        Json {
+            fen: self.to_fen(),
+            hash: format!("{:016X}", self.hash),
+            player: self.player(),
+            check: self.is_check(),
+            moves: moves.iter().map(|_move| _move.uci_notation(self)).collect(),
+            result: self.result().as_str(),
+            opening: self.opening(),
+            start: self.initial_position().to_fen(),
+            chess960: self.chess960,
+            // The notation of castling only depends on where the rooks start
+            history: self
+                .move_stack
+                .iter()
+                .map(|_move| _move.uci_notation(self))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Reads the game written by `Serialize`, from its start and the moves played since,
+/// which must be legal. The other fields are ignored.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Game {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        struct Json {
+            start: String,
+            #[serde(default)]
+            chess960: bool,
+            #[serde(default)]
+            history: Vec<String>,
+        }
+
+        let json = Json::deserialize(deserializer)?;
+        let mut game = Game::new(&json.start).map_err(D::Error::custom)?;
+        game.set_chess960(json.chess960);
+        for uci in &json.history {
+            let _move = Move::from_uci_notation(uci, &game).map_err(D::Error::custom)?;
+            game.push_history(_move);
+        }
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
     use crate::constants::TESTING_GAME;
+    use move_struct::UciMoveError;
+    use snapshot::Snapshot;
     #[test]
     fn fen_startpos() {
         let game = Game::default();
 
         assert_eq!(
-            game.fen(),
-            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -"
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
         );
     }
 
@@ -1032,16 +1600,246 @@ mod tests {
             let _move = Move::from_uci_notation(_move, &game).unwrap();
             game.push(_move);
 
-            let fen = game.fen();
+            let fen = game.to_fen();
 
             let game2 = Game::new(&fen).unwrap();
 
-            let fen2 = game2.fen();
+            let fen2 = game2.to_fen();
 
             assert_eq!(fen, fen2);
         }
     }
 
+    #[test]
+    fn fen_move_numbers() {
+        let fen = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 3 17";
+        let mut game = Game::new(fen).unwrap();
+        assert_eq!(game.to_fen(), fen);
+
+        let _move = Move::from_uci_notation("g8f6", &game).unwrap();
+        game.push(_move);
+        assert!(game.to_fen().ends_with(" w KQkq - 4 18"));
+        let _move = Move::from_uci_notation("d2d3", &game).unwrap();
+        game.push(_move);
+        assert!(game.to_fen().ends_with(" b KQkq - 0 18"));
+
+        // Both counters may be left out
+        let game = Game::new("4k3/8/8/8/8/8/8/4K3 b - -").unwrap();
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/4K3 b - - 0 1");
+
+        assert!(Game::new("4k3/8/8/8/8/8/8/4K3 w - - 0 0").is_err());
+        assert!(Game::new("4k3/8/8/8/8/8/8/4K3 w - - 0 x").is_err());
+    }
+
+    #[test]
+    fn fen_errors() {
+        let error = |fen| Game::new(fen).err();
+
+        assert_eq!(error(""), Some(FenError::MissingBoard));
+        assert_eq!(
+            error("4k3/8/8/8/8/8/4K3 w"),
+            Some(FenError::InvalidRankCount(7))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K4 w"),
+            Some(FenError::InvalidRankLength(1))
+        );
+        assert_eq!(
+            error("4k3/9/8/8/8/8/8/4K3 w"),
+            Some(FenError::InvalidRankLength(7))
+        );
+        assert_eq!(
+            error("4k2/8/8/8/8/8/8/4K3 w"),
+            Some(FenError::InvalidRankLength(8))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4X3 w"),
+            Some(FenError::InvalidPiece('X'))
+        );
+        assert_eq!(
+            error("4k2P/8/8/8/8/8/8/4K3 w"),
+            Some(FenError::PawnOnLastRank)
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/8 w"),
+            Some(FenError::InvalidKingCount(Player::White))
+        );
+        assert_eq!(
+            error("4k2k/8/8/8/8/8/8/4K3 w"),
+            Some(FenError::InvalidKingCount(Player::Black))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 white"),
+            Some(FenError::InvalidPlayer("white".to_string()))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K2R w KX"),
+            Some(FenError::InvalidCastlingRight('X'))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K2R w Q"),
+            Some(FenError::MissingCastlingPieces('Q'))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/4K3/7R w K"),
+            Some(FenError::MissingCastlingPieces('K'))
+        );
+        assert_eq!(
+            error("4k3/8/8/4pP2/8/8/8/4K3 w - e3"),
+            Some(FenError::InvalidEnPassant("e3".to_string()))
+        );
+        assert_eq!(
+            error("4k3/8/8/4pP2/8/8/8/4K3 w - d6"),
+            Some(FenError::InvalidEnPassant("d6".to_string()))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - -1"),
+            Some(FenError::InvalidHalfmoveClock("-1".to_string()))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - 0 0"),
+            Some(FenError::InvalidFullmoveNumber("0".to_string()))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4K3 w - - 0 15101912594050682418"),
+            Some(FenError::InvalidFullmoveNumber(
+                "15101912594050682418".to_string()
+            ))
+        );
+        assert_eq!(
+            error("4k3/8/8/8/8/8/8/4R1K1 w"),
+            Some(FenError::OpponentInCheck)
+        );
+        assert_eq!(
+            error("4k3/pppppppp/8/8/8/8/PPPPPPPP/QQ2K3 w"),
+            Some(FenError::TooManyPieces(Player::White))
+        );
+        assert_eq!(
+            error("qq2k3/pppppppp/8/8/8/8/8/4K3 b"),
+            Some(FenError::TooManyPieces(Player::Black))
+        );
+        assert!(Game::new("4k3/ppppppp1/8/8/8/8/8/RNNNK3 w").is_ok());
+        assert_eq!(
+            error("4k3/8/8/8/8/8/2n3n1/4K2r w"),
+            Some(FenError::TooManyCheckers)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        let game = Game::new("7k/5Q2/6K1/8/8/8/8/8 w - - 0 1").unwrap();
+        let json = serde_json::to_value(&game).unwrap();
+        assert_eq!(json["fen"], "7k/5Q2/6K1/8/8/8/8/8 w - - 0 1");
+        assert_eq!(json["hash"], format!("{:016X}", game.hash()));
+        assert_eq!(json["player"], "white");
+        assert_eq!(json["check"], false);
+        assert_eq!(json["moves"].as_array().unwrap().len(), 26);
+        assert_eq!(json["result"], "*");
+        assert!(json["opening"].is_null());
+
+        let mut game = Game::default();
+        for uci in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            let _move = Move::from_uci_notation(uci, &game).unwrap();
+            game.push_history(_move);
+        }
+        let json = serde_json::to_value(&game).unwrap();
+        assert_eq!(json["player"], "white");
+        assert_eq!(json["check"], true);
+        assert_eq!(json["moves"].as_array().unwrap().len(), 0);
+        assert_eq!(json["result"], "0-1");
+        assert_eq!(json["opening"]["name"], "Barnes Opening: Fool's Mate");
+        assert_eq!(json["start"], START_FEN);
+        assert_eq!(json["chess960"], false);
+        assert_eq!(
+            json["history"],
+            serde_json::json!(["f2f3", "e7e5", "g2g4", "d8h4"])
+        );
+    }
+
+    #[test]
+    fn legal_moves_order() {
+        for fen in [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        ] {
+            let game = Game::new(fen).unwrap();
+            let moves = game.legal_moves();
+            assert!(moves
+                .windows(2)
+                .all(|pair| pair[0].start().as_usize() <= pair[1].start().as_usize()));
+            assert!(moves == game.clone().legal_moves());
+        }
+
+        let game = Game::new("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(game.legal_moves().is_empty());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trip() {
+        let mut game = Game::new("rk5r/p7/8/8/8/8/P7/RK5R w HAha - 0 1").unwrap();
+        game.set_chess960(true);
+        for uci in ["b1h1", "b8a8", "a2a4"] {
+            let _move = Move::from_uci_notation(uci, &game).unwrap();
+            game.push_history(_move);
+        }
+
+        let json = serde_json::to_string(&game).unwrap();
+        let read: Game = serde_json::from_str(&json).unwrap();
+        assert_eq!(read.to_fen(), game.to_fen());
+        assert_eq!(read.hash(), game.hash());
+        assert!(read.is_chess960());
+        assert_eq!(read.pgn_movetext(), game.pgn_movetext());
+
+        let error = |json: &str| match serde_json::from_str::<Game>(json) {
+            Ok(_) => String::new(),
+            Err(err) => err.to_string(),
+        };
+        assert!(error(r#"{"start":"8/8/8/8 w"}"#).starts_with("Expected 8 ranks, found 4"));
+        assert!(error(&format!(
+            r#"{{"start":"{}","history":["e2e5"]}}"#,
+            START_FEN
+        ))
+        .starts_with("Illegal move: e2e5"));
+    }
+
+    #[test]
+    fn fen_optional_fields() {
+        let game = Game::new("4k3/8/8/8/8/8/8/4K3").unwrap();
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+
+        // The en passant square is only kept when the pawn can be taken,
+        // like after playing the double step
+        let game = Game::new("4k3/8/8/4pP2/8/8/8/4K3 w - e6 0 2").unwrap();
+        assert_eq!(game.to_fen(), "4k3/8/8/4pP2/8/8/8/4K3 w - e6 0 2");
+        let game = Game::new("4k3/8/8/4p3/8/8/8/4K3 w - e6 0 2").unwrap();
+        assert_eq!(game.to_fen(), "4k3/8/8/4p3/8/8/8/4K3 w - - 0 2");
+
+        // Extra fields, like the operations of an EPD, are ignored
+        assert!(Game::new("4k3/8/8/8/8/8/8/4K3 w - - 0 1 c0 \"draw\";").is_ok());
+    }
+
+    #[test]
+    fn pgn_movetext() {
+        let mut game = Game::default();
+        for uci in ["e2e4", "e7e5", "d1h5", "b8c6", "f1c4", "g8f6", "h5f7"] {
+            let _move = Move::from_uci_notation(uci, &game).unwrap();
+            game.push_history(_move);
+        }
+        assert_eq!(
+            game.pgn_movetext().trim_end(),
+            "1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#"
+        );
+
+        let mut game = Game::new("4k3/8/8/8/8/8/8/R3K3 b Q - 0 20").unwrap();
+        for uci in ["e8d7", "e1c1"] {
+            let _move = Move::from_uci_notation(uci, &game).unwrap();
+            game.push_history(_move);
+        }
+        assert_eq!(game.pgn_movetext().trim_end(), "20... Kd7 21. O-O-O+");
+    }
+
     #[test]
     fn fen_en_passant() {
         let mut game = Game::default();
@@ -1058,15 +1856,543 @@ mod tests {
         let _move = Move::from_uci_notation("d7d5", &game).unwrap();
         game.push(_move);
 
-        let fen = game.fen();
+        let fen = game.to_fen();
 
         let game2 = Game::new(&fen).unwrap();
 
-        let fen2 = game2.fen();
+        let fen2 = game2.to_fen();
 
         assert_eq!(fen, fen2);
     }
 
+    #[test]
+    fn en_passant_rules() {
+        let is_en_passant = |_move: &Move| _move.kind() == MoveKind::EnPassant;
+        let mut moves = ArrayVec::new();
+
+        // The capture is only possible right after the double step
+        let mut game = Game::new("4k3/8/8/8/3p4/8/4P3/4K3 w - - 0 1").unwrap();
+        for (uci, en_passant) in [("e2e4", true), ("e8d8", false), ("e1d1", false)] {
+            game.push(Move::from_uci_notation(uci, &game).unwrap());
+            game.get_moves(&mut moves);
+            assert_eq!(moves.iter().any(is_en_passant), en_passant, "after {}", uci);
+        }
+        assert_eq!(
+            Move::from_uci_notation("d4e3", &game),
+            Err(UciMoveError::Illegal("d4e3".to_string()))
+        );
+
+        // Both pawns leave the row of the king, which the queen then attacks,
+        // even when the square comes from the FEN
+        let game = Game::new("8/8/8/8/k2Pp2Q/8/8/3K4 b - d3 0 1").unwrap();
+        game.get_moves(&mut moves);
+        assert!(!moves.iter().any(is_en_passant));
+        let capture = Move::en_passant(Position::new_assert(3, 4), Position::new_assert(2, 3));
+        assert!(!game.is_legal(capture));
+
+        // Without the queen the capture is fine
+        let game = Game::new("8/8/8/8/k2Pp3/8/8/3K4 b - d3 0 1").unwrap();
+        assert!(game.is_legal(capture));
+    }
+
+    #[test]
+    fn incremental_state_matches_recomputation() {
+        let mut game = Game::default();
+        let mut moves = ArrayVec::new();
+
+        for _move in TESTING_GAME.split_ascii_whitespace() {
+            // Every legal move, including captures, promotions and castling, must be undone exactly
+            game.get_moves(&mut moves);
+            let snapshot = Snapshot::of(&game);
+            for &candidate in &moves {
+                game.push(candidate);
+                game.verify_incremental_state();
+                game.pop();
+                game.verify_incremental_state();
+                snapshot.assert_restored(&game, "Move not undone");
+            }
+
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            game.push_history(_move);
+            game.verify_incremental_state();
+        }
+
+        // The kings are scored with the endgame table as soon as the endgame begins
+        let game = Game::new("8/8/4k3/8/8/4K3/4R3/8 w - - 0 1").unwrap();
+        assert_eq!(game.phase(), GamePhase::Endgame);
+        game.verify_incremental_state();
+    }
+
+    #[test]
+    #[should_panic(expected = "Move not undone")]
+    fn corrupted_incremental_state() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut game = Game::new(fen).unwrap();
+        let snapshot = Snapshot::of(&game);
+        game.push(Move::from_uci_notation("e5f7", &game).unwrap());
+        // Updated by the changes of every move, so the error outlives the move
+        game.material[Player::Black.as_index()] += 100;
+        game.pop();
+        snapshot.assert_restored(&game, "Move not undone");
+    }
+
+    #[test]
+    fn long_game() {
+        let mut game = Game::default();
+        let moves = ["g1f3", "g8f6", "f3g1", "f6g8"];
+
+        for move_str in moves.iter().cycle().take(1000) {
+            let _move = Move::from_uci_notation(move_str, &game).unwrap();
+            game.push_history(_move);
+        }
+        assert_eq!(game.len(), 1001);
+        assert_eq!(game.fullmove_number(), 501);
+        assert!(game.to_fen().ends_with(" w KQkq - 1000 501"));
+        assert_eq!(game.hash, Game::default().hash);
+
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 300 200";
+        let game = Game::new(fen).unwrap();
+        assert_eq!(game.halfmove_clock(), 300);
+        assert!(game.is_fifty_move_draw());
+        assert_eq!(game.to_fen(), fen);
+    }
+
+    #[test]
+    fn single_move_legality() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "4k3/8/8/8/1b6/8/3P4/4K3 w - - 0 1",
+            "4k3/8/8/2KPp2r/8/8/8/8 w - e6 0 2",
+        ];
+
+        for fen in fens {
+            let mut game = Game::new(fen).unwrap();
+            let mut moves = ArrayVec::new();
+            game.get_moves(&mut moves);
+
+            for &first in &moves {
+                game.push(first);
+
+                let mut legal = ArrayVec::new();
+                game.get_moves(&mut legal);
+
+                // Every move a piece could make if pins and checks are ignored,
+                // and the moves of the position before which might come from a stale table
+                let mut candidates = Vec::from(moves.as_slice());
+                for position in bitboard::positions(game.occupied()) {
+                    let piece = game.get_position(position).unwrap();
+                    piece.get_moves(|_move| candidates.push(_move), &game, position);
+                }
+
+                for _move in candidates {
+                    assert_eq!(game.is_legal(_move), legal.contains(&_move), "{:?}", _move);
+                }
+
+                game.pop();
+            }
+        }
+    }
+
+    #[test]
+    fn attackers_to() {
+        let game = Game::new("4k3/8/2n5/3p4/1R1B2Rq/2P5/8/4K3 w - - 0 1").unwrap();
+        let square = |name: &str| {
+            let name = name.as_bytes();
+            Position::new_assert((name[1] - b'1') as i8, (name[0] - b'a') as i8)
+        };
+        let squares = |bitboard| {
+            let mut names: Vec<String> = bitboard::positions(bitboard)
+                .map(|position| {
+                    let col = (position.col() as u8 + b'a') as char;
+                    format!("{}{}", col, position.row() + 1)
+                })
+                .collect();
+            names.sort();
+            names
+        };
+
+        let d4 = square("d4");
+        assert_eq!(
+            squares(game.attackers_to(d4, game.occupied())),
+            ["b4", "c3", "c6", "g4"]
+        );
+        assert!(game.is_targeted(d4, Player::White));
+        assert!(!game.is_targeted(square("d5"), Player::Black));
+
+        // Removing the rook on g4 shows the queen behind it
+        let occupied = game.occupied() ^ bitboard::bit(square("g4"));
+        assert_eq!(
+            squares(game.attackers_to(square("f4"), occupied) & game.occupied_by(Player::Black)),
+            ["h4"]
+        );
+        assert_eq!(
+            squares(game.attackers_to(square("c4"), game.occupied())),
+            ["b4", "d5"]
+        );
+    }
+
+    #[test]
+    fn checkers() {
+        let mut game = Game::new("4k3/8/8/8/4N3/8/8/4R1K1 w - - 0 1").unwrap();
+        assert!(!game.is_check());
+
+        // Moving the knight uncovers the rook, and may check with the knight too
+        let _move = Move::from_uci_notation("e4c3", &game).unwrap();
+        game.push(_move);
+        assert_eq!(game.checkers(), bitboard::bit(Position::new_assert(0, 4)));
+        game.pop();
+        assert!(!game.is_check());
+
+        let _move = Move::from_uci_notation("e4d6", &game).unwrap();
+        game.push(_move);
+        assert_eq!(game.checkers().count_ones(), 2);
+
+        let mut moves = ArrayVec::new();
+        game.get_moves(&mut moves);
+        assert!(moves
+            .iter()
+            .all(|_move| _move.piece(&game).piece_type == PieceType::King));
+    }
+
+    #[test]
+    fn mirrored_and_flipped() {
+        let game = Game::new("r3k2r/8/8/3pP3/8/8/8/R3K1R1 w Qkq d6 3 20").unwrap();
+
+        let mirrored = game.mirrored();
+        assert_eq!(
+            mirrored.to_fen(),
+            "r3k1r1/8/8/8/3Pp3/8/8/R3K2R b KQq d3 3 20"
+        );
+        assert_eq!(mirrored.mirrored().to_fen(), game.to_fen());
+
+        let flipped = game.flipped();
+        assert_eq!(flipped.to_fen(), "r2k3r/8/8/3Pp3/8/8/8/1R1K3R w - e6 3 20");
+        assert_eq!(
+            flipped.flipped().to_fen(),
+            "r3k2r/8/8/3pP3/8/8/8/R3K1R1 w - d6 3 20"
+        );
+
+        // The weights of the game are kept
+        let params = Arc::new(params::default().as_ref().clone());
+        let game = Game::with_params(&game.to_fen(), params).unwrap();
+        assert!(core::ptr::eq(game.mirrored().params(), game.params()));
+        assert!(core::ptr::eq(game.flipped().params(), game.params()));
+    }
+
+    #[test]
+    fn pins() {
+        // The knight is pinned by the queen and the rook by the other rook,
+        // the pawn isn't in the way of any slider
+        let game = Game::new("4k3/8/8/q7/8/2N5/5P2/4KR1r w - - 0 1").unwrap();
+        let pins = game.pins(Player::White);
+        let mut pinned: Vec<_> = pins.iter().map(|pin| pin.pinned.as_usize()).collect();
+        pinned.sort();
+        assert_eq!(pinned, [5, 18]);
+
+        let knight = pins
+            .iter()
+            .find(|pin| pin.pinned == Position::new_assert(2, 2))
+            .unwrap();
+        assert_eq!(
+            knight.ray,
+            bitboard::bit(Position::new_assert(1, 3))
+                | bitboard::bit(Position::new_assert(2, 2))
+                | bitboard::bit(Position::new_assert(3, 1))
+                | bitboard::bit(Position::new_assert(4, 0))
+        );
+
+        assert!(game.pins(Player::Black).is_empty());
+    }
+
+    #[test]
+    fn gives_check() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            "1k6/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+            "4k3/8/8/8/8/8/8/rR2K3 w B - 0 1",
+            "8/8/8/K1pP3q/8/8/8/7k w - c6 0 2",
+        ];
+
+        for fen in fens {
+            let mut game = Game::new(fen).unwrap();
+            let mut moves = ArrayVec::new();
+            game.get_moves(&mut moves);
+
+            for first in moves {
+                let gives_check = game.gives_check(first);
+                game.push(first);
+                assert_eq!(gives_check, game.is_check(), "{:?}", first);
+
+                let mut replies = ArrayVec::new();
+                game.get_moves(&mut replies);
+                for _move in replies {
+                    let gives_check = game.gives_check(_move);
+                    game.push(_move);
+                    assert_eq!(gives_check, game.is_check(), "{:?}", _move);
+                    game.pop();
+                }
+
+                game.pop();
+            }
+        }
+    }
+
+    #[test]
+    fn null_move() {
+        let mut game = Game::new("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2").unwrap();
+        let fen = game.to_fen();
+        let hash = game.hash();
+
+        game.push_null();
+        assert_eq!(game.player(), Player::Black);
+        assert_eq!(game.to_fen(), "4k3/8/8/3pP3/8/8/8/4K3 b - - 0 2");
+        assert_eq!(game.hash(), game.compute_hash());
+        assert!(!game.is_repetition());
+
+        game.pop_null();
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(game.hash(), hash);
+    }
+
+    #[test]
+    fn occupancy() {
+        let game = Game::default();
+        assert_eq!(game.occupied(), 0xFFFF_0000_0000_FFFF);
+        assert_eq!(game.occupied_by(Player::White), 0xFFFF);
+        assert_eq!(game.occupied_by(Player::Black), 0xFFFF_0000_0000_0000);
+        assert_eq!(game.pieces(PieceType::Pawn), 0x00FF_0000_0000_FF00);
+        assert_eq!(game.pieces_of(Player::White, PieceType::Pawn), 0xFF00);
+        assert_eq!(game.pieces_of(Player::Black, PieceType::King), 1 << 60);
+        assert_eq!(
+            game.pieces_of(Player::White, PieceType::Knight),
+            0b0100_0010
+        );
+
+        // Every piece is in exactly one set of each kind
+        let game =
+            Game::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -").unwrap();
+        for position in bitboard::positions(!0) {
+            let piece = game.get_position(position);
+            for player in [Player::White, Player::Black] {
+                for piece_type in [
+                    PieceType::Queen,
+                    PieceType::Rook,
+                    PieceType::Bishop,
+                    PieceType::Knight,
+                    PieceType::Pawn,
+                    PieceType::King,
+                ] {
+                    let expected = piece
+                        == Some(Piece {
+                            piece_type,
+                            owner: player,
+                        });
+                    let found = game.pieces_of(player, piece_type) & bitboard::bit(position) != 0;
+                    assert_eq!(found, expected);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn undo_stack() {
+        let mut game = Game::default();
+        let mut positions = Vec::new();
+
+        for _move in TESTING_GAME.split_ascii_whitespace() {
+            positions.push((game.to_fen(), game.hash()));
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            game.push(_move);
+        }
+
+        // Moves are undone in reverse order without being given back
+        while let Some((fen, hash)) = positions.pop() {
+            game.pop();
+            assert_eq!(game.to_fen(), fen);
+            assert_eq!(game.hash(), hash);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "No move to undo")]
+    fn pop_without_moves() {
+        let mut game = Game::default();
+        game.push_null();
+        game.pop();
+    }
+
+    #[test]
+    fn repetition() {
+        let mut game = Game::default();
+
+        for move_str in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+            assert!(!game.is_repetition());
+            let _move = Move::from_uci_notation(move_str, &game).unwrap();
+            game.push(_move);
+        }
+        assert!(game.is_repetition());
+
+        let _move = Move::from_uci_notation("g1f3", &game).unwrap();
+        game.push(_move);
+        assert!(game.is_repetition());
+        game.pop();
+        assert!(game.is_repetition());
+    }
+
+    #[test]
+    fn material_api() {
+        let mut game = Game::new("4k3/1p6/8/8/8/8/5PP1/2R1K1N1 w - - 0 1").unwrap();
+
+        assert_eq!(game.piece_count(Player::White, PieceType::Pawn), 2);
+        assert_eq!(game.piece_count(Player::Black, PieceType::King), 1);
+        assert_eq!(game.material(Player::White), 1020);
+        assert_eq!(game.non_pawn_material(Player::White), 820);
+        assert_eq!(game.non_pawn_material(Player::Black), 0);
+        assert_eq!(game.phase(), GamePhase::Endgame);
+
+        let _move = Move::from_uci_notation("c1c8", &game).unwrap();
+        game.push(_move);
+        let _move = Move::from_uci_notation("e8d7", &game).unwrap();
+        game.push(_move);
+        let _move = Move::from_uci_notation("c8c7", &game).unwrap();
+        game.push(_move);
+        let capture = Move::from_uci_notation("d7c7", &game).unwrap();
+        game.push(capture);
+        assert_eq!(game.piece_count(Player::White, PieceType::Rook), 0);
+        assert_eq!(game.non_pawn_material(Player::White), 320);
+
+        game.pop();
+        assert_eq!(game.piece_count(Player::White, PieceType::Rook), 1);
+    }
+
+    #[test]
+    fn halfmove_clock() {
+        let mut game = Game::new("4k3/8/8/8/8/8/4P3/4K2R w K - 7 40").unwrap();
+        assert_eq!(game.halfmove_clock(), 7);
+
+        let _move = Move::from_uci_notation("h1h5", &game).unwrap();
+        game.push(_move);
+        assert_eq!(game.halfmove_clock(), 8);
+        assert!(game.to_fen().ends_with(" 8 40"));
+
+        let _move = Move::from_uci_notation("e8d7", &game).unwrap();
+        game.push(_move);
+        let _move = Move::from_uci_notation("e2e4", &game).unwrap();
+        game.push(_move);
+        assert_eq!(game.halfmove_clock(), 0);
+
+        game.pop();
+        assert_eq!(game.halfmove_clock(), 9);
+
+        let game = Game::new("4k3/8/8/8/8/8/8/4K2R w K - 100 80").unwrap();
+        assert!(game.is_fifty_move_draw());
+    }
+
+    #[test]
+    fn legal_moves() {
+        let count_moves = |fen: &str| {
+            let game = Game::new(fen).unwrap();
+            let mut moves = ArrayVec::new();
+            game.get_moves(&mut moves);
+            (moves.len(), game.is_check())
+        };
+
+        // The bishop is pinned by the queen and may only move along the diagonal
+        assert_eq!(count_moves("4k3/8/8/8/q7/8/2B5/3K4 w - - 0 1"), (6, false));
+        // Only the king moves out of a double check
+        assert_eq!(count_moves("4k3/8/8/8/8/5n2/3Q4/r3K3 w - - 0 1"), (2, true));
+        // A check by a slider can be blocked or captured
+        assert_eq!(count_moves("4k3/8/8/8/8/8/3R4/r3K3 w - - 0 1"), (3, true));
+        // Checkmate and stalemate
+        assert_eq!(count_moves("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1"), (0, true));
+        assert_eq!(count_moves("7k/5Q2/8/8/8/8/8/6K1 b - - 0 1"), (0, false));
+    }
+
+    #[test]
+    fn chess960_castling() {
+        // The inner rook on b1 castles, so its file is written instead of Q
+        let mut game = Game::new("1r2k3/8/8/8/8/8/8/RR2K3 w B - 0 1").unwrap();
+        assert_eq!(game.to_fen(), "1r2k3/8/8/8/8/8/8/RR2K3 w B - 0 1");
+
+        let castling = Move::castling(
+            Position::new_assert(0, 4),
+            Position::new_assert(0, 2),
+            CastlingSide::Long,
+        );
+        assert_eq!(castling.uci_notation(&game), "e1c1");
+        game.set_chess960(true);
+        assert_eq!(castling.uci_notation(&game), "e1b1");
+        assert!(Move::from_uci_notation("e1b1", &game) == Ok(castling));
+
+        let mut moves = ArrayVec::new();
+        game.get_moves(&mut moves);
+        assert!(moves.contains(&castling));
+
+        game.push(castling);
+        assert_eq!(game.to_fen(), "1r2k3/8/8/8/8/8/8/R1KR4 b - - 1 1");
+        game.pop();
+        assert_eq!(game.to_fen(), "1r2k3/8/8/8/8/8/8/RR2K3 w B - 0 1");
+
+        // Without the castling rook in the way the king would land in check from a1
+        let game = Game::new("4k3/8/8/8/8/8/8/rR2K3 w B - 0 1").unwrap();
+        game.get_moves(&mut moves);
+        assert!(!moves.contains(&castling));
+
+        // The king may already stand on its destination, the outermost rook is written as K
+        let mut game = Game::new("3k4/8/8/8/8/8/8/4R1KR w H - 0 1").unwrap();
+        assert_eq!(game.to_fen(), "3k4/8/8/8/8/8/8/4R1KR w K - 0 1");
+        let castling = Move::castling(
+            Position::new_assert(0, 6),
+            Position::new_assert(0, 6),
+            CastlingSide::Short,
+        );
+        game.get_moves(&mut moves);
+        assert!(moves.contains(&castling));
+        game.push(castling);
+        assert_eq!(game.to_fen(), "3k4/8/8/8/8/8/8/4RRK1 b - - 1 1");
+
+        assert!(Game::new("4k3/8/8/8/8/8/8/R3K3 w K - 0 1").is_err());
+    }
+
+    #[test]
+    fn maximum_mobility() {
+        // The two known positions with the most legal moves
+        let mut moves = ArrayVec::new();
+        for fen in [
+            "R6R/3Q4/1Q4Q1/4Q3/2Q4Q/Q4Q2/pp1Q4/kBNN1KB1 w - - 0 1",
+            "3Q4/1Q4Q1/4Q3/2Q4R/Q4Q2/3Q4/1Q4Rp/1K1BBNNk w - - 0 1",
+        ] {
+            Game::new(fen).unwrap().get_moves(&mut moves);
+            assert_eq!(moves.len(), 218);
+            assert!(moves.len() < MAX_MOVES);
+        }
+    }
+
+    #[test]
+    fn hash_special_moves() {
+        // Castling on both wings, en passant and a promotion with capture
+        let mut game = Game::new("r3k2r/pPpp1ppp/8/4P3/8/8/P1PP1PPP/R3K2R b KQkq - 0 1").unwrap();
+
+        for _move in ["d7d5", "e5d6", "e8g8", "e1c1", "f8e8", "b7a8q"] {
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            let hash = game.hash();
+
+            game.push(_move);
+            assert_eq!(game.hash(), game.compute_hash());
+            assert_eq!(game.hash(), Game::new(&game.to_fen()).unwrap().hash());
+
+            game.pop();
+            assert_eq!(game.hash(), hash);
+
+            game.push_history(_move);
+        }
+    }
+
     #[test]
     fn check_hashing_consistency() {
         let mut game = Game::default();
@@ -1075,7 +2401,7 @@ mod tests {
             let _move = Move::from_uci_notation(_move, &game).unwrap();
             game.push(_move);
 
-            let game2 = Game::new(&game.fen()).unwrap();
+            let game2 = Game::new(&game.to_fen()).unwrap();
 
             assert_eq!(game.hash(), game2.hash());
         }
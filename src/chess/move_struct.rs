@@ -1,87 +1,187 @@
 use super::piece::{Piece, PieceType};
 use super::position::Position;
-use super::{Game, Player};
-use std::str::FromStr;
+use super::{CastlingSide, Game, Player};
+use alloc::string::{String, ToString};
 
+/// Capacity of the move lists filled by `Game::get_moves`
+///
+/// No position has more than 218 legal moves, so there is room to spare.
+pub const MAX_MOVES: usize = 256;
+
+/// A move packed in 16 bits: the starting square, the destination and the kind of move
+///
+/// The pieces involved aren't stored, they are read from the board of the position the
+/// move is played in. Castling goes from the square of the king to the one it ends on.
+///
+/// With the `serde` feature, moves are written with their squares and kind, which don't
+/// depend on the position, e.g. `{"start":"e1","end":"g1","kind":{"castling":"short"}}`.
+/// Like moves read from any other source, they must be checked with `Game::is_legal`
+/// before being played.
 #[derive(PartialEq, Eq, Clone, Copy)]
-pub enum Move {
-    Normal {
-        piece: Piece,
-        start: Position,
-        end: Position,
-        captured_piece: Option<Piece>,
-    },
-    Promotion {
-        owner: Player,
-        new_piece: PieceType,
-        start: Position,
-        end: Position,
-        captured_piece: Option<Piece>,
-    },
-    CastlingShort {
-        owner: Player,
-    },
-    CastlingLong {
-        owner: Player,
-    },
-    EnPassant {
-        owner: Player,
-        start_col: i8,
-        end_col: i8,
-    },
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "MoveJson", try_from = "MoveJson")
+)]
+pub struct Move(u16);
+
+/// What a move does besides taking a piece from one square to the other
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum MoveKind {
+    Normal,
+    EnPassant,
+    Castling(CastlingSide),
+    Promotion(PieceType),
 }
 
+// The kind is stored in the 4 highest bits, promotions are followed by the new piece
+const NORMAL: u16 = 0;
+const EN_PASSANT: u16 = 1;
+const CASTLING_SHORT: u16 = 2;
+const CASTLING_LONG: u16 = 3;
+const PROMOTION: u16 = 4;
+
 impl Move {
-    pub fn is_tactical_move(&self) -> bool {
-        match self {
-            Self::Normal {
-                piece,
-                captured_piece,
-                ..
-            } => captured_piece.is_some_and(|captured_piece| {
-                piece.material_value() <= captured_piece.material_value()
+    #[inline]
+    const fn pack(start: Position, end: Position, kind: u16) -> Self {
+        Self(start.as_usize() as u16 | (end.as_usize() as u16) << 6 | kind << 12)
+    }
+
+    #[inline]
+    pub const fn new(start: Position, end: Position) -> Self {
+        Self::pack(start, end, NORMAL)
+    }
+
+    /// `new_piece` is a queen, a rook, a bishop or a knight
+    #[inline]
+    pub const fn promotion(start: Position, end: Position, new_piece: PieceType) -> Self {
+        Self::pack(start, end, PROMOTION + new_piece as u16)
+    }
+
+    #[inline]
+    pub const fn en_passant(start: Position, end: Position) -> Self {
+        Self::pack(start, end, EN_PASSANT)
+    }
+
+    /// `start` and `end` are the squares of the king before and after castling
+    #[inline]
+    pub const fn castling(start: Position, end: Position, side: CastlingSide) -> Self {
+        Self::pack(
+            start,
+            end,
+            match side {
+                CastlingSide::Short => CASTLING_SHORT,
+                CastlingSide::Long => CASTLING_LONG,
+            },
+        )
+    }
+
+    #[inline]
+    pub const fn start(self) -> Position {
+        Position::from_usize((self.0 & 0b111111) as usize)
+    }
+
+    #[inline]
+    pub const fn end(self) -> Position {
+        Position::from_usize((self.0 >> 6 & 0b111111) as usize)
+    }
+
+    #[inline]
+    pub const fn kind(self) -> MoveKind {
+        match self.0 >> 12 {
+            NORMAL => MoveKind::Normal,
+            EN_PASSANT => MoveKind::EnPassant,
+            CASTLING_SHORT => MoveKind::Castling(CastlingSide::Short),
+            CASTLING_LONG => MoveKind::Castling(CastlingSide::Long),
+            kind => MoveKind::Promotion(match kind - PROMOTION {
+                0 => PieceType::Queen,
+                1 => PieceType::Rook,
+                2 => PieceType::Bishop,
+                _ => PieceType::Knight,
             }),
-            Self::Promotion { .. } => true,
-            Self::EnPassant { .. } => true,
-            _ => false,
+        }
+    }
+
+    /// The moving piece, `game` is the position before the move
+    ///
+    /// Panics if the start square is empty, as it is for moves of another position.
+    #[inline]
+    pub fn piece(self, game: &Game) -> Piece {
+        game.get_position(self.start())
+            .expect("No piece on the start square of the move")
+    }
+
+    /// The piece taken by the move, `game` is the position before the move
+    #[inline]
+    pub fn captured_piece(self, game: &Game) -> Option<Piece> {
+        match self.kind() {
+            MoveKind::Normal | MoveKind::Promotion(_) => game.get_position(self.end()),
+            MoveKind::EnPassant => Some(Piece {
+                piece_type: PieceType::Pawn,
+                owner: game.current_player.the_other(),
+            }),
+            MoveKind::Castling(_) => None,
+        }
+    }
+
+    pub fn is_tactical_move(&self, game: &Game) -> bool {
+        match self.kind() {
+            MoveKind::Normal => self.captured_piece(game).is_some_and(|captured_piece| {
+                self.piece(game).material_value() <= captured_piece.material_value()
+            }),
+            MoveKind::Promotion(_) | MoveKind::EnPassant => true,
+            MoveKind::Castling(_) => false,
+        }
+    }
+
+    /// Pawn moves and captures reset the halfmove clock used by the fifty-move rule
+    pub fn resets_halfmove_clock(&self, game: &Game) -> bool {
+        match self.kind() {
+            MoveKind::Normal => {
+                self.piece(game).piece_type == PieceType::Pawn
+                    || self.captured_piece(game).is_some()
+            }
+            MoveKind::Promotion(_) | MoveKind::EnPassant => true,
+            MoveKind::Castling(_) => false,
         }
     }
 
     // Return the moves index inside history, if it is a quiet move
-    pub fn index_history(&self) -> Option<usize> {
-        match self {
-            Move::Normal {
-                piece,
-                end,
-                captured_piece,
-                ..
-            } => match captured_piece {
+    pub fn index_history(&self, game: &Game) -> Option<usize> {
+        match self.kind() {
+            MoveKind::Normal => match self.captured_piece(game) {
                 Some(_) => None,
-                None => Some(piece.as_index() * 64 + end.as_usize()),
+                None => Some(self.piece(game).as_index() * 64 + self.end().as_usize()),
             },
             _ => None,
         }
     }
 
-    pub fn uci_notation(&self) -> String {
+    /// Castling is written as the move of the king, or as the king taking its own rook
+    /// in Chess960, so the game is needed to know where the rook starts
+    pub fn uci_notation(&self, game: &Game) -> String {
         let mut s = String::new();
-        match self {
-            Self::Normal { start, end, .. } => {
-                s.push((start.col() as u8 + b'a') as char);
-                s.push((start.row() as u8 + b'1') as char);
-                s.push((end.col() as u8 + b'a') as char);
-                s.push((end.row() as u8 + b'1') as char);
+        let mut push_position = |position: Position| {
+            s.push((position.col() as u8 + b'a') as char);
+            s.push((position.row() as u8 + b'1') as char);
+        };
+        push_position(self.start());
+        match self.kind() {
+            MoveKind::Castling(side) if game.is_chess960() => {
+                let owner = match self.start().row() {
+                    0 => Player::White,
+                    _ => Player::Black,
+                };
+                let (_, _, old_rook, _) = game.castling_squares(owner, side);
+                push_position(old_rook);
             }
-            Self::Promotion {
-                start,
-                end,
-                new_piece,
-                ..
-            } => {
-                s.push((start.col() as u8 + b'a') as char);
-                s.push((start.row() as u8 + b'1') as char);
-                s.push((end.col() as u8 + b'a') as char);
-                s.push((end.row() as u8 + b'1') as char);
+            MoveKind::Promotion(new_piece) => {
+                push_position(self.end());
                 s.push(match new_piece {
                     PieceType::Queen => 'q',
                     PieceType::Rook => 'r',
@@ -90,200 +190,288 @@ impl Move {
                     _ => unreachable!(),
                 });
             }
-            Self::CastlingShort { owner } => {
-                let row = match owner {
-                    Player::White => '1',
-                    Player::Black => '8',
-                };
-                s.push('e');
-                s.push(row);
-                s.push('g');
-                s.push(row);
-            }
-            Self::CastlingLong { owner } => {
-                let row = match owner {
-                    Player::White => '1',
-                    Player::Black => '8',
-                };
-                s.push('e');
-                s.push(row);
-                s.push('c');
-                s.push(row);
-            }
-            Self::EnPassant {
-                owner,
-                start_col,
-                end_col,
-            } => {
-                let (start_row, end_row) = match owner {
-                    Player::White => ('5', '6'),
-                    Player::Black => ('4', '3'),
-                };
-                s.push((*start_col as u8 + b'a') as char);
-                s.push(start_row);
-                s.push((*end_col as u8 + b'a') as char);
-                s.push(end_row);
-            }
+            _ => push_position(self.end()),
         }
         s
     }
 
-    pub fn pgn_notation(&self) -> String {
-        match self {
-            Self::Normal {
-                piece,
-                start,
-                end,
-                captured_piece,
-            } => {
-                let mut s = String::new();
-                s.push_str(piece.as_str_pgn());
-                s.push(((start.col()) as u8 + b'a') as char);
-                if captured_piece.is_some() {
-                    s.push('x');
-                }
-                s.push(((end.col()) as u8 + b'a') as char);
-                s.push_str((end.row() + 1).to_string().as_str());
-                s
-            }
-            Self::CastlingShort { .. } => String::from_str("O-O").unwrap(),
-            Self::CastlingLong { .. } => String::from_str("O-O-O").unwrap(),
-            Self::EnPassant {
-                start_col,
-                end_col,
-                owner,
-            } => {
-                let mut s = String::new();
-                s.push((*start_col as u8 + b'a') as char);
-                s.push('x');
-                s.push((*end_col as u8 + b'a') as char);
-                match owner {
-                    Player::White => s.push('6'),
-                    Player::Black => s.push('3'),
+    /// Reads a move in the long algebraic notation of UCI, e.g. `e2e4` or `a7a8n`
+    ///
+    /// Only legal moves are returned, the error tells apart strings that aren't a move
+    /// from moves that can't be played in this position.
+    pub fn from_uci_notation(s: &str, game: &Game) -> Result<Self, UciMoveError> {
+        let unparseable = || UciMoveError::Unparseable(s.to_string());
+
+        let (start, end, promotion) = match *s.as_bytes() {
+            [start_col, start_row, end_col, end_row, ref promotion @ ..] => {
+                let position = |col: u8, row: u8| {
+                    Position::new(row.wrapping_sub(b'1') as i8, col.wrapping_sub(b'a') as i8)
                 };
-                s
-            }
-            Self::Promotion {
-                end,
-                captured_piece,
-                new_piece,
-                ..
-            } => {
-                let mut s = String::new();
-                if captured_piece.is_some() {
-                    s.push('x');
-                }
-                s.push(((end.col()) as u8 + b'a') as char);
-                s.push_str((end.row() + 1).to_string().as_str());
-                s.push('=');
-                s.push(match new_piece {
-                    PieceType::Queen => 'Q',
-                    PieceType::Rook => 'R',
-                    PieceType::Bishop => 'K',
-                    PieceType::Knight => 'B',
-                    _ => unreachable!(),
-                });
-                s
+                let start = position(start_col, start_row).ok_or_else(unparseable)?;
+                let end = position(end_col, end_row).ok_or_else(unparseable)?;
+
+                let promotion = match promotion {
+                    [] => None,
+                    [new_piece] => Some(match new_piece.to_ascii_lowercase() {
+                        b'q' => PieceType::Queen,
+                        b'r' => PieceType::Rook,
+                        b'b' => PieceType::Bishop,
+                        b'n' => PieceType::Knight,
+                        _ => return Err(unparseable()),
+                    }),
+                    _ => return Err(unparseable()),
+                };
+
+                (start, end, promotion)
             }
+            _ => return Err(unparseable()),
+        };
+
+        let _move = Self::from_squares(start, end, promotion, game)
+            .ok_or_else(|| UciMoveError::Illegal(s.to_string()))?;
+
+        if game.is_legal(_move) {
+            Ok(_move)
+        } else {
+            Err(UciMoveError::Illegal(s.to_string()))
         }
     }
 
-    pub fn from_uci_notation(s: &str, game: &Game) -> Option<Self> {
-        if s == "e1g1" && game.get_king_position(Player::White) == Position::new_assert(0, 4) {
-            Some(Self::CastlingShort {
-                owner: Player::White,
-            })
-        } else if s == "e8g8" && game.get_king_position(Player::Black) == Position::new_assert(7, 4)
-        {
-            Some(Self::CastlingShort {
-                owner: Player::Black,
-            })
-        } else if s == "e1c1" && game.get_king_position(Player::White) == Position::new_assert(0, 4)
-        {
-            Some(Self::CastlingLong {
-                owner: Player::White,
-            })
-        } else if s == "e8c8" && game.get_king_position(Player::Black) == Position::new_assert(7, 4)
-        {
-            Some(Self::CastlingLong {
-                owner: Player::Black,
-            })
-        } else {
-            let mut chars = s.bytes();
-            let start_col = chars.next()?.wrapping_sub(b'a') as i8;
-            let start_row = chars.next()?.wrapping_sub(b'1') as i8;
-            let end_col = chars.next()?.wrapping_sub(b'a') as i8;
-            let end_row = chars.next()?.wrapping_sub(b'1') as i8;
-
-            let start = Position::new(start_row, start_col)?;
-            let end = Position::new(end_row, end_col)?;
-
-            if let Some(new_piece) = s.chars().nth(4) {
-                let new_piece = match new_piece {
-                    'q' | 'Q' => PieceType::Queen,
-                    'r' | 'R' => PieceType::Rook,
-                    'n' | 'N' => PieceType::Knight,
-                    'b' | 'B' => PieceType::Bishop,
-                    _ => return None,
-                };
+    /// The move between two squares in this position, which might not be legal
+    fn from_squares(
+        start: Position,
+        end: Position,
+        promotion: Option<PieceType>,
+        game: &Game,
+    ) -> Option<Self> {
+        let player = game.current_player;
+        let piece = game.get_position(start)?;
 
-                return Some(Self::Promotion {
-                    owner: game.current_player,
-                    start,
-                    end,
-                    new_piece,
-                    captured_piece: game.get_position(end),
-                });
-            }
+        if let Some(new_piece) = promotion {
+            return Some(Self::promotion(start, end, new_piece));
+        }
 
-            if let Some(piece) = game.get_position(start) {
-                // This move is either en passant or normal
-                return if piece.piece_type == PieceType::Pawn
-                    && game.get_position(end).is_none()
-                    && i8::abs(start.col() - end.col()) == 1
-                {
-                    Some(Self::EnPassant {
-                        owner: game.current_player,
-                        start_col: start.col(),
-                        end_col: end.col(),
-                    })
-                } else {
-                    Some(Self::Normal {
-                        piece,
-                        start,
-                        end,
-                        captured_piece: game.get_position(end),
-                    })
-                };
+        let state = game.state();
+        for side in CastlingSide::ALL {
+            let (old_king, new_king, old_rook, _) = game.castling_squares(player, side);
+            let target = if game.is_chess960() {
+                old_rook
+            } else {
+                new_king
+            };
+
+            if state.castling(player, side) && start == old_king && end == target {
+                return Some(Self::castling(old_king, new_king, side));
             }
+        }
 
-            None
+        // This move is either en passant or normal
+        if piece.piece_type == PieceType::Pawn
+            && game.get_position(end).is_none()
+            && i8::abs(start.col() - end.col()) == 1
+        {
+            Some(Self::en_passant(start, end))
+        } else {
+            Some(Self::new(start, end))
         }
     }
 }
 
-impl std::fmt::Debug for Move {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+/// Reasons a move is rejected by `Move::from_uci_notation`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum UciMoveError {
+    /// The string isn't a move in UCI notation
+    Unparseable(String),
+    /// The move is well formed but can't be played in the position
+    Illegal(String),
+}
+
+impl core::fmt::Display for UciMoveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         match self {
-            Self::Normal {
-                piece,
-                start,
-                end,
-                captured_piece,
-            } => write!(
-                f,
-                "{:?} {:?} from {} {} to {} {}, captured {:?} ",
-                piece.owner,
-                piece.piece_type,
-                start.row(),
-                start.col(),
-                end.row(),
-                end.col(),
-                captured_piece.map(|piece| format!("{:?} {:?}", piece.owner, piece.piece_type))
+            Self::Unparseable(s) => write!(f, "Invalid move: {}", s),
+            Self::Illegal(s) => write!(f, "Illegal move: {}", s),
+        }
+    }
+}
+
+impl core::error::Error for UciMoveError {}
+
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct MoveJson {
+    start: Position,
+    end: Position,
+    kind: MoveKind,
+}
+
+#[cfg(feature = "serde")]
+impl From<Move> for MoveJson {
+    fn from(_move: Move) -> Self {
+        Self {
+            start: _move.start(),
+            end: _move.end(),
+            kind: _move.kind(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<MoveJson> for Move {
+    type Error = String;
+
+    fn try_from(json: MoveJson) -> Result<Self, Self::Error> {
+        Ok(match json.kind {
+            MoveKind::Normal => Self::new(json.start, json.end),
+            MoveKind::EnPassant => Self::en_passant(json.start, json.end),
+            MoveKind::Castling(side) => Self::castling(json.start, json.end, side),
+            MoveKind::Promotion(PieceType::Pawn | PieceType::King) => {
+                return Err(String::from("Promotion to a pawn or a king"))
+            }
+            MoveKind::Promotion(new_piece) => Self::promotion(json.start, json.end, new_piece),
+        })
+    }
+}
+
+impl core::fmt::Debug for Move {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        write!(
+            f,
+            "{:?} from {} {} to {} {}",
+            self.kind(),
+            self.start().row(),
+            self.start().col(),
+            self.end().row(),
+            self.end().col()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str, fen: &str) -> Result<String, UciMoveError> {
+        let game = Game::new(fen).unwrap();
+        Move::from_uci_notation(s, &game).map(|_move| _move.uci_notation(&game))
+    }
+
+    #[test]
+    fn packing() {
+        assert_eq!(std::mem::size_of::<Move>(), 2);
+
+        let a1 = Position::new_assert(0, 0);
+        let h8 = Position::new_assert(7, 7);
+        let kinds = [
+            (Move::new(a1, h8), MoveKind::Normal),
+            (Move::en_passant(h8, a1), MoveKind::EnPassant),
+            (
+                Move::castling(a1, a1, CastlingSide::Short),
+                MoveKind::Castling(CastlingSide::Short),
             ),
-            Self::CastlingLong { owner } => write!(f, "castling long {:?} ", *owner),
-            Self::CastlingShort { owner } => write!(f, "castling short {:?} ", *owner),
-            _ => write!(f, "not supported"),
+            (
+                Move::castling(h8, a1, CastlingSide::Long),
+                MoveKind::Castling(CastlingSide::Long),
+            ),
+        ];
+        let promotions = [
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+        ]
+        .map(|new_piece| {
+            (
+                Move::promotion(h8, h8, new_piece),
+                MoveKind::Promotion(new_piece),
+            )
+        });
+
+        for (_move, kind) in kinds.into_iter().chain(promotions) {
+            assert_eq!(_move.kind(), kind);
+        }
+        assert_eq!(Move::new(a1, h8).start(), a1);
+        assert_eq!(Move::new(a1, h8).end(), h8);
+        assert_eq!(Move::promotion(h8, a1, PieceType::Knight).start(), h8);
+        assert_eq!(Move::promotion(h8, a1, PieceType::Knight).end(), a1);
+    }
+
+    #[test]
+    #[should_panic(expected = "No piece on the start square of the move")]
+    fn move_of_another_position() {
+        let mut game = Game::default();
+        let empty = Position::new_assert(3, 3);
+        game.push(Move::new(empty, Position::new_assert(4, 3)));
+    }
+
+    #[test]
+    fn parse_uci() {
+        let promotion = "1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1";
+        for uci in ["a7a8q", "a7a8r", "a7a8b", "a7a8n", "a7b8q", "a7b8n"] {
+            assert_eq!(parse(uci, promotion).as_deref(), Ok(uci));
+        }
+        assert_eq!(parse("a7a8Q", promotion).as_deref(), Ok("a7a8q"));
+
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+        assert_eq!(parse("e5d6", en_passant).as_deref(), Ok("e5d6"));
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1";
+        assert_eq!(parse("e1c1", castling).as_deref(), Ok("e1c1"));
+
+        let unparseable = [
+            "", "e2", "e2e", "e2e4 ", "e2e4qq", "e2e4k", "e2e4p", "e2e4=q", "i2e4", "e0e4", "e2e9",
+            "E2E4", "0000", "Nf3", "é2e4", "e2e4é",
+        ];
+        for uci in unparseable {
+            assert_eq!(
+                parse(uci, promotion),
+                Err(UciMoveError::Unparseable(uci.to_string()))
+            );
+        }
+
+        let illegal = [
+            // The promotion piece is missing or the move doesn't promote
+            ("a7a8", promotion),
+            ("e1e2q", promotion),
+            // Empty starting square, pieces of the opponent, impossible destinations
+            ("d4d5", promotion),
+            ("b8b7", promotion),
+            ("e1e3", promotion),
+            ("e5f6", en_passant),
+            ("e1g1", en_passant),
+            ("e1a1", castling),
+        ];
+        for (uci, fen) in illegal {
+            assert_eq!(parse(uci, fen), Err(UciMoveError::Illegal(uci.to_string())));
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json() {
+        let game = Game::new("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        for (uci, json) in [
+            (
+                "e1g1",
+                r#"{"start":"e1","end":"g1","kind":{"castling":"short"}}"#,
+            ),
+            ("e5d6", r#"{"start":"e5","end":"d6","kind":"en_passant"}"#),
+            (
+                "b7a8n",
+                r#"{"start":"b7","end":"a8","kind":{"promotion":"knight"}}"#,
+            ),
+            ("a1a8", r#"{"start":"a1","end":"a8","kind":"normal"}"#),
+        ] {
+            let _move = Move::from_uci_notation(uci, &game).unwrap();
+            assert_eq!(serde_json::to_string(&_move).unwrap(), json);
+            assert_eq!(serde_json::from_str::<Move>(json).unwrap(), _move);
+        }
+
+        for json in [
+            r#"{"start":"b7","end":"b8","kind":{"promotion":"king"}}"#,
+            r#"{"start":"i1","end":"a8","kind":"normal"}"#,
+            r#"{"start":"a1","end":"a9","kind":"normal"}"#,
+        ] {
+            assert!(serde_json::from_str::<Move>(json).is_err(), "{}", json);
         }
     }
 }
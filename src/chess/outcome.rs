@@ -0,0 +1,190 @@
+use super::bitboard::Bitboard;
+use super::pgn::GameResult;
+use super::piece::PieceType;
+use super::{Game, Player};
+
+/// Light squares, the ones of the same color as h1
+const LIGHT_SQUARES: Bitboard = 0x55AA_55AA_55AA_55AA;
+
+/// Whether the game is over in a position, and how it ended
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Outcome {
+    Ongoing,
+    /// The player who gave the checkmate won
+    Checkmate(Player),
+    Stalemate,
+    /// The position occurred for the third time
+    DrawByRepetition,
+    DrawByFiftyMoves,
+    /// No sequence of legal moves can lead to a checkmate
+    DrawByInsufficientMaterial,
+}
+
+impl Outcome {
+    pub fn is_over(self) -> bool {
+        self != Self::Ongoing
+    }
+
+    /// Result of the game as written at the end of a PGN
+    pub fn result(self) -> GameResult {
+        match self {
+            Self::Ongoing => GameResult::Unfinished,
+            Self::Checkmate(winner) => GameResult::win(winner),
+            Self::Stalemate
+            | Self::DrawByRepetition
+            | Self::DrawByFiftyMoves
+            | Self::DrawByInsufficientMaterial => GameResult::Draw,
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Ongoing => "ongoing",
+            Self::Checkmate(_) => "checkmate",
+            Self::Stalemate => "stalemate",
+            Self::DrawByRepetition => "threefold repetition",
+            Self::DrawByFiftyMoves => "fifty-move rule",
+            Self::DrawByInsufficientMaterial => "insufficient material",
+        }
+    }
+}
+
+impl Game {
+    /// Whether the game is over in this position, by the rules alone
+    ///
+    /// A checkmate or a stalemate ends the game even when the move giving it also
+    /// completes fifty moves without captures or pawn moves. Unlike the search, which
+    /// avoids the first repetition, the game is only drawn when the position occurs for
+    /// the third time.
+    pub fn outcome(&self) -> Outcome {
+        if self.legal_moves().is_empty() {
+            return if self.is_check() {
+                Outcome::Checkmate(self.player().the_other())
+            } else {
+                Outcome::Stalemate
+            };
+        }
+
+        if self.is_insufficient_material() {
+            return Outcome::DrawByInsufficientMaterial;
+        }
+        if self.is_fifty_move_draw() {
+            return Outcome::DrawByFiftyMoves;
+        }
+
+        // Same as is_repetition, but the position must have occurred twice before
+        let repetitions = self
+            .hash_history
+            .iter()
+            .rev()
+            .take(self.halfmove_clock() as usize)
+            .skip(1)
+            .step_by(2)
+            .filter(|&&hash| hash == self.hash)
+            .count();
+        if repetitions >= 2 {
+            Outcome::DrawByRepetition
+        } else {
+            Outcome::Ongoing
+        }
+    }
+
+    /// Whether neither player can give checkmate: only kings are left with at most one
+    /// knight or bishop, or with bishops which are all on squares of the same color
+    pub fn is_insufficient_material(&self) -> bool {
+        let mating_pieces = self.pieces(PieceType::Queen)
+            | self.pieces(PieceType::Rook)
+            | self.pieces(PieceType::Pawn);
+        if mating_pieces != 0 {
+            return false;
+        }
+
+        let bishops = self.pieces(PieceType::Bishop);
+        match self.pieces(PieceType::Knight).count_ones() {
+            0 => bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0,
+            1 => bishops == 0,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::move_struct::Move;
+
+    fn play(fen: &str, moves: &str) -> Game {
+        let mut game = Game::new(fen).unwrap();
+        for _move in moves.split_ascii_whitespace() {
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            game.push_history(_move);
+        }
+        game
+    }
+
+    #[test]
+    fn outcomes() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        for (fen, moves, outcome) in [
+            (start, "", Outcome::Ongoing),
+            (
+                start,
+                "f2f3 e7e5 g2g4 d8h4",
+                Outcome::Checkmate(Player::Black),
+            ),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", "", Outcome::Stalemate),
+            (
+                start,
+                "g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8",
+                Outcome::DrawByRepetition,
+            ),
+            (
+                "7k/8/6K1/8/8/8/8/R7 b - - 100 80",
+                "",
+                Outcome::DrawByFiftyMoves,
+            ),
+            // Mate on the move which completes the fifty moves
+            (
+                "6k1/5ppp/8/8/8/8/8/R5K1 w - - 99 80",
+                "a1a8",
+                Outcome::Checkmate(Player::White),
+            ),
+            (
+                "7k/8/6K1/8/8/8/8/8 w - - 0 1",
+                "",
+                Outcome::DrawByInsufficientMaterial,
+            ),
+            // The last piece which could give mate is taken
+            (
+                "7k/8/8/8/8/8/6r1/7K w - - 0 1",
+                "h1g2",
+                Outcome::DrawByInsufficientMaterial,
+            ),
+        ] {
+            let game = play(fen, moves);
+            assert_eq!(game.outcome(), outcome, "{} {}", fen, moves);
+            assert_eq!(game.result(), outcome.result(), "{} {}", fen, moves);
+        }
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for (fen, insufficient) in [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/4KN2 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/4KB2 w - - 0 1", true),
+            // Bishops on squares of the same color, even of both players
+            ("2b1k3/8/8/8/8/8/8/4KB2 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/3BKB2 w - - 0 1", true),
+            ("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/4KNN1 w - - 0 1", false),
+            ("4kn2/8/8/8/8/8/8/4KN2 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/4KBN1 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", false),
+            ("4k3/8/8/8/8/8/8/4KR2 w - - 0 1", false),
+        ] {
+            let game = Game::new(fen).unwrap();
+            assert_eq!(game.is_insufficient_material(), insufficient, "{}", fen);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use super::piece::PieceType;
+use super::scores::*;
+use super::{GamePhase, Score};
+use alloc::{boxed::Box, format, sync::Arc, vec, vec::Vec};
+use anyhow::{bail, Context};
+use core::fmt;
+use once_cell::race::OnceBox;
+
+/// Declares the parameters along with the constants holding their default values
+macro_rules! eval_params {
+    ($($field:ident: $type:ty = $constant:ident,)*) => {
+        /// Weights of the evaluation which can be loaded from a file at runtime,
+        /// without recompiling the engine, the defaults are the constants of `scores.rs`
+        ///
+        /// The file lists every parameter by the name of its constant, followed by its values,
+        /// e.g. `DOUBLED_PAWN_PENALTY 10` or `PASSED_PAWN_BONUS 0 5 10 20 35 60 100 0`.
+        /// Values may span several lines, `#` starts a comment, and parameters which
+        /// are left out keep their default value.
+        ///
+        /// The piece values and the endgame threshold aren't included, since the material
+        /// is kept up to date by push/pop and the tables already include it.
+        #[derive(Clone, PartialEq, Debug)]
+        pub struct EvalParams {
+            $(pub $field: $type,)*
+        }
+
+        impl EvalParams {
+            pub const DEFAULT: Self = Self {
+                $($field: $constant,)*
+            };
+
+            fn values(&self) -> Vec<(&'static str, Vec<Score>)> {
+                vec![$((stringify!($constant), self.$field.values()),)*]
+            }
+
+            fn set_values(&mut self, name: &str, values: &[Score]) -> anyhow::Result<()> {
+                match name {
+                    $(stringify!($constant) => self.$field.set_values(values),)*
+                    _ => bail!("Unknown parameter: {}", name),
+                }
+            }
+        }
+    };
+}
+
+eval_params! {
+    queen_scores: [i16; 64] = QUEEN_SCORES,
+    rook_scores: [i16; 64] = ROOK_SCORES,
+    bishop_scores: [i16; 64] = BISHOP_SCORES,
+    knight_scores: [i16; 64] = KNIGHT_SCORES,
+    pawn_scores: [i16; 64] = PAWN_SCORES,
+    king_scores_middle: [i16; 64] = KING_SCORES_MIDDLE,
+    king_scores_end: [i16; 64] = KING_SCORES_END,
+    doubled_pawn_penalty: Score = DOUBLED_PAWN_PENALTY,
+    isolated_pawn_penalty: Score = ISOLATED_PAWN_PENALTY,
+    passed_pawn_bonus: [Score; 8] = PASSED_PAWN_BONUS,
+    connected_pawn_bonus: [Score; 8] = CONNECTED_PAWN_BONUS,
+    supported_pawn_bonus: Score = SUPPORTED_PAWN_BONUS,
+    trapped_knight_penalty: Score = TRAPPED_KNIGHT_PENALTY,
+    trapped_bishop_penalty: Score = TRAPPED_BISHOP_PENALTY,
+    bad_bishop_penalty: Score = BAD_BISHOP_PENALTY,
+    pinned_piece_penalty: Score = PINNED_PIECE_PENALTY,
+    missing_shield_pawn_penalty: Score = MISSING_SHIELD_PAWN_PENALTY,
+    advanced_shield_pawn_penalty: Score = ADVANCED_SHIELD_PAWN_PENALTY,
+    open_file_near_king_penalty: Score = OPEN_FILE_NEAR_KING_PENALTY,
+    pawn_storm_penalty: [Score; 8] = PAWN_STORM_PENALTY,
+    space_bonus: Score = SPACE_BONUS,
+    threat_by_pawn_bonus: Score = THREAT_BY_PAWN_BONUS,
+    threat_by_lesser_piece_bonus: Score = THREAT_BY_LESSER_PIECE_BONUS,
+    hanging_piece_bonus: Score = HANGING_PIECE_BONUS,
+    underdefended_piece_bonus: Score = UNDERDEFENDED_PIECE_BONUS,
+    kpk_win_bonus: Score = KPK_WIN_BONUS,
+    kbnk_corner_bonus: Score = KBNK_CORNER_BONUS,
+    mop_up_center_distance_bonus: Score = MOP_UP_CENTER_DISTANCE_BONUS,
+    mop_up_king_proximity_bonus: Score = MOP_UP_KING_PROXIMITY_BONUS,
+    pawnless_scale: Score = PAWNLESS_SCALE,
+    pawnless_against_minor_scale: Score = PAWNLESS_AGAINST_MINOR_SCALE,
+    opposite_bishops_scale: Score = OPPOSITE_BISHOPS_SCALE,
+    opposite_bishops_with_pieces_scale: Score = OPPOSITE_BISHOPS_WITH_PIECES_SCALE,
+    blocked_rook_endgame_scale: Score = BLOCKED_ROOK_ENDGAME_SCALE,
+    mobility_bonus: [Score; 6] = MOBILITY_BONUS,
+    mobility_baseline: [Score; 6] = MOBILITY_BASELINE,
+}
+
+/// A parameter made of one or more numbers
+trait ParamValues {
+    fn values(&self) -> Vec<Score>;
+    fn set_values(&mut self, values: &[Score]) -> anyhow::Result<()>;
+}
+
+impl ParamValues for Score {
+    fn values(&self) -> Vec<Score> {
+        vec![*self]
+    }
+
+    fn set_values(&mut self, values: &[Score]) -> anyhow::Result<()> {
+        let [value] = values else {
+            bail!("Expected a single value, found {}", values.len());
+        };
+        *self = *value;
+        Ok(())
+    }
+}
+
+impl<const N: usize> ParamValues for [Score; N] {
+    fn values(&self) -> Vec<Score> {
+        self.to_vec()
+    }
+
+    fn set_values(&mut self, values: &[Score]) -> anyhow::Result<()> {
+        if values.len() != N {
+            bail!("Expected {} values, found {}", N, values.len());
+        }
+        self.copy_from_slice(values);
+        Ok(())
+    }
+}
+
+impl ParamValues for [i16; 64] {
+    fn values(&self) -> Vec<Score> {
+        self.iter().map(|&value| value as Score).collect()
+    }
+
+    fn set_values(&mut self, values: &[Score]) -> anyhow::Result<()> {
+        if values.len() != 64 {
+            bail!("Expected 64 values, found {}", values.len());
+        }
+        for (entry, &value) in self.iter_mut().zip(values) {
+            *entry = i16::try_from(value).context("Piece-square value out of range")?;
+        }
+        Ok(())
+    }
+}
+
+impl EvalParams {
+    /// Scores of `piece_type` by square from the side of white, the king has its own in
+    /// the endgame
+    #[inline]
+    pub fn piece_scores(&self, piece_type: PieceType, phase: GamePhase) -> &[i16; 64] {
+        match (piece_type, phase) {
+            (PieceType::Queen, _) => &self.queen_scores,
+            (PieceType::Rook, _) => &self.rook_scores,
+            (PieceType::Bishop, _) => &self.bishop_scores,
+            (PieceType::Knight, _) => &self.knight_scores,
+            (PieceType::Pawn, _) => &self.pawn_scores,
+            (PieceType::King, GamePhase::Opening) => &self.king_scores_middle,
+            (PieceType::King, GamePhase::Endgame) => &self.king_scores_end,
+        }
+    }
+
+    /// Parses the format described on the struct, starting from the defaults
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let mut params = Self::DEFAULT;
+
+        let mut terms = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(str::split_ascii_whitespace)
+            .peekable();
+
+        while let Some(name) = terms.next() {
+            let mut values = Vec::new();
+            while let Some(value) = terms.next_if(|term| !term.starts_with(char::is_alphabetic)) {
+                values.push(
+                    value
+                        .parse()
+                        .with_context(|| format!("Invalid value of {}: {}", name, value))?,
+                );
+            }
+
+            params
+                .set_values(name, &values)
+                .with_context(|| format!("Invalid parameter {}", name))?;
+        }
+
+        Ok(params)
+    }
+
+    #[cfg(feature = "std")]
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text =
+            std::fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+        Self::parse(&text).with_context(|| format!("Failed to parse {}", path))
+    }
+}
+
+/// Written in the format read by `EvalParams::parse`, tables are split into rows
+impl fmt::Display for EvalParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, values) in self.values() {
+            write!(f, "{}", name)?;
+            for (index, value) in values.iter().enumerate() {
+                if values.len() > 8 && index % 8 == 0 {
+                    write!(f, "\n   ")?;
+                }
+                write!(f, " {}", value)?;
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The defaults, shared by every game which isn't given other parameters
+pub fn default() -> Arc<EvalParams> {
+    static DEFAULT: OnceBox<Arc<EvalParams>> = OnceBox::new();
+    DEFAULT
+        .get_or_init(|| Box::new(Arc::new(EvalParams::DEFAULT)))
+        .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut params = EvalParams::DEFAULT;
+        params.doubled_pawn_penalty = 42;
+        params.passed_pawn_bonus[6] = 150;
+        params.knight_scores[27] = 345;
+
+        assert_eq!(EvalParams::parse(&params.to_string()).unwrap(), params);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let params =
+            EvalParams::parse("# comment\nSPACE_BONUS 6 # inline\nMOBILITY_BONUS 1 2 3\n 4 5 6")
+                .unwrap();
+        assert_eq!(params.space_bonus, 6);
+        assert_eq!(params.mobility_bonus, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(params.hanging_piece_bonus, HANGING_PIECE_BONUS);
+
+        assert!(EvalParams::parse("UNKNOWN_BONUS 5").is_err());
+        assert!(EvalParams::parse("SPACE_BONUS").is_err());
+        assert!(EvalParams::parse("SPACE_BONUS 1 2").is_err());
+        assert!(EvalParams::parse("PASSED_PAWN_BONUS 1 2 3").is_err());
+        assert!(EvalParams::parse("SPACE_BONUS x").is_err());
+    }
+}
@@ -0,0 +1,792 @@
+use super::move_struct::Move;
+use super::score::{self, Score};
+use super::{Game, Player, START_FEN};
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use anyhow::{bail, Context};
+use core::fmt;
+
+// Portable Game Notation: https://www.chessprogramming.org/Portable_Game_Notation
+
+/// Lines of the movetext are wrapped before this length, as in the export format of PGN
+const MAX_LINE_LENGTH: usize = 80;
+
+/// Drops of the score of the player who moved, in centipawns, from which the move
+/// is marked as dubious (`$6`), a mistake (`$2`) or a blunder (`$4`)
+const INACCURACY: Score = 50;
+const MISTAKE: Score = 100;
+const BLUNDER: Score = 300;
+
+/// Result of a game, as written at the end of a PGN
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    /// The game is still going on, or was abandoned
+    Unfinished,
+}
+
+impl GameResult {
+    pub fn win(player: Player) -> Self {
+        match player {
+            Player::White => Self::WhiteWins,
+            Player::Black => Self::BlackWins,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::WhiteWins => "1-0",
+            Self::BlackWins => "0-1",
+            Self::Draw => "1/2-1/2",
+            Self::Unfinished => "*",
+        }
+    }
+}
+
+/// Evaluation of the position after a move, written in a comment after it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Annotation {
+    /// Score from the point of view of white
+    pub score: Score,
+    /// Depth of the search which found the score
+    pub depth: u8,
+}
+
+impl Annotation {
+    /// Numeric Annotation Glyph of a move by `player`, according to how much worse
+    /// the position became for them
+    fn glyph(self, before: Self, player: Player) -> Option<u8> {
+        let loss = (before.score - self.score) * player as Score;
+        if loss >= BLUNDER {
+            Some(4)
+        } else if loss >= MISTAKE {
+            Some(2)
+        } else if loss >= INACCURACY {
+            Some(6)
+        } else {
+            None
+        }
+    }
+}
+
+/// A move of a game or of a variation, with the annotations written after it
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PgnMove {
+    pub _move: Move,
+    /// Numeric Annotation Glyphs, e.g. 2 for `$2`, which is also how `?` is read
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+    /// Lines played instead of this move, from the position before it
+    pub variations: Vec<Vec<PgnMove>>,
+}
+
+impl PgnMove {
+    pub fn new(_move: Move) -> Self {
+        Self {
+            _move,
+            nags: Vec::new(),
+            comment: None,
+            variations: Vec::new(),
+        }
+    }
+}
+
+/// A game read from a PGN document
+pub struct PgnGame {
+    /// Tag pairs in the order they were written, e.g. `("White", "Tal, Mikhail")`
+    pub tags: Vec<(String, String)>,
+    /// The position the game starts from, given by the `FEN` tag if there is one
+    pub start: Game,
+    pub moves: Vec<PgnMove>,
+    pub result: GameResult,
+}
+
+impl PgnGame {
+    /// Value of the first tag with this name
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Replaces the value of the tag, or adds it after the others
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some((_, tag_value)) => *tag_value = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+}
+
+/// Writes the moves of a line played from `game`, each variation in parentheses after
+/// the move it replaces. Black's moves are numbered at the start of the line and after
+/// anything that interrupts the moves.
+fn write_line(tokens: &mut Vec<String>, mut game: Game, line: &[PgnMove]) {
+    let mut needs_number = true;
+
+    for pgn_move in line {
+        if game.player() == Player::White {
+            tokens.push(format!("{}.", game.fullmove_number()));
+        } else if needs_number {
+            tokens.push(format!("{}...", game.fullmove_number()));
+        }
+        tokens.push(pgn_move._move.to_san(&game));
+        tokens.extend(pgn_move.nags.iter().map(|nag| format!("${}", nag)));
+        needs_number = false;
+
+        if let Some(comment) = &pgn_move.comment {
+            // Comments end at the first closing brace
+            tokens.push(format!("{{{}}}", comment.replace('}', ")")));
+            needs_number = true;
+        }
+
+        for variation in &pgn_move.variations {
+            let start = tokens.len();
+            write_line(tokens, game.clone(), variation);
+            tokens[start].insert(0, '(');
+            tokens.last_mut().unwrap().push(')');
+            needs_number = true;
+        }
+
+        game.push_history(pgn_move._move);
+    }
+}
+
+/// The tags, then the moves and the result, with lines of movetext wrapped
+/// like in the export format
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            // Quotes and backslashes are the only characters escaped in tag values
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{} \"{}\"]", name, value)?;
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        write_line(&mut tokens, self.start.clone(), &self.moves);
+        tokens.push(self.result.as_str().to_string());
+
+        let mut line_length = 0;
+        for token in &tokens {
+            if line_length > 0 && line_length + 1 + token.len() >= MAX_LINE_LENGTH {
+                writeln!(f)?;
+                line_length = 0;
+            } else if line_length > 0 {
+                write!(f, " ")?;
+                line_length += 1;
+            }
+            write!(f, "{}", token)?;
+            line_length += token.len();
+        }
+        writeln!(f)
+    }
+}
+
+/// The parts of a PGN document that matter, escaped lines and move numbers are left out
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Token {
+    Tag(String, String),
+    San(String),
+    Nag(u8),
+    Comment(String),
+    VariationStart,
+    VariationEnd,
+    Result(GameResult),
+}
+
+/// Pushes a move, with the annotation written after it, like `!?`, as its glyph
+fn push_san(tokens: &mut Vec<Token>, symbol: &str) {
+    let san = symbol.trim_end_matches(['!', '?']);
+    let nag = match &symbol[san.len()..] {
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        _ => None,
+    };
+
+    tokens.push(Token::San(san.to_string()));
+    tokens.extend(nag.map(Token::Nag));
+}
+
+fn tokenize(text: &str) -> anyhow::Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(character) = chars.next() {
+        match character {
+            // Lines starting with a percent sign are ignored, like the rest of the line after a semicolon
+            '%' if line_start => {
+                chars.find(|&character| character == '\n');
+            }
+            ';' => {
+                chars.find(|&character| character == '\n');
+            }
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next().context("Unterminated comment")? {
+                        '}' => break,
+                        character => comment.push(character),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '[' => {
+                let mut name = String::new();
+                while let Some(character) = chars.next_if(|character| !character.is_whitespace()) {
+                    name.push(character);
+                }
+                while chars
+                    .next_if(|character| character.is_whitespace())
+                    .is_some()
+                {}
+                if chars.next() != Some('"') {
+                    bail!("Missing value of tag {}", name);
+                }
+
+                let mut value = String::new();
+                loop {
+                    match chars.next().context("Unterminated tag")? {
+                        '"' => break,
+                        '\\' => value.push(chars.next().context("Unterminated tag")?),
+                        character => value.push(character),
+                    }
+                }
+                chars
+                    .find(|&character| character == ']')
+                    .context("Unterminated tag")?;
+
+                tokens.push(Token::Tag(name, value));
+            }
+            _ if character.is_whitespace() => (),
+            _ => {
+                let mut symbol = String::from(character);
+                while let Some(character) = chars.next_if(|character| {
+                    !character.is_whitespace() && !"{}()[];".contains(*character)
+                }) {
+                    symbol.push(character);
+                }
+
+                let result = match symbol.as_str() {
+                    "1-0" => Some(GameResult::WhiteWins),
+                    "0-1" => Some(GameResult::BlackWins),
+                    "1/2-1/2" => Some(GameResult::Draw),
+                    "*" => Some(GameResult::Unfinished),
+                    _ => None,
+                };
+
+                if let Some(result) = result {
+                    tokens.push(Token::Result(result));
+                } else if let Some(nag) = symbol.strip_prefix('$') {
+                    let nag = nag
+                        .parse()
+                        .with_context(|| format!("Invalid glyph {}", symbol))?;
+                    tokens.push(Token::Nag(nag));
+                } else if symbol.starts_with("0-0") {
+                    push_san(&mut tokens, &symbol);
+                } else if symbol.starts_with(|character: char| character.is_ascii_digit()) {
+                    // A move number, which may be followed by the move without a space
+                    let san = symbol
+                        .trim_start_matches(|character: char| character.is_ascii_digit())
+                        .strip_prefix('.')
+                        .with_context(|| format!("Invalid symbol {}", symbol))?
+                        .trim_start_matches('.');
+                    if !san.is_empty() {
+                        push_san(&mut tokens, san);
+                    }
+                } else {
+                    push_san(&mut tokens, &symbol);
+                }
+            }
+        }
+
+        line_start = character == '\n';
+    }
+
+    Ok(tokens)
+}
+
+/// The position a game starts from, according to its tags
+fn start_position(tags: &[(String, String)]) -> anyhow::Result<Game> {
+    let tag = |name: &str| {
+        tags.iter()
+            .find(|(tag_name, _)| tag_name == name)
+            .map(|(_, value)| value.as_str())
+    };
+
+    let mut game = match tag("FEN") {
+        Some(fen) => Game::new(fen).with_context(|| format!("Invalid FEN {}", fen))?,
+        None => Game::default(),
+    };
+    game.set_chess960(tag("Variant").is_some_and(|variant| {
+        variant.eq_ignore_ascii_case("chess960") || variant.eq_ignore_ascii_case("fischerandom")
+    }));
+
+    Ok(game)
+}
+
+/// A game whose result wasn't read yet
+struct PartialGame {
+    start: Game,
+    /// The main line, then the variations being read inside each other,
+    /// each one with the position after its moves
+    lines: Vec<(Game, Vec<PgnMove>)>,
+}
+
+impl PartialGame {
+    fn new(tags: &[(String, String)]) -> anyhow::Result<Self> {
+        let start = start_position(tags)?;
+        Ok(Self {
+            lines: vec![(start.clone(), Vec::new())],
+            start,
+        })
+    }
+
+    /// Reads a token of the movetext
+    fn read(&mut self, token: Token) -> anyhow::Result<()> {
+        let (game, moves) = self.lines.last_mut().unwrap();
+        match token {
+            Token::San(san) => {
+                let _move = Move::from_san(&san, game).with_context(|| {
+                    format!("Invalid move {} at move {}", san, game.fullmove_number())
+                })?;
+                game.push_history(_move);
+                moves.push(PgnMove::new(_move));
+            }
+            // Annotations before the first move of a line are left out
+            Token::Nag(nag) => {
+                if let Some(last) = moves.last_mut() {
+                    last.nags.push(nag);
+                }
+            }
+            Token::Comment(comment) => {
+                if let Some(last) = moves.last_mut() {
+                    last.comment = Some(match last.comment.take() {
+                        Some(previous) => format!("{} {}", previous, comment),
+                        None => comment,
+                    });
+                }
+            }
+            Token::VariationStart => {
+                if moves.is_empty() {
+                    bail!("Variation without a move to replace");
+                }
+                // The variation replaces the last move
+                let mut game = game.clone();
+                game.pop();
+                game.move_stack.pop();
+                self.lines.push((game, Vec::new()));
+            }
+            Token::VariationEnd => {
+                if self.lines.len() == 1 {
+                    bail!("Unexpected end of variation");
+                }
+                let (_, variation) = self.lines.pop().unwrap();
+                let (_, moves) = self.lines.last_mut().unwrap();
+                if !variation.is_empty() {
+                    moves.last_mut().unwrap().variations.push(variation);
+                }
+            }
+            Token::Tag(..) | Token::Result(_) => unreachable!("Not part of the movetext"),
+        }
+
+        Ok(())
+    }
+
+    fn finish(
+        mut self,
+        tags: Vec<(String, String)>,
+        result: GameResult,
+    ) -> anyhow::Result<PgnGame> {
+        if self.lines.len() > 1 {
+            bail!("Unterminated variation");
+        }
+        let (_, moves) = self.lines.pop().unwrap();
+
+        Ok(PgnGame {
+            tags,
+            start: self.start,
+            moves,
+            result,
+        })
+    }
+}
+
+/// Reads every game of a PGN document, moves are written in Standard Algebraic Notation
+///
+/// A game without a result at the end of its moves is still read, as unfinished.
+/// Variations, glyphs and the comments after moves are kept, move suffixes like `?!`
+/// are read as their glyph.
+pub fn parse_pgn(text: &str) -> anyhow::Result<Vec<PgnGame>> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    let mut current: Option<PartialGame> = None;
+
+    for token in tokenize(text)? {
+        let number = games.len() + 1;
+        let context = || format!("Game {}", number);
+        match token {
+            Token::Tag(name, value) => {
+                // A game without a result ends where the tags of the next one begin
+                if let Some(game) = current.take() {
+                    let tags = core::mem::take(&mut tags);
+                    games.push(
+                        game.finish(tags, GameResult::Unfinished)
+                            .with_context(context)?,
+                    );
+                }
+                tags.push((name, value));
+            }
+            Token::Result(result) => {
+                let game = match current.take() {
+                    Some(game) => game,
+                    None => PartialGame::new(&tags).with_context(context)?,
+                };
+                games.push(
+                    game.finish(core::mem::take(&mut tags), result)
+                        .with_context(context)?,
+                );
+            }
+            token => {
+                let game = match current {
+                    Some(ref mut game) => game,
+                    None => current.insert(PartialGame::new(&tags).with_context(context)?),
+                };
+                game.read(token).with_context(context)?;
+            }
+        }
+    }
+
+    if current.is_some() || !tags.is_empty() {
+        let game = match current {
+            Some(game) => game,
+            None => PartialGame::new(&tags)?,
+        };
+        games.push(game.finish(tags, GameResult::Unfinished)?);
+    }
+
+    Ok(games)
+}
+
+impl Game {
+    /// Result of the game in this position, see `outcome` for how it ended
+    pub fn result(&self) -> GameResult {
+        self.outcome().result()
+    }
+
+    /// The game as a PGN document, with the Seven Tag Roster, the moves played since
+    /// the game was created and `result`
+    ///
+    /// Games which don't start from the standard position have their first position
+    /// in the `FEN` tag, and Chess960 games are marked as such. The opening is classified
+    /// in the `ECO` and `Opening` tags when it is known.
+    ///
+    /// Moves with an annotation, matched by index, are followed by an `[%eval]` comment with
+    /// the score and the depth, and moves which lose much more than the previous annotation
+    /// expected are marked with a glyph.
+    pub fn get_pgn(
+        &self,
+        white: &str,
+        black: &str,
+        result: GameResult,
+        annotations: &[Annotation],
+    ) -> String {
+        self.to_pgn_game(white, black, result, annotations)
+            .to_string()
+    }
+
+    /// Same as `get_pgn`, for callers which add their own tags
+    pub fn to_pgn_game(
+        &self,
+        white: &str,
+        black: &str,
+        result: GameResult,
+        annotations: &[Annotation],
+    ) -> PgnGame {
+        let initial = self.initial_position();
+        let fen = initial.to_fen();
+
+        let mut tags = vec![
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", white),
+            ("Black", black),
+            ("Result", result.as_str()),
+        ];
+        if self.is_chess960() {
+            tags.push(("Variant", "Chess960"));
+        }
+        if fen != START_FEN || self.is_chess960() {
+            tags.push(("SetUp", "1"));
+            tags.push(("FEN", &fen));
+        }
+        let opening = self.opening();
+        if let Some(opening) = opening {
+            tags.push(("ECO", opening.eco));
+            tags.push(("Opening", opening.name));
+        }
+
+        let mut player = initial.player();
+        let mut moves = Vec::with_capacity(self.move_stack.len());
+        for (index, &_move) in self.move_stack.iter().enumerate() {
+            let mut pgn_move = PgnMove::new(_move);
+            if let Some(&annotation) = annotations.get(index) {
+                let glyph = index
+                    .checked_sub(1)
+                    .and_then(|previous| annotation.glyph(annotations[previous], player));
+                pgn_move.nags.extend(glyph);
+                pgn_move.comment = Some(format!(
+                    "[%eval {},{}]",
+                    score::to_pgn(annotation.score),
+                    annotation.depth
+                ));
+            }
+            moves.push(pgn_move);
+            player = player.the_other();
+        }
+
+        PgnGame {
+            tags: tags
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            start: initial,
+            moves,
+            result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chess::move_struct::Move, constants::TESTING_GAME};
+
+    fn play(fen: &str, moves: &str) -> Game {
+        let mut game = Game::new(fen).unwrap();
+        for _move in moves.split_ascii_whitespace() {
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            game.push_history(_move);
+        }
+        game
+    }
+
+    #[test]
+    fn document() {
+        let game = play(START_FEN, "e2e4 e7e5 d1h5 b8c6 f1c4 g8f6 h5f7");
+        assert_eq!(game.result(), GameResult::WhiteWins);
+        assert_eq!(
+            game.get_pgn("RustyBait", "Opponent", game.result(), &[]),
+            "[Event \"?\"]\n\
+             [Site \"?\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"RustyBait\"]\n\
+             [Black \"Opponent\"]\n\
+             [Result \"1-0\"]\n\
+             [ECO \"C20\"]\n\
+             [Opening \"King's Pawn Game\"]\n\
+             \n\
+             1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7# 1-0\n"
+        );
+
+        // Tags are replaced in place or added at the end
+        let mut document = game.to_pgn_game("RustyBait", "Opponent", game.result(), &[]);
+        document.set_tag("Round", "3");
+        document.set_tag("Termination", "normal");
+        assert_eq!(document.tag("Round"), Some("3"));
+        assert_eq!(document.tags[3].0, "Round");
+        assert_eq!(
+            document.tags.last(),
+            Some(&(String::from("Termination"), String::from("normal")))
+        );
+
+        // The position the game starts from is kept, even if it was left
+        let game = play("4k3/8/8/8/8/8/8/R3K3 b Q - 0 20", "e8d7 e1c1");
+        let pgn = game.get_pgn("A \"quoted\" name", "?", GameResult::Unfinished, &[]);
+        assert!(pgn.contains("[White \"A \\\"quoted\\\" name\"]\n"));
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 20\"]\n"));
+        assert!(pgn.ends_with("\n\n20... Kd7 21. O-O-O+ *\n"));
+    }
+
+    #[test]
+    fn long_lines_are_wrapped() {
+        let game = play(START_FEN, TESTING_GAME);
+        let pgn = game.get_pgn("?", "?", GameResult::Unfinished, &[]);
+        let movetext = pgn.split("\n\n").nth(1).unwrap();
+
+        assert!(movetext.lines().count() > 1);
+        assert!(movetext.lines().all(|line| line.len() < MAX_LINE_LENGTH));
+        assert_eq!(
+            movetext.split_whitespace().collect::<Vec<_>>(),
+            (game.pgn_movetext() + "*")
+                .split_whitespace()
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn annotations() {
+        let game = play(START_FEN, "e2e4 e7e5 g1f3 f7f6 f3e5");
+        let annotation = |score, depth| Annotation { score, depth };
+        let annotations = [
+            annotation(30, 8),
+            annotation(25, 9),
+            annotation(-80, 7),
+            annotation(240, 8),
+        ];
+
+        let pgn = game.get_pgn("?", "?", GameResult::Unfinished, &annotations);
+        assert!(pgn.ends_with(
+            "\n\n1. e4 {[%eval 0.30,8]} 1... e5 {[%eval 0.25,9]} 2. Nf3 $2 {[%eval -0.80,7]}\n\
+             2... f6 $4 {[%eval 2.40,8]} 3. Nxe5 *\n"
+        ));
+
+        let games = parse_pgn(&pgn).unwrap();
+        assert_eq!(games[0].moves.len(), 5);
+    }
+
+    #[test]
+    fn results() {
+        assert_eq!(
+            play("7k/5Q2/8/8/8/8/8/6K1 b - - 0 1", "").result(),
+            GameResult::Draw
+        );
+        assert_eq!(
+            play("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1", "").result(),
+            GameResult::WhiteWins
+        );
+        assert_eq!(
+            play("4k3/8/8/8/8/8/8/R3K3 w - - 99 80", "").result(),
+            GameResult::Unfinished
+        );
+        assert_eq!(
+            play("4k3/8/8/8/8/8/8/R3K3 w - - 99 80", "a1a2").result(),
+            GameResult::Draw
+        );
+
+        // The starting position occurs for the third time
+        let shuffle = "g1f3 g8f6 f3g1 f6g8";
+        let game = play(START_FEN, shuffle);
+        assert_eq!(game.result(), GameResult::Unfinished);
+        let game = play(START_FEN, &format!("{} {}", shuffle, shuffle));
+        assert_eq!(game.result(), GameResult::Draw);
+    }
+
+    #[test]
+    fn read_back() {
+        let game = play(START_FEN, TESTING_GAME);
+        let pgn = game.get_pgn("White \\ \"1\"", "?", GameResult::BlackWins, &[]);
+        let games = parse_pgn(&pgn).unwrap();
+
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].tag("White"), Some("White \\ \"1\""));
+        assert_eq!(games[0].tag("Round"), Some("?"));
+        assert_eq!(games[0].result, GameResult::BlackWins);
+        assert_eq!(games[0].start.to_fen(), START_FEN);
+
+        let mut end = games[0].start.clone();
+        for pgn_move in &games[0].moves {
+            end.push_history(pgn_move._move);
+        }
+        assert_eq!(end.to_fen(), game.to_fen());
+        assert_eq!(games[0].to_string(), pgn);
+        assert_eq!(
+            end.get_pgn("White \\ \"1\"", "?", GameResult::BlackWins, &[]),
+            pgn
+        );
+    }
+
+    #[test]
+    fn variations() {
+        let text = "[Event \"?\"]\n\
+                    \n\
+                    1. e4 $1 {Best} 1... c5 (1... e5 2. Nf3 (2. f4 $5 exf4) 2... Nc6) (1... c6) 2.\n\
+                    Nf3 d6 *\n";
+        let games = parse_pgn(text).unwrap();
+        assert_eq!(games[0].to_string(), text);
+
+        let e5 = &games[0].moves[1].variations[0];
+        assert_eq!(e5[1].variations[0][0].nags, [5]);
+        assert_eq!(games[0].moves[1].variations[1].len(), 1);
+        assert_eq!(games[0].moves.len(), 4);
+    }
+
+    #[test]
+    fn read_annotated() {
+        let text = "\
+% An escaped line 1. d4
+[Event \"First\"]
+[Result \"1/2-1/2\"]
+
+1.e4 {A comment (with parentheses)} e5 $1 2. Nf3!? (2. Bc4 Nf6 (2... Bc5) 3. d3) ; comment
+2... Nc6 3.O-O-O?? 1/2-1/2
+
+[Event \"Second\"]
+[SetUp \"1\"]
+[FEN \"4k3/8/8/8/8/8/8/R3K3 b Q - 0 20\"]
+
+20... Kd7 21. 0-0-0+
+
+[Event \"Third\"]
+*";
+        // Castling long isn't possible in the first game
+        assert!(parse_pgn(text).is_err());
+
+        let text = text.replace("3.O-O-O??", "3. Bb5");
+        let games = parse_pgn(&text).unwrap();
+        assert_eq!(games.len(), 3);
+
+        assert_eq!(games[0].tag("Event"), Some("First"));
+        assert_eq!(games[0].moves.len(), 5);
+        assert_eq!(games[0].result, GameResult::Draw);
+        assert_eq!(
+            games[0].moves[0].comment.as_deref(),
+            Some("A comment (with parentheses)")
+        );
+        assert_eq!(games[0].moves[1].nags, [1]);
+        assert_eq!(games[0].moves[2].nags, [5]);
+        assert_eq!(games[0].moves[2].variations.len(), 1);
+        assert_eq!(games[0].moves[2].variations[0].len(), 3);
+        assert_eq!(games[0].moves[2].variations[0][1].variations[0].len(), 1);
+
+        assert_eq!(games[1].tag("Event"), Some("Second"));
+        assert_eq!(games[1].start.to_fen(), "4k3/8/8/8/8/8/8/R3K3 b Q - 0 20");
+        assert_eq!(games[1].moves.len(), 2);
+        assert_eq!(games[1].result, GameResult::Unfinished);
+
+        assert_eq!(games[2].tag("Event"), Some("Third"));
+        assert!(games[2].moves.is_empty());
+
+        for text in [
+            "[Event \"?",
+            "1. e4 {",
+            "1. e4 (1. d4",
+            "1. e4 (1. d4 *",
+            "(1. d4) 1. e4",
+            "1. e4 )",
+            "1. e4 $x",
+            "1. e5",
+            "1x e4",
+            "[FEN \"8/8\"] 1. e4",
+        ] {
+            assert!(parse_pgn(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -1,11 +1,40 @@
+use super::bitboard;
 use super::move_struct::Move;
+use super::params::EvalParams;
 use super::position::Position;
+use super::unchecked;
 use super::zobrist;
 use super::Score;
-use super::{Game, Player};
-use std::cell::{Cell, OnceCell};
+use super::{CastlingSide, Game, GamePhase, Player};
+
+const KNIGHT_DELTAS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (-1, -2),
+    (-2, -1),
+    (1, -2),
+    (-2, 1),
+    (-1, 2),
+    (2, -1),
+];
+
+const KING_DELTAS: [(i8, i8); 8] = [
+    (0, 1),
+    (0, -1),
+    (1, 0),
+    (-1, 0),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum PieceType {
     Queen,
     Rook,
@@ -15,13 +44,25 @@ pub enum PieceType {
     King,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Piece {
     pub piece_type: PieceType,
     pub owner: Player,
 }
 
 impl PieceType {
+    /// Letter of the piece in algebraic notation, pawns have none
+    pub fn as_str_pgn(self) -> &'static str {
+        match self {
+            PieceType::King => "K",
+            PieceType::Queen => "Q",
+            PieceType::Rook => "R",
+            PieceType::Bishop => "B",
+            PieceType::Knight => "N",
+            PieceType::Pawn => "",
+        }
+    }
+
     pub fn material_value(self) -> u8 {
         match self {
             PieceType::Pawn => 1,
@@ -35,8 +76,8 @@ impl PieceType {
 }
 
 impl Piece {
-    pub fn score(self, pos: Position, scores: &[Cell<&[i16; 64]>; 6]) -> Score {
-        let piece_score_array = scores[self.piece_type as usize].get();
+    pub fn score(self, pos: Position, params: &EvalParams, phase: GamePhase) -> Score {
+        let piece_score_array = params.piece_scores(self.piece_type, phase);
 
         let row = match self.owner {
             Player::White => 7 - pos.row(),
@@ -46,10 +87,10 @@ impl Piece {
         // SAFETY: Position is always valid
         let piece_score = unsafe {
             let position = Position::new_unsafe(row, pos.col());
-            *piece_score_array.get_unchecked(position.as_usize())
+            *unchecked::get(piece_score_array, position.as_usize())
         };
 
-        piece_score * self.owner as Score
+        piece_score as Score * self.owner as Score
     }
     pub fn material_value(self) -> u8 {
         self.piece_type.material_value()
@@ -70,72 +111,54 @@ impl Piece {
     pub fn hash(self, pos: Position) -> u64 {
         // SAFETY: self.as_usize() is always in 0..12 and pos.as_usize() is always in 0..64
         unsafe {
-            *zobrist::PIECE
-                .get_unchecked(pos.as_usize())
-                .get_unchecked(self.as_index())
+            let square = unchecked::get(&zobrist::PIECE, pos.as_usize());
+            *unchecked::get(square, self.as_index())
         }
     }
 
     pub fn get_moves(self, mut push: impl FnMut(Move), game: &Game, pos: Position) {
-        macro_rules! search_deltas {
-            ( $( $deltas:expr ),* ) => { $ (
-                for delta in $deltas {
-                    if let Some(new_pos) = pos.add(delta) {
-                        let place = game.get_position(new_pos);
-                        let _move = Move::Normal {
-                            piece: self,
-                            start: pos,
-                            end: new_pos,
-                            captured_piece: place,
-                        };
-
-                        if let Some(piece) = place  {
-                            if piece.owner != game.current_player {
-                                push(_move);
-                            }
-                            break;
-                        }
-
-                        push(_move);
-                    } else {
-                        break;
-                    }
-                }
-            )* };
-        }
-
         match self.piece_type {
             PieceType::Pawn => self.get_pawn_moves(push, game, pos),
             PieceType::King => self.get_king_moves(push, game, pos),
             PieceType::Knight => self.get_knight_moves(push, game, pos),
-            PieceType::Rook => {
-                search_deltas![
-                    (1..).map(|x| (0, x)),
-                    (1..).map(|x| (0, -x)),
-                    (1..).map(|x| (x, 0)),
-                    (1..).map(|x| (-x, 0))
-                ];
-            }
-            PieceType::Bishop => {
-                search_deltas![
-                    (1..).map(|x| (x, x)),
-                    (1..).map(|x| (-x, -x)),
-                    (1..).map(|x| (x, -x)),
-                    (1..).map(|x| (-x, x))
-                ];
+            PieceType::Rook | PieceType::Bishop | PieceType::Queen => {
+                let attacks = bitboard::slider_attacks(self.piece_type, pos, game.occupied());
+                for end in bitboard::positions(attacks & !game.occupied_by(self.owner)) {
+                    push(Move::new(pos, end));
+                }
             }
+        }
+    }
 
-            PieceType::Queen => {
-                search_deltas![
-                    (1..).map(|x| (0, x)),
-                    (1..).map(|x| (0, -x)),
-                    (1..).map(|x| (x, 0)),
-                    (1..).map(|x| (-x, 0)),
-                    (1..).map(|x| (x, x)),
-                    (1..).map(|x| (-x, -x)),
-                    (1..).map(|x| (x, -x)),
-                    (1..).map(|x| (-x, x))
-                ];
+    /// Calls `visit` for every square this piece attacks, whether it is empty or occupied
+    /// by a piece of any color. Sliding pieces stop at the first occupied square.
+    pub fn for_each_attack(self, game: &Game, pos: Position, mut visit: impl FnMut(Position)) {
+        match self.piece_type {
+            PieceType::Pawn => {
+                let row_delta = self.owner as i8;
+                for delta in [(row_delta, 1), (row_delta, -1)] {
+                    if let Some(new_pos) = pos.add(delta) {
+                        visit(new_pos);
+                    }
+                }
+            }
+            PieceType::Knight => {
+                for delta in KNIGHT_DELTAS {
+                    if let Some(new_pos) = pos.add(delta) {
+                        visit(new_pos);
+                    }
+                }
+            }
+            PieceType::King => {
+                for delta in KING_DELTAS {
+                    if let Some(new_pos) = pos.add(delta) {
+                        visit(new_pos);
+                    }
+                }
+            }
+            PieceType::Rook | PieceType::Bishop | PieceType::Queen => {
+                let attacks = bitboard::slider_attacks(self.piece_type, pos, game.occupied());
+                bitboard::positions(attacks).for_each(visit);
             }
         }
     }
@@ -172,12 +195,7 @@ impl Piece {
                 && game.get_position(pos.add_unsafe(normal_delta)).is_none()
                 && game.get_position(pos.add_unsafe(first_row_delta)).is_none()
             {
-                push(Move::Normal {
-                    piece: self,
-                    start: pos,
-                    end: pos.add_unsafe(first_row_delta),
-                    captured_piece: None,
-                });
+                push(Move::new(pos, pos.add_unsafe(first_row_delta)));
             }
         }
 
@@ -195,23 +213,10 @@ impl Piece {
                         PieceType::Bishop,
                         PieceType::Knight,
                     ] {
-                        let _move = Move::Promotion {
-                            owner: game.current_player,
-                            start: pos,
-                            end: new_pos,
-                            captured_piece: None,
-                            new_piece,
-                        };
-                        push(_move);
+                        push(Move::promotion(pos, new_pos, new_piece));
                     }
                 } else {
-                    let _move = Move::Normal {
-                        piece: self,
-                        start: pos,
-                        end: new_pos,
-                        captured_piece: None,
-                    };
-                    push(_move);
+                    push(Move::new(pos, new_pos));
                 };
             }
         }
@@ -227,23 +232,10 @@ impl Piece {
                             PieceType::Bishop,
                             PieceType::Knight,
                         ] {
-                            let _move = Move::Promotion {
-                                owner: game.current_player,
-                                start: pos,
-                                end: new_pos,
-                                captured_piece: place,
-                                new_piece,
-                            };
-                            push(_move);
+                            push(Move::promotion(pos, new_pos, new_piece));
                         }
                     } else {
-                        let _move = Move::Normal {
-                            piece: self,
-                            start: pos,
-                            end: new_pos,
-                            captured_piece: place,
-                        };
-                        push(_move);
+                        push(Move::new(pos, new_pos));
                     };
                 }
             }
@@ -254,111 +246,63 @@ impl Piece {
             && valid_en_passant < 8
             && i8::abs(valid_en_passant - pos.col()) == 1
         {
-            let _move = Move::EnPassant {
-                owner: game.current_player,
-                start_col: pos.col(),
-                end_col: valid_en_passant,
-            };
-            push(_move);
+            let end = Position::new_assert(pos.row() + self.owner as i8, valid_en_passant);
+            push(Move::en_passant(pos, end));
         }
     }
 
     fn get_king_moves(self, mut push: impl FnMut(Move), game: &Game, pos: Position) {
-        let other_king_pos = game.get_king_position(game.current_player.the_other());
-        for delta in [
-            (0, 1),
-            (0, -1),
-            (1, 0),
-            (-1, 0),
-            (1, 1),
-            (1, -1),
-            (-1, 1),
-            (-1, -1),
-        ] {
+        // Attacked squares, including the ones next to the other king, are left to Game::get_moves
+        for delta in KING_DELTAS {
             if let Some(new_pos) = pos.add(delta) {
                 let place = game.get_position(new_pos);
                 if !place.is_some_and(|piece| piece.owner == game.current_player) {
-                    // Kings can't move into each other
-                    if i8::abs(new_pos.row() - other_king_pos.row()) <= 1
-                        && i8::abs(new_pos.col() - other_king_pos.col()) <= 1
-                    {
-                        continue;
-                    }
-                    push(Move::Normal {
-                        piece: self,
-                        start: pos,
-                        end: new_pos,
-                        captured_piece: place,
-                    });
+                    push(Move::new(pos, new_pos));
                 }
             }
         }
+        let player = game.current_player;
         let state = game.state();
-        let (king_side_castling, queen_side_castling) = match game.current_player {
-            Player::White => (state.white_king_castling(), state.white_queen_castling()),
-            Player::Black => (state.black_king_castling(), state.black_queen_castling()),
-        };
-        let row = match game.current_player {
-            Player::White => 0,
-            Player::Black => 7,
-        };
-        // We may need this value 0, 1, or 2 times so we lazy-initialize it.
-        let is_king_targeted = OnceCell::new();
-        let king = Position::new_assert(row, 4);
-        if king_side_castling {
-            let (pos1, pos2) = (Position::new_assert(row, 5), Position::new_assert(row, 6));
-            if game.get_position(pos1).is_none()
-                && game.get_position(pos2).is_none()
-                && !*is_king_targeted.get_or_init(|| game.is_targeted(king, game.current_player))
-                && !game.is_targeted(pos1, game.current_player)
-                && !game.is_targeted(pos2, game.current_player)
-            {
-                push(Move::CastlingShort {
-                    owner: game.current_player,
-                });
+        for side in CastlingSide::ALL {
+            if !state.castling(player, side) {
+                continue;
             }
-        }
-        if queen_side_castling {
-            let (pos1, pos2, pos3) = (
-                Position::new_assert(row, 1),
-                Position::new_assert(row, 2),
-                Position::new_assert(row, 3),
-            );
-
-            if game.get_position(pos1).is_none()
-                && game.get_position(pos2).is_none()
-                && game.get_position(pos3).is_none()
-                && !*is_king_targeted.get_or_init(|| game.is_targeted(king, game.current_player))
-                && !game.is_targeted(pos2, game.current_player)
-                && !game.is_targeted(pos3, game.current_player)
+
+            let (old_king, new_king, old_rook, new_rook) = game.castling_squares(player, side);
+            let castling_pieces = bitboard::bit(old_king) | bitboard::bit(old_rook);
+
+            // Apart from the king and the rook, every square they cross must be empty
+            let path = bitboard::between(old_king, new_king)
+                | bitboard::between(old_rook, new_rook)
+                | bitboard::bit(new_king)
+                | bitboard::bit(new_rook);
+            if path & game.occupied() & !castling_pieces != 0 {
+                continue;
+            }
+
+            // The king can't castle out of, through or into check. Both pieces are left out
+            // of the occupancy, since in Chess960 the rook may hide the king from a slider
+            let occupied = game.occupied() ^ castling_pieces;
+            let enemies = game.occupied_by(player.the_other());
+            let king_path = bitboard::between(old_king, new_king)
+                | bitboard::bit(old_king)
+                | bitboard::bit(new_king);
+            if bitboard::positions(king_path)
+                .any(|position| game.attackers_to(position, occupied) & enemies != 0)
             {
-                push(Move::CastlingLong {
-                    owner: game.current_player,
-                });
+                continue;
             }
+
+            push(Move::castling(old_king, new_king, side));
         }
     }
 
     fn get_knight_moves(self, mut push: impl FnMut(Move), game: &Game, pos: Position) {
-        for delta in [
-            (1, 2),
-            (2, 1),
-            (-1, -2),
-            (-2, -1),
-            (1, -2),
-            (-2, 1),
-            (-1, 2),
-            (2, -1),
-        ] {
+        for delta in KNIGHT_DELTAS {
             if let Some(new_pos) = pos.add(delta) {
                 let place = game.get_position(new_pos);
                 if !place.is_some_and(|piece| piece.owner == game.current_player) {
-                    push(Move::Normal {
-                        piece: self,
-                        start: pos,
-                        end: new_pos,
-                        captured_piece: place,
-                    });
+                    push(Move::new(pos, new_pos));
                 }
             }
         }
@@ -385,17 +329,6 @@ impl Piece {
         }
     }
 
-    pub fn as_str_pgn(self) -> &'static str {
-        match self.piece_type {
-            PieceType::King => "K",
-            PieceType::Queen => "Q",
-            PieceType::Rook => "R",
-            PieceType::Bishop => "B",
-            PieceType::Knight => "N",
-            PieceType::Pawn => "",
-        }
-    }
-
     pub fn as_char_ascii(self) -> char {
         let piece = match self.piece_type {
             PieceType::King => 'K',
@@ -433,8 +366,8 @@ impl Piece {
     }
 }
 
-impl std::fmt::Display for Piece {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl core::fmt::Display for Piece {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(f, "{}", self.as_char())
     }
 }
@@ -4,11 +4,6 @@
 pub struct Position(i8, i8);
 
 impl Position {
-    pub const WHITE_QUEEN_ROOK: Self = Self(0, 0);
-    pub const WHITE_KING_ROOK: Self = Self(0, 7);
-    pub const BLACK_QUEEN_ROOK: Self = Self(7, 0);
-    pub const BLACK_KING_ROOK: Self = Self(7, 7);
-
     #[inline]
     pub fn new(row: i8, col: i8) -> Option<Self> {
         if (0..8).contains(&row) && (0..8).contains(&col) {
@@ -30,6 +25,8 @@ impl Position {
     /// Caller must guarantee that row and col are valid
     #[inline]
     pub unsafe fn new_unsafe(row: i8, col: i8) -> Self {
+        #[cfg(feature = "safe")]
+        assert!((0..8).contains(&row) && (0..8).contains(&col));
         debug_assert!((0..8).contains(&row) && (0..8).contains(&col));
         Self(row, col)
     }
@@ -44,6 +41,9 @@ impl Position {
         self.1
     }
 
+    /// The square `delta` rows and columns away, if it is on the board
+    // Not the Add trait, which can't fail
+    #[allow(clippy::should_implement_trait)]
     #[inline]
     pub fn add(self, delta: (i8, i8)) -> Option<Self> {
         let row = self.0 + delta.0;
@@ -63,6 +63,8 @@ impl Position {
     pub unsafe fn add_unsafe(self, delta: (i8, i8)) -> Self {
         let row = self.0 + delta.0;
         let col = self.1 + delta.1;
+        #[cfg(feature = "safe")]
+        assert!((0..8).contains(&row) && (0..8).contains(&col));
         debug_assert!((0..8).contains(&row) && (0..8).contains(&col));
         Self(row, col)
     }
@@ -70,7 +72,52 @@ impl Position {
     /// Returns the index this position would take in a linear board array
     /// i.e. it always lies in 0..64
     #[inline]
-    pub fn as_usize(self) -> usize {
+    pub const fn as_usize(self) -> usize {
         (self.0 * 8 + self.1) as usize
     }
+
+    /// The position at `index` in a linear board array, the inverse of `as_usize`
+    #[inline]
+    pub const fn from_usize(index: usize) -> Self {
+        #[cfg(feature = "safe")]
+        assert!(index < 64);
+        debug_assert!(index < 64);
+        Self((index / 8) as i8, (index % 8) as i8)
+    }
+
+    /// Number of king moves needed to go from one position to the other
+    #[inline]
+    pub fn distance(self, other: Self) -> i8 {
+        i8::max(i8::abs(self.0 - other.0), i8::abs(self.1 - other.1))
+    }
+
+    /// Returns true for the squares of the same color as h1
+    #[inline]
+    pub fn is_light(self) -> bool {
+        (self.0 + self.1) % 2 == 1
+    }
+}
+
+/// Squares are written by their names, e.g. `"e4"`
+#[cfg(feature = "serde")]
+impl serde::Serialize for Position {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!(
+            "{}{}",
+            (self.1 as u8 + b'a') as char,
+            self.0 + 1
+        ))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Position {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = alloc::string::String::deserialize(deserializer)?;
+        match *name.as_bytes() {
+            [col, row] => Self::new(row.wrapping_sub(b'1') as i8, col.wrapping_sub(b'a') as i8),
+            _ => None,
+        }
+        .ok_or_else(|| serde::de::Error::custom(alloc::format!("Invalid square: {}", name)))
+    }
 }
@@ -0,0 +1,346 @@
+use super::{gamestate::CastlingSide, piece::Piece, snapshot::Snapshot, Game, PieceType, Player};
+use arrayvec::ArrayVec;
+use proptest::prelude::*;
+
+// Property tests of the move generation and of undoing moves: random legal games are
+// played from a few positions, every move must be undone exactly by `pop`, and the legal
+// moves must match the ones of a slow generator written without bitboards or pins.
+
+/// Positions the games start from, with castling, en passant, promotions, pins and checks
+/// close by, and whether they are Chess960 positions
+const POSITIONS: [(&str, bool); 7] = [
+    (
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        false,
+    ),
+    (
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        false,
+    ),
+    ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", false),
+    (
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+        false,
+    ),
+    ("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1", false),
+    (
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+        true,
+    ),
+    (
+        "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w GBgb - 0 1",
+        true,
+    ),
+];
+
+type Board = [Option<Piece>; 64];
+
+const KNIGHT_STEPS: [(i8, i8); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_STEPS: [(i8, i8); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const ROOK_STEPS: [(i8, i8); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const BISHOP_STEPS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+fn square(row: i8, col: i8) -> Option<usize> {
+    ((0..8).contains(&row) && (0..8).contains(&col)).then_some((row * 8 + col) as usize)
+}
+
+fn name(index: usize) -> String {
+    format!("{}{}", (b'a' + index as u8 % 8) as char, index / 8 + 1)
+}
+
+fn is_piece(board: &Board, index: usize, owner: Player, piece_type: PieceType) -> bool {
+    board[index] == Some(Piece { piece_type, owner })
+}
+
+/// Whether a piece of `attacker` attacks the square, by looking from it in every direction
+fn is_attacked(board: &Board, row: i8, col: i8, attacker: Player) -> bool {
+    // Pawns of the attacker are one row behind the square they take on
+    let pawn_row = row - attacker as i8;
+    if [-1, 1].into_iter().any(|delta| {
+        square(pawn_row, col + delta)
+            .is_some_and(|index| is_piece(board, index, attacker, PieceType::Pawn))
+    }) {
+        return true;
+    }
+
+    for (steps, piece_type) in [
+        (KNIGHT_STEPS, PieceType::Knight),
+        (KING_STEPS, PieceType::King),
+    ] {
+        if steps.into_iter().any(|(rows, cols)| {
+            square(row + rows, col + cols)
+                .is_some_and(|index| is_piece(board, index, attacker, piece_type))
+        }) {
+            return true;
+        }
+    }
+
+    for (steps, slider) in [
+        (ROOK_STEPS, PieceType::Rook),
+        (BISHOP_STEPS, PieceType::Bishop),
+    ] {
+        for (rows, cols) in steps {
+            let (mut target_row, mut target_col) = (row + rows, col + cols);
+            while let Some(index) = square(target_row, target_col) {
+                if let Some(piece) = board[index] {
+                    if piece.owner == attacker
+                        && (piece.piece_type == slider || piece.piece_type == PieceType::Queen)
+                    {
+                        return true;
+                    }
+                    break;
+                }
+                target_row += rows;
+                target_col += cols;
+            }
+        }
+    }
+
+    false
+}
+
+fn is_king_safe(board: &Board, player: Player) -> bool {
+    let king = (0..64)
+        .find(|&index| is_piece(board, index, player, PieceType::King))
+        .unwrap();
+    !is_attacked(board, king as i8 / 8, king as i8 % 8, player.the_other())
+}
+
+/// Legal moves of `game` in UCI notation, generated square by square: the moves of each
+/// piece are made on a copy of the board, and kept if they don't leave the king attacked
+fn reference_moves(game: &Game) -> Vec<String> {
+    let player = game.player();
+    let opponent = player.the_other();
+    let state = game.state();
+    let board = game.board;
+    let forward = player as i8;
+
+    let mut moves = Vec::new();
+    let mut try_move = |start: usize, end: usize, promotion: Option<PieceType>, taken: usize| {
+        let mut after = board;
+        let piece = after[start].take().unwrap();
+        after[taken] = None;
+        after[end] = Some(Piece {
+            piece_type: promotion.unwrap_or(piece.piece_type),
+            owner: player,
+        });
+        if !is_king_safe(&after, player) {
+            return;
+        }
+
+        let mut uci = name(start) + &name(end);
+        if let Some(promotion) = promotion {
+            uci.push(match promotion {
+                PieceType::Queen => 'q',
+                PieceType::Rook => 'r',
+                PieceType::Bishop => 'b',
+                _ => 'n',
+            });
+        }
+        moves.push(uci);
+    };
+    let is_free = |index: usize| board[index].is_none_or(|piece| piece.owner == opponent);
+
+    for start in 0..64 {
+        let Some(piece) = board[start].filter(|piece| piece.owner == player) else {
+            continue;
+        };
+        let (row, col) = (start as i8 / 8, start as i8 % 8);
+
+        match piece.piece_type {
+            PieceType::Pawn => {
+                let mut targets = Vec::new();
+                if let Some(end) = square(row + forward, col).filter(|&end| board[end].is_none()) {
+                    targets.push((end, end));
+                    let start_row = if player == Player::White { 1 } else { 6 };
+                    if let Some(end) = square(row + 2 * forward, col)
+                        .filter(|&end| row == start_row && board[end].is_none())
+                    {
+                        targets.push((end, end));
+                    }
+                }
+                for delta in [-1, 1] {
+                    let Some(end) = square(row + forward, col + delta) else {
+                        continue;
+                    };
+                    if board[end].is_some_and(|piece| piece.owner == opponent) {
+                        targets.push((end, end));
+                    }
+                    let en_passant_row = if player == Player::White { 4 } else { 3 };
+                    if row == en_passant_row && state.en_passant() == col + delta {
+                        targets.push((end, square(row, col + delta).unwrap()));
+                    }
+                }
+
+                for (end, taken) in targets {
+                    if end / 8 == 0 || end / 8 == 7 {
+                        for promotion in [
+                            PieceType::Queen,
+                            PieceType::Rook,
+                            PieceType::Bishop,
+                            PieceType::Knight,
+                        ] {
+                            try_move(start, end, Some(promotion), taken);
+                        }
+                    } else {
+                        try_move(start, end, None, taken);
+                    }
+                }
+            }
+            PieceType::Knight | PieceType::King => {
+                let steps = if piece.piece_type == PieceType::Knight {
+                    KNIGHT_STEPS
+                } else {
+                    KING_STEPS
+                };
+                for (rows, cols) in steps {
+                    if let Some(end) = square(row + rows, col + cols).filter(|&end| is_free(end)) {
+                        try_move(start, end, None, end);
+                    }
+                }
+            }
+            slider => {
+                let steps: &[(i8, i8)] = match slider {
+                    PieceType::Rook => &ROOK_STEPS,
+                    PieceType::Bishop => &BISHOP_STEPS,
+                    _ => &KING_STEPS,
+                };
+                for &(rows, cols) in steps {
+                    let (mut end_row, mut end_col) = (row + rows, col + cols);
+                    while let Some(end) = square(end_row, end_col).filter(|&end| is_free(end)) {
+                        try_move(start, end, None, end);
+                        if board[end].is_some() {
+                            break;
+                        }
+                        end_row += rows;
+                        end_col += cols;
+                    }
+                }
+            }
+        }
+    }
+
+    let row = player.first_row();
+    for side in CastlingSide::ALL {
+        if !state.castling(player, side) {
+            continue;
+        }
+        let king_start = game.king_start_cols[player.as_index()];
+        let rook_start = game.rook_start_cols[player.as_index()][side as usize];
+        let (king_end, rook_end) = match side {
+            CastlingSide::Short => (6, 5),
+            CastlingSide::Long => (2, 3),
+        };
+        let index = |col| square(row, col).unwrap();
+
+        // Every square both pieces go over is empty but for themselves, and the king
+        // isn't attacked on its way
+        let first = king_start.min(king_end).min(rook_start).min(rook_end);
+        let last = king_start.max(king_end).max(rook_start).max(rook_end);
+        let is_blocked = (first..=last)
+            .any(|col| board[index(col)].is_some() && col != king_start && col != rook_start);
+        let is_king_attacked = (king_start.min(king_end)..=king_start.max(king_end))
+            .any(|col| is_attacked(&board, row, col, opponent));
+        if is_blocked || is_king_attacked {
+            continue;
+        }
+
+        let mut after = board;
+        after[index(king_start)] = None;
+        after[index(rook_start)] = None;
+        after[index(king_end)] = Some(Piece {
+            piece_type: PieceType::King,
+            owner: player,
+        });
+        after[index(rook_end)] = Some(Piece {
+            piece_type: PieceType::Rook,
+            owner: player,
+        });
+        if !is_king_safe(&after, player) {
+            continue;
+        }
+
+        let end = if game.is_chess960() {
+            rook_start
+        } else {
+            king_end
+        };
+        moves.push(name(index(king_start)) + &name(index(end)));
+    }
+
+    moves.sort();
+    moves
+}
+
+fn generated_moves(game: &Game) -> Vec<String> {
+    let mut moves = ArrayVec::new();
+    game.get_moves(&mut moves);
+    let mut moves: Vec<_> = moves
+        .into_iter()
+        .map(|_move| _move.uci_notation(game))
+        .collect();
+    moves.sort();
+    moves
+}
+
+proptest! {
+    /// Plays a random game, where `choices` pick each move among the legal ones, checking
+    /// the moves of every position and that each of them is undone, then takes the game
+    /// back move by move
+    #[test]
+    fn random_games(
+        position in 0..POSITIONS.len(),
+        choices in prop::collection::vec(any::<u16>(), 1..80),
+    ) {
+        let (fen, chess960) = POSITIONS[position];
+        let mut game = Game::new(fen).unwrap();
+        game.set_chess960(chess960);
+        let mut snapshots = Vec::new();
+
+        for choice in choices {
+            let snapshot = Snapshot::of(&game);
+            let fen = &game.to_fen();
+            prop_assert_eq!(generated_moves(&game), reference_moves(&game), "{}", fen);
+
+            let mut moves = ArrayVec::new();
+            game.get_moves(&mut moves);
+            for &_move in &moves {
+                let uci = _move.uci_notation(&game);
+                game.push(_move);
+                game.verify_incremental_state();
+                game.pop();
+                prop_assert_eq!(&Snapshot::of(&game), &snapshot, "{} {}", fen, uci);
+            }
+
+            if moves.is_empty() {
+                break;
+            }
+            game.push(moves[choice as usize % moves.len()]);
+            snapshots.push(snapshot);
+        }
+
+        while let Some(snapshot) = snapshots.pop() {
+            game.pop();
+            game.verify_incremental_state();
+            prop_assert_eq!(Snapshot::of(&game), snapshot);
+        }
+    }
+}
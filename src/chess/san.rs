@@ -0,0 +1,315 @@
+use super::move_struct::{Move, MoveKind};
+use super::piece::PieceType;
+use super::position::Position;
+use super::{CastlingSide, Game};
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use arrayvec::ArrayVec;
+
+// Standard Algebraic Notation, as used by PGN: https://www.chessprogramming.org/Algebraic_Chess_Notation
+
+/// Pieces are written with an uppercase letter, pawns with none
+fn piece_type_from_char(character: char) -> Option<PieceType> {
+    match character {
+        'K' => Some(PieceType::King),
+        'Q' => Some(PieceType::Queen),
+        'R' => Some(PieceType::Rook),
+        'B' => Some(PieceType::Bishop),
+        'N' => Some(PieceType::Knight),
+        _ => None,
+    }
+}
+
+fn push_col(s: &mut String, position: Position) {
+    s.push((position.col() as u8 + b'a') as char);
+}
+
+fn push_row(s: &mut String, position: Position) {
+    s.push((position.row() as u8 + b'1') as char);
+}
+
+impl Move {
+    /// The moving piece, the squares it moves between and the piece it promotes to,
+    /// None for castling which is written differently
+    fn san_parts(self, game: &Game) -> Option<(PieceType, Position, Position, Option<PieceType>)> {
+        let (start, end) = (self.start(), self.end());
+        match self.kind() {
+            MoveKind::Normal => Some((self.piece(game).piece_type, start, end, None)),
+            MoveKind::Promotion(new_piece) => Some((PieceType::Pawn, start, end, Some(new_piece))),
+            MoveKind::EnPassant => Some((PieceType::Pawn, start, end, None)),
+            MoveKind::Castling(_) => None,
+        }
+    }
+
+    /// Writes the move in Standard Algebraic Notation, `game` is the position before the move
+    ///
+    /// The starting file or rank, or both, are only added when another piece of the same type
+    /// can reach the same square.
+    pub fn to_san(self, game: &Game) -> String {
+        let mut s = String::new();
+
+        match self.san_parts(game) {
+            None => s.push_str(match self.kind() {
+                MoveKind::Castling(CastlingSide::Short) => "O-O",
+                _ => "O-O-O",
+            }),
+            Some((piece_type, start, end, promotion)) => {
+                let is_capture = self.captured_piece(game).is_some();
+
+                s.push_str(piece_type.as_str_pgn());
+
+                if piece_type == PieceType::Pawn {
+                    if is_capture {
+                        push_col(&mut s, start);
+                    }
+                } else {
+                    let moves = game.legal_moves();
+
+                    let others: ArrayVec<Position, 256> = moves
+                        .iter()
+                        .filter_map(|other| other.san_parts(game))
+                        .filter(|&(other_type, other_start, other_end, _)| {
+                            other_type == piece_type && other_end == end && other_start != start
+                        })
+                        .map(|(_, other_start, _, _)| other_start)
+                        .collect();
+
+                    if !others.is_empty() {
+                        if others.iter().all(|other| other.col() != start.col()) {
+                            push_col(&mut s, start);
+                        } else if others.iter().all(|other| other.row() != start.row()) {
+                            push_row(&mut s, start);
+                        } else {
+                            push_col(&mut s, start);
+                            push_row(&mut s, start);
+                        }
+                    }
+                }
+
+                if is_capture {
+                    s.push('x');
+                }
+                push_col(&mut s, end);
+                push_row(&mut s, end);
+
+                if let Some(promotion) = promotion {
+                    s.push('=');
+                    s.push_str(promotion.as_str_pgn());
+                }
+            }
+        }
+
+        if game.gives_check(self) {
+            let mut after = game.clone();
+            after.push(self);
+            let moves = after.legal_moves();
+            s.push(if moves.is_empty() { '#' } else { '+' });
+        }
+
+        s
+    }
+
+    /// Reads a move in Standard Algebraic Notation, e.g. `Nbd2`, `exd8=Q+` or `O-O-O#`
+    ///
+    /// Check and mate suffixes and annotations such as `!?` aren't verified. Exactly one
+    /// legal move must match, so ambiguous moves are rejected.
+    pub fn from_san(s: &str, game: &Game) -> Result<Self, SanMoveError> {
+        let unparseable = || SanMoveError::Unparseable(s.to_string());
+        let illegal = || SanMoveError::Illegal(s.to_string());
+
+        let moves = game.legal_moves();
+
+        let san = s.trim_end_matches(['+', '#', '!', '?']);
+
+        let castling_side = match san {
+            "O-O" | "0-0" => Some(CastlingSide::Short),
+            "O-O-O" | "0-0-0" => Some(CastlingSide::Long),
+            _ => None,
+        };
+        if let Some(side) = castling_side {
+            return moves
+                .into_iter()
+                .find(|_move| _move.kind() == MoveKind::Castling(side))
+                .ok_or_else(illegal);
+        }
+
+        let mut chars: Vec<char> = san.chars().collect();
+
+        // The promotion comes after the destination, with or without an equal sign
+        let promotion = match chars.as_slice() {
+            [.., '=', piece] | [.., '1'..='8', piece] => {
+                let piece_type = piece_type_from_char(*piece).ok_or_else(unparseable)?;
+                chars.pop();
+                if chars.last() == Some(&'=') {
+                    chars.pop();
+                }
+                Some(piece_type)
+            }
+            _ => None,
+        };
+
+        let rank = chars.pop().ok_or_else(unparseable)?;
+        let file = chars.pop().ok_or_else(unparseable)?;
+        let end = Position::new(
+            (rank as u8).wrapping_sub(b'1') as i8,
+            (file as u8).wrapping_sub(b'a') as i8,
+        )
+        .ok_or_else(unparseable)?;
+
+        let piece_type = match chars.first().and_then(|&piece| piece_type_from_char(piece)) {
+            Some(piece_type) => {
+                chars.remove(0);
+                piece_type
+            }
+            None => PieceType::Pawn,
+        };
+
+        // What is left is the square or part of the square the piece starts on,
+        // which tells apart pieces of the same type reaching the same square
+        let mut start_col = None;
+        let mut start_row = None;
+        for character in chars {
+            match character {
+                'a'..='h' => start_col = Some((character as u8 - b'a') as i8),
+                '1'..='8' => start_row = Some((character as u8 - b'1') as i8),
+                'x' => (),
+                _ => return Err(unparseable()),
+            }
+        }
+
+        let mut candidates = moves.into_iter().filter(|_move| {
+            _move.san_parts(game).is_some_and(
+                |(move_piece_type, move_start, move_end, move_promotion)| {
+                    move_piece_type == piece_type
+                        && move_end == end
+                        && move_promotion == promotion
+                        && start_col.is_none_or(|col| move_start.col() == col)
+                        && start_row.is_none_or(|row| move_start.row() == row)
+                },
+            )
+        });
+
+        let _move = candidates.next().ok_or_else(illegal)?;
+        match candidates.next() {
+            Some(_) => Err(SanMoveError::Ambiguous(s.to_string())),
+            None => Ok(_move),
+        }
+    }
+}
+
+/// Reasons a move is rejected by `Move::from_san`
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum SanMoveError {
+    /// The string isn't a move in SAN
+    Unparseable(String),
+    /// No legal move of the position matches
+    Illegal(String),
+    /// Several legal moves match, the starting square must tell them apart
+    Ambiguous(String),
+}
+
+impl core::fmt::Display for SanMoveError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Unparseable(s) => write!(f, "Invalid move: {}", s),
+            Self::Illegal(s) => write!(f, "Illegal move: {}", s),
+            Self::Ambiguous(s) => write!(f, "Ambiguous move: {}", s),
+        }
+    }
+}
+
+impl core::error::Error for SanMoveError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_san(san: &str, fen: &str) -> Option<String> {
+        let game = Game::new(fen).unwrap();
+        Move::from_san(san, &game)
+            .ok()
+            .map(|_move| _move.uci_notation(&game))
+    }
+
+    #[test]
+    fn parse_san() {
+        let startpos = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(from_san("e4", startpos).as_deref(), Some("e2e4"));
+        assert_eq!(from_san("Nf3!?", startpos).as_deref(), Some("g1f3"));
+        assert_eq!(from_san("e5", startpos), None);
+        assert_eq!(from_san("Nd2", startpos), None);
+
+        // Disambiguation by file, rank or both
+        let knights = "4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1";
+        assert_eq!(from_san("Nd2", knights), None);
+        assert_eq!(from_san("Nbd2", knights).as_deref(), Some("b1d2"));
+        assert_eq!(from_san("Nf3d2", knights).as_deref(), Some("f3d2"));
+        let rooks = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(from_san("Ra3", rooks), None);
+        assert_eq!(from_san("R1a3", rooks).as_deref(), Some("a1a3"));
+
+        // Promotions, captures and en passant
+        let promotion = "3r2k1/4P3/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(from_san("exd8=Q+", promotion).as_deref(), Some("e7d8q"));
+        assert_eq!(from_san("e8N", promotion).as_deref(), Some("e7e8n"));
+        assert_eq!(from_san("e8", promotion), None);
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+        assert_eq!(from_san("exd6", en_passant).as_deref(), Some("e5d6"));
+
+        // Castling, with zeros too
+        let castling = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1";
+        assert_eq!(from_san("O-O-O#", castling).as_deref(), Some("e8c8"));
+        assert_eq!(from_san("0-0", castling).as_deref(), Some("e8g8"));
+
+        for invalid in ["", "Z4", "e9", "Nxx3", "Kd", "exd8=K"] {
+            assert_eq!(from_san(invalid, promotion), None);
+        }
+    }
+
+    #[test]
+    fn san_errors() {
+        let game = Game::new("4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1").unwrap();
+        let error = |san| Move::from_san(san, &game).unwrap_err();
+        assert_eq!(error("Nz2"), SanMoveError::Unparseable(String::from("Nz2")));
+        assert_eq!(error("Nb5"), SanMoveError::Illegal(String::from("Nb5")));
+        assert_eq!(error("O-O"), SanMoveError::Illegal(String::from("O-O")));
+        assert_eq!(error("Nd2"), SanMoveError::Ambiguous(String::from("Nd2")));
+    }
+
+    fn to_san(uci: &str, fen: &str) -> String {
+        let game = Game::new(fen).unwrap();
+        let _move = Move::from_uci_notation(uci, &game).unwrap();
+        let san = _move.to_san(&game);
+        assert_eq!(Move::from_san(&san, &game), Ok(_move));
+        san
+    }
+
+    #[test]
+    fn write_san() {
+        let startpos = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(to_san("e2e4", startpos), "e4");
+        assert_eq!(to_san("g1f3", startpos), "Nf3");
+
+        let knights = "4k3/8/8/8/8/5N2/8/1N2K3 w - - 0 1";
+        assert_eq!(to_san("b1d2", knights), "Nbd2");
+        let rooks = "4k3/8/8/R7/8/8/8/R3K3 w - - 0 1";
+        assert_eq!(to_san("a1a3", rooks), "R1a3");
+        let queens = "5k2/8/8/8/Q6Q/8/8/Q3K3 w - - 0 1";
+        assert_eq!(to_san("a4d4", queens), "Qa4d4");
+
+        let promotion = "3r2k1/4P3/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(to_san("e7d8q", promotion), "exd8=Q+");
+        assert_eq!(to_san("e7e8n", promotion), "e8=N");
+        let en_passant = "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 2";
+        assert_eq!(to_san("e5d6", en_passant), "exd6");
+
+        let castling = "1k6/8/8/8/8/8/8/R3K2R w KQ - 0 1";
+        assert_eq!(to_san("e1c1", castling), "O-O-O");
+        assert_eq!(to_san("e1g1", castling), "O-O");
+        let mate = "r3k2r/8/8/8/8/8/5PPP/6K1 b kq - 0 1";
+        assert_eq!(to_san("e8c8", mate), "O-O-O");
+        assert_eq!(to_san("a8a1", mate), "Ra1#");
+    }
+}
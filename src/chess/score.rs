@@ -0,0 +1,126 @@
+use alloc::{format, string::String};
+#[cfg(feature = "serde")]
+use serde::{Serialize, Serializer};
+
+/// Evaluation and search scores, in centipawns from the point of view of a player
+///
+/// Every score stays within -INFINITY..=INFINITY, so negating one never overflows.
+pub type Score = i32;
+
+/// Bound of every score, used for the initial alpha-beta window
+pub const INFINITY: Score = 32000;
+
+/// Score of delivering checkmate on the board, mates further away score lower
+pub const MATE: Score = 31000;
+
+/// Score of every drawn position
+pub const DRAW: Score = 0;
+
+/// Longest distance to a mate which can be told apart from a regular score
+pub const MAX_MATE_PLY: Score = 1000;
+
+/// Score of the side delivering mate in `ply` half-moves from the root of the search
+#[inline]
+pub const fn mate_in(ply: Score) -> Score {
+    MATE - ply
+}
+
+/// Score of the side being mated in `ply` half-moves from the root of the search
+#[inline]
+pub const fn mated_in(ply: Score) -> Score {
+    -MATE + ply
+}
+
+/// Whether the score is a forced mate, for either side, ±INFINITY being only bounds
+#[inline]
+pub fn is_mate(score: Score) -> bool {
+    score.abs() > mate_in(MAX_MATE_PLY) && score.abs() <= MATE
+}
+
+/// Full moves until the mate of a mate score, negative when being mated
+pub fn mate_moves(score: Score) -> Score {
+    debug_assert!(is_mate(score), "{} isn't a mate score", score);
+    let ply = MATE - score.abs();
+    (ply + 1) / 2 * score.signum()
+}
+
+/// Formats a score for the `info score` UCI output, either `cp <centipawns>`
+/// or `mate <moves>`, where a negative number of moves means being mated
+pub fn to_uci(score: Score) -> String {
+    if is_mate(score) {
+        format!("mate {}", mate_moves(score))
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+/// Formats a score for the `[%eval]` command of PGN comments, either pawns
+/// with two decimals or `#<moves>`, where a negative number of moves means being mated
+pub fn to_pgn(score: Score) -> String {
+    if is_mate(score) {
+        format!("#{}", mate_moves(score))
+    } else {
+        format!("{:.2}", score as f64 / 100.0)
+    }
+}
+
+/// Serializes a score like the UCI output, as `{"cp": <centipawns>}` or `{"mate": <moves>}`
+#[cfg(feature = "serde")]
+pub fn serialize<S: Serializer>(score: &Score, serializer: S) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Value {
+        Cp(Score),
+        Mate(Score),
+    }
+
+    if is_mate(*score) {
+        Value::Mate(mate_moves(*score))
+    } else {
+        Value::Cp(*score)
+    }
+    .serialize(serializer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_conversion() {
+        let json = |score| serialize(&score, serde_json::value::Serializer).unwrap();
+        assert_eq!(json(-150).to_string(), r#"{"cp":-150}"#);
+        assert_eq!(json(mate_in(3)).to_string(), r#"{"mate":2}"#);
+        assert_eq!(json(mated_in(2)).to_string(), r#"{"mate":-1}"#);
+    }
+
+    #[test]
+    fn uci_conversion() {
+        assert_eq!(to_uci(0), "cp 0");
+        assert_eq!(to_uci(-150), "cp -150");
+        assert_eq!(to_uci(mate_in(1)), "mate 1");
+        assert_eq!(to_uci(mate_in(3)), "mate 2");
+        assert_eq!(to_uci(mated_in(2)), "mate -1");
+        assert_eq!(to_uci(-mate_in(4)), "mate -2");
+        assert_eq!(
+            to_uci(MATE - MAX_MATE_PLY),
+            format!("cp {}", MATE - MAX_MATE_PLY)
+        );
+        assert_eq!(to_uci(mated_in(0)), "mate 0");
+
+        // The bounds of the window aren't mates
+        assert!(!is_mate(INFINITY));
+        assert!(!is_mate(-INFINITY));
+        assert!(is_mate(MATE) && is_mate(-MATE));
+    }
+
+    #[test]
+    fn pgn_conversion() {
+        assert_eq!(to_pgn(0), "0.00");
+        assert_eq!(to_pgn(35), "0.35");
+        assert_eq!(to_pgn(-150), "-1.50");
+        assert_eq!(to_pgn(mate_in(3)), "#2");
+        assert_eq!(to_pgn(mated_in(2)), "#-1");
+    }
+}
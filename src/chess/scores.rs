@@ -1,6 +1,9 @@
 // Source: https://www.chessprogramming.org/Simplified_Evaluation_Function
 
-pub const ENDGAME_THRESHOLD: u32 = 1500 + 20000; // 15 pawns + king
+use super::Score;
+
+/// Value of the pieces of a player, without the king, under which the endgame begins
+pub const ENDGAME_THRESHOLD: Score = 1500; // 15 pawns
 
 pub const PAWN_SCORES: [i16; 64] = [
     100, 100, 100, 100, 100, 100, 100, 100, 150, 150, 150, 150, 150, 150, 150, 150, 110, 110, 120,
@@ -50,3 +53,84 @@ pub const KING_SCORES_END: [i16; 64] = [
     19970, 19970, 19990, 20020, 20030, 20030, 20020, 19990, 19970, 19970, 19970, 20000, 20000,
     20000, 20000, 19970, 19970, 19950, 19970, 19970, 19970, 19970, 19970, 19970, 19950,
 ];
+
+/// Material value of every piece type, in the order of PieceType
+///
+/// These values are already included in the tables above,
+/// they are only used to split the evaluation into material and position
+pub const PIECE_VALUES: [Score; 6] = [900, 500, 330, 320, 100, 20000];
+
+// Pawn structure
+pub const DOUBLED_PAWN_PENALTY: Score = 10;
+pub const ISOLATED_PAWN_PENALTY: Score = 15;
+/// Indexed by the row of the pawn relative to its owner
+pub const PASSED_PAWN_BONUS: [Score; 8] = [0, 5, 10, 20, 35, 60, 100, 0];
+/// For a pawn next to another pawn of the same color, or defended by one,
+/// indexed by the row of the pawn relative to its owner
+pub const CONNECTED_PAWN_BONUS: [Score; 8] = [0, 3, 5, 8, 14, 25, 45, 0];
+/// For every pawn defending another pawn
+pub const SUPPORTED_PAWN_BONUS: Score = 5;
+
+// Pieces which take no part in the game
+/// A knight in a corner of the opponent's side whose every move is covered by pawns
+pub const TRAPPED_KNIGHT_PENALTY: Score = 60;
+/// A bishop on the a7 or h7 square, relative to its owner, cut off by a defended enemy pawn
+pub const TRAPPED_BISHOP_PENALTY: Score = 100;
+/// For every own pawn on the color of the bishop, blocked pawns count twice
+pub const BAD_BISHOP_PENALTY: Score = 3;
+/// For every piece other than a pawn pinned to its king
+pub const PINNED_PIECE_PENALTY: Score = 15;
+
+// King safety, only used outside of the endgame
+pub const MISSING_SHIELD_PAWN_PENALTY: Score = 15;
+pub const ADVANCED_SHIELD_PAWN_PENALTY: Score = 5;
+pub const OPEN_FILE_NEAR_KING_PENALTY: Score = 10;
+/// For the most advanced enemy pawn on every file next to the king, when the kings are
+/// castled on opposite wings, indexed by the row of the pawn relative to its owner
+pub const PAWN_STORM_PENALTY: [Score; 8] = [0, 0, 5, 15, 25, 30, 0, 0];
+
+/// Bonus for every safe square behind the own pawns on the central files, multiplied by
+/// the number of pieces other than pawns and the king, then divided by 16.
+/// Squares in the opponent's half count twice. Only used outside of the endgame.
+pub const SPACE_BONUS: Score = 4;
+
+// Threats, given to the attacking player
+/// A piece other than a pawn attacked by an enemy pawn
+pub const THREAT_BY_PAWN_BONUS: Score = 50;
+/// A piece attacked by a less valuable piece other than a pawn
+pub const THREAT_BY_LESSER_PIECE_BONUS: Score = 25;
+/// A piece which is attacked and not defended
+pub const HANGING_PIECE_BONUS: Score = 30;
+/// A defended piece which is attacked more times than it is defended
+pub const UNDERDEFENDED_PIECE_BONUS: Score = 15;
+
+/// Bonus for king and pawn versus king endgames which are known to be won,
+/// small enough that promoting the pawn still improves the evaluation
+pub const KPK_WIN_BONUS: Score = 200;
+
+/// Bonus for every square the losing king is closer to a corner of the bishop's color
+/// than the maximum distance of 7, in king, bishop and knight versus king endgames
+pub const KBNK_CORNER_BONUS: Score = 20;
+
+// Mop-up, used when one side has a decisive material advantage and there are no pawns
+/// Bonus per unit of Manhattan distance between the losing king and the center
+pub const MOP_UP_CENTER_DISTANCE_BONUS: Score = 10;
+/// Bonus per unit of Manhattan distance the kings are closer than the maximum of 14
+pub const MOP_UP_KING_PROXIMITY_BONUS: Score = 4;
+
+// Endgame scale factors, out of 64
+/// The player who is ahead has no pawns and at most a minor piece more
+pub const PAWNLESS_SCALE: Score = 14;
+/// Same as above, but the other player has at most a minor piece
+pub const PAWNLESS_AGAINST_MINOR_SCALE: Score = 4;
+/// Opposite-colored bishops, without other pieces
+pub const OPPOSITE_BISHOPS_SCALE: Score = 18;
+/// Opposite-colored bishops, with other pieces
+pub const OPPOSITE_BISHOPS_WITH_PIECES_SCALE: Score = 46;
+/// Rook and pawn against rook, with the defending king in front of the pawn
+pub const BLOCKED_ROOK_ENDGAME_SCALE: Score = 16;
+
+/// Bonus for every square attacked by a piece, in the order of PieceType
+pub const MOBILITY_BONUS: [Score; 6] = [1, 2, 5, 4, 0, 0];
+/// Number of attacked squares considered average for every piece type
+pub const MOBILITY_BASELINE: [Score; 6] = [14, 7, 7, 4, 0, 0];
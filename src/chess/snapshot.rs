@@ -0,0 +1,60 @@
+use super::{
+    bitboard::Bitboard, gamestate::CastlingSide, piece::Piece, position::Position, score::Score,
+    Game, GamePhase, Player,
+};
+
+/// Everything `push` changes and `pop` must restore, taken before a move to compare the
+/// position after it is undone with
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Snapshot {
+    player: Player,
+    hash: u64,
+    score: Score,
+    board: [Option<Piece>; 64],
+    castling: [bool; 4],
+    en_passant: i8,
+    halfmove_clock: u16,
+    checkers: Bitboard,
+    king_positions: [Position; 2],
+    material: [Score; 2],
+    piece_counts: [[u8; 6]; 2],
+    occupied: [Bitboard; 2],
+    pieces: [Bitboard; 6],
+    phase: GamePhase,
+    plies: usize,
+}
+
+impl Snapshot {
+    pub fn of(game: &Game) -> Self {
+        let state = game.state();
+        let castling = |player, side| state.castling(player, side);
+
+        Self {
+            player: game.player(),
+            hash: game.hash,
+            score: game.score,
+            board: game.board,
+            castling: [
+                castling(Player::White, CastlingSide::Short),
+                castling(Player::White, CastlingSide::Long),
+                castling(Player::Black, CastlingSide::Short),
+                castling(Player::Black, CastlingSide::Long),
+            ],
+            en_passant: state.en_passant(),
+            halfmove_clock: state.halfmove_clock(),
+            checkers: state.checkers(),
+            king_positions: game.king_positions,
+            material: game.material,
+            piece_counts: game.piece_counts,
+            occupied: game.occupied,
+            pieces: game.pieces,
+            phase: game.phase,
+            plies: game.len(),
+        }
+    }
+
+    /// Panics with `message` and the differences unless `game` is back in this position
+    pub fn assert_restored(&self, game: &Game, message: &str) {
+        assert_eq!(&Self::of(game), self, "{}", message);
+    }
+}
@@ -0,0 +1,80 @@
+// Accesses which the callers guarantee to be valid, so that the hot paths of the move
+// generation and the evaluation skip the bounds checks. With the `safe` feature they are
+// checked like any other access, and debug builds assert them either way.
+
+/// Element `index` of `slice`
+///
+/// # Safety
+/// `index` is less than the length of `slice`
+#[inline(always)]
+pub unsafe fn get<T>(slice: &[T], index: usize) -> &T {
+    #[cfg(feature = "safe")]
+    {
+        &slice[index]
+    }
+    #[cfg(not(feature = "safe"))]
+    {
+        debug_assert!(index < slice.len());
+        unsafe { slice.get_unchecked(index) }
+    }
+}
+
+/// Element `index` of `slice`
+///
+/// # Safety
+/// `index` is less than the length of `slice`
+#[inline(always)]
+pub unsafe fn get_mut<T>(slice: &mut [T], index: usize) -> &mut T {
+    #[cfg(feature = "safe")]
+    {
+        &mut slice[index]
+    }
+    #[cfg(not(feature = "safe"))]
+    {
+        debug_assert!(index < slice.len());
+        unsafe { slice.get_unchecked_mut(index) }
+    }
+}
+
+/// The value of `option`
+///
+/// # Safety
+/// `option` is `Some`
+#[inline(always)]
+pub unsafe fn unwrap<T>(option: Option<T>) -> T {
+    #[cfg(feature = "safe")]
+    {
+        option.unwrap()
+    }
+    #[cfg(not(feature = "safe"))]
+    {
+        debug_assert!(option.is_some());
+        unsafe { option.unwrap_unchecked() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_accesses() {
+        let mut values = [1, 2, 3];
+        unsafe {
+            assert_eq!(*get(&values, 2), 3);
+            *get_mut(&mut values, 0) = 4;
+            assert_eq!(unwrap(values.first()), &4);
+        }
+    }
+
+    // Out of bounds accesses are undefined behavior in release builds without the feature
+    #[cfg(any(feature = "safe", debug_assertions))]
+    #[test]
+    #[should_panic]
+    fn out_of_bounds() {
+        let values = [1, 2, 3];
+        unsafe {
+            get(&values, 3);
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::{bail, Context};
+use std::{fs, path::Path};
+
+/// File read by the UCI mode from the working directory when no other one is given
+pub const DEFAULT_PATH: &str = "rustybait.ini";
+
+/// Reads the options of a configuration file, which sets them like `setoption` before the
+/// GUI gets to, one `<name> = <value>` per line, e.g.
+///
+/// ```text
+/// # Options of the engine on the server
+/// Hash = 1024
+/// BookFile = books/performance.bin
+/// MoveTime = 2000
+/// LogFile = rustybait.log
+/// ```
+///
+/// Names are the ones of the UCI options, in any case. Empty lines and lines starting
+/// with `#` or `;` are skipped.
+pub fn parse(text: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut options = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['#', ';']) {
+            continue;
+        }
+
+        let Some((name, value)) = line.split_once('=') else {
+            bail!("Expected <name> = <value> on line {}", number + 1);
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("Missing option name on line {}", number + 1);
+        }
+        options.push((name.to_string(), value.trim().to_string()));
+    }
+
+    Ok(options)
+}
+
+/// Options of the configuration file at `path`, none if `path` is the default one and
+/// doesn't exist
+pub fn load(path: &str) -> anyhow::Result<Vec<(String, String)>> {
+    if path == DEFAULT_PATH && !Path::new(path).exists() {
+        return Ok(Vec::new());
+    }
+
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    parse(&text).with_context(|| format!("Failed to parse {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options() {
+        let text = "# Server\n\nHash = 64\n  ; Not used\nBookFile=books/my book.bin\nContempt =\n";
+        assert_eq!(
+            parse(text).unwrap(),
+            [
+                (String::from("Hash"), String::from("64")),
+                (String::from("BookFile"), String::from("books/my book.bin")),
+                (String::from("Contempt"), String::new()),
+            ]
+        );
+
+        assert!(parse("Hash 64").is_err());
+        assert!(parse("= 64").is_err());
+        assert!(load("missing.ini").is_err());
+        assert!(load(DEFAULT_PATH).is_ok());
+    }
+}
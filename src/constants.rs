@@ -1,11 +1,19 @@
+use crate::chess::Score;
+
 /// Default transposition table capacity.
 pub const TT_CAPACITY: usize = 10_000_000;
 
+/// Default size of the transposition table of the UCI engine, in MB.
+pub const DEFAULT_HASH_MB: usize = 256;
+
+/// Default value of the Contempt option, in centipawns.
+pub const DEFAULT_CONTEMPT: Score = 10;
+
 /// Source: https://lichess.org/study/rROPNxQX/NucjwPjN
 ///
 /// This game does not have a drawn-out endgame \
-/// It is a good test for the engine's opening and midgame play
-/// Also used for unit tests
+/// It is a good test for the engine's opening and midgame play, used by unit tests
+#[cfg(test)]
 pub const TESTING_GAME: &str = "g1f3 g8f6 c2c4 g7g6 b1c3 f8g7 d2d4 e8g8 c1f4 d7d5 d1b3 d5c4
                 b3c4 c7c6 e2e4 b8d7 a1d1 d7b6 c4c5 c8g4 f4g5 b6a4 c5a3 a4c3 
                 b2c3 f6e4 g5e7 d8b6 f1c4 e4c3 e7c5 f8e8 e1f1 g4e6 c5b6 e6c4 
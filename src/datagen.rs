@@ -0,0 +1,274 @@
+use crate::{
+    chess::{
+        epd::Epd,
+        marlinformat,
+        move_struct::MoveKind,
+        pgn::GameResult,
+        score::{self, Score},
+        Game,
+    },
+    constants::DEFAULT_CONTEMPT,
+    engine_match::{Adjudication, Adjudicator},
+    openings::{OpeningSettings, Openings},
+    search::{search, SearchLimits, TranspositionTable},
+    wdl,
+};
+use anyhow::Context;
+use nohash_hasher::BuildNoHashHasher;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Write},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed},
+        Mutex,
+    },
+    thread,
+};
+
+/// Positions whose score is further from 0 are decided and teach nothing to the evaluation
+const MAX_SCORE: Score = 2000;
+/// Plies after which a game which is still going on is dropped, since it has no result
+const MAX_PLIES: usize = 400;
+/// Entries the transposition table of each thread starts with, enough for short searches
+const TABLE_ENTRIES: usize = 1 << 18;
+/// Games between two progress reports
+const REPORT_INTERVAL: usize = 100;
+
+/// Settings of the games of the engine against itself played for training data
+pub struct DatagenSettings {
+    /// Where the games start from, the positions of the openings aren't written
+    pub openings: OpeningSettings,
+    /// Games played in total
+    pub games: usize,
+    /// Games played at the same time, each one on its own thread
+    pub threads: usize,
+    /// Nodes of the search of every move
+    pub nodes: u64,
+    /// File of the positions, in Marlinformat if it ends in `.bin`, else one EPD per line
+    pub output: String,
+    /// How the games are decided from the scores before they are over
+    pub adjudication: Adjudication,
+}
+
+/// A position of a game kept for training, with the score of its search from the point
+/// of view of the player to move
+struct Sample {
+    game: Game,
+    score: Score,
+}
+
+/// Plays games of the engine against itself on several threads and writes their quiet
+/// positions with the score of the search and the result of the game, for tuning the
+/// evaluation or training a network.
+///
+/// Positions in check, with a single legal move, with a capture or promotion as the best
+/// move or with a score close to a mate are left out, since the score of their search
+/// doesn't match their evaluation. Unfinished games are dropped.
+pub fn generate(settings: &DatagenSettings) -> anyhow::Result<()> {
+    let openings = Mutex::new(Openings::new(&settings.openings)?);
+    let file = File::create(&settings.output)
+        .with_context(|| format!("Failed to create {}", settings.output))?;
+    let output = Mutex::new(BufWriter::new(file));
+    let binary = settings.output.ends_with(".bin");
+
+    let next_game = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let positions = AtomicUsize::new(0);
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..settings.threads.clamp(1, settings.games.max(1)))
+            .map(|_| {
+                let (openings, output, next_game, finished, positions) =
+                    (&openings, &output, &next_game, &finished, &positions);
+                scope.spawn(move || -> anyhow::Result<()> {
+                    let mut table: TranspositionTable = HashMap::with_capacity_and_hasher(
+                        TABLE_ENTRIES,
+                        BuildNoHashHasher::default(),
+                    );
+
+                    while next_game.fetch_add(1, Relaxed) < settings.games {
+                        let game = openings.lock().unwrap().next_game();
+                        table.clear();
+                        let (samples, result) = play_game(settings, game, &mut table);
+
+                        if result != GameResult::Unfinished {
+                            let mut output = output.lock().unwrap();
+                            for sample in &samples {
+                                write_sample(&mut *output, sample, result, binary)?;
+                            }
+                            positions.fetch_add(samples.len(), Relaxed);
+                        }
+
+                        let count = finished.fetch_add(1, Relaxed) + 1;
+                        if count % REPORT_INTERVAL == 0 {
+                            println!(
+                                "Played {} of {} games, {} positions",
+                                count,
+                                settings.games,
+                                positions.load(Relaxed)
+                            );
+                        }
+                    }
+                    Ok(())
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .try_for_each(|handle| handle.join().unwrap())
+    })
+    .with_context(|| format!("Failed to write {}", settings.output))?;
+
+    output
+        .into_inner()
+        .unwrap()
+        .flush()
+        .with_context(|| format!("Failed to write {}", settings.output))?;
+
+    println!(
+        "Wrote {} positions of {} games to {}",
+        positions.into_inner(),
+        settings.games,
+        settings.output
+    );
+
+    Ok(())
+}
+
+/// Plays a game from `game` with the searches limited to the nodes of the settings, and
+/// returns the positions kept for training with the result of the game
+fn play_game(
+    settings: &DatagenSettings,
+    mut game: Game,
+    table: &mut TranspositionTable,
+) -> (Vec<Sample>, GameResult) {
+    let limits = SearchLimits::new().nodes(settings.nodes);
+    let mut adjudicator = Adjudicator::new(settings.adjudication);
+    let mut samples = Vec::new();
+
+    let result = loop {
+        let outcome = game.outcome();
+        if outcome.is_over() {
+            break outcome.result();
+        }
+        if game.history().len() >= MAX_PLIES {
+            break GameResult::Unfinished;
+        }
+
+        let (next_move, score, _) = search(
+            &game,
+            &limits,
+            table,
+            &AtomicBool::new(true),
+            DEFAULT_CONTEMPT,
+            |_| {},
+        )
+        .expect("The game goes on, so there is a legal move");
+
+        // A single legal move is played without searching it, its score of 0 means nothing
+        let is_searched = game.legal_moves().len() > 1;
+        let is_quiet = !game.is_check()
+            && next_move.captured_piece(&game).is_none()
+            && !matches!(next_move.kind(), MoveKind::Promotion(_));
+        if is_searched && is_quiet && !score::is_mate(score) && score.abs() <= MAX_SCORE {
+            samples.push(Sample {
+                game: game.clone(),
+                score,
+            });
+        }
+
+        let adjudicated = is_searched
+            .then(|| adjudicator.update(&game, wdl::normalize(score, &game)))
+            .flatten();
+        game.push_history(next_move);
+        if let Some(result) = adjudicated {
+            break result;
+        }
+    };
+
+    (samples, result)
+}
+
+/// Writes a position as a Marlinformat record, or as an EPD with the score in its `ce`
+/// operation and the result in `c9`, which `tuning::tune` reads
+fn write_sample(
+    output: &mut impl Write,
+    sample: &Sample,
+    result: GameResult,
+    binary: bool,
+) -> anyhow::Result<()> {
+    if binary {
+        let white_score = sample.score * sample.game.player() as Score;
+        output.write_all(&marlinformat::pack(&sample.game, white_score, result))?;
+    } else {
+        let mut epd = Epd::new(sample.game.clone());
+        epd.centipawn_evaluation = Some(sample.score);
+        epd.operations
+            .push((String::from("c9"), vec![result.as_str().to_string()]));
+        writeln!(output, "{}", epd)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chess::marlinformat::RECORD_SIZE;
+
+    #[test]
+    fn training_data() {
+        let path =
+            std::env::temp_dir().join(format!("rustybait-{}-datagen.bin", std::process::id()));
+        let settings = DatagenSettings {
+            openings: OpeningSettings {
+                random_plies: 8,
+                seed: Some(7),
+                ..OpeningSettings::default()
+            },
+            games: 4,
+            threads: 2,
+            nodes: 1000,
+            output: path.to_string_lossy().into_owned(),
+            adjudication: Adjudication::default(),
+        };
+        generate(&settings).unwrap();
+
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(!bytes.is_empty());
+        assert_eq!(bytes.len() % RECORD_SIZE, 0);
+        for record in bytes.chunks_exact(RECORD_SIZE) {
+            let (game, score, result) = marlinformat::unpack(record.try_into().unwrap()).unwrap();
+            assert!(!game.is_check());
+            assert!(score.abs() <= MAX_SCORE);
+            assert_ne!(result, GameResult::Unfinished);
+        }
+
+        // Mate in one, the position before the mate isn't kept
+        let game = Game::new("6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1").unwrap();
+        let mut table = HashMap::default();
+        let (samples, result) = play_game(&settings, game, &mut table);
+        assert_eq!(result, GameResult::WhiteWins);
+        assert!(samples.is_empty());
+
+        // The only move of black isn't searched, so the start position isn't kept
+        let game = Game::new("k7/8/8/8/8/8/8/1R5K b - - 0 1").unwrap();
+        let (samples, _) = play_game(&settings, game, &mut table);
+        assert!(samples
+            .iter()
+            .all(|sample| sample.game.legal_moves().len() > 1));
+
+        let mut line = Vec::new();
+        let sample = Sample {
+            game: Game::default(),
+            score: 25,
+        };
+        write_sample(&mut line, &sample, GameResult::Draw, false).unwrap();
+        let line = String::from_utf8(line).unwrap();
+        let epd = Epd::parse(line.trim()).unwrap();
+        assert_eq!(epd.centipawn_evaluation, Some(25));
+        assert_eq!(epd.operation("c9"), Some("1/2-1/2"));
+    }
+}
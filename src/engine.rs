@@ -0,0 +1,764 @@
+use crate::{
+    book::Book,
+    chess::{
+        move_struct::Move,
+        params::{self, EvalParams},
+        Game, Score, START_FEN,
+    },
+    constants::{DEFAULT_CONTEMPT, DEFAULT_HASH_MB},
+    log,
+    openings::Random,
+    search::{
+        self, search, SearchInfo, SearchLimits, SearchParams, TableEntry, TranspositionTable,
+    },
+    spsa,
+    strength::{self, Strength, LEVEL_RANGE},
+};
+use anyhow::{bail, Context};
+use nohash_hasher::BuildNoHashHasher;
+use std::{
+    collections::HashMap,
+    mem,
+    ops::RangeInclusive,
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering::Relaxed},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Contempt accepted by the `Contempt` option, in centipawns
+pub const CONTEMPT_RANGE: RangeInclusive<Score> = -100..=100;
+/// Sizes of the transposition table accepted by the `Hash` option, in MB
+pub const HASH_RANGE: RangeInclusive<usize> = 1..=65536;
+/// Times accepted by the `MoveTime` option, in ms, 0 for none
+pub const MOVE_TIME_RANGE: RangeInclusive<u64> = 0..=3_600_000;
+/// Best lines reported by the searches accepted by the `MultiPV` option
+pub const MULTI_PV_RANGE: RangeInclusive<u8> = 1..=20;
+/// Percentages accepted by the `BookRandomness` option, see `Book::pick`
+pub const BOOK_RANDOMNESS_RANGE: RangeInclusive<u8> = 0..=100;
+/// Last moves played from the book accepted by the `BookDepth` option, 0 for no limit
+pub const BOOK_DEPTH_RANGE: RangeInclusive<usize> = 0..=200;
+
+/// Entries of the transposition table fitting in about `megabytes` MB
+fn table_entries(megabytes: usize) -> usize {
+    (megabytes << 20) / mem::size_of::<(u64, TableEntry)>()
+}
+
+fn new_table(entries: usize) -> TranspositionTable {
+    HashMap::with_capacity_and_hasher(entries, BuildNoHashHasher::default())
+}
+
+/// What the engine reports to the receiver created with it
+#[derive(Clone, Debug)]
+pub enum EngineEvent {
+    /// Progress of the search, after every depth
+    Info(SearchInfo),
+    /// End of the search with the best move, None if there is no legal move
+    ///
+    /// Every search ends with it, also when it is stopped.
+    BestMove(Option<Move>),
+}
+
+/// The engine driven from the same process, e.g. by a GUI or a bot, instead of through
+/// UCI over pipes
+///
+/// Searches run on a worker thread of their own, the calls return right away and the
+/// progress is sent to the receiver returned by `new`. The position and the options can't
+/// be changed while a search is running.
+///
+/// Engines are independent of each other: each has its own transposition table, options
+/// and evaluation parameters, so that a process can play or analyze several games at once
+/// with an engine per game. The table takes most of the memory, `with_hash` keeps it small
+/// when there are many engines.
+///
+/// ```
+/// use rustybait::{
+///     engine::{Engine, EngineEvent},
+///     search::SearchLimits,
+///     Game,
+/// };
+///
+/// let (mut engine, events) = Engine::new();
+/// engine.set_option("Contempt", "20").unwrap();
+/// engine.set_position(Game::default()).unwrap();
+/// engine.start_search(SearchLimits::new().depth(4)).unwrap();
+///
+/// for event in events.iter() {
+///     match event {
+///         EngineEvent::Info(info) => println!("Depth {}: {}", info.depth, info.score),
+///         EngineEvent::BestMove(best_move) => {
+///             assert!(best_move.is_some());
+///             break;
+///         }
+///     }
+/// }
+/// ```
+pub struct Engine {
+    game: Game,
+    table: Arc<Mutex<TranspositionTable>>,
+    /// Entries of the table after which it is emptied before the next search
+    table_entries: usize,
+    /// How much worse than an equal position the engine considers a draw
+    contempt: Score,
+    /// Evaluation parameters of the positions searched, set by the `EvalFile` option
+    params: Arc<EvalParams>,
+    /// Constants of the search, set by the options of `spsa::TUNABLES`
+    search_params: SearchParams,
+    /// Whether castling moves are exchanged in the Chess960 notation
+    chess960: bool,
+    /// Opening book whose moves are played without searching
+    book: Option<Book>,
+    /// How far from the moves of the book with the highest weight the choice may go
+    book_randomness: u8,
+    /// Fullmove number after which the book isn't probed anymore, 0 for none
+    book_depth: usize,
+    /// Time of the searches started without any limit
+    default_move_time: Option<Duration>,
+    /// Best lines reported after every depth
+    multi_pv: u8,
+    /// Handicaps of the `SkillLevel` option, None at full strength
+    strength: Option<Strength>,
+    running: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+    events: Sender<EngineEvent>,
+}
+
+impl Engine {
+    /// The engine in the start position, with the receiver of its events
+    pub fn new() -> (Self, Receiver<EngineEvent>) {
+        Self::with_hash(DEFAULT_HASH_MB).unwrap()
+    }
+
+    /// The engine with a transposition table of `megabytes` MB instead of the default
+    /// size, as set by the `Hash` option
+    pub fn with_hash(megabytes: usize) -> anyhow::Result<(Self, Receiver<EngineEvent>)> {
+        check_hash(megabytes)?;
+        let (events, receiver) = mpsc::channel();
+        let table_entries = table_entries(megabytes);
+        let params = params::default();
+        let engine = Self {
+            game: Game::with_params(START_FEN, params.clone()).unwrap(),
+            table: Arc::new(Mutex::new(new_table(table_entries))),
+            table_entries,
+            contempt: DEFAULT_CONTEMPT,
+            params,
+            search_params: SearchParams::DEFAULT,
+            chess960: false,
+            book: None,
+            book_randomness: *BOOK_RANDOMNESS_RANGE.end(),
+            book_depth: 0,
+            default_move_time: None,
+            multi_pv: 1,
+            strength: None,
+            running: Arc::new(AtomicBool::new(false)),
+            worker: None,
+            events,
+        };
+        Ok((engine, receiver))
+    }
+
+    /// The position searched by `start_search`
+    pub fn position(&self) -> &Game {
+        &self.game
+    }
+
+    /// Replaces the position, castling moves of the game are written as set by the
+    /// `UCI_Chess960` option and it is evaluated with the parameters of the engine
+    pub fn set_position(&mut self, mut game: Game) -> anyhow::Result<()> {
+        self.check_idle()?;
+        if !ptr::eq(game.params(), &*self.params) {
+            game = recreate(&game, self.params.clone());
+        }
+        game.set_chess960(self.chess960);
+        self.game = game;
+        Ok(())
+    }
+
+    pub fn is_chess960(&self) -> bool {
+        self.chess960
+    }
+
+    /// Best lines reported after every depth, as set by the `MultiPV` option
+    pub fn multi_pv(&self) -> u8 {
+        self.multi_pv
+    }
+
+    /// Parameters of the evaluation, the current ones when the engine was created unless
+    /// the `EvalFile` option was set
+    pub fn params(&self) -> Arc<EvalParams> {
+        self.params.clone()
+    }
+
+    /// Sets an option by its UCI name, which is case insensitive: `Contempt`,
+    /// `UCI_Chess960`, `EvalFile`, `BookFile`, `BookRandomness`, `BookDepth` (the last move
+    /// played from the book), `Hash` in MB, `Threads`, `MoveTime`, the time in ms of the
+    /// searches started without limits, `MultiPV`, the best lines reported, or one of the
+    /// constants of `spsa::TUNABLES`. Files are unset by `<empty>`.
+    pub fn set_option(&mut self, name: &str, value: &str) -> anyhow::Result<()> {
+        self.check_idle()?;
+
+        match name.to_ascii_lowercase().as_str() {
+            "contempt" => {
+                let contempt: Score = value.parse().context("Invalid contempt value")?;
+                if !CONTEMPT_RANGE.contains(&contempt) {
+                    bail!(
+                        "Contempt must be between {} and {}",
+                        CONTEMPT_RANGE.start(),
+                        CONTEMPT_RANGE.end()
+                    );
+                }
+                self.contempt = contempt;
+            }
+            "uci_chess960" => {
+                self.chess960 = parse_check("UCI_Chess960", value)?;
+                self.game.set_chess960(self.chess960);
+            }
+            "evalfile" => {
+                // Only this engine uses the new parameters, other engines of the process
+                // keep theirs
+                self.params = if value.is_empty() || value == "<empty>" {
+                    params::default()
+                } else {
+                    Arc::new(EvalParams::load(value)?)
+                };
+
+                // Scores in the table were computed with the old parameters, and the game
+                // keeps the ones it was created with
+                self.table.lock().unwrap().clear();
+                self.game = recreate(&self.game, self.params.clone());
+            }
+            "bookfile" => {
+                self.book = if value.is_empty() || value == "<empty>" {
+                    None
+                } else {
+                    Some(Book::open(value)?)
+                };
+            }
+            "bookrandomness" => {
+                let randomness: u8 = value.parse().context("Invalid book randomness")?;
+                if !BOOK_RANDOMNESS_RANGE.contains(&randomness) {
+                    bail!(
+                        "BookRandomness must be between {} and {}",
+                        BOOK_RANDOMNESS_RANGE.start(),
+                        BOOK_RANDOMNESS_RANGE.end()
+                    );
+                }
+                self.book_randomness = randomness;
+            }
+            "bookdepth" => {
+                let depth: usize = value.parse().context("Invalid book depth")?;
+                if !BOOK_DEPTH_RANGE.contains(&depth) {
+                    bail!(
+                        "BookDepth must be between {} and {}",
+                        BOOK_DEPTH_RANGE.start(),
+                        BOOK_DEPTH_RANGE.end()
+                    );
+                }
+                self.book_depth = depth;
+            }
+            "hash" => {
+                let megabytes: usize = value.parse().context("Invalid hash size")?;
+                check_hash(megabytes)?;
+
+                // The old table is freed before the new one is allocated
+                self.table_entries = table_entries(megabytes);
+                let mut table = self.table.lock().unwrap();
+                *table = TranspositionTable::default();
+                *table = new_table(self.table_entries);
+            }
+            "threads" => {
+                // Accepted since GUIs and configuration files commonly set it
+                let threads: usize = value.parse().context("Invalid number of threads")?;
+                if threads != 1 {
+                    bail!("The search runs on a single thread");
+                }
+            }
+            "movetime" => {
+                let millis: u64 = value.parse().context("Invalid move time")?;
+                if !MOVE_TIME_RANGE.contains(&millis) {
+                    bail!(
+                        "MoveTime must be between {} and {} ms",
+                        MOVE_TIME_RANGE.start(),
+                        MOVE_TIME_RANGE.end()
+                    );
+                }
+                self.default_move_time = (millis > 0).then(|| Duration::from_millis(millis));
+            }
+            "multipv" => {
+                let lines: u8 = value.parse().context("Invalid number of lines")?;
+                if !MULTI_PV_RANGE.contains(&lines) {
+                    bail!(
+                        "MultiPV must be between {} and {}",
+                        MULTI_PV_RANGE.start(),
+                        MULTI_PV_RANGE.end()
+                    );
+                }
+                self.multi_pv = lines;
+            }
+            "skilllevel" => {
+                let level: u8 = value.parse().context("Invalid skill level")?;
+                if !LEVEL_RANGE.contains(&level) {
+                    bail!(
+                        "SkillLevel must be between {} and {}",
+                        LEVEL_RANGE.start(),
+                        LEVEL_RANGE.end()
+                    );
+                }
+                self.strength = Strength::of_level(level);
+            }
+            _ => {
+                let tunable =
+                    spsa::tunable(name).with_context(|| format!("Unknown option: {}", name))?;
+                let value = value
+                    .parse()
+                    .with_context(|| format!("Invalid {}", tunable.name))?;
+                let mut params = EvalParams::clone(&self.params);
+                tunable.apply(&mut self.search_params, &mut params, value)?;
+
+                if params != *self.params {
+                    self.params = Arc::new(params);
+                    self.table.lock().unwrap().clear();
+                    self.game = recreate(&self.game, self.params.clone());
+                }
+            }
+        }
+
+        log::debug!("Option {} set to {}", name, value);
+        Ok(())
+    }
+
+    /// Stops the search and forgets what was learned from the previous game,
+    /// the position goes back to the start
+    pub fn new_game(&mut self) {
+        self.stop();
+        self.table.lock().unwrap().clear();
+        self.game = Game::with_params(START_FEN, self.params.clone()).unwrap();
+        self.game.set_chess960(self.chess960);
+    }
+
+    /// Starts searching the position within `limits`, the move of the opening book is
+    /// played instead if there is one and the game is within `BookDepth` moves, unless
+    /// the search is infinite or restricted to some of the moves. Without any limit the
+    /// search takes the time of the `MoveTime` option if it is set, and it reports the
+    /// lines of the `MultiPV` option unless `limits` asks for several. Below the highest
+    /// `SkillLevel` the search is capped and its move picked by `Strength::choose`.
+    pub fn start_search(&mut self, mut limits: SearchLimits) -> anyhow::Result<()> {
+        // A worker which failed is still marked as searching
+        if self.worker.as_ref().is_some_and(JoinHandle::is_finished) {
+            self.join_worker();
+        }
+        self.check_idle()?;
+        // The previous worker has sent its best move and is about to finish
+        self.join_worker();
+
+        // The clock is random enough to vary the openings between games
+        let random = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_nanos() as u64);
+        // Analysis and searches of chosen moves want the search's answer
+        let in_book = (self.book_depth == 0 || self.game.fullmove_number() <= self.book_depth)
+            && !limits.is_infinite()
+            && !limits.is_restricted();
+        let book_move = self
+            .book
+            .as_ref()
+            .filter(|_| in_book)
+            .and_then(|book| book.pick(&self.game, self.book_randomness, random));
+        if let Some(book_move) = book_move {
+            log::debug!("Book move {}", book_move.uci_notation(&self.game));
+            let _ = self.events.send(EngineEvent::BestMove(Some(book_move)));
+            return Ok(());
+        }
+
+        if let Some(move_time) = self.default_move_time.filter(|_| limits.is_unlimited()) {
+            limits = limits.move_time(move_time);
+        }
+        if limits.lines() == 1 {
+            limits = limits.multi_pv(self.multi_pv);
+        }
+        // The lines asked for are reported, the other candidates of the level aren't.
+        // Analysis until `stop` is always at full strength.
+        let shown_lines = limits.lines();
+        let strength = self.strength.filter(|_| !limits.is_infinite());
+        if let Some(strength) = strength {
+            limits = strength.limit(limits);
+        }
+        // Only the searches fill the table, which grows past its size at most once
+        {
+            let mut table = self.table.lock().unwrap();
+            if table.len() >= self.table_entries {
+                log::debug!(
+                    "Transposition table full with {} entries, cleared",
+                    table.len()
+                );
+                table.clear();
+            }
+        }
+        log::debug!("Search of {} with {:?}", self.game.to_fen(), limits);
+
+        self.running.store(true, Relaxed);
+        let game = self.game.clone();
+        let table = self.table.clone();
+        let running = self.running.clone();
+        let events = self.events.clone();
+        let contempt = self.contempt;
+        let search_params = self.search_params;
+
+        self.worker = Some(thread::spawn(move || {
+            search::set_params(search_params);
+            let mut table = table.lock().unwrap();
+            let mut last_depth = None;
+            let mut lines = Vec::new();
+            let best_move = search(&game, &limits, &mut table, &running, contempt, |info| {
+                last_depth = Some((info.depth, info.nodes));
+                if strength.is_some() {
+                    strength::record_line(&mut lines, info);
+                }
+                if info.multipv <= shown_lines {
+                    let _ = events.send(EngineEvent::Info(info.clone()));
+                }
+            });
+            if let Some((depth, nodes)) = last_depth {
+                log::debug!("Search ended at depth {} after {} nodes", depth, nodes);
+            }
+            let mut best_move = best_move.map(|(best_move, _, _)| best_move);
+            if let Some(strength) = strength {
+                let chosen = strength.choose(&lines, &mut Random::new(Some(random)));
+                best_move = chosen.or(best_move);
+            }
+
+            // The search must be marked as stopped before the best move is known, which
+            // may be answered with the next position right away
+            running.store(false, Relaxed);
+            let _ = events.send(EngineEvent::BestMove(best_move));
+        }));
+
+        Ok(())
+    }
+
+    /// Stops the search, once this returns the best move has been sent
+    pub fn stop(&mut self) {
+        self.running.store(false, Relaxed);
+        self.wait();
+    }
+
+    /// Waits for the search to reach its limits, infinite searches only end once they are
+    /// stopped from another thread
+    pub fn wait(&mut self) {
+        self.join_worker();
+    }
+
+    /// Waits for the worker to finish, if it panicked its search is ended without a move
+    /// and the table it was filling is replaced
+    fn join_worker(&mut self) {
+        let Some(worker) = self.worker.take() else {
+            return;
+        };
+        if worker.join().is_err() {
+            log::error!("The search failed, it has no best move");
+            self.table = Arc::new(Mutex::new(new_table(self.table_entries)));
+            self.running.store(false, Relaxed);
+            let _ = self.events.send(EngineEvent::BestMove(None));
+        }
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.running.load(Relaxed)
+    }
+
+    fn check_idle(&self) -> anyhow::Result<()> {
+        if self.is_searching() {
+            bail!("Search is still running, it must be stopped first");
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn check_hash(megabytes: usize) -> anyhow::Result<()> {
+    if !HASH_RANGE.contains(&megabytes) {
+        bail!(
+            "Hash must be between {} and {} MB",
+            HASH_RANGE.start(),
+            HASH_RANGE.end()
+        );
+    }
+    Ok(())
+}
+
+/// Reads the value of a check option
+pub fn parse_check(name: &str, value: &str) -> anyhow::Result<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => bail!("{} must be true or false", name),
+    }
+}
+
+/// The same game created again, evaluated with `params`
+fn recreate(game: &Game, params: Arc<EvalParams>) -> Game {
+    let mut recreated = Game::with_params(&game.initial_position().to_fen(), params).unwrap();
+    recreated.set_chess960(game.is_chess960());
+    for &_move in game.history() {
+        recreated.push_history(_move);
+    }
+    recreated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn search_and_stop() {
+        let (mut engine, events) = Engine::new();
+        let game = Game::new("6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1").unwrap();
+        engine.set_position(game).unwrap();
+        engine.start_search(SearchLimits::new().depth(3)).unwrap();
+        engine.wait();
+
+        let events: Vec<_> = events.try_iter().collect();
+        assert!(matches!(events[0], EngineEvent::Info(_)));
+        let Some(EngineEvent::BestMove(Some(best_move))) = events.last() else {
+            panic!("No best move: {:?}", events);
+        };
+        assert_eq!(best_move.uci_notation(engine.position()), "e1e8");
+
+        let (mut engine, events) = Engine::new();
+        engine.start_search(SearchLimits::new().infinite()).unwrap();
+        assert!(engine.is_searching());
+        assert!(engine.set_position(Game::default()).is_err());
+        assert!(engine.start_search(SearchLimits::new()).is_err());
+
+        thread::sleep(Duration::from_millis(50));
+        engine.stop();
+        assert!(!engine.is_searching());
+        assert!(matches!(
+            events.try_iter().last(),
+            Some(EngineEvent::BestMove(Some(_)))
+        ));
+
+        for limits in [SearchLimits::new().depth(0), SearchLimits::new().mate(0)] {
+            engine.start_search(limits).unwrap();
+            engine.wait();
+            assert!(matches!(
+                events.try_iter().last(),
+                Some(EngineEvent::BestMove(Some(_)))
+            ));
+        }
+
+        // The weakest level searches one depth and only reports the line asked for
+        engine.set_option("SkillLevel", "1").unwrap();
+        engine.start_search(SearchLimits::new().depth(10)).unwrap();
+        engine.wait();
+        let events: Vec<_> = events.try_iter().collect();
+        assert!(events.iter().all(|event| match event {
+            EngineEvent::Info(info) => info.depth == 1 && info.multipv == 1,
+            EngineEvent::BestMove(best_move) => best_move.is_some(),
+        }));
+        assert!(engine.set_option("SkillLevel", "0").is_err());
+        assert!(engine.set_option("SkillLevel", "21").is_err());
+    }
+
+    #[test]
+    fn failed_search() {
+        let (mut engine, events) = Engine::new();
+        engine.running.store(true, Relaxed);
+        let table = engine.table.clone();
+        engine.worker = Some(thread::spawn(move || {
+            let _table = table.lock().unwrap();
+            panic!("Search bug");
+        }));
+        while !engine.worker.as_ref().unwrap().is_finished() {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        // The next search starts as if the failed one had stopped
+        engine.start_search(SearchLimits::new().depth(3)).unwrap();
+        engine.wait();
+        let events: Vec<_> = events.try_iter().collect();
+        assert!(matches!(events[0], EngineEvent::BestMove(None)));
+        assert!(matches!(
+            events.last(),
+            Some(EngineEvent::BestMove(Some(_)))
+        ));
+    }
+
+    #[test]
+    fn options() {
+        let (mut engine, _) = Engine::new();
+        engine.set_option("Contempt", "-20").unwrap();
+        assert!(engine.set_option("contempt", "1000").is_err());
+        assert!(engine.set_option("Ponder", "true").is_err());
+        engine.set_option("Hash", "16").unwrap();
+        assert_eq!(engine.table_entries, table_entries(16));
+        assert!(engine.set_option("Hash", "0").is_err());
+        engine.set_option("Threads", "1").unwrap();
+        assert!(engine.set_option("Threads", "4").is_err());
+        engine.set_option("multipv", "3").unwrap();
+        assert_eq!(engine.multi_pv(), 3);
+        assert!(engine.set_option("MultiPV", "0").is_err());
+
+        let game =
+            Game::new("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9").unwrap();
+        engine.set_position(game).unwrap();
+        assert!(!engine.position().is_chess960());
+        engine.set_option("UCI_Chess960", "true").unwrap();
+        assert!(engine.position().is_chess960());
+        assert!(engine.set_option("UCI_Chess960", "yes").is_err());
+
+        // Tunable constants of the search and of the evaluation
+        engine.set_option("NullMoveReduction", "3").unwrap();
+        assert_eq!(engine.search_params.null_move_reduction, 3);
+        engine.set_option("spacebonus", "8").unwrap();
+        assert_eq!(engine.params().space_bonus, 8);
+        assert_eq!(engine.position().params().space_bonus, 8);
+        // Replaced parameters are freed, and kept when nothing changes
+        let tuned = Arc::downgrade(&engine.params());
+        engine.set_option("SpaceBonus", "8").unwrap();
+        engine.set_option("NullMoveReduction", "2").unwrap();
+        assert!(tuned.upgrade().is_some());
+        engine.set_option("SpaceBonus", "9").unwrap();
+        assert!(tuned.upgrade().is_none());
+        assert!(engine.set_option("SpaceBonus", "-1").is_err());
+        assert!(engine.set_option("HistoryLimit", "much").is_err());
+    }
+
+    #[test]
+    fn independent_engines() {
+        let mut params = EvalParams::DEFAULT;
+        params.doubled_pawn_penalty += 10;
+        let path = std::env::temp_dir().join(format!("rustybait-{}.params", std::process::id()));
+        std::fs::write(&path, params.to_string()).unwrap();
+
+        let (mut first, first_events) = Engine::with_hash(1).unwrap();
+        let (mut second, second_events) = Engine::with_hash(1).unwrap();
+        assert!(Engine::with_hash(0).is_err());
+        first
+            .set_option("EvalFile", path.to_str().unwrap())
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // Games set from outside are evaluated with the parameters of the engine
+        first.set_position(Game::default()).unwrap();
+        assert_eq!(*first.position().params(), params);
+        assert_eq!(*second.params(), *params::default());
+        assert_eq!(*second.position().params(), *params::default());
+
+        first.start_search(SearchLimits::new().depth(4)).unwrap();
+        second.start_search(SearchLimits::new().depth(4)).unwrap();
+        for events in [first_events, second_events] {
+            assert!(events
+                .iter()
+                .any(|event| matches!(event, EngineEvent::BestMove(Some(_)))));
+        }
+
+        // The parameters of the file are freed once the engine and its games are done
+        // with them, and the defaults are shared
+        first.wait();
+        let loaded = Arc::downgrade(&first.params());
+        first.set_option("EvalFile", "<empty>").unwrap();
+        assert!(loaded.upgrade().is_none());
+        assert!(ptr::eq(
+            first.position().params(),
+            second.position().params()
+        ));
+    }
+
+    #[test]
+    fn default_move_time() {
+        let (mut engine, events) = Engine::new();
+        engine.set_option("MoveTime", "50").unwrap();
+        assert!(engine.set_option("MoveTime", "-1").is_err());
+
+        // Would never end without the option
+        engine.start_search(SearchLimits::new()).unwrap();
+        engine.wait();
+        assert!(matches!(
+            events.try_iter().last(),
+            Some(EngineEvent::BestMove(Some(_)))
+        ));
+
+        engine.set_option("MoveTime", "0").unwrap();
+        assert_eq!(engine.default_move_time, None);
+    }
+
+    #[test]
+    fn book_depth() {
+        // a2a3 in the start position, which the search doesn't play
+        let mut entry = Game::default().polyglot_key().to_be_bytes().to_vec();
+        entry.extend((8u16 << 6 | 16).to_be_bytes());
+        entry.extend([0, 1, 0, 0, 0, 0]);
+        let path = std::env::temp_dir().join(format!("rustybait-{}.bin", std::process::id()));
+        std::fs::write(&path, entry).unwrap();
+
+        let (mut engine, events) = Engine::new();
+        engine
+            .set_option("BookFile", path.to_str().unwrap())
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        engine.set_option("BookRandomness", "0").unwrap();
+        assert!(engine.set_option("BookRandomness", "101").is_err());
+        engine.set_option("BookDepth", "4").unwrap();
+        assert!(engine.set_option("BookDepth", "-1").is_err());
+
+        let mut best_moves = Vec::new();
+        for fullmove_number in [4, 5] {
+            let fen = START_FEN.replace(" 1", &format!(" {}", fullmove_number));
+            engine.set_position(Game::new(&fen).unwrap()).unwrap();
+            engine.start_search(SearchLimits::new().depth(2)).unwrap();
+            engine.wait();
+            let Some(EngineEvent::BestMove(Some(best_move))) = events.try_iter().last() else {
+                panic!("No best move");
+            };
+            best_moves.push(best_move.uci_notation(engine.position()));
+        }
+        assert_eq!(best_moves[0], "a2a3");
+        assert_ne!(best_moves[1], "a2a3");
+
+        // The book doesn't answer analysis or searches of other moves
+        let fen = START_FEN.replace(" 1", " 4");
+        let e2e4 = Move::from_uci_notation("e2e4", &Game::default()).unwrap();
+        for limits in [
+            SearchLimits::new().depth(2).search_moves(vec![e2e4]),
+            SearchLimits::new().infinite(),
+        ] {
+            engine.set_position(Game::new(&fen).unwrap()).unwrap();
+            let infinite = limits.is_infinite();
+            engine.start_search(limits).unwrap();
+            if infinite {
+                thread::sleep(Duration::from_millis(50));
+                assert!(engine.is_searching());
+                engine.stop();
+            }
+            engine.wait();
+            let Some(EngineEvent::BestMove(Some(best_move))) = events.try_iter().last() else {
+                panic!("No best move");
+            };
+            assert_ne!(best_move.uci_notation(engine.position()), "a2a3");
+        }
+    }
+
+    #[test]
+    fn recreated_game() {
+        let mut game = Game::new("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1").unwrap();
+        for uci in ["e2e4", "e8d7", "e1d2"] {
+            let _move = Move::from_uci_notation(uci, &game).unwrap();
+            game.push_history(_move);
+        }
+
+        let recreated = recreate(&game, params::default());
+        assert_eq!(recreated.to_fen(), game.to_fen());
+        assert_eq!(recreated.history(), game.history());
+    }
+}
@@ -0,0 +1,647 @@
+use crate::chess::{
+    epd::Epd,
+    move_struct::Move,
+    pgn::{GameResult, PgnGame},
+    score::{self, Score},
+    Game, Outcome, Player,
+};
+use anyhow::{bail, Context};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    time::Instant,
+};
+
+/// Balanced positions after common openings, each one is played twice with the colors
+/// swapped so that neither engine is favored by the opening
+const OPENINGS: &str = include_str!("../openings.epd");
+
+/// How to start an engine, and the UCI options set before its first game
+pub struct EngineConfig {
+    /// Name in the output and in the PGN, the one the engine gives itself if there is none
+    pub name: Option<String>,
+    /// Program followed by its arguments, separated by whitespace
+    pub command: String,
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineConfig {
+    /// This program, which plays as an engine when it is started without arguments
+    pub fn this_program(name: Option<&str>) -> anyhow::Result<Self> {
+        let program = std::env::current_exe().context("Failed to find the path of this program")?;
+
+        Ok(Self {
+            name: name.map(String::from),
+            command: program.to_string_lossy().into_owned(),
+            options: Vec::new(),
+        })
+    }
+}
+
+/// An engine running in another process, spoken to with UCI
+pub struct Engine {
+    pub name: String,
+    process: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Engine {
+    pub fn start(config: &EngineConfig) -> anyhow::Result<Self> {
+        let mut words = config.command.split_ascii_whitespace();
+        let program = words.next().context("Empty engine command")?;
+        let mut process = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to start {}", config.command))?;
+
+        let mut engine = Self {
+            name: program.to_string(),
+            stdin: process.stdin.take().unwrap(),
+            stdout: BufReader::new(process.stdout.take().unwrap()),
+            process,
+        };
+
+        engine.send("uci")?;
+        let mut id_name = None;
+        engine.read_until("uciok", |line| {
+            if let Some(name) = line.strip_prefix("id name ") {
+                id_name = Some(name.trim().to_string());
+            }
+        })?;
+        if let Some(name) = config.name.clone().or(id_name) {
+            engine.name = name;
+        }
+
+        for (name, value) in &config.options {
+            engine.send(&format!("setoption name {} value {}", name, value))?;
+        }
+        engine.send("isready")?;
+        engine.read_until("readyok", |_| ())?;
+
+        Ok(engine)
+    }
+
+    fn send(&mut self, command: &str) -> anyhow::Result<()> {
+        writeln!(self.stdin, "{}", command)
+            .with_context(|| format!("Failed to write to {}", self.name))
+    }
+
+    /// Reads the output of the engine until a line starting with `token`, which is returned,
+    /// the lines before it are given to `on_line`
+    fn read_until(&mut self, token: &str, mut on_line: impl FnMut(&str)) -> anyhow::Result<String> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = self
+                .stdout
+                .read_line(&mut line)
+                .with_context(|| format!("Failed to read from {}", self.name))?;
+            if read == 0 {
+                bail!("{} exited while waiting for {}", self.name, token);
+            }
+            if line.split_ascii_whitespace().next() == Some(token) {
+                return Ok(line.trim_end().to_string());
+            }
+            on_line(line.trim_end());
+        }
+    }
+
+    fn new_game(&mut self) -> anyhow::Result<()> {
+        self.send("ucinewgame")?;
+        self.send("isready")?;
+        self.read_until("readyok", |_| ())?;
+        Ok(())
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        if self.send("quit").is_err() || self.process.wait().is_err() {
+            let _ = self.process.kill();
+        }
+    }
+}
+
+/// Why a game ended
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    TimeForfeit,
+    IllegalMove,
+    /// Decided from the evaluations before it was over
+    Adjudication,
+    /// Stopped unfinished after the maximum number of moves
+    MaxLength,
+}
+
+impl Termination {
+    /// The reason of a game which ended by the rules, None if it goes on
+    pub fn of(outcome: Outcome) -> Option<Self> {
+        match outcome {
+            Outcome::Ongoing => None,
+            Outcome::Checkmate(_) => Some(Self::Checkmate),
+            Outcome::Stalemate => Some(Self::Stalemate),
+            Outcome::DrawByRepetition => Some(Self::Repetition),
+            Outcome::DrawByFiftyMoves => Some(Self::FiftyMoves),
+            Outcome::DrawByInsufficientMaterial => Some(Self::InsufficientMaterial),
+        }
+    }
+
+    /// Value of the PGN `Termination` tag
+    pub fn tag(self) -> &'static str {
+        match self {
+            Self::Checkmate
+            | Self::Stalemate
+            | Self::Repetition
+            | Self::FiftyMoves
+            | Self::InsufficientMaterial => "normal",
+            Self::TimeForfeit => "time forfeit",
+            Self::IllegalMove => "rules infraction",
+            Self::Adjudication => "adjudication",
+            Self::MaxLength => "unterminated",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Self::Checkmate => "checkmate",
+            Self::Stalemate => "stalemate",
+            Self::Repetition => "threefold repetition",
+            Self::FiftyMoves => "fifty-move rule",
+            Self::InsufficientMaterial => "insufficient material",
+            Self::TimeForfeit => "time forfeit",
+            Self::IllegalMove => "illegal move",
+            Self::Adjudication => "adjudication",
+            Self::MaxLength => "maximum length",
+        }
+    }
+}
+
+/// When games are decided before they are over, from the scores of the engines
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Adjudication {
+    /// Score in centipawns from which a game is won, once the scores of both sides
+    /// agreed for `resign_moves` moves in a row, 0 moves to play on
+    pub resign_score: Score,
+    pub resign_moves: usize,
+    /// Score in centipawns up to which a game is drawn, once the scores of both sides
+    /// agreed for `draw_moves` moves in a row from move `draw_after` on, 0 moves to play on
+    pub draw_score: Score,
+    pub draw_moves: usize,
+    pub draw_after: usize,
+}
+
+impl Default for Adjudication {
+    fn default() -> Self {
+        Self {
+            resign_score: 600,
+            resign_moves: 4,
+            draw_score: 10,
+            draw_moves: 8,
+            draw_after: 40,
+        }
+    }
+}
+
+/// Follows the scores of a game to adjudicate it
+pub struct Adjudicator {
+    settings: Adjudication,
+    /// Side ahead by at least the resign score in the last `winning_plies` scores
+    winner: Option<Player>,
+    winning_plies: usize,
+    /// Last scores within the draw score
+    drawn_plies: usize,
+}
+
+impl Adjudicator {
+    pub fn new(settings: Adjudication) -> Self {
+        Self {
+            settings,
+            winner: None,
+            winning_plies: 0,
+            drawn_plies: 0,
+        }
+    }
+
+    /// Takes the score of the player to move in `game`, from their point of view, and
+    /// tells the result once the game is decided
+    pub fn update(&mut self, game: &Game, score: Score) -> Option<GameResult> {
+        let settings = &self.settings;
+        let white_score = score * game.player() as Score;
+
+        let winner = match white_score {
+            score if score >= settings.resign_score => Some(Player::White),
+            score if score <= -settings.resign_score => Some(Player::Black),
+            _ => None,
+        };
+        if winner.is_some() && winner == self.winner {
+            self.winning_plies += 1;
+        } else {
+            self.winning_plies = usize::from(winner.is_some());
+        }
+        self.winner = winner;
+
+        if score.abs() <= settings.draw_score && game.fullmove_number() >= settings.draw_after {
+            self.drawn_plies += 1;
+        } else {
+            self.drawn_plies = 0;
+        }
+
+        match self.winner {
+            Some(winner)
+                if settings.resign_moves > 0 && self.winning_plies >= 2 * settings.resign_moves =>
+            {
+                Some(GameResult::win(winner))
+            }
+            _ if settings.draw_moves > 0 && self.drawn_plies >= 2 * settings.draw_moves => {
+                Some(GameResult::Draw)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The score of an `info` line, `score cp <centipawns>` or `score mate <moves>`
+fn parse_score(line: &str) -> Option<Score> {
+    let mut words = line.split_ascii_whitespace();
+    words.find(|&word| word == "score")?;
+    let kind = words.next()?;
+    let value: Score = words.next()?.parse().ok()?;
+    match kind {
+        "cp" => Some(value),
+        "mate" if value > 0 => Some(score::mate_in(2 * value - 1)),
+        "mate" => Some(score::mated_in(-2 * value)),
+        _ => None,
+    }
+}
+
+/// A finished game between two engines
+pub struct PlayedGame {
+    pub game: Game,
+    pub result: GameResult,
+    pub termination: Termination,
+}
+
+impl PlayedGame {
+    /// The game as a PGN document, with its round and how it ended
+    pub fn to_pgn_game(&self, white: &str, black: &str, round: usize) -> PgnGame {
+        let mut document = self.game.to_pgn_game(white, black, self.result, &[]);
+        document.set_tag("Round", &round.to_string());
+        document.set_tag("Termination", self.termination.tag());
+        document
+    }
+}
+
+/// Plays a game from `opening`, with `time` ms for each engine and `increment` ms added
+/// after each move. A player loses when they run out of time or answer with a move which
+/// isn't legal, and the game may be adjudicated from the scores the engines report.
+pub fn play_game(
+    white: &mut Engine,
+    black: &mut Engine,
+    opening: &str,
+    time: u64,
+    increment: u64,
+    adjudication: Adjudication,
+) -> anyhow::Result<PlayedGame> {
+    white.new_game()?;
+    black.new_game()?;
+
+    let mut game = Game::new(opening)?;
+    let mut adjudicator = Adjudicator::new(adjudication);
+    let mut moves = Vec::new();
+    // Remaining time of white and black, in ms
+    let mut clocks = [time as i64, time as i64];
+
+    loop {
+        let outcome = game.outcome();
+        if let Some(termination) = Termination::of(outcome) {
+            return Ok(PlayedGame {
+                game,
+                result: outcome.result(),
+                termination,
+            });
+        }
+
+        let player = game.player();
+        let (engine, clock_index) = match player {
+            Player::White => (&mut *white, 0),
+            Player::Black => (&mut *black, 1),
+        };
+        let lost = |game, termination| PlayedGame {
+            game,
+            result: GameResult::win(player.the_other()),
+            termination,
+        };
+
+        let mut position = format!("position fen {}", opening);
+        if !moves.is_empty() {
+            position += " moves ";
+            position += &moves.join(" ");
+        }
+        engine.send(&position)?;
+
+        let start = Instant::now();
+        engine.send(&format!(
+            "go wtime {} btime {} winc {} binc {}",
+            clocks[0], clocks[1], increment, increment
+        ))?;
+        let mut score = None;
+        let line = engine.read_until("bestmove", |line| {
+            if let Some(line_score) = parse_score(line) {
+                score = Some(line_score);
+            }
+        })?;
+
+        let clock = &mut clocks[clock_index];
+        *clock -= start.elapsed().as_millis() as i64;
+        if *clock < 0 {
+            return Ok(lost(game, Termination::TimeForfeit));
+        }
+        *clock += increment as i64;
+
+        let move_str = line.split_ascii_whitespace().nth(1).unwrap_or_default();
+        let Ok(_move) = Move::from_uci_notation(move_str, &game) else {
+            println!("{} played the illegal move {}", engine.name, move_str);
+            return Ok(lost(game, Termination::IllegalMove));
+        };
+        let adjudicated = score.and_then(|score| adjudicator.update(&game, score));
+        moves.push(move_str.to_string());
+        game.push_history(_move);
+
+        if let Some(result) = adjudicated {
+            return Ok(PlayedGame {
+                game,
+                result,
+                termination: Termination::Adjudication,
+            });
+        }
+    }
+}
+
+/// Reads a time control in seconds like most tools, e.g. `10+0.1`, as the time and the
+/// increment in ms
+pub fn parse_time_control(value: &str) -> anyhow::Result<(u64, u64)> {
+    let (time, increment) = value.split_once('+').unwrap_or((value, "0"));
+    let millis = |seconds: &str| -> anyhow::Result<u64> {
+        let seconds: f64 = seconds
+            .parse()
+            .with_context(|| format!("Invalid time control: {}", value))?;
+        if !seconds.is_finite() || seconds < 0.0 {
+            bail!("Invalid time control: {}", value);
+        }
+        Ok((seconds * 1000.0).round() as u64)
+    };
+
+    let (time, increment) = (millis(time)?, millis(increment)?);
+    if time == 0 {
+        bail!("The time control must give some time");
+    }
+
+    Ok((time, increment))
+}
+
+/// Reads a UCI option given as `<name>=<value>`, e.g. `Skill Level=3`
+pub fn parse_option(value: &str) -> anyhow::Result<(String, String)> {
+    let (name, value) = value.split_once('=').context("Expected <name>=<value>")?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// FENs of the positions of an EPD or FEN file, or of the bundled openings
+pub fn load_openings(path: Option<&str>) -> anyhow::Result<Vec<String>> {
+    let text = match path {
+        Some(path) => {
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?
+        }
+        None => OPENINGS.to_string(),
+    };
+
+    let mut openings = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        if !line.trim().is_empty() {
+            let epd = Epd::parse(line).with_context(|| format!("Line {}", line_number + 1))?;
+            openings.push(epd.game.to_fen());
+        }
+    }
+    if openings.is_empty() {
+        bail!("No openings to play");
+    }
+
+    Ok(openings)
+}
+
+/// Opens a PGN file to which games are appended
+pub fn open_pgn(path: &str) -> anyhow::Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path))
+}
+
+/// Settings of a match between this engine and another one
+pub struct MatchSettings {
+    pub opponent: EngineConfig,
+    pub games: usize,
+    /// Time of each engine at the start of a game and added after each move, in ms
+    pub time: u64,
+    pub increment: u64,
+    /// EPD or FEN file of the openings, the bundled ones if there is none
+    pub openings: Option<String>,
+    /// File to which the games are appended
+    pub pgn: String,
+    pub adjudication: Adjudication,
+}
+
+/// Plays games against another engine, alternating colors, and writes them to a PGN file
+pub fn run_match(settings: &MatchSettings) -> anyhow::Result<()> {
+    let openings = load_openings(settings.openings.as_deref())?;
+    let mut pgn = open_pgn(&settings.pgn)?;
+
+    let mut engine = Engine::start(&EngineConfig::this_program(None)?)?;
+    let mut opponent = Engine::start(&settings.opponent)?;
+
+    // Wins, losses and draws of this engine
+    let (mut wins, mut losses, mut draws) = (0, 0, 0);
+
+    for round in 1..=settings.games {
+        // Each opening is played with both colors
+        let opening = &openings[(round - 1) / 2 % openings.len()];
+        let engine_player = if round % 2 == 1 {
+            Player::White
+        } else {
+            Player::Black
+        };
+        let (white, black) = match engine_player {
+            Player::White => (&mut engine, &mut opponent),
+            Player::Black => (&mut opponent, &mut engine),
+        };
+
+        let played = play_game(
+            white,
+            black,
+            opening,
+            settings.time,
+            settings.increment,
+            settings.adjudication,
+        )?;
+        println!(
+            "Game {}: {} - {} {} ({})",
+            round,
+            white.name,
+            black.name,
+            played.result.as_str(),
+            played.termination.description()
+        );
+        writeln!(
+            pgn,
+            "{}",
+            played.to_pgn_game(&white.name, &black.name, round)
+        )
+        .context("Failed to write the game")?;
+
+        match played.result {
+            GameResult::Draw => draws += 1,
+            result if result == GameResult::win(engine_player) => wins += 1,
+            _ => losses += 1,
+        }
+    }
+
+    println!(
+        "Score of {} vs {}: {} - {} - {}",
+        engine.name, opponent.name, wins, losses, draws
+    );
+    println!("Games written to {}", settings.pgn);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openings() {
+        for fen in load_openings(None).unwrap() {
+            assert_eq!(
+                Game::new(&fen).unwrap().result(),
+                GameResult::Unfinished,
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn time_control() {
+        assert_eq!(parse_time_control("10+0.1").unwrap(), (10000, 100));
+        assert_eq!(parse_time_control("60").unwrap(), (60000, 0));
+        for value in ["0+1", "-1+0", "ten", "1+"] {
+            assert!(parse_time_control(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn options() {
+        assert_eq!(
+            parse_option("Skill Level=3").unwrap(),
+            (String::from("Skill Level"), String::from("3"))
+        );
+        assert_eq!(
+            parse_option("BookFile=").unwrap(),
+            (String::from("BookFile"), String::new())
+        );
+        assert!(parse_option("Contempt").is_err());
+    }
+
+    #[test]
+    fn adjudication() {
+        let game = Game::new("4k3/8/8/8/8/8/8/R3K3 w Q - 0 50").unwrap();
+        let mut black_to_move = game.clone();
+        black_to_move.push_history(Move::from_uci_notation("a1a2", &game).unwrap());
+
+        let settings = Adjudication {
+            resign_moves: 2,
+            ..Adjudication::default()
+        };
+        let mut adjudicator = Adjudicator::new(settings);
+        // White is ahead in the scores of both sides, which tell apart the players
+        assert_eq!(adjudicator.update(&game, 700), None);
+        assert_eq!(adjudicator.update(&black_to_move, -700), None);
+        assert_eq!(adjudicator.update(&game, 500), None);
+        assert_eq!(adjudicator.update(&black_to_move, -700), None);
+        assert_eq!(adjudicator.update(&game, 700), None);
+        assert_eq!(adjudicator.update(&black_to_move, -score::mate_in(3)), None);
+        assert_eq!(adjudicator.update(&game, 900), Some(GameResult::WhiteWins));
+
+        let mut adjudicator = Adjudicator::new(Adjudication {
+            draw_moves: 1,
+            ..settings
+        });
+        assert_eq!(adjudicator.update(&game, 5), None);
+        assert_eq!(
+            adjudicator.update(&black_to_move, -3),
+            Some(GameResult::Draw)
+        );
+
+        // Before the first move of draws
+        let game = Game::new("4k3/8/8/8/8/8/8/R3K3 w Q - 0 20").unwrap();
+        let mut adjudicator = Adjudicator::new(Adjudication {
+            draw_moves: 1,
+            ..settings
+        });
+        assert_eq!(adjudicator.update(&game, 0), None);
+        assert_eq!(adjudicator.update(&game, 0), None);
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(
+            parse_score("info depth 5 score cp -23 nodes 100"),
+            Some(-23)
+        );
+        assert_eq!(parse_score("info score mate 2"), Some(score::mate_in(3)));
+        assert_eq!(parse_score("info score mate -1"), Some(score::mated_in(2)));
+        assert_eq!(parse_score("info depth 5 nodes 100"), None);
+        assert_eq!(parse_score("info score lowerbound"), None);
+    }
+
+    #[test]
+    fn termination() {
+        let mut game = Game::default();
+        for _move in ["f2f3", "e7e5", "g2g4", "d8h4"] {
+            game.push_history(Move::from_uci_notation(_move, &game).unwrap());
+        }
+        assert_eq!(
+            Termination::of(game.outcome()),
+            Some(Termination::Checkmate)
+        );
+
+        let game = Game::new("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(
+            Termination::of(game.outcome()),
+            Some(Termination::Stalemate)
+        );
+
+        let game = Game::new("7k/8/6K1/8/8/8/8/R7 b - - 100 80").unwrap();
+        assert_eq!(
+            Termination::of(game.outcome()),
+            Some(Termination::FiftyMoves)
+        );
+
+        let game = Game::new("7k/8/6K1/8/8/8/8/7B b - - 0 1").unwrap();
+        assert_eq!(
+            Termination::of(game.outcome()),
+            Some(Termination::InsufficientMaterial)
+        );
+
+        assert_eq!(Termination::of(Game::default().outcome()), None);
+    }
+}
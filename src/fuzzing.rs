@@ -0,0 +1,161 @@
+use crate::{
+    chess::{move_struct::Move, Game},
+    uci,
+};
+
+// Checks run on arbitrary input by the targets of the fuzz directory, with cargo-fuzz:
+// https://rust-fuzz.github.io/book/cargo-fuzz.html
+// Input must be rejected or give a consistent game, never make the engine panic.
+
+/// Positions in which the fuzzed moves are read, chosen by the first byte of the input
+const POSITIONS: [&str; 4] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+    "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+];
+
+/// Panics if the game isn't consistent: its incremental state must match a recomputation,
+/// its FEN must be read back to the same position, and every legal move must be undone
+/// exactly
+fn check_game(game: &Game) {
+    game.verify_incremental_state();
+
+    let fen = game.to_fen();
+    let read = Game::new(&fen).unwrap_or_else(|err| panic!("{} can't be read: {}", fen, err));
+    assert_eq!(read.to_fen(), fen);
+    assert_eq!(read.hash(), game.hash(), "{}", fen);
+
+    let mut game = game.clone();
+    let moves = game.legal_moves();
+    for _move in moves {
+        game.push(_move);
+        game.verify_incremental_state();
+        game.pop();
+        assert_eq!(
+            game.to_fen(),
+            fen,
+            "{} not undone",
+            _move.uci_notation(&game)
+        );
+    }
+}
+
+/// Reads the input as a FEN
+pub fn fen(data: &[u8]) {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Ok(game) = Game::new(text) {
+        check_game(&game);
+    }
+}
+
+/// Reads the input after its first byte as a move in UCI notation and in SAN, in one of
+/// a few positions. Moves which are accepted must be legal.
+pub fn uci_move(data: &[u8]) {
+    let Some((&index, text)) = data.split_first() else {
+        return;
+    };
+    let Ok(text) = std::str::from_utf8(text) else {
+        return;
+    };
+    let game = Game::new(POSITIONS[index as usize % POSITIONS.len()]).unwrap();
+
+    let moves = game.legal_moves();
+
+    for _move in [
+        Move::from_uci_notation(text, &game).ok(),
+        Move::from_san(text, &game).ok(),
+    ]
+    .into_iter()
+    .flatten()
+    {
+        assert!(moves.contains(&_move), "{} isn't legal", text);
+        let mut game = game.clone();
+        game.push_history(_move);
+        check_game(&game);
+    }
+}
+
+/// Reads the input as lines of UCI commands setting up a position
+pub fn uci_commands(data: &[u8]) {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+    if let Some(game) = uci::apply_commands(text) {
+        check_game(&game);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Variations of `seed` with random bytes replaced, inserted or removed, for a quick
+    /// fuzzing run without cargo-fuzz
+    fn mutations(seed: &str, count: usize) -> Vec<Vec<u8>> {
+        const ALPHABET: &[u8] = b"pnbrqkPNBRQK012345678/ -abcdefghw";
+
+        // Xorshift, to find the same inputs on every run
+        let mut state = 0x2545F4914F6CDD1Du64 ^ seed.len() as u64;
+        let mut random = move |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        (0..count)
+            .map(|_| {
+                let mut bytes = seed.as_bytes().to_vec();
+                for _ in 0..1 + random(3) {
+                    let index = random(bytes.len() + 1);
+                    let byte = ALPHABET[random(ALPHABET.len())];
+                    match random(3) {
+                        0 if index < bytes.len() => bytes[index] = byte,
+                        1 if index < bytes.len() => {
+                            bytes.remove(index);
+                        }
+                        _ => bytes.insert(index, byte),
+                    }
+                }
+                bytes
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fens() {
+        for seed in POSITIONS {
+            for input in mutations(seed, 500) {
+                fen(&input);
+            }
+        }
+    }
+
+    #[test]
+    fn moves() {
+        for seed in [
+            "\0e2e4", "\x01e1g1", "\x02e5f6", "\x03f1g1", "\x01Nxe5", "\x02exf6",
+        ] {
+            for input in mutations(seed, 500) {
+                uci_move(&input);
+            }
+        }
+    }
+
+    #[test]
+    fn commands() {
+        for seed in [
+            "position startpos moves e2e4 e7e5 g1f3",
+            "setoption name UCI_Chess960 value true\n\
+             position fen bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9 moves g1h1",
+            "position fen 8/8/8/8/k2Pp2Q/8/8/3K4 b - d3 0 1 moves e4d3",
+        ] {
+            for input in mutations(seed, 100) {
+                uci_commands(&input);
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use crate::{analysis::JsonReport, chess::Game, log};
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Prints the output from now on as JSON lines instead of text, for the `--json` flag
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// A line of the JSON output, whose kind is its `type` field
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Message<'a> {
+    /// Position about to be searched, with its legal moves
+    Position(&'a Game),
+    /// Progress of a search, like the `info` lines of UCI
+    Info(JsonReport),
+    /// End of a search, the move is null when the game is over
+    BestMove {
+        bestmove: Option<String>,
+    },
+    Error {
+        message: &'a str,
+    },
+    /// Result of the benchmark, `nodes` is its signature
+    Bench {
+        depth: u8,
+        positions: usize,
+        time_ms: u128,
+        nps: u64,
+        nodes: u64,
+    },
+    /// Any other line, e.g. `readyok` or the options of `uci`
+    Line {
+        text: &'a str,
+    },
+}
+
+/// Prints the message as one line and writes it to the log like the UCI answers
+pub fn print(message: &Message) {
+    write(&mut io::stdout().lock(), message);
+}
+
+/// Writes the message as one line to `output`, e.g. the connection of a GUI, and to the log
+pub fn write(output: &mut dyn Write, message: &Message) {
+    let line = serde_json::to_string(message).unwrap();
+    // A GUI which is gone is noticed when its next command can't be read
+    let _ = writeln!(output, "{}", line);
+    match message {
+        Message::Error { .. } => log::error!("<< {}", line),
+        _ => log::info!("<< {}", line),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        let json = |message| serde_json::to_string(&message).unwrap();
+        assert_eq!(
+            json(Message::BestMove {
+                bestmove: Some(String::from("e2e4"))
+            }),
+            r#"{"type":"bestmove","bestmove":"e2e4"}"#
+        );
+        assert_eq!(
+            json(Message::BestMove { bestmove: None }),
+            r#"{"type":"bestmove","bestmove":null}"#
+        );
+        assert_eq!(
+            json(Message::Error {
+                message: "Unknown option"
+            }),
+            r#"{"type":"error","message":"Unknown option"}"#
+        );
+        assert_eq!(
+            json(Message::Line { text: "readyok" }),
+            r#"{"type":"line","text":"readyok"}"#
+        );
+        assert!(json(Message::Position(&Game::default())).starts_with(
+            r#"{"type":"position","fen":"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1","#
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Chess engine speaking UCI, with the board, the move generation, the evaluation and the
+//! search usable on their own, e.g. by GUIs, bots or analysis scripts
+//!
+//! [`Game`] holds a position with the moves played to reach it, [`Move`] is a move of one
+//! of its pieces, and [`search::search`] finds the best move within [`search::SearchLimits`]:
+//!
+//! ```
+//! # #[cfg(feature = "std")]
+//! # fn main() {
+//! use rustybait::{
+//!     search::{self, SearchLimits},
+//!     Game, Move,
+//! };
+//! use std::{sync::atomic::AtomicBool, time::Duration};
+//!
+//! let mut game = Game::default();
+//! for uci in ["e2e4", "e7e5", "g1f3"] {
+//!     let _move = Move::from_uci_notation(uci, &game).unwrap();
+//!     game.push_history(_move);
+//! }
+//!
+//! // Stops after depth 6 or half a second, whichever comes first
+//! let limits = SearchLimits::new().depth(6).move_time(Duration::from_millis(500));
+//! let mut table = search::TranspositionTable::default();
+//! let (best_move, score, depth) = search::search(
+//!     &game,
+//!     &limits,
+//!     &mut table,
+//!     &AtomicBool::new(true),
+//!     0,
+//!     |info| println!("Depth {}: {} nodes", info.depth, info.nodes),
+//! )
+//! .unwrap();
+//! println!("{} ({} cp at depth {})", best_move.to_san(&game), score, depth);
+//! # }
+//! # #[cfg(not(feature = "std"))]
+//! # fn main() {}
+//! ```
+//!
+//! The command line, whose commands are mostly thin wrappers of the modules below, is in
+//! main.rs.
+//!
+//! Without the default `std` feature only [`chess`] and [`constants`] are left, which need
+//! nothing but an allocator, e.g. to generate moves on embedded targets.
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+/// Search of a position for a given time, printed as JSON
+#[cfg(feature = "std")]
+pub mod analysis;
+/// Games of the engine against itself in the terminal
+#[cfg(feature = "std")]
+pub mod autoplay;
+/// Fixed-depth search of a set of positions, for speed measurements and PGO
+#[cfg(feature = "std")]
+pub mod benchmark;
+/// Opening books in the Polyglot format
+#[cfg(feature = "std")]
+pub mod book;
+/// Board, moves, evaluation and the chess file formats
+pub mod chess;
+/// Options read at startup from a configuration file
+#[cfg(feature = "std")]
+pub mod config;
+pub mod constants;
+/// Training data from fast games of the engine against itself
+#[cfg(feature = "std")]
+pub mod datagen;
+/// The engine driven from another program in the same process
+#[cfg(feature = "std")]
+pub mod engine;
+/// Games against other UCI engines
+#[cfg(feature = "std")]
+pub mod engine_match;
+#[cfg(any(all(test, feature = "std"), feature = "fuzzing"))]
+pub mod fuzzing;
+/// Output as JSON lines for scripts, with the `--json` flag
+#[cfg(feature = "std")]
+pub mod json;
+/// Bot account on Lichess, playing through its API
+#[cfg(feature = "lichess")]
+pub mod lichess;
+/// Log file of the UCI commands and answers and of the diagnostics, by level
+#[cfg(feature = "std")]
+pub mod log;
+/// Forced mates in a given number of moves
+#[cfg(feature = "std")]
+pub mod mate;
+/// Varied start positions of games of the engine against itself
+#[cfg(feature = "std")]
+pub mod openings;
+/// Perft, the count of the positions reached by the legal moves
+#[cfg(feature = "std")]
+pub mod performance_test;
+/// Games against the user in the terminal
+#[cfg(feature = "std")]
+pub mod play;
+/// Alpha-beta search with a transposition table
+#[cfg(feature = "std")]
+pub mod search;
+/// Analysis served over HTTP and WebSocket to web front ends
+#[cfg(feature = "server")]
+pub mod server;
+/// Sequential probability ratio tests of two versions of the engine
+#[cfg(feature = "std")]
+pub mod sprt;
+/// Tuning of constants of the search and the evaluation by self-play
+#[cfg(feature = "std")]
+pub mod spsa;
+/// Playing-strength levels below the full strength of the engine
+#[cfg(feature = "std")]
+pub mod strength;
+/// EPD test suites such as WAC and STS
+#[cfg(feature = "std")]
+pub mod test_suite;
+/// Analysis of games in a terminal user interface
+#[cfg(feature = "tui")]
+pub mod tui;
+/// Tuning of the evaluation on labeled positions
+#[cfg(feature = "std")]
+pub mod tuning;
+/// The UCI protocol
+#[cfg(feature = "std")]
+pub mod uci;
+/// Win, draw and loss probabilities of a score
+#[cfg(feature = "std")]
+pub mod wdl;
+
+pub use chess::{move_struct::Move, Game, Player};
@@ -0,0 +1,400 @@
+use crate::{
+    chess::Player,
+    engine::{Engine, EngineEvent},
+    log,
+    search::SearchLimits,
+    uci,
+};
+use anyhow::{bail, Context};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+const API: &str = "https://lichess.org";
+
+/// Environment variable with the API token of the bot account, which needs the
+/// `bot:play` scope
+pub const TOKEN_VARIABLE: &str = "LICHESS_TOKEN";
+
+/// Variants whose challenges are accepted, `fromPosition` is standard chess from a FEN
+const VARIANTS: [&str; 3] = ["standard", "chess960", "fromPosition"];
+
+/// Which challenges the bot accepts and how it plays its games
+pub struct BotSettings {
+    /// Games played at the same time, other challenges are declined until one of them ends
+    pub games: usize,
+    /// Size of the transposition table of every game, in MB
+    pub hash: usize,
+    /// Speeds of the challenges accepted, among `ultraBullet`, `bullet`, `blitz`, `rapid`,
+    /// `classical` and `correspondence`
+    pub speeds: Vec<String>,
+    /// UCI options of the engine of every game, e.g. `BookFile`
+    pub options: Vec<(String, String)>,
+}
+
+impl Default for BotSettings {
+    /// One game at a time at any speed but correspondence, with 64 MB of hash
+    fn default() -> Self {
+        Self {
+            games: 1,
+            hash: 64,
+            speeds: ["bullet", "blitz", "rapid", "classical"]
+                .map(String::from)
+                .to_vec(),
+            options: Vec::new(),
+        }
+    }
+}
+
+impl BotSettings {
+    /// Why a challenge is declined, in the reasons of the API, None if it is accepted
+    fn decline_reason(&self, challenge: &Challenge, games: usize) -> Option<&'static str> {
+        if !VARIANTS.contains(&challenge.variant.key.as_str()) {
+            Some("variant")
+        } else if !self.speeds.contains(&challenge.speed) {
+            Some("timeControl")
+        } else if games >= self.games {
+            Some("later")
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Account {
+    id: String,
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct User {
+    /// Missing for the computer of Lichess
+    id: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Variant {
+    key: String,
+}
+
+#[derive(Deserialize)]
+struct Challenge {
+    id: String,
+    challenger: User,
+    variant: Variant,
+    speed: String,
+}
+
+#[derive(Deserialize)]
+struct GameStart {
+    id: String,
+}
+
+/// Event of the stream of the account, keep-alive lines left aside
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum Event {
+    Challenge {
+        challenge: Challenge,
+    },
+    GameStart {
+        game: GameStart,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// Moves played and clocks, the times are in ms
+#[derive(Deserialize)]
+struct GameState {
+    /// Moves in UCI notation separated by spaces
+    moves: String,
+    wtime: u64,
+    btime: u64,
+    winc: u64,
+    binc: u64,
+    /// `started` until the game is over
+    status: String,
+}
+
+/// Event of the stream of a game
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum GameEvent {
+    /// The first event, with the players and the state of the game
+    GameFull {
+        white: User,
+        variant: Variant,
+        /// `startpos` or a FEN
+        #[serde(rename = "initialFen")]
+        initial_fen: String,
+        state: GameState,
+    },
+    GameState(GameState),
+    #[serde(other)]
+    Other,
+}
+
+/// Requests to the API with the token of the bot account
+struct Client {
+    agent: ureq::Agent,
+    authorization: String,
+}
+
+impl Client {
+    fn new(token: &str) -> Self {
+        Self {
+            agent: ureq::Agent::new(),
+            authorization: format!("Bearer {}", token),
+        }
+    }
+
+    fn get(&self, path: &str) -> anyhow::Result<ureq::Response> {
+        self.agent
+            .get(&format!("{}{}", API, path))
+            .set("Authorization", &self.authorization)
+            .call()
+            .with_context(|| format!("Failed to get {}", path))
+    }
+
+    fn get_json<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        serde_json::from_reader(self.get(path)?.into_reader())
+            .with_context(|| format!("Invalid answer to {}", path))
+    }
+
+    /// The events of a stream of newline delimited JSON, until it is closed
+    fn stream<T: DeserializeOwned>(
+        &self,
+        path: &str,
+    ) -> anyhow::Result<impl Iterator<Item = anyhow::Result<T>>> {
+        let reader = BufReader::new(self.get(path)?.into_reader());
+        Ok(reader
+            .lines()
+            // Empty lines keep the connection alive
+            .filter(|line| !line.as_ref().is_ok_and(|line| line.trim().is_empty()))
+            .map(|line| {
+                let line = line.context("Failed to read the stream")?;
+                serde_json::from_str(&line).with_context(|| format!("Invalid event: {}", line))
+            }))
+    }
+
+    fn post(&self, path: &str, form: &[(&str, &str)]) -> anyhow::Result<()> {
+        self.agent
+            .post(&format!("{}{}", API, path))
+            .set("Authorization", &self.authorization)
+            .send_form(form)
+            .with_context(|| format!("Failed to post {}", path))?;
+        Ok(())
+    }
+}
+
+/// Plays on Lichess with the bot account of the token in `LICHESS_TOKEN`: accepts the
+/// challenges allowed by `settings` and plays every game with an engine of its own, until
+/// the connection is closed
+pub fn run(settings: BotSettings) -> anyhow::Result<()> {
+    if settings.games == 0 {
+        bail!("The bot must be able to play at least one game");
+    }
+    let token = std::env::var(TOKEN_VARIABLE)
+        .with_context(|| format!("Missing API token of the bot account in {}", TOKEN_VARIABLE))?;
+    let client = Arc::new(Client::new(&token));
+    let account: Account = client.get_json("/api/account")?;
+    println!("Playing on Lichess as {}", account.username);
+
+    let settings = Arc::new(settings);
+    // Games being played, which are announced again when the stream reconnects
+    let playing = Arc::new(Mutex::new(HashSet::new()));
+
+    for event in client.stream("/api/stream/event")? {
+        match event? {
+            Event::Challenge { challenge } => {
+                // Challenges of the bot to others come through the stream too
+                if challenge.challenger.id.as_deref() == Some(&account.id) {
+                    continue;
+                }
+
+                let games = playing.lock().unwrap().len();
+                let id = &challenge.id;
+                match settings.decline_reason(&challenge, games) {
+                    None => {
+                        println!("Accepting challenge {}", id);
+                        client.post(&format!("/api/challenge/{}/accept", id), &[])?;
+                    }
+                    Some(reason) => {
+                        println!("Declining challenge {}: {}", id, reason);
+                        client.post(
+                            &format!("/api/challenge/{}/decline", id),
+                            &[("reason", reason)],
+                        )?;
+                    }
+                }
+            }
+            Event::GameStart { game } => {
+                if !playing.lock().unwrap().insert(game.id.clone()) {
+                    continue;
+                }
+                println!("Game {} started", game.id);
+
+                let (client, settings, playing) =
+                    (client.clone(), settings.clone(), playing.clone());
+                let account_id = account.id.clone();
+                thread::spawn(move || {
+                    if let Err(err) = play_game(&client, &game.id, &account_id, &settings) {
+                        log::error!("Game {}: {:#}", game.id, err);
+                    }
+                    println!("Game {} ended", game.id);
+                    playing.lock().unwrap().remove(&game.id);
+                });
+            }
+            Event::Other => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Plays the moves of the bot in game `id` until it is over
+fn play_game(
+    client: &Client,
+    id: &str,
+    account_id: &str,
+    settings: &BotSettings,
+) -> anyhow::Result<()> {
+    let (mut engine, events) = Engine::with_hash(settings.hash)?;
+    for (name, value) in &settings.options {
+        engine.set_option(name, value)?;
+    }
+
+    let mut bot = None;
+    let mut start = String::new();
+    for event in client.stream(&format!("/api/bot/game/stream/{}", id))? {
+        let state = match event? {
+            GameEvent::GameFull {
+                white,
+                variant,
+                initial_fen,
+                state,
+            } => {
+                bot = Some(if white.id.as_deref() == Some(account_id) {
+                    Player::White
+                } else {
+                    Player::Black
+                });
+                // Castling moves are sent as the king taking its rook in Chess960
+                engine.set_option("UCI_Chess960", &(variant.key == "chess960").to_string())?;
+                start = if initial_fen == "startpos" {
+                    initial_fen
+                } else {
+                    format!("fen {}", initial_fen)
+                };
+                state
+            }
+            GameEvent::GameState(state) => state,
+            GameEvent::Other => continue,
+        };
+
+        if state.status != "started" {
+            break;
+        }
+        let Some(bot) = bot else {
+            continue;
+        };
+        let position = format!("{} moves {}", start, state.moves);
+        let game = uci::parse_position(&mut position.split_ascii_whitespace(), &engine)?;
+        if game.player() != bot || game.outcome().is_over() {
+            continue;
+        }
+
+        let millis = Duration::from_millis;
+        let limits = SearchLimits::new()
+            .clock(Player::White, millis(state.wtime), millis(state.winc))
+            .clock(Player::Black, millis(state.btime), millis(state.binc));
+        engine.set_position(game.clone())?;
+        engine.start_search(limits)?;
+        let best_move = events
+            .iter()
+            .find_map(|event| match event {
+                EngineEvent::BestMove(best_move) => Some(best_move),
+                EngineEvent::Info(_) => None,
+            })
+            .flatten()
+            .context("No move to play")?;
+
+        let uci = best_move.uci_notation(&game);
+        log::info!("Game {}: {}", id, uci);
+        client.post(&format!("/api/bot/game/{}/move/{}", id, uci), &[])?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn challenges() {
+        let settings = BotSettings::default();
+        let challenge = |variant: &str, speed: &str| {
+            let json = format!(
+                r#"{{"type":"challenge","challenge":{{"id":"x","challenger":{{"id":"a"}},"variant":{{"key":"{}"}},"speed":"{}","rated":true}}}}"#,
+                variant, speed
+            );
+            let Event::Challenge { challenge } = serde_json::from_str(&json).unwrap() else {
+                panic!("Not a challenge: {}", json);
+            };
+            challenge
+        };
+
+        assert_eq!(
+            settings.decline_reason(&challenge("standard", "blitz"), 0),
+            None
+        );
+        assert_eq!(
+            settings.decline_reason(&challenge("chess960", "rapid"), 0),
+            None
+        );
+        assert_eq!(
+            settings.decline_reason(&challenge("atomic", "blitz"), 0),
+            Some("variant")
+        );
+        assert_eq!(
+            settings.decline_reason(&challenge("standard", "correspondence"), 0),
+            Some("timeControl")
+        );
+        assert_eq!(
+            settings.decline_reason(&challenge("standard", "blitz"), 1),
+            Some("later")
+        );
+    }
+
+    #[test]
+    fn game_events() {
+        let full = r#"{"type":"gameFull","id":"g","rated":false,"variant":{"key":"standard"},
+            "white":{"id":"bot","name":"Bot"},"black":{"aiLevel":3},"initialFen":"startpos",
+            "state":{"type":"gameState","moves":"e2e4","wtime":60000,"btime":60000,
+            "winc":0,"binc":0,"status":"started"}}"#;
+        let GameEvent::GameFull { white, state, .. } = serde_json::from_str(full).unwrap() else {
+            panic!("Not the full game");
+        };
+        assert_eq!(white.id.as_deref(), Some("bot"));
+        assert_eq!(state.moves, "e2e4");
+
+        let chat = r#"{"type":"chatLine","room":"player","username":"a","text":"hi"}"#;
+        assert!(matches!(
+            serde_json::from_str(chat).unwrap(),
+            GameEvent::Other
+        ));
+        let finish = r#"{"type":"gameFinish","game":{"id":"g"}}"#;
+        assert!(matches!(
+            serde_json::from_str(finish).unwrap(),
+            Event::Other
+        ));
+    }
+}
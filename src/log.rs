@@ -0,0 +1,188 @@
+use anyhow::{bail, Context};
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::Write,
+    str::FromStr,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How important a message is, the log only keeps the ones up to its level
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    /// The commands received and the answers of the UCI mode
+    Info,
+    /// Details of the searches and the options
+    Debug,
+}
+
+impl Level {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Error => "error",
+            Self::Warn => "warn",
+            Self::Info => "info",
+            Self::Debug => "debug",
+        }
+    }
+}
+
+impl FromStr for Level {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        Ok(match s.to_ascii_lowercase().as_str() {
+            "error" => Self::Error,
+            "warn" => Self::Warn,
+            "info" => Self::Info,
+            "debug" => Self::Debug,
+            _ => bail!("Log level must be error, warn, info or debug"),
+        })
+    }
+}
+
+struct Logger {
+    file: Option<File>,
+    level: Level,
+}
+
+static LOGGER: Mutex<Logger> = Mutex::new(Logger {
+    file: None,
+    level: Level::Info,
+});
+
+/// Appends the messages from now on to the file at `path`, or stops logging if it is None
+pub fn set_file(path: Option<&str>) -> anyhow::Result<()> {
+    let file = match path {
+        Some(path) => Some(
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Failed to open {}", path))?,
+        ),
+        None => None,
+    };
+    LOGGER.lock().unwrap().file = file;
+    Ok(())
+}
+
+/// Keeps the messages up to `level`, `Info` by default
+pub fn set_level(level: Level) {
+    LOGGER.lock().unwrap().level = level;
+}
+
+/// Writes a message with the time and its level, if there is a log file and the level is
+/// kept. Use the macros of the levels instead, e.g. `log::info!("Read {}", path)`, or
+/// `log::warning!` for `Level::Warn`.
+pub fn write(level: Level, message: fmt::Arguments) {
+    let mut logger = LOGGER.lock().unwrap();
+    if level > logger.level {
+        return;
+    }
+    if let Some(file) = logger.file.as_mut() {
+        // The log is no reason to stop the engine
+        let _ = writeln!(
+            file,
+            "{} {:<5} {}",
+            timestamp(SystemTime::now()),
+            level.as_str(),
+            message
+        );
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Error, format_args!($($arg)*))
+    };
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Warn, format_args!($($arg)*))
+    };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Info, format_args!($($arg)*))
+    };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Debug, format_args!($($arg)*))
+    };
+}
+
+pub(crate) use {debug, error, info, warning};
+
+/// UTC date and time with milliseconds, e.g. `2024-03-01 17:05:09.042`
+fn timestamp(time: SystemTime) -> String {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_millis()) as u64;
+    let (days, millis) = (millis / 86_400_000, millis % 86_400_000);
+
+    // Days to the civil date, from https://howardhinnant.github.io/date_algorithms.html
+    let days = days as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps() {
+        for (seconds, text) in [
+            (0, "1970-01-01 00:00:00.000"),
+            (951_782_400, "2000-02-29 00:00:00.000"),
+            (1_709_312_709, "2024-03-01 17:05:09.000"),
+            (4_102_444_799, "2099-12-31 23:59:59.000"),
+        ] {
+            assert_eq!(
+                timestamp(UNIX_EPOCH + Duration::from_secs(seconds)),
+                text,
+                "{}",
+                seconds
+            );
+        }
+        assert!(timestamp(UNIX_EPOCH + Duration::from_millis(42)).ends_with(".042"));
+    }
+
+    #[test]
+    fn levels() {
+        assert_eq!("Debug".parse::<Level>().unwrap(), Level::Debug);
+        assert!("verbose".parse::<Level>().is_err());
+        assert!(Level::Error < Level::Warn && Level::Info < Level::Debug);
+    }
+}
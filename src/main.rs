@@ -1,81 +1,796 @@
-// #![feature(str_split_whitespace_remainder)]
-
-mod autoplay;
-mod benchmark;
-mod chess;
-mod constants;
-mod performance_test;
-mod search;
-mod uci;
-
-use arrayvec::ArrayVec;
-use chess::move_struct::Move;
-use chess::Game;
-
-fn get_parameter<T>(args: &mut std::env::Args, default: T) -> T
-where
-    T: std::str::FromStr + std::string::ToString,
-{
-    args.next()
-        .unwrap_or_else(|| default.to_string())
-        .parse()
-        .unwrap_or(default)
+use anyhow::{bail, Context};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rustybait::chess::evaluation::EvalTerm;
+use rustybait::chess::move_struct::Move;
+use rustybait::chess::{Game, Player, Score};
+use rustybait::engine_match::Adjudication;
+use rustybait::openings::OpeningSettings;
+use rustybait::strength::Strength;
+use rustybait::{
+    analysis, autoplay, benchmark, chess, datagen, engine_match, json, log, mate, performance_test,
+    play, sprt, spsa, test_suite, tuning, uci,
+};
+use std::str::FromStr;
+
+/// A chess engine speaking UCI, which it does without a command
+#[derive(Parser)]
+#[command(version)]
+struct Cli {
+    /// Options of another configuration file than rustybait.ini in UCI mode
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<String>,
+    /// Append the UCI commands and answers and the diagnostics to a file
+    #[arg(long, global = true, value_name = "PATH")]
+    log: Option<String>,
+    /// Keep the messages of the log up to error, warn, info or debug
+    #[arg(long, global = true, value_name = "LEVEL")]
+    log_level: Option<log::Level>,
+    /// Print the UCI output, the errors and the benchmark as JSON lines
+    #[arg(long, global = true)]
+    json: bool,
+    /// Threads of perft and datagen, every core by default, the search of the UCI mode uses one
+    #[arg(long, global = true)]
+    threads: Option<usize>,
+    /// Size in MB of the transposition table of the UCI mode and of Lichess games, and of
+    /// the tables of perft
+    #[arg(long, global = true, value_name = "MB")]
+    hash: Option<usize>,
+    /// Speak UCI over TCP connections to this address, e.g. 0.0.0.0:5000, instead of
+    /// stdin and stdout
+    #[arg(long, value_name = "ADDRESS")]
+    listen: Option<String>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Search a fixed set of positions, the node count tells apart functional changes.
+    /// This is used for benchmarking and PGO optimization
+    Bench {
+        #[arg(default_value_t = 6)]
+        depth: u8,
+    },
+    /// Count the leaf nodes below every move, in the format of most engines' divide
+    Perft {
+        #[arg(default_value_t = 7, value_parser = clap::value_parser!(u8).range(1..))]
+        depth: u8,
+        /// A FEN, the start position if there is none, followed by moves as in the UCI
+        /// position command
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        position: Vec<String>,
+    },
+    /// Check the perft counts of a suite of positions, the bundled one by default
+    Perftsuite {
+        #[arg(default_value_t = 6)]
+        max_depth: u8,
+        path: Option<String>,
+    },
+    /// Play two versions of the engine against each other until the SPRT tells whether
+    /// the new one is stronger
+    Sprt {
+        /// Command of the new version, followed by its arguments, this program by default
+        #[arg(long, value_name = "COMMAND")]
+        new: Option<String>,
+        /// Command of the baseline, followed by its arguments, this program by default
+        #[arg(long, value_name = "COMMAND")]
+        base: Option<String>,
+        /// UCI option of the new version, as <name>=<value>
+        #[arg(long, value_name = "NAME=VALUE", value_parser = engine_match::parse_option)]
+        new_option: Vec<(String, String)>,
+        /// UCI option of the baseline, as <name>=<value>
+        #[arg(long, value_name = "NAME=VALUE", value_parser = engine_match::parse_option)]
+        base_option: Vec<(String, String)>,
+        /// Time control in seconds, e.g. 10+0.1 for 10 s and 0.1 s added after each move
+        #[arg(long, value_name = "TIME+INCREMENT", default_value = "10+0.1", value_parser = engine_match::parse_time_control)]
+        tc: (u64, u64),
+        /// Elo difference of the null hypothesis, that the new version isn't better
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        elo0: f64,
+        /// Elo difference of the alternative hypothesis, that the new version is better
+        #[arg(long, default_value_t = 5.0, allow_negative_numbers = true)]
+        elo1: f64,
+        /// Probability of accepting the new version when it isn't better
+        #[arg(long, default_value_t = 0.05)]
+        alpha: f64,
+        /// Probability of rejecting the new version when it is better
+        #[arg(long, default_value_t = 0.05)]
+        beta: f64,
+        /// EPD or FEN file of the openings, the bundled ones by default
+        #[arg(long, value_name = "PATH")]
+        openings: Option<String>,
+        /// Games after which the test stops even if it is undecided
+        #[arg(long)]
+        games: Option<usize>,
+        /// PGN file to which the games are appended
+        #[arg(long, value_name = "PATH")]
+        pgn: Option<String>,
+        #[command(flatten)]
+        adjudication: AdjudicationArgs,
+    },
+    /// Play on Lichess with the bot account of the token in LICHESS_TOKEN, with the
+    /// transposition tables of --hash (64 MB by default)
+    Lichess {
+        /// Games played at the same time, other challenges are declined until one ends
+        #[arg(long, default_value_t = 1)]
+        games: usize,
+        /// Speeds of the challenges accepted
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_name = "SPEEDS",
+            default_value = "bullet,blitz,rapid,classical"
+        )]
+        speeds: Vec<String>,
+        /// UCI option of the engines, as <name>=<value>
+        #[arg(long, value_name = "NAME=VALUE", value_parser = engine_match::parse_option)]
+        option: Vec<(String, String)>,
+    },
+    /// Play games against another UCI engine and write them to a PGN file
+    Match {
+        /// Command of the opponent, followed by its arguments
+        #[arg(long, value_name = "COMMAND")]
+        engine: String,
+        /// UCI option of the opponent, as <name>=<value>
+        #[arg(long, value_name = "NAME=VALUE", value_parser = engine_match::parse_option)]
+        option: Vec<(String, String)>,
+        #[arg(long, default_value_t = 10)]
+        games: usize,
+        /// Time control in seconds, e.g. 10+0.1 for 10 s and 0.1 s added after each move
+        #[arg(long, value_name = "TIME+INCREMENT", default_value = "10+0.1", value_parser = engine_match::parse_time_control)]
+        tc: (u64, u64),
+        /// EPD or FEN file of the openings, the bundled ones by default
+        #[arg(long, value_name = "PATH")]
+        openings: Option<String>,
+        /// PGN file to which the games are appended
+        #[arg(long, value_name = "PATH", default_value = "match.pgn")]
+        pgn: String,
+        #[command(flatten)]
+        adjudication: AdjudicationArgs,
+    },
+    /// Tune constants of the search and the evaluation with SPSA, playing pairs of games
+    /// of the engine against itself with the constants perturbed in opposite directions
+    Spsa {
+        /// Game pairs played
+        #[arg(long, default_value_t = 1000)]
+        iterations: usize,
+        /// Time control in seconds, e.g. 2+0.02 for 2 s and 0.02 s added after each move
+        #[arg(long, value_name = "TIME+INCREMENT", default_value = "2+0.02", value_parser = engine_match::parse_time_control)]
+        tc: (u64, u64),
+        /// Names of the UCI options of the constants to tune, all of them by default
+        #[arg(long, value_delimiter = ',', value_name = "NAMES")]
+        params: Vec<String>,
+        /// Learning rate of the last iteration, higher values move the constants faster
+        #[arg(long, default_value_t = 0.002)]
+        learning_rate: f64,
+        /// EPD or FEN file of the openings, the bundled ones by default
+        #[arg(long, value_name = "PATH")]
+        openings: Option<String>,
+        /// UCI option set in both engines, as <name>=<value>
+        #[arg(long, value_name = "NAME=VALUE", value_parser = engine_match::parse_option)]
+        option: Vec<(String, String)>,
+        /// Seed of the perturbations and of the choice of the openings
+        #[arg(long)]
+        seed: Option<u64>,
+        #[command(flatten)]
+        adjudication: AdjudicationArgs,
+    },
+    /// Let the engine play against itself in the terminal
+    Auto {
+        /// Time per move in ms, unless there is a time control
+        #[arg(default_value_t = 1000)]
+        millis: u64,
+        /// Time control in seconds, e.g. 10+0.1 for 10 s and 0.1 s added after each move
+        #[arg(long, value_name = "TIME+INCREMENT", value_parser = engine_match::parse_time_control)]
+        tc: Option<(u64, u64)>,
+        /// Stop the game unfinished after this many moves of each side
+        #[arg(long)]
+        max_moves: Option<usize>,
+        /// Games played one after the other
+        #[arg(long, default_value_t = 1)]
+        games: usize,
+        #[command(flatten)]
+        openings: OpeningArgs,
+        #[command(flatten)]
+        adjudication: AdjudicationArgs,
+    },
+    /// Play fast games of the engine against itself on every thread and write their
+    /// quiet positions with their score and the result, for training the evaluation
+    Datagen {
+        /// Positions are written in Marlinformat if the file ends in .bin, else as EPD
+        #[arg(default_value = "datagen.bin")]
+        output: String,
+        #[arg(long, default_value_t = 1000)]
+        games: usize,
+        /// Nodes of the search of every move
+        #[arg(long, default_value_t = 5000)]
+        nodes: u64,
+        #[command(flatten)]
+        openings: OpeningArgs,
+        #[command(flatten)]
+        adjudication: AdjudicationArgs,
+    },
+    /// Play against the engine in the terminal
+    Play {
+        /// Time per move of the engine in ms
+        #[arg(default_value_t = 1000)]
+        millis: u64,
+        /// Color of the user
+        #[arg(value_enum, default_value_t = Color::White)]
+        color: Color,
+        /// Playing strength from 1 to 20, the full one
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u8).range(1..=20))]
+        level: u8,
+    },
+    /// Search a position and print the results as JSON
+    Analyze {
+        /// Time of the search in ms, or `infinite` to search until stdin is closed, going
+        /// on with every position read from it
+        #[arg(default_value = "1000")]
+        time: AnalysisTime,
+        /// Best lines of every depth reported by an infinite analysis
+        #[arg(long, default_value_t = 1)]
+        lines: u8,
+        /// A FEN, the start position if there is none
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        fen: Vec<String>,
+    },
+    /// Analyze a game in a terminal user interface, entering its moves, in a build with
+    /// --features tui
+    Tui {
+        /// Best lines shown
+        #[arg(long, default_value_t = 3)]
+        lines: u8,
+        /// Clocks of both sides in seconds, e.g. 300+2 for 5 minutes and 2 s added after
+        /// each move
+        #[arg(long, value_name = "TIME+INCREMENT", value_parser = engine_match::parse_time_control)]
+        tc: Option<(u64, u64)>,
+        /// A FEN, the start position if there is none
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        fen: Vec<String>,
+    },
+    /// Serve the analysis of the engine over HTTP and WebSocket to web front ends, in a
+    /// build with --features server
+    Serve {
+        /// Address and port listened on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        address: String,
+    },
+    /// Find forced mates in at most the given number of moves, in a FEN or in every
+    /// position of a file
+    Mate {
+        #[arg(default_value_t = 3)]
+        max_moves: u8,
+        /// A FEN or the path of a file of positions
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        position: Vec<String>,
+    },
+    /// Print the static evaluation of a position, split by term
+    Eval {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        fen: Vec<String>,
+    },
+    /// Compare the evaluation with the one of the mirrored position, which must be the
+    /// opposite, and of the position with the files reversed
+    Symmetry {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        fen: Vec<String>,
+    },
+    /// Write the default evaluation parameters, as a starting point for a parameter file
+    Params {
+        /// File to write, stdout if it is missing
+        path: Option<String>,
+    },
+    /// Tune the piece-square tables on a file of labeled positions
+    Tune {
+        path: String,
+        #[arg(default_value_t = 1000)]
+        epochs: u32,
+        #[arg(default_value = "tuned-params.txt")]
+        output: String,
+    },
+    /// Write the quiet positions of the games of a PGN file, labeled for tuning
+    Extract {
+        path: String,
+        #[arg(default_value = "quiet-labeled.epd")]
+        output: String,
+    },
+    /// Search the positions of an EPD test suite and score the moves found
+    #[command(alias = "solve")]
+    Testsuite {
+        path: String,
+        /// Time per position in ms
+        #[arg(default_value_t = 1000)]
+        millis: u64,
+    },
+}
+
+/// Where games of the engine against itself start from
+#[derive(Args)]
+struct OpeningArgs {
+    /// FEN of the start position, the standard one if there is none
+    #[arg(long, conflicts_with = "openings")]
+    fen: Option<String>,
+    /// EPD or FEN file whose positions are picked at random as start positions
+    #[arg(long, value_name = "PATH")]
+    openings: Option<String>,
+    /// Polyglot book whose lines are followed from the start position, by weight
+    #[arg(long, value_name = "PATH")]
+    book: Option<String>,
+    /// Random moves played after the start position and the book moves, 0 by default
+    /// for auto and 8 for datagen
+    #[arg(long)]
+    random_plies: Option<usize>,
+    /// Play every opening a second time from the mirrored position
+    #[arg(long)]
+    mirrored: bool,
+    /// Seed of the random openings, for games which can be played again
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+impl OpeningArgs {
+    fn settings(self, default_random_plies: usize) -> OpeningSettings {
+        OpeningSettings {
+            fen: self.fen,
+            epd: self.openings,
+            book: self.book,
+            random_plies: self.random_plies.unwrap_or(default_random_plies),
+            mirrored: self.mirrored,
+            seed: self.seed,
+        }
+    }
+}
+
+/// How games of the engine against itself are decided before they are over
+#[derive(Args)]
+struct AdjudicationArgs {
+    /// Score in centipawns from which the game is adjudicated as won
+    #[arg(long, default_value_t = Adjudication::default().resign_score)]
+    resign: Score,
+    /// Moves in a row with the resign score before the game is won, 0 to play on
+    #[arg(long, default_value_t = Adjudication::default().resign_moves)]
+    resign_moves: usize,
+    /// Score in centipawns up to which the game is adjudicated as drawn
+    #[arg(long, default_value_t = Adjudication::default().draw_score)]
+    draw: Score,
+    /// Moves in a row with the draw score before the game is drawn, 0 to play on
+    #[arg(long, default_value_t = Adjudication::default().draw_moves)]
+    draw_moves: usize,
+    /// First move from which the game can be adjudicated as drawn
+    #[arg(long, default_value_t = Adjudication::default().draw_after)]
+    draw_after: usize,
+}
+
+impl From<AdjudicationArgs> for Adjudication {
+    fn from(args: AdjudicationArgs) -> Self {
+        Self {
+            resign_score: args.resign,
+            resign_moves: args.resign_moves,
+            draw_score: args.draw,
+            draw_moves: args.draw_moves,
+            draw_after: args.draw_after,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Color {
+    White,
+    Black,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum AnalysisTime {
+    Millis(u64),
+    Infinite,
+}
+
+impl FromStr for AnalysisTime {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "infinite" => Ok(Self::Infinite),
+            millis => Ok(Self::Millis(
+                millis
+                    .parse()
+                    .context("Expected a time in ms or infinite")?,
+            )),
+        }
+    }
+}
+
+/// The position of a FEN given as the remaining arguments, the start position if there are none
+fn read_game(fen: &[String]) -> anyhow::Result<Game> {
+    if fen.is_empty() {
+        Ok(Game::default())
+    } else {
+        Ok(Game::new(&fen.join(" "))?)
+    }
 }
 
 fn main() -> anyhow::Result<()> {
-    let mut args = std::env::args();
-    args.next();
-
-    if let Some(arg) = args.next() {
-        if arg == "bench" {
-            // Generate best moves for a couple different positions
-            // This is used for benchmarking and PGO optimization
-            let depth = get_parameter(&mut args, 7);
-            let steps = get_parameter(&mut args, 5);
-
-            benchmark::run_simple_benchmark(depth, steps);
-        } else if arg == "iterbench" {
-            // Generate best moves for a couple different positions
-            // This is used for benchmarking and PGO optimization
-            let depth = get_parameter(&mut args, 7);
-            let steps = get_parameter(&mut args, 5);
-
-            benchmark::run_iterative_benchmark(depth, steps);
-        } else if arg == "perft" {
-            // Generate perft test result
-            let depth = get_parameter(&mut args, 7);
-            let fen = args.next().unwrap_or_default();
-            let mut game = Game::new(&fen).unwrap_or_default();
-            while let Some(move_str) = &args.next() {
-                let _move = Move::from_uci_notation(move_str, &game).unwrap();
+    let cli = Cli::parse();
+    if cli.json {
+        json::enable();
+    }
+
+    let result = run(cli);
+    if json::is_enabled() {
+        if let Err(err) = &result {
+            json::print(&json::Message::Error {
+                message: &format!("{:#}", err),
+            });
+            std::process::exit(1);
+        }
+    }
+    result
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    if let Some(path) = &cli.log {
+        log::set_file(Some(path))?;
+    }
+    if let Some(level) = cli.log_level {
+        log::set_level(level);
+    }
+    let threads = cli
+        .threads
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |threads| threads.get()));
+
+    let Some(command) = cli.command else {
+        // Enter UCI mode, the flags are set like options after the configuration file
+        let mut options = Vec::new();
+        if let Some(megabytes) = cli.hash {
+            options.push((String::from("Hash"), megabytes.to_string()));
+        }
+        return match &cli.listen {
+            Some(address) => uci::uci_listen(address, cli.config.as_deref(), &options),
+            None => uci::uci_talk(cli.config.as_deref(), &options),
+        };
+    };
+
+    match command {
+        Command::Bench { depth } => benchmark::run_benchmark(depth),
+        Command::Perft { depth, position } => {
+            let (fen, moves) = match position.iter().position(|arg| arg == "moves") {
+                Some(index) => (&position[..index], &position[index + 1..]),
+                None => (&position[..], &[][..]),
+            };
+            let mut game = if fen.is_empty() || fen == ["startpos"] {
+                Game::default()
+            } else {
+                Game::new(&fen.join(" "))?
+            };
+            for move_str in moves {
+                let _move = Move::from_uci_notation(move_str, &game)?;
                 game.push(_move);
-                println!("{}", &game);
             }
 
-            let mut moves = ArrayVec::new();
-            game.get_moves(&mut moves, true);
+            // The counts of subtrees are stored in tables of the --hash size, if it is set
+            let hash_megabytes = cli.hash.unwrap_or(0);
+            let start = std::time::Instant::now();
+            let counts = performance_test::divide(&game, depth, threads, hash_megabytes);
+            let elapsed = start.elapsed();
 
-            moves.sort_by_cached_key(|_move| _move.uci_notation());
+            for (_move, count) in &counts {
+                println!("{}: {}", _move.uci_notation(&game), count);
+            }
+            let nodes: usize = counts.iter().map(|(_, count)| count).sum();
+            println!();
+            println!("Nodes searched: {}", nodes);
+            println!("Threads: {}", threads);
+            println!("Time: {} ms", elapsed.as_millis());
+            println!(
+                "Nodes per second: {:.0}",
+                nodes as f64 / elapsed.as_secs_f64()
+            );
+        }
+        Command::Perftsuite { max_depth, path } => {
+            // Like perft, the counts of subtrees are stored in tables of the --hash size
+            let hash_megabytes = cli.hash.unwrap_or(0);
+            if !performance_test::run_suite(path.as_deref(), max_depth, threads, hash_megabytes)? {
+                bail!("Some perft counts are wrong");
+            }
+        }
+        Command::Sprt {
+            new,
+            base,
+            new_option,
+            base_option,
+            tc: (time, increment),
+            elo0,
+            elo1,
+            alpha,
+            beta,
+            openings,
+            games,
+            pgn,
+            adjudication,
+        } => {
+            let engine = |name, command: Option<String>, options| {
+                let mut engine = engine_match::EngineConfig::this_program(Some(name))?;
+                if let Some(command) = command {
+                    engine.command = command;
+                }
+                engine.options = options;
+                anyhow::Ok(engine)
+            };
+            sprt::run(&sprt::Settings {
+                new: engine("New", new, new_option)?,
+                base: engine("Base", base, base_option)?,
+                elo0,
+                elo1,
+                alpha,
+                beta,
+                time,
+                increment,
+                openings,
+                max_games: games,
+                pgn,
+                adjudication: adjudication.into(),
+            })?;
+        }
+        Command::Lichess {
+            games,
+            speeds,
+            option,
+        } => {
+            #[cfg(feature = "lichess")]
+            {
+                let defaults = rustybait::lichess::BotSettings::default();
+                rustybait::lichess::run(rustybait::lichess::BotSettings {
+                    games,
+                    hash: cli.hash.unwrap_or(defaults.hash),
+                    speeds,
+                    options: option,
+                })?;
+            }
+            #[cfg(not(feature = "lichess"))]
+            {
+                let _ = (games, speeds, option);
+                bail!("Playing on Lichess needs a build with --features lichess");
+            }
+        }
+        Command::Match {
+            engine,
+            option,
+            games,
+            tc: (time, increment),
+            openings,
+            pgn,
+            adjudication,
+        } => {
+            engine_match::run_match(&engine_match::MatchSettings {
+                opponent: engine_match::EngineConfig {
+                    name: None,
+                    command: engine,
+                    options: option,
+                },
+                games,
+                time,
+                increment,
+                openings,
+                pgn,
+                adjudication: adjudication.into(),
+            })?;
+        }
+        Command::Spsa {
+            iterations,
+            tc: (time, increment),
+            params,
+            learning_rate,
+            openings,
+            option,
+            seed,
+            adjudication,
+        } => {
+            let mut engine = engine_match::EngineConfig::this_program(None)?;
+            engine.options = option;
+            let tunables = if params.is_empty() {
+                spsa::TUNABLES.iter().collect()
+            } else {
+                params
+                    .iter()
+                    .map(|name| {
+                        spsa::tunable(name).with_context(|| format!("Unknown constant: {}", name))
+                    })
+                    .collect::<anyhow::Result<_>>()?
+            };
 
-            let mut sum = 0;
-            for _move in moves {
-                game.push(_move);
-                let count = performance_test::perft(&mut game, depth - 1);
-                game.pop(_move);
-                sum += count;
-                println!("{}: {}", _move.uci_notation(), count);
+            spsa::run(&spsa::Settings {
+                engine,
+                tunables,
+                iterations,
+                time,
+                increment,
+                learning_rate,
+                openings,
+                seed,
+                adjudication: adjudication.into(),
+            })?;
+        }
+        Command::Auto {
+            millis,
+            tc,
+            max_moves,
+            games,
+            openings,
+            adjudication,
+        } => autoplay::autoplay(&autoplay::AutoplaySettings {
+            openings: openings.settings(0),
+            games,
+            millis,
+            time_control: tc,
+            max_moves,
+            adjudication: adjudication.into(),
+        })?,
+        Command::Datagen {
+            output,
+            games,
+            nodes,
+            openings,
+            adjudication,
+        } => datagen::generate(&datagen::DatagenSettings {
+            openings: openings.settings(8),
+            games,
+            threads,
+            nodes,
+            output,
+            adjudication: adjudication.into(),
+        })?,
+        Command::Play {
+            millis,
+            color,
+            level,
+        } => {
+            let human = match color {
+                Color::White => Player::White,
+                Color::Black => Player::Black,
+            };
+            play::play(millis, human, Strength::of_level(level))?;
+        }
+        Command::Analyze { time, lines, fen } => {
+            let game = read_game(&fen)?;
+            match time {
+                AnalysisTime::Infinite => analysis::analyze_continuously(game, lines)?,
+                AnalysisTime::Millis(_) if lines != 1 => {
+                    bail!("Several lines are only reported by an infinite analysis")
+                }
+                AnalysisTime::Millis(millis) => analysis::analyze(&game, millis),
+            }
+        }
+        Command::Tui { lines, tc, fen } => {
+            let game = read_game(&fen)?;
+            #[cfg(feature = "tui")]
+            rustybait::tui::run(
+                game,
+                lines,
+                tc,
+                cli.hash.unwrap_or(rustybait::constants::DEFAULT_HASH_MB),
+            )?;
+            #[cfg(not(feature = "tui"))]
+            {
+                let _ = (game, lines, tc);
+                bail!("The terminal user interface needs a build with --features tui");
             }
-            println!("");
-            println!("{}", sum);
-        } else if arg == "auto" {
-            // Auto play in terminal
-            let millis = get_parameter(&mut args, 1000);
-            autoplay::autoplay(millis);
         }
+        Command::Serve { address } => {
+            #[cfg(feature = "server")]
+            rustybait::server::serve(
+                &address,
+                cli.hash.unwrap_or(rustybait::constants::DEFAULT_HASH_MB),
+            )?;
+            #[cfg(not(feature = "server"))]
+            {
+                let _ = address;
+                bail!("Serving the analysis needs a build with --features server");
+            }
+        }
+        Command::Mate {
+            max_moves,
+            position,
+        } => mate::solve(max_moves, &position)?,
+        Command::Eval { fen } => {
+            let game = read_game(&fen)?;
+            println!("{}", game.evaluation());
+        }
+        Command::Symmetry { fen } => {
+            let game = read_game(&fen)?;
+            let evaluation = game.evaluation();
+            let mirrored = game.mirrored().evaluation();
 
-        Ok(())
-    } else {
-        // Enter UCI mode
-        uci::uci_talk()
+            println!("Position: {}", evaluation.total());
+            println!("Mirrored: {}", -mirrored.total());
+            println!("Flipped:  {}", game.flipped().evaluation().total());
+
+            for term in EvalTerm::ALL {
+                for player in [Player::White, Player::Black] {
+                    let (value, mirrored_value) = (
+                        evaluation.get(term, player),
+                        mirrored.get(term, player.the_other()),
+                    );
+                    if value != mirrored_value {
+                        println!(
+                            "Asymmetric {} of {:?}: {} mirrored to {}",
+                            term.name(),
+                            player,
+                            value,
+                            mirrored_value
+                        );
+                    }
+                }
+            }
+        }
+        Command::Params { path } => {
+            let params = chess::params::EvalParams::DEFAULT;
+            if let Some(path) = path {
+                std::fs::write(&path, params.to_string())
+                    .with_context(|| format!("Failed to write {}", path))?;
+            } else {
+                print!("{}", params);
+            }
+        }
+        Command::Tune {
+            path,
+            epochs,
+            output,
+        } => tuning::tune(&path, epochs, &output)?,
+        Command::Extract { path, output } => tuning::extract(&path, &output)?,
+        Command::Testsuite { path, millis } => test_suite::solve(&path, millis)?,
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn arguments() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::parse_from([
+            "rustybait",
+            "perft",
+            "3",
+            "8/8/8/8/8/8/8/K6k",
+            "w",
+            "-",
+            "-",
+        ]);
+        let Some(Command::Perft { depth, position }) = cli.command else {
+            panic!("Not perft");
+        };
+        assert_eq!(depth, 3);
+        assert_eq!(position.join(" "), "8/8/8/8/8/8/8/K6k w - -");
+
+        let cli = Cli::parse_from(["rustybait", "--hash", "64", "bench", "--json"]);
+        assert!(cli.json);
+        assert_eq!(cli.hash, Some(64));
+        assert!(matches!(cli.command, Some(Command::Bench { depth: 6 })));
+
+        assert!(Cli::try_parse_from(["rustybait", "perft", "0"]).is_err());
+        let cli = Cli::parse_from(["rustybait", "analyze", "infinite", "--lines", "3"]);
+        let Some(Command::Analyze { time, lines, fen }) = cli.command else {
+            panic!("Not analyze");
+        };
+        assert_eq!((time, lines), (AnalysisTime::Infinite, 3));
+        assert!(fen.is_empty());
+        assert!(Cli::try_parse_from(["rustybait", "analyze", "soon"]).is_err());
+        assert!(Cli::try_parse_from(["rustybait", "--log-level", "verbose"]).is_err());
+        assert!(Cli::try_parse_from(["rustybait", "solve", "wac.epd"]).is_ok());
+        assert!(Cli::try_parse_from([
+            "rustybait",
+            "auto",
+            "--fen",
+            "8/8/8/8/8/8/8/K6k w - - 0 1",
+            "--openings",
+            "openings.epd"
+        ])
+        .is_err());
     }
 }
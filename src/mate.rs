@@ -0,0 +1,208 @@
+use crate::chess::{
+    epd::Epd,
+    move_struct::{Move, MAX_MOVES},
+    Game, Player,
+};
+use anyhow::{bail, Context};
+use arrayvec::ArrayVec;
+use std::{fs, path::Path};
+
+/// Legal moves of the player to move in `game`, the ones giving check first
+///
+/// Only checks are returned when `only_checks` is set, which is the case of the last move
+/// of a mate.
+fn attacking_moves(game: &mut Game, only_checks: bool) -> ArrayVec<Move, MAX_MOVES> {
+    let mut moves = ArrayVec::new();
+    game.get_moves(&mut moves);
+
+    let mut checks = ArrayVec::new();
+    let mut quiet: ArrayVec<Move, MAX_MOVES> = ArrayVec::new();
+    for _move in moves {
+        game.push(_move);
+        let is_check = game.is_check();
+        game.pop();
+
+        if is_check {
+            checks.push(_move);
+        } else if !only_checks {
+            quiet.push(_move);
+        }
+    }
+
+    checks.extend(quiet);
+    checks
+}
+
+/// Whether the player to move can force mate in at most `moves` of their moves
+fn can_mate(game: &mut Game, moves: u8) -> bool {
+    attacking_moves(game, moves == 1).into_iter().any(|_move| {
+        game.push(_move);
+        let mates = defender_loses(game, moves - 1);
+        game.pop();
+        mates
+    })
+}
+
+/// Whether the player to move is mated now or by every reply in at most `moves` moves
+/// of the attacker
+fn defender_loses(game: &mut Game, moves: u8) -> bool {
+    if moves == 0 && !game.is_check() {
+        return false;
+    }
+
+    let mut replies = ArrayVec::new();
+    game.get_moves(&mut replies);
+    if replies.is_empty() {
+        return game.is_check();
+    }
+
+    moves > 0
+        && replies.into_iter().all(|reply| {
+            game.push(reply);
+            let mates = can_mate(game, moves);
+            game.pop();
+            mates
+        })
+}
+
+/// Fewest moves, up to `max_moves`, in which the player to move can force mate
+fn shortest_mate(game: &mut Game, max_moves: u8) -> Option<u8> {
+    (1..=max_moves).find(|&moves| can_mate(game, moves))
+}
+
+/// The shortest forced mate of the player to move in at most `max_moves` moves, as a line
+/// where the defender delays it as long as possible
+///
+/// The fifty-move rule and repetitions are ignored, as usual for compositions.
+pub fn find_mate(game: &Game, max_moves: u8) -> Option<Vec<Move>> {
+    let mut game = game.clone();
+    let mut moves = shortest_mate(&mut game, max_moves)?;
+    let mut line = Vec::new();
+
+    loop {
+        let attack = attacking_moves(&mut game, moves == 1)
+            .into_iter()
+            .find(|&_move| {
+                game.push(_move);
+                let mates = defender_loses(&mut game, moves - 1);
+                game.pop();
+                mates
+            })
+            .unwrap();
+        game.push(attack);
+        line.push(attack);
+
+        let mut replies = ArrayVec::new();
+        game.get_moves(&mut replies);
+        let Some((reply, remaining)) = replies
+            .into_iter()
+            .map(|reply| {
+                game.push(reply);
+                let remaining = shortest_mate(&mut game, moves - 1).unwrap();
+                game.pop();
+                (reply, remaining)
+            })
+            .max_by_key(|&(_, remaining)| remaining)
+        else {
+            return Some(line);
+        };
+
+        game.push(reply);
+        line.push(reply);
+        moves = remaining;
+    }
+}
+
+/// Moves played from `game` in SAN with their numbers, e.g. `1. Qg6+ hxg6 2. Rh8#`
+fn format_line(game: &Game, line: &[Move]) -> String {
+    let mut game = game.clone();
+    let mut tokens = Vec::with_capacity(line.len() * 3 / 2 + 1);
+
+    for (index, &_move) in line.iter().enumerate() {
+        if game.player() == Player::White {
+            tokens.push(format!("{}.", game.fullmove_number()));
+        } else if index == 0 {
+            tokens.push(format!("{}...", game.fullmove_number()));
+        }
+        tokens.push(_move.to_san(&game));
+        game.push_history(_move);
+    }
+
+    tokens.join(" ")
+}
+
+/// Looks for a mate in at most `max_moves` moves in a position, given as a FEN in `args`,
+/// or in every position of a file of FENs or EPDs, and prints the mating lines
+pub fn solve(max_moves: u8, args: &[String]) -> anyhow::Result<()> {
+    if max_moves == 0 {
+        bail!("The number of moves must be at least 1");
+    }
+
+    let positions = match args {
+        [] => bail!("Missing FEN or file of positions"),
+        // FENs have slashes, unlike most relative paths
+        [path] if !path.contains('/') || Path::new(path).is_file() => {
+            let text =
+                fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+            let mut positions = Vec::new();
+            for (line_number, line) in text.lines().enumerate() {
+                if !line.trim().is_empty() {
+                    let epd =
+                        Epd::parse(line).with_context(|| format!("Line {}", line_number + 1))?;
+                    positions.push(epd.game);
+                }
+            }
+            positions
+        }
+        fen => vec![Game::new(&fen.join(" "))?],
+    };
+
+    for game in &positions {
+        match find_mate(game, max_moves) {
+            Some(line) => println!(
+                "{}: mate in {}: {}",
+                game.to_fen(),
+                line.len().div_ceil(2),
+                format_line(game, &line)
+            ),
+            None => println!("{}: no mate in {}", game.to_fen(), max_moves),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve_fen(fen: &str, max_moves: u8) -> Option<String> {
+        let game = Game::new(fen).unwrap();
+        find_mate(&game, max_moves).map(|line| format_line(&game, &line))
+    }
+
+    #[test]
+    fn mates() {
+        assert_eq!(
+            solve_fen(
+                "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+                3
+            )
+            .as_deref(),
+            Some("2... Qh4#")
+        );
+
+        // The rook can't mate alone, the king must first be cut off on the seventh rank
+        let line = solve_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 3).unwrap();
+        assert!(line.starts_with("1. R") && line.ends_with('#'), "{}", line);
+        assert_eq!(line.split(' ').count(), 5, "{}", line);
+        assert!(solve_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 1).is_none());
+
+        // Shorter mates are found first
+        assert_eq!(
+            solve_fen("6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1", 2).as_deref(),
+            Some("1. Re8#")
+        );
+        assert!(solve_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1", 2).is_none());
+    }
+}
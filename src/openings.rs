@@ -0,0 +1,174 @@
+use crate::{book::Book, chess::Game, engine_match};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Moves followed in the book at most, lines of the usual books are shorter
+const MAX_BOOK_PLIES: usize = 16;
+/// Tries to find an opening in which the game isn't over yet
+const MAX_TRIES: usize = 100;
+
+/// Where games of the engine against itself start from, so that they differ from each
+/// other
+#[derive(Clone, Default)]
+pub struct OpeningSettings {
+    /// Position every opening starts from, the standard one if neither it nor `epd` is set
+    pub fen: Option<String>,
+    /// EPD or FEN file whose positions are picked at random
+    pub epd: Option<String>,
+    /// Polyglot book whose lines are followed, picking the moves by weight
+    pub book: Option<String>,
+    /// Random legal moves played after the position and the moves of the book
+    pub random_plies: usize,
+    /// Whether every opening is followed by the mirrored position, which has the colors
+    /// swapped
+    pub mirrored: bool,
+    /// Seed of the random choices, taken from the clock if there is none
+    pub seed: Option<u64>,
+}
+
+/// The positions of the games, one after the other
+pub struct Openings {
+    /// FENs the openings start from
+    positions: Vec<String>,
+    book: Option<Book>,
+    random_plies: usize,
+    mirrored: bool,
+    random: Random,
+    /// The mirrored position of the last opening, which is played next
+    mirror: Option<Game>,
+}
+
+impl Openings {
+    pub fn new(settings: &OpeningSettings) -> anyhow::Result<Self> {
+        let positions = match (&settings.fen, &settings.epd) {
+            (Some(fen), _) => {
+                Game::new(fen)?;
+                vec![fen.clone()]
+            }
+            (None, Some(path)) => engine_match::load_openings(Some(path))?,
+            (None, None) => vec![Game::default().to_fen()],
+        };
+        let book = settings.book.as_deref().map(Book::open).transpose()?;
+
+        Ok(Self {
+            positions,
+            book,
+            random_plies: settings.random_plies,
+            mirrored: settings.mirrored,
+            random: Random::new(settings.seed),
+            mirror: None,
+        })
+    }
+
+    /// The position of the next game, without the moves which led to it
+    pub fn next_game(&mut self) -> Game {
+        if let Some(mirror) = self.mirror.take() {
+            return mirror;
+        }
+
+        // Random moves may end the game, in which case another opening is tried
+        let game = (0..MAX_TRIES)
+            .find_map(|_| self.random_opening())
+            .unwrap_or_else(|| Game::new(&self.positions[0]).unwrap());
+        if self.mirrored {
+            self.mirror = Some(game.mirrored());
+        }
+        game
+    }
+
+    fn random_opening(&mut self) -> Option<Game> {
+        let fen = &self.positions[self.random.below(self.positions.len())];
+        let mut game = Game::new(fen).unwrap();
+
+        if let Some(book) = &self.book {
+            for _ in 0..MAX_BOOK_PLIES {
+                let Some(_move) = book.pick(&game, 100, self.random.next()) else {
+                    break;
+                };
+                game.push_history(_move);
+            }
+        }
+        for _ in 0..self.random_plies {
+            let moves = game.legal_moves();
+            if moves.is_empty() {
+                return None;
+            }
+            game.push_history(moves[self.random.below(moves.len())]);
+        }
+
+        if game.outcome().is_over() {
+            return None;
+        }
+        Some(Game::new(&game.to_fen()).unwrap())
+    }
+}
+
+/// Xorshift generator, random enough for picking openings and reproducible from its seed
+pub(crate) struct Random(u64);
+
+impl Random {
+    /// The generator of `seed`, or of the clock if there is none
+    pub(crate) fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_nanos() as u64)
+        });
+        // Xorshift never leaves 0
+        Self(seed.max(1))
+    }
+
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number from 0 to `bound` - 1
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn random_openings() {
+        let settings = OpeningSettings {
+            random_plies: 6,
+            mirrored: true,
+            seed: Some(42),
+            ..OpeningSettings::default()
+        };
+        let mut openings = Openings::new(&settings).unwrap();
+        let first = openings.next_game();
+        let mirror = openings.next_game();
+        assert_eq!(first.fullmove_number(), 4);
+        assert!(first.history().is_empty());
+        assert_eq!(mirror.to_fen(), first.mirrored().to_fen());
+
+        // Reproducible from the seed, and different from one opening to the next
+        let fens: Vec<String> = (0..4).map(|_| openings.next_game().to_fen()).collect();
+        let mut again = Openings::new(&settings).unwrap();
+        again.next_game();
+        again.next_game();
+        let fens_again: Vec<String> = (0..4).map(|_| again.next_game().to_fen()).collect();
+        assert_eq!(fens, fens_again);
+        assert_ne!(fens[0], fens[2]);
+
+        let settings = OpeningSettings {
+            fen: Some(String::from("4k3/8/8/8/8/8/8/4K2R w K - 0 1")),
+            ..OpeningSettings::default()
+        };
+        let game = Openings::new(&settings).unwrap().next_game();
+        assert_eq!(game.to_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+
+        let settings = OpeningSettings {
+            fen: Some(String::from("8/8/8")),
+            ..OpeningSettings::default()
+        };
+        assert!(Openings::new(&settings).is_err());
+    }
+}
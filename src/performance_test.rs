@@ -1,12 +1,21 @@
-use crate::chess::Game;
+use crate::chess::{epd::Epd, move_struct::Move, Game};
+use anyhow::{bail, Context};
 use arrayvec::ArrayVec;
+use std::{
+    fs,
+    sync::{
+        atomic::{AtomicUsize, Ordering::Relaxed},
+        Mutex,
+    },
+    thread,
+};
 
 // Documentation and source of correct values for perft: https://www.chessprogramming.org/Perft_Results
 
 /// Performance Test
 pub fn perft(game: &mut Game, depth: u8) -> usize {
     let mut moves = ArrayVec::new();
-    game.get_moves(&mut moves, true);
+    game.get_moves(&mut moves);
 
     let mut count = 0;
     if depth == 0 {
@@ -18,16 +27,269 @@ pub fn perft(game: &mut Game, depth: u8) -> usize {
     for &_move in &moves {
         game.push(_move);
         count += perft(game, depth - 1);
-        game.pop(_move);
+        game.pop();
     }
 
     count
 }
 
-#[cfg(test)]
+/// Perft result of a position, the hash is stored whole to tell apart positions sharing
+/// the same slot
+#[derive(Clone, Copy, Default)]
+struct PerftEntry {
+    hash: u64,
+    depth: u8,
+    count: usize,
+}
+
+/// Results of subtrees already counted, the same position is often reached by different
+/// move orders. Entries are always replaced by the newest result.
+pub struct PerftTable {
+    entries: Vec<PerftEntry>,
+}
+
+impl PerftTable {
+    pub fn new(megabytes: usize) -> Self {
+        let len = (megabytes << 20) / std::mem::size_of::<PerftEntry>();
+        Self {
+            entries: vec![PerftEntry::default(); len.max(1)],
+        }
+    }
+}
+
+/// Performance Test which looks up every position with 2 or more plies left in `table`
+///
+/// The counts are only right when positions with the same hash are the same, so this
+/// also tests the hash of the game.
+pub fn perft_hashed(game: &mut Game, depth: u8, table: &mut PerftTable) -> usize {
+    if depth <= 1 {
+        return perft(game, depth);
+    }
+
+    let hash = game.hash();
+    let index = (hash % table.entries.len() as u64) as usize;
+    let entry = table.entries[index];
+    if entry.hash == hash && entry.depth == depth {
+        return entry.count;
+    }
+
+    let mut moves = ArrayVec::new();
+    game.get_moves(&mut moves);
+
+    let mut count = 0;
+    for &_move in &moves {
+        game.push(_move);
+        count += perft_hashed(game, depth - 1, table);
+        game.pop();
+    }
+
+    table.entries[index] = PerftEntry { hash, depth, count };
+    count
+}
+
+/// Perft split by the moves of the position, sorted by their UCI notation, which can be
+/// compared line by line with the output of another engine to find the faulty move
+///
+/// The moves are shared between `threads` threads, each one takes the next move which
+/// hasn't been counted yet on its own copy of the game. With `hash_megabytes` above 0 the
+/// threads split that much memory into tables for `perft_hashed`.
+pub fn divide(game: &Game, depth: u8, threads: usize, hash_megabytes: usize) -> Vec<(Move, usize)> {
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    let mut moves = ArrayVec::new();
+    game.get_moves(&mut moves);
+    moves.sort_by_cached_key(|_move| _move.uci_notation(game));
+
+    let next = AtomicUsize::new(0);
+    let counts = Mutex::new(vec![0; moves.len()]);
+    thread::scope(|scope| {
+        let threads = threads.clamp(1, moves.len().max(1));
+        for _ in 0..threads {
+            let mut game = game.clone();
+            let (moves, next, counts) = (&moves, &next, &counts);
+            scope.spawn(move || {
+                let mut table =
+                    (hash_megabytes > 0).then(|| PerftTable::new(hash_megabytes.div_ceil(threads)));
+                loop {
+                    let index = next.fetch_add(1, Relaxed);
+                    let Some(&_move) = moves.get(index) else {
+                        break;
+                    };
+                    game.push(_move);
+                    let count = match &mut table {
+                        Some(table) => perft_hashed(&mut game, depth - 1, table),
+                        None => perft(&mut game, depth - 1),
+                    };
+                    game.pop();
+                    counts.lock().unwrap()[index] = count;
+                }
+            });
+        }
+    });
+
+    moves
+        .into_iter()
+        .zip(counts.into_inner().unwrap())
+        .collect()
+}
+
+/// Standard positions with their counts, including the tricky ones of Martin Sedlak for
+/// en passant, castling and promotions, in the format of `perftsuite.epd`
+const SUITE: &str = include_str!("../perft_suite.epd");
+
+/// A position of a suite with its expected counts by depth
+type SuiteEntry = (Game, Vec<(u8, usize)>);
+
+/// Reads the positions of a suite, each one followed by operations like `;D5 4865609`
+fn parse_suite(text: &str) -> anyhow::Result<Vec<SuiteEntry>> {
+    let mut positions = Vec::new();
+
+    for (line_number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let context = || format!("Line {}", line_number + 1);
+        let epd = Epd::parse(line).with_context(context)?;
+
+        let mut counts = Vec::new();
+        for (opcode, operands) in &epd.operations {
+            let (Some(depth), [count]) = (opcode.strip_prefix('D'), operands.as_slice()) else {
+                continue;
+            };
+            let depth = depth.parse().with_context(context)?;
+            let count = count.parse().with_context(context)?;
+            counts.push((depth, count));
+        }
+        if counts.is_empty() {
+            bail!("{}: no perft count", context());
+        }
+
+        positions.push((epd.game, counts));
+    }
+
+    Ok(positions)
+}
+
+/// Checks every count of a suite up to `max_depth`, of the bundled suite if no path is given,
+/// and returns whether they were all right. The tables are sized like those of `divide`.
+pub fn run_suite(
+    path: Option<&str>,
+    max_depth: u8,
+    threads: usize,
+    hash_megabytes: usize,
+) -> anyhow::Result<bool> {
+    let text = match path {
+        Some(path) => {
+            fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?
+        }
+        None => SUITE.to_string(),
+    };
+    let positions = parse_suite(&text)?;
+
+    let (mut passed, mut total) = (0, 0);
+    for (game, counts) in &positions {
+        for &(depth, expected) in counts.iter().filter(|&&(depth, _)| depth <= max_depth) {
+            let count: usize = divide(game, depth, threads, hash_megabytes)
+                .iter()
+                .map(|(_, count)| count)
+                .sum();
+
+            total += 1;
+            if count == expected {
+                passed += 1;
+                println!("{} D{}: {} pass", game.to_fen(), depth, count);
+            } else {
+                println!(
+                    "{} D{}: {} FAIL, expected {}",
+                    game.to_fen(),
+                    depth,
+                    count,
+                    expected
+                );
+            }
+        }
+    }
+
+    println!("Passed {} of {}", passed, total);
+
+    Ok(passed == total)
+}
 
+#[cfg(test)]
 mod tests {
     use super::*;
+
+    #[test]
+    fn divide_kiwipete() {
+        let game =
+            Game::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -").unwrap();
+        let counts = divide(&game, 3, 4, 0);
+        assert_eq!(counts.len(), 48);
+        assert_eq!(counts.iter().map(|(_, count)| count).sum::<usize>(), 97862);
+
+        let count = |uci: &str| {
+            counts
+                .iter()
+                .find(|(_move, _)| _move.uci_notation(&game) == uci)
+                .map(|&(_, count)| count)
+        };
+        assert_eq!(count("e1g1"), Some(2059));
+        assert_eq!(count("d5e6"), Some(2241));
+        assert_eq!(count("a2a4"), Some(2149));
+
+        assert_eq!(divide(&game, 3, 1, 0).len(), 48);
+        assert_eq!(
+            divide(&game, 3, 2, 1)
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<usize>(),
+            97862
+        );
+        assert!(divide(&game, 0, 4, 0).is_empty());
+    }
+
+    #[test]
+    fn suite() {
+        let positions = parse_suite(SUITE).unwrap();
+        assert!(positions.len() >= 20);
+
+        // The shallow counts, the others have their own tests
+        for (mut game, counts) in positions {
+            for (depth, count) in counts.into_iter().filter(|&(depth, _)| depth <= 3) {
+                assert_eq!(perft(&mut game, depth), count, "{}", game.to_fen());
+            }
+        }
+
+        assert!(parse_suite("8/8/8/8/8/8/8/K6k w - - 0 1").is_err());
+        assert!(parse_suite("8/8/8/8/8/8/8/K6k w - - 0 1 ;D1 x").is_err());
+    }
+
+    #[test]
+    fn perft_hashed_matches() {
+        // The smallest table has a single entry, which every position shares
+        for (fen, depth, count) in [
+            (crate::chess::START_FEN, 5, 4865609),
+            (
+                "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq -",
+                4,
+                4085603,
+            ),
+            ("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - -", 5, 674624),
+            (
+                "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+                4,
+                422333,
+            ),
+        ] {
+            let mut game = Game::new(fen).unwrap();
+            for megabytes in [0, 16] {
+                let mut table = PerftTable::new(megabytes);
+                assert_eq!(perft_hashed(&mut game, depth, &mut table), count, "{}", fen);
+            }
+        }
+    }
     #[test]
     fn perft1_startpos() {
         let mut game = Game::default();
@@ -269,6 +531,57 @@ mod tests {
         assert_eq!(perft(&mut game, 5), 89941194);
     }
 
+    // Chess960 values from the Fischer Random perft suite, castling rights given as rook files
+
+    #[test]
+    fn perft4_chess960_1() {
+        let mut game =
+            Game::new("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9").unwrap();
+        assert_eq!(perft(&mut game, 4), 326672);
+    }
+
+    #[test]
+    fn perft4_chess960_2() {
+        let mut game =
+            Game::new("2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9").unwrap();
+        assert_eq!(perft(&mut game, 4), 667366);
+    }
+
+    #[test]
+    fn perft4_chess960_3() {
+        let mut game =
+            Game::new("1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9")
+                .unwrap();
+        assert_eq!(perft(&mut game, 4), 287739);
+    }
+
+    // En passant positions from the tricky perft suite of Martin Sedlak, where the capture
+    // would expose the king along a diagonal or a row, or gives check itself
+
+    #[test]
+    fn perft6_en_passant_diagonal_pin() {
+        let mut game = Game::new("8/5bk1/8/2Pp4/8/1K6/8/8 w - d6 0 1").unwrap();
+        assert_eq!(perft(&mut game, 6), 824064);
+        let mut game = Game::new("8/8/1k6/8/2pP4/8/5BK1/8 b - d3 0 1").unwrap();
+        assert_eq!(perft(&mut game, 6), 824064);
+    }
+
+    #[test]
+    fn perft6_en_passant_gives_check() {
+        let mut game = Game::new("8/8/1k6/2b5/2pP4/8/5K2/8 b - d3 0 1").unwrap();
+        assert_eq!(perft(&mut game, 6), 1440467);
+        let mut game = Game::new("8/5k2/8/2Pp4/2B5/1K6/8/8 w - d6 0 1").unwrap();
+        assert_eq!(perft(&mut game, 6), 1440467);
+    }
+
+    #[test]
+    fn perft6_en_passant_row_pin() {
+        let mut game = Game::new("3k4/3p4/8/K1P4r/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(perft(&mut game, 6), 1134888);
+        let mut game = Game::new("8/8/4k3/8/2p5/8/B2P2K1/8 w - - 0 1").unwrap();
+        assert_eq!(perft(&mut game, 6), 1015133);
+    }
+
     #[test]
     fn zobrist1() {
         let mut game = Game::default();
@@ -0,0 +1,292 @@
+use crate::{
+    chess::{
+        move_struct::Move,
+        pgn::GameResult,
+        position::Position,
+        score::{self, Score},
+        Game, Player,
+    },
+    constants::{DEFAULT_CONTEMPT, TT_CAPACITY},
+    openings::Random,
+    search::{search, SearchLimits, TranspositionTable},
+    strength::{self, Strength},
+    uci, wdl,
+};
+use nohash_hasher::BuildNoHashHasher;
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    sync::atomic::AtomicBool,
+    time::Duration,
+};
+
+const HELP: &str = "\
+Enter a move in SAN (e.g. Nf3) or UCI notation (e.g. g1f3), or one of the commands:
+  undo    take back your last move and the reply to it
+  hint    the move the engine would play in your place
+  moves   the legal moves
+  ascii   switch between chess symbols and letters for the pieces
+  help    this list
+  quit    leave, printing the game as PGN";
+
+/// Plays a game against the user in the terminal, who has the pieces of `human` and
+/// enters moves and commands on stdin, the engine thinks `millis` on every move with the
+/// handicaps of `strength` if it has any. Hints are always at full strength.
+pub fn play(millis: u64, human: Player, strength: Option<Strength>) -> anyhow::Result<()> {
+    let mut table: TranspositionTable =
+        HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildNoHashHasher::default());
+    run(
+        io::stdin().lock(),
+        io::stdout(),
+        millis,
+        human,
+        strength,
+        &mut table,
+    )
+}
+
+/// The game of `play`, reading the user's lines from `input` and writing to `output`
+fn run(
+    input: impl BufRead,
+    mut output: impl Write,
+    millis: u64,
+    human: Player,
+    strength: Option<Strength>,
+    table: &mut TranspositionTable,
+) -> anyhow::Result<()> {
+    let limits = SearchLimits::new().move_time(Duration::from_millis(millis));
+    let mut random = Random::new(None);
+    let mut game = Game::default();
+    let mut ascii = false;
+    // The board is shown again only once it changed
+    let mut show_board = true;
+    let mut lines = input.lines();
+
+    writeln!(output, "You play {:?}, help lists the commands", human)?;
+
+    loop {
+        let outcome = game.outcome();
+        if game.player() != human && !outcome.is_over() {
+            let reply = match strength {
+                Some(strength) => think_weaker(&game, &limits, strength, &mut random, table),
+                None => think(&game, &limits, table),
+            };
+            if let Some((reply, score, depth)) = reply {
+                writeln!(
+                    output,
+                    "RustyBait plays {} ({}, depth {})",
+                    reply.to_san(&game),
+                    evaluation(score, &game),
+                    depth
+                )?;
+                game.push_history(reply);
+            }
+            continue;
+        }
+
+        if show_board {
+            write_board(&mut output, &game, human, ascii)?;
+            if outcome.is_over() {
+                writeln!(output, "Game over: {}", outcome.description())?;
+            }
+            show_board = false;
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+
+        let Some(line) = lines.next() else {
+            break;
+        };
+        let line = line?;
+
+        match line.trim() {
+            "" => {}
+            "help" => writeln!(output, "{}", HELP)?,
+            "quit" | "exit" => break,
+            "ascii" => {
+                ascii = !ascii;
+                show_board = true;
+            }
+            "moves" => {
+                let mut moves: Vec<String> = game
+                    .legal_moves()
+                    .iter()
+                    .map(|_move| _move.to_san(&game))
+                    .collect();
+                moves.sort();
+                writeln!(output, "{}", moves.join(" "))?;
+            }
+            "undo" => {
+                // The engine replies right away, so both moves are taken back unless the
+                // game ended with the user's move
+                let plies = if game.player() == human { 2 } else { 1 };
+                let history = game.history();
+                if history.len() < plies {
+                    writeln!(output, "No move of yours to take back")?;
+                } else {
+                    let mut undone = game.initial_position();
+                    for &_move in &history[..history.len() - plies] {
+                        undone.push_history(_move);
+                    }
+                    game = undone;
+                    show_board = true;
+                }
+            }
+            "hint" if !outcome.is_over() => {
+                if let Some((hint, score, _)) = think(&game, &limits, table) {
+                    writeln!(
+                        output,
+                        "Hint: {} ({})",
+                        hint.to_san(&game),
+                        evaluation(score, &game)
+                    )?;
+                }
+            }
+            _ if outcome.is_over() => {
+                writeln!(output, "The game is over, undo a move or quit")?;
+            }
+            text => match uci::parse_move(text, &game) {
+                Ok(_move) => {
+                    game.push_history(_move);
+                    show_board = true;
+                }
+                Err(err) => writeln!(output, "{}, moves lists the legal ones", err)?,
+            },
+        }
+    }
+
+    let result = match game.outcome() {
+        outcome if outcome.is_over() => outcome.result(),
+        _ => GameResult::Unfinished,
+    };
+    let (white, black) = match human {
+        Player::White => ("You", "RustyBait"),
+        Player::Black => ("RustyBait", "You"),
+    };
+    writeln!(output)?;
+    writeln!(output, "{}", game.get_pgn(white, black, result, &[]))?;
+
+    Ok(())
+}
+
+fn think(
+    game: &Game,
+    limits: &SearchLimits,
+    table: &mut TranspositionTable,
+) -> Option<(Move, Score, u8)> {
+    search(
+        game,
+        limits,
+        table,
+        &AtomicBool::new(true),
+        DEFAULT_CONTEMPT,
+        |_| {},
+    )
+}
+
+/// The move of a search with the handicaps of `strength`, with the score and depth of its
+/// line
+fn think_weaker(
+    game: &Game,
+    limits: &SearchLimits,
+    strength: Strength,
+    random: &mut Random,
+    table: &mut TranspositionTable,
+) -> Option<(Move, Score, u8)> {
+    let mut lines = Vec::new();
+    let best = search(
+        game,
+        &strength.limit(limits.clone()),
+        table,
+        &AtomicBool::new(true),
+        DEFAULT_CONTEMPT,
+        |info| strength::record_line(&mut lines, info),
+    )?;
+
+    let chosen = strength.choose(&lines, random)?;
+    let line = lines.iter().find(|line| line.pv.first() == Some(&chosen));
+    Some(line.map_or(best, |line| (chosen, line.score, line.depth)))
+}
+
+/// A score of the player to move in pawns from the point of view of white, like in PGN
+fn evaluation(score: Score, game: &Game) -> String {
+    let score = wdl::normalize(score, game) * game.player() as Score;
+    match score::to_pgn(score) {
+        pawns if score > 0 && !score::is_mate(score) => format!("+{}", pawns),
+        text => text,
+    }
+}
+
+/// The board from the side of `human`, with the pieces as chess symbols or as the letters
+/// of FEN
+fn write_board(output: &mut impl Write, game: &Game, human: Player, ascii: bool) -> io::Result<()> {
+    let (rows, files) = match human {
+        Player::White => ([7, 6, 5, 4, 3, 2, 1, 0], "a b c d e f g h"),
+        Player::Black => ([0, 1, 2, 3, 4, 5, 6, 7], "h g f e d c b a"),
+    };
+
+    writeln!(output)?;
+    for row in rows {
+        write!(output, "{} ", row + 1)?;
+        for col in 0..8 {
+            let col = if human == Player::White { col } else { 7 - col };
+            let piece = game.get_position(Position::new_assert(row, col));
+            let symbol = piece.map_or(' ', |piece| {
+                if ascii {
+                    piece.as_char_ascii()
+                } else {
+                    piece.as_char()
+                }
+            });
+            write!(output, "|{}", symbol)?;
+        }
+        writeln!(output, "|")?;
+    }
+    writeln!(output, "   {}", files)?;
+    writeln!(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play_lines(lines: &str, human: Player) -> String {
+        play_level(lines, human, None)
+    }
+
+    fn play_level(lines: &str, human: Player, strength: Option<Strength>) -> String {
+        let mut table = TranspositionTable::default();
+        let mut output = Vec::new();
+        run(
+            lines.as_bytes(),
+            &mut output,
+            20,
+            human,
+            strength,
+            &mut table,
+        )
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn moves_and_commands() {
+        let output = play_lines("e4\nKe3\nundo\nundo\nascii\nd2d4\nquit\n", Player::White);
+        assert!(output.contains("Illegal move: Ke3"));
+        assert!(output.contains("No move of yours to take back"));
+        assert!(output.contains("|P|P|P|"));
+        assert!(output.contains("1. d4 "));
+        assert!(!output.contains("1. e4"));
+
+        let output = play_lines("hint\nmoves\n", Player::Black);
+        assert!(output.starts_with("You play Black"));
+        assert!(output.contains("RustyBait plays "));
+        assert!(output.contains("Hint: "));
+        assert!(output.contains("[White \"RustyBait\"]"));
+        assert!(output.contains("   h g f e d c b a"));
+
+        let output = play_level("e4\nd4\n", Player::White, Strength::of_level(1));
+        assert!(output.contains("depth 1)"));
+        assert!(!output.contains("depth 2)"));
+    }
+}
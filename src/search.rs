@@ -1,13 +1,296 @@
-use crate::chess::{move_struct::Move, Game, Score};
+use crate::{
+    chess::{
+        move_struct::{Move, MoveKind},
+        piece::PieceType,
+        score::{self, Score, DRAW, INFINITY},
+        Game, Player,
+    },
+    wdl::{self, Wdl},
+};
 use arrayvec::ArrayVec;
 use nohash_hasher::BuildNoHashHasher;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
 use std::{
+    cell::Cell,
     collections::HashMap,
     sync::atomic::{AtomicBool, Ordering::Relaxed},
+    thread,
+    time::Duration,
 };
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
 pub type TranspositionTable = HashMap<u64, TableEntry, BuildNoHashHasher<u64>>;
 
+/// Part of the time left on the clock used for a move, on top of the increment
+const FRACTION_OF_TOTAL_TIME: f64 = 0.02;
+/// Time lost between the GUI and the engine, left out of the time of every move
+const LATENCY_MS_COMPENSATE: u64 = 150;
+/// Time needed to finish the first depths, even when the clock is almost out
+const MIN_MOVE_TIME_MS: u64 = 20;
+/// Nodes visited between two checks of the time, which is slower to read than the count
+const TIME_CHECK_INTERVAL: u64 = 1024;
+/// Deepest depth of the iterative deepening, which sizes the table of killer moves indexed
+/// by the moves made since the root
+pub const MAX_PLY: u8 = 128;
+
+/// Node count and time at which the search of a thread stops, see `search`
+#[derive(Clone, Copy)]
+struct StopAt {
+    nodes: u64,
+    time: Option<Instant>,
+    next_time_check: u64,
+}
+
+impl StopAt {
+    const NEVER: Self = Self {
+        nodes: u64::MAX,
+        time: None,
+        next_time_check: u64::MAX,
+    };
+}
+
+/// Constants of the heuristics of the search, which can be changed at runtime to tune them
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SearchParams {
+    /// Depth skipped by the search after a null move, on top of the move itself
+    pub null_move_reduction: u8,
+    /// Remaining depth from which null moves are tried
+    pub null_move_depth: u8,
+    /// Moves searched with the full window, the later ones are first tried with a null
+    /// window around alpha
+    pub full_window_moves: usize,
+    /// Value which the history scores of the moves approach without reaching it
+    pub history_limit: u16,
+}
+
+impl SearchParams {
+    pub const DEFAULT: Self = Self {
+        null_move_reduction: 2,
+        null_move_depth: 3,
+        full_window_moves: 3,
+        history_limit: 10000,
+    };
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+thread_local! {
+    /// Positions visited by the searches of this thread, every search runs on a single thread
+    static NODES: Cell<u64> = const { Cell::new(0) };
+    static STOP_AT: Cell<StopAt> = const { Cell::new(StopAt::NEVER) };
+    static PARAMS: Cell<SearchParams> = const { Cell::new(SearchParams::DEFAULT) };
+}
+
+/// Replaces the parameters of the searches run by the current thread from now on, the
+/// other threads keep theirs
+pub fn set_params(params: SearchParams) {
+    PARAMS.with(|current| current.set(params));
+}
+
+/// Parameters of the searches of the current thread
+pub fn params() -> SearchParams {
+    PARAMS.with(Cell::get)
+}
+
+/// Called before every move played by the search, including null moves
+#[inline]
+fn count_node() {
+    NODES.with(|nodes| nodes.set(nodes.get() + 1));
+}
+
+/// Positions visited by the searches of the current thread since it started
+pub fn nodes() -> u64 {
+    NODES.with(Cell::get)
+}
+
+/// Whether the search of this thread reached the node or time limit of `search`
+fn is_limit_reached() -> bool {
+    STOP_AT.with(|stop_at| {
+        let mut limits = stop_at.get();
+        let nodes = nodes();
+        if nodes >= limits.nodes {
+            return true;
+        }
+        if nodes < limits.next_time_check {
+            return false;
+        }
+        limits.next_time_check = nodes + TIME_CHECK_INTERVAL;
+        stop_at.set(limits);
+        limits.time.is_some_and(|time| Instant::now() >= time)
+    })
+}
+
+/// What ends a search besides its `continue_running` flag, e.g.
+/// `SearchLimits::new().depth(12).move_time(Duration::from_secs(1))`
+///
+/// The search stops at the first limit it reaches, but always finishes its first depth
+/// so that there is a move to play. Without limits it goes on until the flag is cleared.
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    depth: Option<u8>,
+    nodes: Option<u64>,
+    move_time: Option<Duration>,
+    /// Time left and increment of each player
+    clocks: [Option<(Duration, Duration)>; 2],
+    mate: Option<u8>,
+    infinite: bool,
+    search_moves: Vec<Move>,
+    multi_pv: u8,
+}
+
+impl SearchLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Deepest depth searched, in half-moves
+    pub fn depth(mut self, depth: u8) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Positions visited after which the search stops
+    pub fn nodes(mut self, nodes: u64) -> Self {
+        self.nodes = Some(nodes);
+        self
+    }
+
+    /// Exact time of the search, which takes precedence over the clocks
+    pub fn move_time(mut self, move_time: Duration) -> Self {
+        self.move_time = Some(move_time);
+        self
+    }
+
+    /// Time left to `player` and added after each of their moves, from which the time
+    /// of the search is taken when `player` is to move
+    pub fn clock(mut self, player: Player, time: Duration, increment: Duration) -> Self {
+        self.clocks[player.as_index()] = Some((time, increment));
+        self
+    }
+
+    /// Looks for a mate in at most `moves` moves, which bounds the depth
+    pub fn mate(mut self, moves: u8) -> Self {
+        self.mate = Some(moves);
+        self
+    }
+
+    /// Ignores the time limits, the search only stops when its flag is cleared
+    pub fn infinite(mut self) -> Self {
+        self.infinite = true;
+        self
+    }
+
+    /// Only searches these moves of the position, all of them if it is empty
+    pub fn search_moves(mut self, moves: Vec<Move>) -> Self {
+        self.search_moves = moves;
+        self
+    }
+
+    /// Reports the `lines` best moves of every depth with their lines instead of only the
+    /// best one, each move after the first one taking another search of the depth
+    pub fn multi_pv(mut self, lines: u8) -> Self {
+        self.multi_pv = lines;
+        self
+    }
+
+    /// Caps the depth and the nodes, the limits which are already lower are kept
+    pub fn at_most(mut self, depth: u8, nodes: u64) -> Self {
+        self.depth = Some(self.depth.map_or(depth, |limit| limit.min(depth)));
+        self.nodes = Some(self.nodes.map_or(nodes, |limit| limit.min(nodes)));
+        self
+    }
+
+    /// Whether the search was asked to go on until it is stopped, like `go infinite`
+    pub fn is_infinite(&self) -> bool {
+        self.infinite
+    }
+
+    /// Whether only some of the moves of the position are searched
+    pub fn is_restricted(&self) -> bool {
+        !self.search_moves.is_empty()
+    }
+
+    /// Best lines reported after every depth, at least one
+    pub fn lines(&self) -> u8 {
+        self.multi_pv.max(1)
+    }
+
+    /// Whether nothing but clearing the flag stops the search, without it being asked
+    /// for with `infinite`
+    pub fn is_unlimited(&self) -> bool {
+        self.max_depth().is_none()
+            && self.nodes.is_none()
+            && self.move_time.is_none()
+            && self.clocks == [None; 2]
+            && !self.infinite
+    }
+
+    /// Deepest depth allowed by the depth and the mate limits
+    pub fn max_depth(&self) -> Option<u8> {
+        // The position after the mating move is searched too, to see it has no moves
+        let mate_depth = self.mate.map(|moves| moves.saturating_mul(2));
+        match (self.depth, mate_depth) {
+            (Some(depth), Some(mate_depth)) => Some(depth.min(mate_depth)),
+            (depth, mate_depth) => depth.or(mate_depth),
+        }
+    }
+
+    /// Time of the search when `player` is to move, if it has any time limit
+    pub fn time_for_move(&self, player: Player) -> Option<Duration> {
+        if self.infinite {
+            return None;
+        }
+
+        self.move_time.or_else(|| {
+            // Less time than there is is used, to be sure never to run out, but short
+            // time controls still need enough of it to finish the first depths
+            self.clocks[player.as_index()].map(|(time, increment)| {
+                let millis = ((time.as_millis() as f64 * FRACTION_OF_TOTAL_TIME) as u64
+                    + increment.as_millis() as u64)
+                    .saturating_sub(LATENCY_MS_COMPENSATE)
+                    .max(MIN_MOVE_TIME_MS);
+                Duration::from_millis(millis)
+            })
+        })
+    }
+}
+
+/// Progress of the search, given to the caller of `search` after every depth
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SearchInfo {
+    pub depth: u8,
+    /// Rank of the line among the best ones of the depth, 1 unless more lines were asked for
+    /// with `SearchLimits::multi_pv`
+    pub multipv: u8,
+    /// Score for the player to move, normalized with the win rate model
+    pub score: Score,
+    pub wdl: Wdl,
+    /// Positions visited by this search so far
+    pub nodes: u64,
+    /// Best line, starting with the best move
+    pub pv: Vec<Move>,
+}
+
+/// Error of a search stopped by clearing its `continue_running` flag before it
+/// finished the depth, whose result is then unknown
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SearchAborted;
+
+impl std::fmt::Display for SearchAborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Search stopped before the end of the depth")
+    }
+}
+
+impl std::error::Error for SearchAborted {}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum NodeType {
     Exact,
@@ -33,6 +316,7 @@ pub struct TableEntry {
 /// 6. Castling
 /// 7. Quiet moves by history heuristic
 fn move_score(
+    game: &Game,
     _move: Move,
     pv_move: Option<Move>,
     killer_move: Option<Move>,
@@ -46,57 +330,52 @@ fn move_score(
         return 1;
     }
 
-    match _move {
-        Move::Promotion { new_piece, .. } => 9 - new_piece.material_value() as u32 + 2,
-        Move::EnPassant { .. } => 12,
-        Move::CastlingLong { .. } => 100000,
-        Move::CastlingShort { .. } => 100000,
-        Move::Normal {
-            captured_piece: capture,
-            piece,
-            ..
-        } => {
-            if let Some(captured_piece) = capture {
-                1000 + piece.material_value() as u32 - captured_piece.material_value() as u32
+    match _move.kind() {
+        MoveKind::Promotion(new_piece) => 9 - new_piece.material_value() as u32 + 2,
+        MoveKind::EnPassant => 12,
+        MoveKind::Castling(_) => 100000,
+        MoveKind::Normal => {
+            if let Some(captured_piece) = _move.captured_piece(game) {
+                1000 + _move.piece(game).material_value() as u32
+                    - captured_piece.material_value() as u32
             } else {
-                10000000 - history[_move.index_history().unwrap()] as u32
+                10000000 - history[_move.index_history(game).unwrap()] as u32
             }
         }
     }
 }
 
-fn quiescence_search(game: &mut Game, mut alpha: Score, beta: Score, real_depth: u8) -> Score {
-    let current_score = game.score() * (game.player() as Score);
+/// `draw_score` is the score of a draw for the player to move, see `get_best_move_entry`
+fn quiescence_search(
+    game: &mut Game,
+    mut alpha: Score,
+    beta: Score,
+    real_depth: u8,
+    draw_score: Score,
+) -> Score {
+    let current_score = game.evaluate_lazy(alpha, beta);
     alpha = alpha.max(current_score);
 
     if alpha >= beta {
         return beta;
     }
 
-    let player = game.player();
     let mut moves = ArrayVec::new();
-
-    // It is possible for the game to be a stalemate, but be recognized as a checkmate
-    // Because we don't validate the king's moves there due to performance reasons
-    game.get_moves(&mut moves, false);
+    game.get_moves(&mut moves);
     if moves.is_empty() {
-        if game.king_exists(player) && !game.is_targeted(game.get_king_position(player), player) {
-            return 0;
-        } else {
-            // The earlier the mate the worse the score for the losing player
-            // This is not a real mate, so it's score reflects that
-            return Score::MIN + 3000 + real_depth as Score;
-        }
+        return no_moves_score(game, real_depth, draw_score);
     }
 
     for &_move in &moves {
-        if !_move.is_tactical_move() {
+        if !_move.is_tactical_move(game) {
             continue;
         }
 
+        count_node();
+
         game.push(_move);
-        let score = -quiescence_search(game, -beta, -alpha, real_depth + 1);
-        game.pop(_move);
+        let score = -quiescence_search(game, -beta, -alpha, real_depth + 1, -draw_score);
+        game.pop();
 
         if score > alpha {
             alpha = score;
@@ -110,6 +389,16 @@ fn quiescence_search(game: &mut Game, mut alpha: Score, beta: Score, real_depth:
     alpha
 }
 
+/// Score of a position without legal moves, a checkmate or a stalemate
+fn no_moves_score(game: &Game, real_depth: u8, draw_score: Score) -> Score {
+    if game.is_check() {
+        // The earlier the mate the worse the score for the losing player
+        score::mated_in(real_depth as Score)
+    } else {
+        draw_score
+    }
+}
+
 /// This function exists in order to improve the performance of the search algorithm
 /// It's the same as get_best_move_score but with a depth always equal to 1
 ///
@@ -120,25 +409,20 @@ fn get_best_move_score_depth_1(
     mut alpha: Score,
     beta: Score,
     real_depth: u8,
+    draw_score: Score,
 ) -> Score {
-    let player = game.player();
     let mut moves = ArrayVec::new();
-    game.get_moves(&mut moves, false);
+    game.get_moves(&mut moves);
 
     if moves.is_empty() {
-        if game.king_exists(player) && !game.is_targeted(game.get_king_position(player), player) {
-            return 0;
-        } else {
-            // The earlier the mate the worse the score for the losing player
-            // This is not a real mate, so it's score reflects that
-            return Score::MIN + 2000 + real_depth as Score;
-        }
+        return no_moves_score(game, real_depth, draw_score);
     }
 
     for &_move in &moves {
+        count_node();
         game.push(_move);
-        let score = -quiescence_search(game, -beta, -alpha, real_depth + 1);
-        game.pop(_move);
+        let score = -quiescence_search(game, -beta, -alpha, real_depth + 1, -draw_score);
+        game.pop();
 
         if score > alpha {
             alpha = score;
@@ -153,8 +437,10 @@ fn get_best_move_score_depth_1(
 }
 
 /// Core function of the alpha beta search algorithm
-/// It halts early and returns None if the should_stop flag is set
+/// It halts early with `SearchAborted` once `continue_running` is cleared or a limit of
+/// `search` is reached
 /// Otherwise returns the best score for the current player
+#[allow(clippy::too_many_arguments)]
 fn get_best_move_score(
     game: &mut Game,
     table: &mut TranspositionTable,
@@ -163,12 +449,21 @@ fn get_best_move_score(
     real_depth: u8,                // Moves made since root of the search tree
     mut alpha: Score,
     beta: Score,
+    draw_score: Score, // Score of a draw for the player to move
     killer_moves: &mut [Option<Move>],
     history: &mut [u16; 64 * 12],
-) -> Option<Score> {
+) -> Result<Score, SearchAborted> {
+    if is_limit_reached() {
+        continue_running.store(false, Relaxed);
+    }
     if !continue_running.load(Relaxed) {
         // Halt the search early
-        return None;
+        return Err(SearchAborted);
+    }
+
+    // A position repeated once is scored as a draw, since it can be repeated again
+    if game.is_repetition() || game.is_fifty_move_draw() || game.is_insufficient_material() {
+        return Ok(draw_score);
     }
 
     let initial_alpha = alpha;
@@ -179,16 +474,16 @@ fn get_best_move_score(
         if entry.depth >= remaining_depth {
             match entry.flag {
                 NodeType::Exact => {
-                    return Some(entry.score);
+                    return Ok(entry.score);
                 }
                 NodeType::LowerBound => {
                     if entry.score >= beta {
-                        return Some(entry.score);
+                        return Ok(entry.score);
                     }
                 }
                 NodeType::UpperBound => {
                     if entry.score <= alpha {
-                        return Some(entry.score);
+                        return Ok(entry.score);
                     }
                 }
             }
@@ -197,33 +492,69 @@ fn get_best_move_score(
     }
 
     if remaining_depth == 1 {
-        return Some(get_best_move_score_depth_1(game, alpha, beta, real_depth));
+        return Ok(get_best_move_score_depth_1(
+            game, alpha, beta, real_depth, draw_score,
+        ));
     } else if remaining_depth == 0 {
-        return Some(quiescence_search(game, alpha, beta, real_depth));
+        return Ok(quiescence_search(game, alpha, beta, real_depth, draw_score));
     }
 
+    // Null move pruning: if passing the turn still fails high, some move will too.
+    // It fails in zugzwang, which is only likely when the player has no pieces but pawns
+    let params = params();
     let player = game.player();
+    let has_pieces = game.occupied_by(player)
+        & !(game.pieces(PieceType::Pawn) | game.pieces(PieceType::King))
+        != 0;
+    if remaining_depth >= params.null_move_depth
+        && beta - alpha == 1
+        && has_pieces
+        && !game.is_check()
+    {
+        count_node();
+        game.push_null();
+        let score = -get_best_move_score(
+            game,
+            table,
+            continue_running,
+            (remaining_depth - 1).saturating_sub(params.null_move_reduction),
+            real_depth + 1,
+            -beta,
+            -beta + 1,
+            -draw_score,
+            killer_moves,
+            history,
+        )?;
+        game.pop_null();
+
+        if score >= beta {
+            return Ok(beta);
+        }
+    }
+
     let mut moves = ArrayVec::new();
-    game.get_moves(&mut moves, true);
+    game.get_moves(&mut moves);
 
     if moves.is_empty() {
-        if game.king_exists(player) && !game.is_targeted(game.get_king_position(player), player) {
-            return Some(0);
-        } else {
-            // The earlier the mate the worse the score for the losing player
-            return Some(Score::MIN + 100 + real_depth as Score);
-        }
+        return Ok(no_moves_score(game, real_depth, draw_score));
     }
 
     moves.sort_by_cached_key(|a| {
-        move_score(*a, pv_move, killer_moves[real_depth as usize], history)
+        move_score(
+            game,
+            *a,
+            pv_move,
+            killer_moves[real_depth as usize],
+            history,
+        )
     });
 
     let mut best_move = None;
-    let mut best_score = Score::MIN;
+    let mut best_score = -INFINITY;
 
     for (index, &_move) in moves.iter().enumerate() {
-        if index <= 2 {
+        if index < params.full_window_moves {
+            count_node();
             game.push(_move);
             let score = -get_best_move_score(
                 game,
@@ -233,10 +564,11 @@ fn get_best_move_score(
                 real_depth + 1,
                 -beta,
                 -alpha,
+                -draw_score,
                 killer_moves,
                 history,
             )?;
-            game.pop(_move);
+            game.pop();
 
             if score > best_score {
                 best_score = score;
@@ -245,6 +577,7 @@ fn get_best_move_score(
 
             alpha = alpha.max(score);
         } else {
+            count_node();
             game.push(_move);
 
             let test_score = -get_best_move_score(
@@ -255,12 +588,14 @@ fn get_best_move_score(
                 real_depth + 1,
                 -alpha - 1,
                 -alpha,
+                -draw_score,
                 killer_moves,
                 history,
             )?;
-            game.pop(_move);
+            game.pop();
 
             if test_score > best_score {
+                count_node();
                 game.push(_move);
                 let score = -get_best_move_score(
                     game,
@@ -270,10 +605,11 @@ fn get_best_move_score(
                     real_depth + 1,
                     -beta,
                     -test_score,
+                    -draw_score,
                     killer_moves,
                     history,
                 )?;
-                game.pop(_move);
+                game.pop();
 
                 best_move = Some(_move);
                 best_score = score;
@@ -283,10 +619,12 @@ fn get_best_move_score(
 
         if alpha >= beta {
             killer_moves[real_depth as usize] = Some(_move);
-            if let Some(index) = _move.index_history() {
+            if let Some(index) = _move.index_history(game) {
                 let bonus = (remaining_depth as f64).powf(3.0);
-                let real_bonus = bonus * (1.0 - history[index] as f64 / 10000.0);
-                history[index] += real_bonus as u16;
+                let real_bonus =
+                    bonus * (1.0 - history[index] as f64 / params.history_limit as f64);
+                // With a limit near u16::MAX the bonus of a deep cutoff could overflow
+                history[index] = history[index].saturating_add(real_bonus as u16);
             }
             break;
         }
@@ -316,56 +654,73 @@ fn get_best_move_score(
         })
         .or_insert(new_entry);
 
-    Some(alpha)
+    Ok(alpha)
 }
 
 /// This function is the entry point for the search algorithm
 /// It returns the best move, the score of the best move
-/// and a flag indicating if there is only one move available
+/// and a flag indicating if there is only one move available,
+/// or `SearchAborted` if `continue_running` was cleared before the end of the depth
+///
+/// `contempt` is how much worse than an equal position a draw is considered by the
+/// player to move, a negative contempt makes the engine look for draws
+///
+/// Only the moves of `search_moves` are searched, unless it is empty.
 pub fn get_best_move_entry(
     mut game: Game,
     continue_running: &AtomicBool,
     depth: u8,
     table: &mut TranspositionTable,
     history: &mut [u16; 64 * 12],
-) -> Option<(Option<Move>, Score, bool)> {
+    contempt: Score,
+    search_moves: &[Move],
+) -> Result<(Option<Move>, Score, bool), SearchAborted> {
+    // The killer moves are indexed by the moves made since the root, and the root itself
+    // is always searched to find its best move
+    let depth = depth.clamp(1, MAX_PLY);
     let mut moves = ArrayVec::new();
-    game.get_moves(&mut moves, true);
-
-    // If there is only one move available don't bother searching
-    if moves.len() == 1 {
-        return Some((moves.first().copied(), 0, true));
+    game.get_moves(&mut moves);
+    // The table holds the results of the search of every move, which aren't used then
+    let is_restricted = !search_moves.is_empty();
+    if is_restricted {
+        moves.retain(|_move| search_moves.contains(_move));
     }
 
-    let mut killer_moves = [None; 32];
-    let mut best_move = None;
-    let mut best_score = Score::MIN + 1;
+    let draw_score = DRAW - contempt;
+    let params = params();
 
-    // Prevent threefold repetition
-    if game.move_stack().len() >= 5
-        && game.move_stack()[game.move_stack().len() - 1]
-            == game.move_stack()[game.move_stack().len() - 5]
-    {
-        let repetition_move = game.move_stack()[game.move_stack().len() - 4];
+    // Checkmate or stalemate at the root, or none of `search_moves` is legal
+    if moves.is_empty() {
+        let score = if game.is_check() {
+            score::mated_in(0)
+        } else {
+            draw_score
+        };
+        return Ok((None, score, true));
+    }
 
-        for (index, &_move) in moves.iter().enumerate() {
-            if repetition_move == _move {
-                moves.swap_remove(index);
-                break;
-            }
-        }
+    // If there is only one move available don't bother searching, unless the caller wants
+    // to know the score of that move
+    if moves.len() == 1 && !is_restricted {
+        return Ok((moves.first().copied(), 0, true));
     }
-    if let Some(entry) = table.get(&game.hash()) {
+
+    let mut killer_moves = [None; MAX_PLY as usize];
+    let mut best_move = None;
+    let mut best_score = -INFINITY;
+
+    if let Some(entry) = table.get(&game.hash()).filter(|_| !is_restricted) {
         if entry.depth >= depth && entry.flag == NodeType::Exact {
-            return Some((entry.pv, entry.score, false));
+            return Ok((entry.pv, entry.score, false));
         }
     }
 
     let pv_move = table.get(&game.hash()).and_then(|entry| entry.pv);
-    moves.sort_by_cached_key(|a| move_score(*a, pv_move, None, history));
+    moves.sort_by_cached_key(|a| move_score(&game, *a, pv_move, None, history));
 
     for (index, &_move) in moves.iter().enumerate() {
-        if index <= 2 {
+        if index < params.full_window_moves {
+            count_node();
             game.push(_move);
             let score = -get_best_move_score(
                 &mut game,
@@ -373,18 +728,20 @@ pub fn get_best_move_entry(
                 continue_running,
                 depth - 1,
                 1,
-                Score::MIN + 1,
+                -INFINITY,
                 -best_score,
+                -draw_score,
                 &mut killer_moves,
                 history,
             )?;
-            game.pop(_move);
+            game.pop();
 
             if score > best_score {
                 best_score = score;
                 best_move = Some(_move);
             }
         } else {
+            count_node();
             game.push(_move);
 
             let score = -get_best_move_score(
@@ -395,12 +752,14 @@ pub fn get_best_move_entry(
                 1,
                 -best_score - 1,
                 -best_score,
+                -draw_score,
                 &mut killer_moves,
                 history,
             )?;
-            game.pop(_move);
+            game.pop();
 
             if score > best_score {
+                count_node();
                 game.push(_move);
                 let score2 = -get_best_move_score(
                     &mut game,
@@ -408,12 +767,13 @@ pub fn get_best_move_entry(
                     continue_running,
                     depth - 1,
                     1,
-                    Score::MIN + 1,
+                    -INFINITY,
                     -score,
+                    -draw_score,
                     &mut killer_moves,
                     history,
                 )?;
-                game.pop(_move);
+                game.pop();
 
                 best_score = score2;
                 best_move = Some(_move);
@@ -428,81 +788,375 @@ pub fn get_best_move_entry(
         flag: NodeType::Exact,
     };
 
-    table
-        .entry(game.hash())
-        .and_modify(|entry| {
-            if entry.depth <= depth {
-                *entry = new_entry;
-            }
-        })
-        .or_insert(new_entry);
+    if !is_restricted {
+        table
+            .entry(game.hash())
+            .and_modify(|entry| {
+                if entry.depth <= depth {
+                    *entry = new_entry;
+                }
+            })
+            .or_insert(new_entry);
+    }
+
+    Ok((best_move, best_score, false))
+}
+
+/// `best_move` followed by the best moves stored in the table from the position after it,
+/// for up to `depth` moves
+fn principal_variation(
+    game: &Game,
+    best_move: Move,
+    table: &TranspositionTable,
+    depth: u8,
+) -> Vec<Move> {
+    let mut pv = Vec::with_capacity(depth as usize);
+    let mut game = game.clone();
+    game.push(best_move);
+    pv.push(best_move);
+
+    while pv.len() < depth as usize {
+        // A move stored for another position with the same hash may not be legal
+        let Some(_move) = table
+            .get(&game.hash())
+            .and_then(|entry| entry.pv)
+            .filter(|&_move| game.is_legal(_move))
+        else {
+            break;
+        };
+        game.push(_move);
+        pv.push(_move);
+    }
 
-    Some((best_move, best_score, false))
+    pv
 }
 
-/// This function repeatedly calls get_best_move with increasing depth,
-/// until `continue_running` is set to false, at which point it returns the best move found so far
-pub fn get_best_move_until_stop(
+/// Searches `game` with increasing depth until a limit is reached or `continue_running`
+/// is set to false, at which point it returns the best move found so far along with its
+/// score for the side to move and the depth it was found at
+///
+/// `on_info` is called after every depth, e.g. to print it or to send it to another thread,
+/// and after every other line of the depth with `SearchLimits::multi_pv`. The flag is
+/// cleared once the node or time limit is reached.
+pub fn search(
     game: &Game,
+    limits: &SearchLimits,
     table: &mut TranspositionTable,
     continue_running: &AtomicBool,
-    max_depth: Option<u8>,
-) -> Option<Move> {
-    let mut found_move = None;
+    contempt: Score,
+    mut on_info: impl FnMut(&SearchInfo),
+) -> Option<(Move, Score, u8)> {
+    let start_nodes = nodes();
+    let start_time = Instant::now();
+    let time = limits
+        .time_for_move(game.player())
+        .map(|time| start_time + time);
+    let stop_at = StopAt {
+        nodes: limits
+            .nodes
+            .map_or(u64::MAX, |nodes| start_nodes.saturating_add(nodes)),
+        time,
+        next_time_check: if time.is_some() {
+            start_nodes
+        } else {
+            u64::MAX
+        },
+    };
+    // A depth of 0, as in `go depth 0` or `go mate 0`, still gives a move to play
+    let max_depth = limits.max_depth().unwrap_or(MAX_PLY).clamp(1, MAX_PLY);
 
+    let mut found_move = None;
     let mut history = [0; 64 * 12];
 
     let starting_depth = table
         .get(&game.hash())
-        .map(|entry| {
-            if entry.flag == NodeType::Exact {
-                entry.depth
-            } else {
-                1
-            }
-        })
-        .unwrap_or(1);
+        .filter(|entry| entry.flag == NodeType::Exact && limits.search_moves.is_empty())
+        .map_or(1, |entry| entry.depth)
+        .clamp(1, max_depth);
 
-    for depth in starting_depth.. {
-        let Some((best_move, best_score, is_only_move)) =
-            get_best_move_entry(game.clone(), continue_running, depth, table, &mut history)
-        else {
-            return found_move;
+    for depth in starting_depth..=max_depth {
+        let Ok((best_move, best_score, is_only_move)) = get_best_move_entry(
+            game.clone(),
+            continue_running,
+            depth,
+            table,
+            &mut history,
+            contempt,
+            &limits.search_moves,
+        ) else {
+            break;
         };
 
-        let mut hash = game.hash();
-        let mut game_clone = game.clone();
-
-        found_move = best_move;
-
-        println!("info depth {}", depth);
-        println!("info score cp {}", best_score);
-        println!("info nodes {}", table.len());
-        print!("info pv ");
-        for _ in 0..depth {
-            if let Some(entry) = table.get(&hash) {
-                if let Some(pv) = entry.pv {
-                    game_clone.push(pv);
-                    print!("{} ", pv.uci_notation());
-                    hash = game_clone.hash();
-                } else {
+        found_move = best_move.map(|best_move| (best_move, best_score, depth));
+        if limits.lines() == 1 {
+            // The first depth gives a move to play, the next ones may be cut
+            STOP_AT.with(|cell| cell.set(stop_at));
+        }
+
+        on_info(&SearchInfo {
+            depth,
+            multipv: 1,
+            score: wdl::normalize(best_score, game),
+            wdl: Wdl::new(best_score, game),
+            nodes: nodes() - start_nodes,
+            pv: best_move.map_or_else(Vec::new, |best_move| {
+                principal_variation(game, best_move, table, depth)
+            }),
+        });
+
+        // The next lines are the best ones without the first moves of the better lines
+        if limits.lines() > 1 {
+            let mut candidates: Vec<Move> = if limits.search_moves.is_empty() {
+                game.legal_moves().to_vec()
+            } else {
+                limits.search_moves.clone()
+            };
+            candidates.retain(|&_move| Some(_move) != best_move);
+
+            let mut aborted = false;
+            for multipv in 2..=limits.lines() {
+                if candidates.is_empty() {
                     break;
                 }
-            } else {
+                let Ok((Some(line_move), line_score, _)) = get_best_move_entry(
+                    game.clone(),
+                    continue_running,
+                    depth,
+                    table,
+                    &mut history,
+                    contempt,
+                    &candidates,
+                ) else {
+                    aborted = true;
+                    break;
+                };
+                candidates.retain(|&_move| _move != line_move);
+
+                on_info(&SearchInfo {
+                    depth,
+                    multipv,
+                    score: wdl::normalize(line_score, game),
+                    wdl: Wdl::new(line_score, game),
+                    nodes: nodes() - start_nodes,
+                    pv: principal_variation(game, line_move, table, depth),
+                });
+            }
+            if aborted {
                 break;
             }
+            // Every line of the first depth is finished too, for a choice among them
+            STOP_AT.with(|cell| cell.set(stop_at));
+        }
+
+        // If mate can be forced, or there is only a single move available, stop searching,
+        // unless the search was asked to go on until it is stopped
+        if (is_only_move || score::is_mate(best_score)) && !limits.is_infinite() {
+            break;
         }
-        println!();
-
-        // If mate can be forced, or there is only a single move available, stop searching
-        if max_depth.is_some_and(|d| d == depth)
-            || is_only_move
-            || best_score > Score::MAX - 1000
-            || best_score < Score::MIN + 1000
-        {
-            return found_move;
+    }
+
+    // An infinite search with nothing left to search waits to be stopped, like `go infinite`
+    // requires before the best move is sent
+    if limits.is_infinite() {
+        while continue_running.load(Relaxed) {
+            thread::sleep(Duration::from_millis(1));
         }
     }
 
-    unreachable!()
+    STOP_AT.with(|cell| cell.set(StopAt::NEVER));
+    found_move
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search_with(
+        game: &Game,
+        limits: &SearchLimits,
+    ) -> (Option<(Move, Score, u8)>, Vec<SearchInfo>) {
+        let mut infos = Vec::new();
+        let result = search(
+            game,
+            limits,
+            &mut TranspositionTable::default(),
+            &AtomicBool::new(true),
+            0,
+            |info| infos.push(info.clone()),
+        );
+        (result, infos)
+    }
+
+    #[test]
+    fn infinite_until_stopped() {
+        // Mate in one, and a single legal move
+        for fen in [
+            "6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1",
+            "k7/8/8/8/8/8/8/1R5K b - - 0 1",
+        ] {
+            let game = Game::new(fen).unwrap();
+            let running = AtomicBool::new(true);
+            let start = Instant::now();
+            let result = thread::scope(|scope| {
+                scope.spawn(|| {
+                    thread::sleep(Duration::from_millis(200));
+                    running.store(false, Relaxed);
+                });
+                search(
+                    &game,
+                    &SearchLimits::new().infinite(),
+                    &mut TranspositionTable::default(),
+                    &running,
+                    0,
+                    |_| {},
+                )
+            });
+            assert!(start.elapsed() >= Duration::from_millis(200), "{}", fen);
+            assert!(result.is_some(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn info_after_every_depth() {
+        let game = Game::default();
+        let (result, infos) = search_with(&game, &SearchLimits::new().depth(4));
+        let (best_move, score, depth) = result.unwrap();
+
+        assert_eq!(depth, 4);
+        let depths: Vec<u8> = infos.iter().map(|info| info.depth).collect();
+        assert_eq!(depths, [1, 2, 3, 4]);
+        let last = infos.last().unwrap();
+        assert_eq!(last.pv.first(), Some(&best_move));
+        assert_eq!(last.score, wdl::normalize(score, &game));
+        assert!(infos.windows(2).all(|pair| pair[0].nodes <= pair[1].nodes));
+    }
+
+    #[test]
+    fn limits() {
+        let game = Game::default();
+
+        // The first depth is always finished
+        let (result, infos) = search_with(&game, &SearchLimits::new().nodes(1));
+        assert_eq!(result.unwrap().2, 1);
+        assert_eq!(infos.len(), 1);
+
+        let (result, infos) = search_with(&game, &SearchLimits::new().nodes(5000));
+        assert!(infos.last().unwrap().nodes < 10000);
+        assert!(result.unwrap().2 < 10);
+
+        let (result, _) = search_with(&game, &SearchLimits::new().move_time(Duration::ZERO));
+        assert_eq!(result.unwrap().2, 1);
+
+        // Like `go depth 0` and `go mate 0`
+        let (result, infos) = search_with(&game, &SearchLimits::new().depth(0));
+        assert_eq!(result.unwrap().2, 1);
+        assert_eq!(infos.len(), 1);
+        let (result, _) = search_with(&game, &SearchLimits::new().mate(0));
+        assert_eq!(result.unwrap().2, 1);
+
+        let e4 = Move::from_uci_notation("e2e4", &game).unwrap();
+        let h3 = Move::from_uci_notation("h2h3", &game).unwrap();
+        let limits = SearchLimits::new().depth(3).search_moves(vec![h3, e4]);
+        let (result, infos) = search_with(&game, &limits);
+        assert_eq!(result.unwrap().0, e4);
+        assert!(infos.iter().all(|info| [e4, h3].contains(&info.pv[0])));
+
+        let mate = Game::new("6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1").unwrap();
+        let (result, _) = search_with(&mate, &SearchLimits::new().mate(1));
+        let (best_move, score, depth) = result.unwrap();
+        assert_eq!(best_move.uci_notation(&mate), "e1e8");
+        assert!(score::is_mate(score));
+        assert!(depth <= 2);
+
+        // The mated side has no move and no line, and its score is a mate in 0
+        let mated = Game::new("4R1k1/5ppp/8/8/8/8/8/6K1 b - - 1 1").unwrap();
+        let (result, infos) = search_with(&mated, &SearchLimits::new().depth(3));
+        assert!(result.is_none());
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].score, score::mated_in(0));
+        assert_eq!(score::to_uci(infos[0].score), "mate 0");
+        assert!(infos[0].pv.is_empty());
+
+        // Deep searches of locked positions go past the plies of the killer moves
+        let locked = Game::new("8/8/1k6/p1p1p1p1/P1P1P1P1/8/1K6/8 w - - 0 1").unwrap();
+        let (result, _) = search_with(&locked, &SearchLimits::new().depth(60));
+        assert_eq!(result.unwrap().2, 60);
+        let (result, _) = search_with(&locked, &SearchLimits::new().depth(255));
+        assert_eq!(result.unwrap().2, MAX_PLY);
+
+        let stalemate = Game::new("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        let (_, infos) = search_with(&stalemate, &SearchLimits::new().depth(3));
+        assert_eq!(infos[0].score, 0);
+    }
+
+    #[test]
+    fn multiple_lines() {
+        let game = Game::default();
+        let (result, infos) = search_with(&game, &SearchLimits::new().depth(3).multi_pv(3));
+
+        let lines: Vec<(u8, u8)> = infos
+            .iter()
+            .map(|info| (info.depth, info.multipv))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (1, 1),
+                (1, 2),
+                (1, 3),
+                (2, 1),
+                (2, 2),
+                (2, 3),
+                (3, 1),
+                (3, 2),
+                (3, 3)
+            ]
+        );
+        let last_depth = &infos[6..];
+        assert_eq!(last_depth[0].pv[0], result.unwrap().0);
+        assert_ne!(last_depth[0].pv[0], last_depth[1].pv[0]);
+        assert_ne!(last_depth[1].pv[0], last_depth[2].pv[0]);
+        assert_ne!(last_depth[0].pv[0], last_depth[2].pv[0]);
+
+        // The limits don't cut the lines of the first depth
+        let (_, infos) = search_with(&game, &SearchLimits::new().nodes(1).multi_pv(3));
+        let lines: Vec<(u8, u8)> = infos
+            .iter()
+            .map(|info| (info.depth, info.multipv))
+            .collect();
+        assert_eq!(lines, [(1, 1), (1, 2), (1, 3)]);
+
+        // No more lines than moves
+        let e4 = Move::from_uci_notation("e2e4", &game).unwrap();
+        let limits = SearchLimits::new()
+            .depth(2)
+            .multi_pv(3)
+            .search_moves(vec![e4]);
+        let (_, infos) = search_with(&game, &limits);
+        assert!(infos
+            .iter()
+            .all(|info| info.multipv == 1 && info.pv[0] == e4));
+    }
+
+    #[test]
+    fn time_for_move() {
+        let millis = Duration::from_millis;
+        let limits = SearchLimits::new()
+            .clock(Player::White, millis(60000), millis(1000))
+            .clock(Player::Black, millis(100), millis(0));
+        assert_eq!(limits.time_for_move(Player::White), Some(millis(2050)));
+        assert_eq!(limits.time_for_move(Player::Black), Some(millis(20)));
+
+        let limits = limits.move_time(millis(500));
+        assert_eq!(limits.time_for_move(Player::Black), Some(millis(500)));
+        assert_eq!(limits.infinite().time_for_move(Player::White), None);
+
+        assert_eq!(SearchLimits::new().depth(9).mate(3).max_depth(), Some(6));
+        assert_eq!(SearchLimits::new().max_depth(), None);
+        assert_eq!(
+            SearchLimits::new().depth(3).at_most(5, 10).max_depth(),
+            Some(3)
+        );
+        assert_eq!(SearchLimits::new().at_most(5, 10).max_depth(), Some(5));
+    }
 }
@@ -0,0 +1,412 @@
+use crate::{
+    analysis::JsonReport,
+    chess::Game,
+    engine::{Engine, EngineEvent},
+    json::Message,
+    log,
+    search::{SearchInfo, SearchLimits},
+    uci,
+};
+use anyhow::{anyhow, bail};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    io::Read,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use tiny_http::{Header, Method, Request, Response};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, WebSocket};
+
+/// Body of `POST /position`: the FEN of the start position, the standard one if there is
+/// none, and the moves played from it in UCI notation or SAN
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PositionRequest {
+    fen: Option<String>,
+    moves: Vec<String>,
+}
+
+/// Body of `POST /analysis` and `POST /bestmove`, the limits are ignored by the analysis,
+/// which runs until it is stopped
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SearchRequest {
+    depth: Option<u8>,
+    nodes: Option<u64>,
+    /// Time of the search in ms
+    movetime: Option<u64>,
+    /// Best lines reported after every depth, 1 by default
+    lines: Option<u8>,
+}
+
+/// Best move of a search in UCI notation, null when the game is over, with the last report
+/// of every line
+type BestMove = (Option<String>, Vec<JsonReport>);
+
+/// A search started by the server whose best move hasn't been sent yet
+struct Search {
+    /// Position searched, in which the moves of its events are written
+    game: Game,
+    /// Request waiting for the best move
+    waiting: Option<Sender<BestMove>>,
+    /// Last report of every line so far, by rank
+    lines: Vec<SearchInfo>,
+}
+
+/// Engine shared by the requests, with the searches and the WebSocket clients waiting for
+/// its events
+struct State {
+    engine: Mutex<Engine>,
+    /// Searches in the order they were started, the events are of the first one
+    searches: Mutex<VecDeque<Search>>,
+    /// Every event is sent as a JSON line to each of these, until their receiver is gone
+    clients: Mutex<Vec<Sender<String>>>,
+}
+
+/// Serves the analysis of the engine over HTTP on `address`, e.g. `127.0.0.1:8080`, for
+/// web front ends. Requests and answers are JSON:
+///
+/// - `GET /position` returns the position, as printed by `analyze`
+/// - `POST /position` with `{"fen": ..., "moves": [...]}` sets it, both may be left out
+/// - `POST /analysis` with `{"lines": 3}` starts analyzing it until it is stopped
+/// - `DELETE /analysis` stops the analysis and returns its best move
+/// - `POST /bestmove` with `depth`, `nodes` or `movetime` in ms searches within these
+///   limits and returns the best move with the last report of every line
+/// - `GET /events` is a WebSocket sending the reports and the best move of every search
+///   as the JSON lines of `--json`
+///
+/// A new position or search stops the one running, whose best move is sent as usual.
+pub fn serve(address: &str, hash_megabytes: usize) -> anyhow::Result<()> {
+    let (engine, events) = Engine::with_hash(hash_megabytes)?;
+    let state = Arc::new(State::new(engine));
+    {
+        let state = state.clone();
+        thread::spawn(move || state.dispatch(events));
+    }
+
+    let server = tiny_http::Server::http(address)
+        .map_err(|err| anyhow!("Failed to listen on {}: {}", address, err))?;
+    println!("Listening on http://{}", server.server_addr());
+
+    // Searching for a best move blocks its request, every request has its own thread
+    for request in server.incoming_requests() {
+        let state = state.clone();
+        thread::spawn(move || handle_request(&state, request));
+    }
+
+    Ok(())
+}
+
+fn handle_request(state: &State, mut request: Request) {
+    log::debug!("{} {}", request.method(), request.url());
+    let path = request
+        .url()
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+
+    if path == "/events" {
+        return stream_events(state, request);
+    }
+    // Preflight of the browsers before they send JSON to another origin
+    if *request.method() == Method::Options {
+        let response = Response::empty(204)
+            .with_header(header("Access-Control-Allow-Methods", "GET, POST, DELETE"))
+            .with_header(header("Access-Control-Allow-Headers", "Content-Type"));
+        let _ = request.respond(with_cors(response));
+        return;
+    }
+
+    let mut body = String::new();
+    let (status, json) = match request.as_reader().read_to_string(&mut body) {
+        Ok(_) => state.respond(request.method(), &path, &body),
+        Err(err) => (400, error_json(&format!("Failed to read request: {}", err))),
+    };
+    let response = Response::from_string(json)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"));
+    let _ = request.respond(with_cors(response));
+}
+
+/// Upgrades the request to a WebSocket and sends it the events until it is closed
+fn stream_events(state: &State, request: Request) {
+    let key = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+        .map(|header| derive_accept_key(header.value.as_bytes()));
+    let Some(accept) = key else {
+        let response = Response::from_string(error_json("WebSocket connection expected"))
+            .with_status_code(400)
+            .with_header(header("Content-Type", "application/json"));
+        let _ = request.respond(with_cors(response));
+        return;
+    };
+
+    let response = Response::empty(101).with_header(header("Sec-WebSocket-Accept", &accept));
+    let stream = request.upgrade("websocket", response);
+    let mut socket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    log::info!("WebSocket client connected");
+
+    // The socket isn't read, a closed one is noticed when the next event can't be sent
+    for line in state.subscribe().iter() {
+        if socket.send(tungstenite::Message::Text(line)).is_err() {
+            break;
+        }
+    }
+    log::info!("WebSocket client disconnected");
+}
+
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("Headers are ASCII")
+}
+
+/// Lets the pages of any origin use the server, which only analyzes positions
+fn with_cors<R: Read>(response: Response<R>) -> Response<R> {
+    response.with_header(header("Access-Control-Allow-Origin", "*"))
+}
+
+fn error_json(message: &str) -> String {
+    serde_json::to_string(&Message::Error { message }).unwrap()
+}
+
+fn parse_body<'a, T: Default + Deserialize<'a>>(body: &'a str) -> anyhow::Result<T> {
+    if body.trim().is_empty() {
+        return Ok(T::default());
+    }
+    serde_json::from_str(body).map_err(|err| anyhow!("Invalid request: {}", err))
+}
+
+impl State {
+    fn new(engine: Engine) -> Self {
+        Self {
+            engine: Mutex::new(engine),
+            searches: Mutex::new(VecDeque::new()),
+            clients: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Answers a request with its status code and its JSON
+    fn respond(&self, method: &Method, path: &str, body: &str) -> (u16, String) {
+        let result = match (method, path) {
+            (Method::Get, "/position") => Ok(self.position()),
+            (Method::Post, "/position") => self.set_position(body),
+            (Method::Post, "/analysis") => self.start_analysis(body),
+            (Method::Delete, "/analysis") => self.stop_analysis(),
+            (Method::Post, "/bestmove") => self.best_move(body),
+            _ => return (404, error_json(&format!("No {} {}", method, path))),
+        };
+        match result {
+            Ok(json) => (200, json),
+            Err(err) => (400, error_json(&err.to_string())),
+        }
+    }
+
+    fn position(&self) -> String {
+        serde_json::to_string(self.engine.lock().unwrap().position()).unwrap()
+    }
+
+    fn set_position(&self, body: &str) -> anyhow::Result<String> {
+        let request: PositionRequest = parse_body(body)?;
+        let mut game = match &request.fen {
+            Some(fen) => Game::new(fen)?,
+            None => Game::default(),
+        };
+        for text in &request.moves {
+            let _move = uci::parse_move(text, &game)?;
+            game.push_history(_move);
+        }
+
+        let mut engine = self.engine.lock().unwrap();
+        engine.stop();
+        engine.set_position(game)?;
+        Ok(serde_json::to_string(engine.position()).unwrap())
+    }
+
+    fn start_analysis(&self, body: &str) -> anyhow::Result<String> {
+        let request: SearchRequest = parse_body(body)?;
+        let limits = SearchLimits::new()
+            .infinite()
+            .multi_pv(request.lines.unwrap_or(1));
+        self.start(limits, None)?;
+        Ok(String::from("{}"))
+    }
+
+    fn stop_analysis(&self) -> anyhow::Result<String> {
+        let (sender, receiver) = mpsc::channel();
+        {
+            let mut engine = self.engine.lock().unwrap();
+            // Searches are only started with the engine locked, the last one is running
+            match self.searches.lock().unwrap().back_mut() {
+                Some(search) if engine.is_searching() && search.waiting.is_none() => {
+                    search.waiting = Some(sender);
+                }
+                _ => bail!("No analysis is running"),
+            }
+            engine.stop();
+        }
+        best_move_json(receiver)
+    }
+
+    fn best_move(&self, body: &str) -> anyhow::Result<String> {
+        let request: SearchRequest = parse_body(body)?;
+        let mut limits = SearchLimits::new().multi_pv(request.lines.unwrap_or(1));
+        if let Some(depth) = request.depth {
+            limits = limits.depth(depth);
+        }
+        if let Some(nodes) = request.nodes {
+            limits = limits.nodes(nodes);
+        }
+        if let Some(millis) = request.movetime {
+            limits = limits.move_time(Duration::from_millis(millis));
+        }
+        if limits.is_unlimited() {
+            bail!("The search needs a depth, nodes or movetime");
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        self.start(limits, Some(sender))?;
+        best_move_json(receiver)
+    }
+
+    /// Stops the running search and starts another one of the position
+    fn start(&self, limits: SearchLimits, waiting: Option<Sender<BestMove>>) -> anyhow::Result<()> {
+        let mut engine = self.engine.lock().unwrap();
+        engine.stop();
+
+        // Known before the first event of the search can arrive
+        let mut searches = self.searches.lock().unwrap();
+        searches.push_back(Search {
+            game: engine.position().clone(),
+            waiting,
+            lines: Vec::new(),
+        });
+        drop(searches);
+
+        let result = engine.start_search(limits);
+        if result.is_err() {
+            self.searches.lock().unwrap().pop_back();
+        }
+        result
+    }
+
+    /// Receiver of the JSON lines of the events from now on
+    fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.clients.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// Sends the events of the engine to the clients and the requests waiting for them,
+    /// until the engine is dropped
+    fn dispatch(&self, events: Receiver<EngineEvent>) {
+        for event in events.iter() {
+            let mut searches = self.searches.lock().unwrap();
+            let Some(search) = searches.front_mut() else {
+                log::error!("Event of a search which wasn't started by the server");
+                continue;
+            };
+
+            let line = match event {
+                EngineEvent::Info(info) => {
+                    let line =
+                        serde_json::to_string(&Message::Info(JsonReport::new(&info, &search.game)))
+                            .unwrap();
+                    let rank = info.multipv as usize - 1;
+                    if rank < search.lines.len() {
+                        search.lines[rank] = info;
+                    } else {
+                        search.lines.push(info);
+                    }
+                    line
+                }
+                EngineEvent::BestMove(best_move) => {
+                    let search = searches.pop_front().unwrap();
+                    let bestmove = best_move.map(|best_move| best_move.uci_notation(&search.game));
+                    if let Some(waiting) = search.waiting {
+                        let lines = search
+                            .lines
+                            .iter()
+                            .map(|info| JsonReport::new(info, &search.game))
+                            .collect();
+                        let _ = waiting.send((bestmove.clone(), lines));
+                    }
+                    serde_json::to_string(&Message::BestMove { bestmove }).unwrap()
+                }
+            };
+            drop(searches);
+
+            self.clients
+                .lock()
+                .unwrap()
+                .retain(|client| client.send(line.clone()).is_ok());
+        }
+    }
+}
+
+/// Waits for the best move of a search and writes it with the last report of every line
+fn best_move_json(receiver: Receiver<BestMove>) -> anyhow::Result<String> {
+    let (bestmove, lines) = receiver
+        .recv()
+        .map_err(|_| anyhow!("The search ended without a best move"))?;
+    Ok(serde_json::json!({ "bestmove": bestmove, "lines": lines }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests() {
+        let (engine, events) = Engine::with_hash(16).unwrap();
+        let state = Arc::new(State::new(engine));
+        {
+            let state = state.clone();
+            thread::spawn(move || state.dispatch(events));
+        }
+        let client = state.subscribe();
+
+        let (status, json) =
+            state.respond(&Method::Post, "/position", r#"{"moves": ["e2e4", "e5"]}"#);
+        assert_eq!(status, 200);
+        assert!(json.contains("rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq"));
+        assert_eq!(state.respond(&Method::Get, "/position", "").1, json);
+
+        let (status, json) = state.respond(&Method::Post, "/position", r#"{"moves": ["e2e5"]}"#);
+        assert_eq!(status, 400);
+        assert!(json.starts_with(r#"{"type":"error""#));
+        assert_eq!(state.respond(&Method::Get, "/moves", "").0, 404);
+        assert_eq!(state.respond(&Method::Post, "/bestmove", "").0, 400);
+        assert_eq!(state.respond(&Method::Delete, "/analysis", "").0, 400);
+
+        let (status, json) =
+            state.respond(&Method::Post, "/bestmove", r#"{"depth": 4, "lines": 2}"#);
+        assert_eq!(status, 200);
+        let answer: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(answer["bestmove"].is_string());
+        assert_eq!(answer["lines"].as_array().unwrap().len(), 2);
+        assert_eq!(answer["lines"][0]["depth"], 4);
+        assert_eq!(answer["lines"][0]["pv"][0], answer["bestmove"]);
+
+        // The WebSocket clients get every event of the search
+        let lines: Vec<String> = client
+            .iter()
+            .take_while(|line| !line.starts_with(r#"{"type":"bestmove""#))
+            .collect();
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with(r#"{"type":"info","depth":4"#)));
+
+        assert_eq!(state.respond(&Method::Post, "/analysis", "").0, 200);
+        thread::sleep(Duration::from_millis(50));
+        let (status, json) = state.respond(&Method::Delete, "/analysis", "");
+        assert_eq!(status, 200);
+        assert!(json.contains(r#""bestmove":""#));
+    }
+}
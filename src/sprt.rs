@@ -0,0 +1,277 @@
+use crate::{
+    chess::{pgn::GameResult, Player},
+    engine_match::{self, Adjudication, Engine, EngineConfig},
+};
+use anyhow::{bail, Context};
+use std::io::Write;
+
+// Sequential probability ratio test:
+// https://www.chessprogramming.org/Sequential_Probability_Ratio_Test
+
+/// Settings of a match between the new version of the engine and the baseline
+pub struct Settings {
+    pub new: EngineConfig,
+    pub base: EngineConfig,
+    /// Elo difference of the null hypothesis, that the new version isn't better
+    pub elo0: f64,
+    /// Elo difference of the alternative hypothesis, that the new version is better
+    pub elo1: f64,
+    /// Probability of accepting the new version when the null hypothesis is true
+    pub alpha: f64,
+    /// Probability of rejecting the new version when the alternative is true
+    pub beta: f64,
+    /// Time of each engine at the start of a game and added after each move, in ms
+    pub time: u64,
+    pub increment: u64,
+    /// EPD or FEN file of the openings, the bundled ones if there is none
+    pub openings: Option<String>,
+    /// Games after which the test stops even if it is undecided
+    pub max_games: Option<usize>,
+    /// File to which the games are appended
+    pub pgn: Option<String>,
+    pub adjudication: Adjudication,
+}
+
+impl Settings {
+    /// Checks that the hypotheses and the error probabilities make a test
+    pub fn check(&self) -> anyhow::Result<()> {
+        if self.elo0 >= self.elo1 {
+            bail!("elo0 must be lower than elo1");
+        }
+        for error in [self.alpha, self.beta] {
+            if !(0.0..0.5).contains(&error) || error == 0.0 {
+                bail!("alpha and beta must be between 0 and 0.5");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Counts of game pairs by the points the new version scored in them, from 0 to 2
+/// in half points. Pairs are counted rather than games since the two games of an
+/// opening aren't independent.
+#[derive(Default)]
+struct Pentanomial([u32; 5]);
+
+impl Pentanomial {
+    /// Mean and variance of the score of a pair, scaled from 0 to 1
+    fn score(&self) -> Option<(f64, f64)> {
+        let pairs: u32 = self.0.iter().sum();
+        if pairs == 0 {
+            return None;
+        }
+
+        let pairs = pairs as f64;
+        let points = |index: usize| index as f64 / 4.0;
+        let mean = (0..5)
+            .map(|index| self.0[index] as f64 * points(index))
+            .sum::<f64>()
+            / pairs;
+        let variance = (0..5)
+            .map(|index| self.0[index] as f64 * (points(index) - mean).powi(2))
+            .sum::<f64>()
+            / pairs;
+
+        Some((mean, variance / pairs))
+    }
+
+    /// Log-likelihood ratio of the hypotheses with the approximation of the generalized
+    /// SPRT, which only depends on the mean and the variance of the score
+    fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let Some((mean, variance)) = self.score() else {
+            return 0.0;
+        };
+        if variance == 0.0 {
+            return 0.0;
+        }
+
+        let (score0, score1) = (expected_score(elo0), expected_score(elo1));
+        (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    /// Elo difference measured so far, with the margin of its 95% confidence interval,
+    /// unless every game was won or lost
+    fn elo(&self) -> Option<(f64, f64)> {
+        let (mean, variance) = self.score()?;
+        if mean == 0.0 || mean == 1.0 {
+            return None;
+        }
+        let margin = 1.96 * variance.sqrt();
+        let (low, high) = (elo((mean - margin).max(0.0)), elo((mean + margin).min(1.0)));
+        Some((elo(mean), (high - low) / 2.0))
+    }
+}
+
+/// Expected score against an engine weaker by `elo`
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+/// Inverse of `expected_score`
+fn elo(score: f64) -> f64 {
+    400.0 * (score / (1.0 - score)).log10()
+}
+
+/// Plays the new version against the baseline until the SPRT accepts one of the
+/// hypotheses, or until the maximum number of games
+pub fn run(settings: &Settings) -> anyhow::Result<()> {
+    settings.check()?;
+    let openings = engine_match::load_openings(settings.openings.as_deref())?;
+    let mut pgn = match &settings.pgn {
+        Some(path) => Some(engine_match::open_pgn(path)?),
+        None => None,
+    };
+
+    let mut new = Engine::start(&settings.new)?;
+    let mut base = Engine::start(&settings.base)?;
+
+    let lower = (settings.beta / (1.0 - settings.alpha)).ln();
+    let upper = ((1.0 - settings.beta) / settings.alpha).ln();
+    println!(
+        "Elo bounds: [{}, {}], LLR bounds: [{:.2}, {:.2}]",
+        settings.elo0, settings.elo1, lower, upper
+    );
+
+    let mut pentanomial = Pentanomial::default();
+    // Wins, losses and draws of the new version
+    let (mut wins, mut losses, mut draws) = (0, 0, 0);
+
+    for opening in openings.iter().cycle() {
+        if settings
+            .max_games
+            .is_some_and(|max_games| wins + losses + draws + 2 > max_games)
+        {
+            println!("No decision after {} games", wins + losses + draws);
+            break;
+        }
+
+        // Half points of the new version in the pair
+        let mut points = 0;
+        for new_player in [Player::White, Player::Black] {
+            let (white, black) = match new_player {
+                Player::White => (&mut new, &mut base),
+                Player::Black => (&mut base, &mut new),
+            };
+            let played = engine_match::play_game(
+                white,
+                black,
+                opening,
+                settings.time,
+                settings.increment,
+                settings.adjudication,
+            )?;
+
+            match played.result {
+                GameResult::Draw => {
+                    draws += 1;
+                    points += 1;
+                }
+                result if result == GameResult::win(new_player) => {
+                    wins += 1;
+                    points += 2;
+                }
+                _ => losses += 1,
+            }
+
+            if let Some(pgn) = &mut pgn {
+                let round = wins + losses + draws;
+                writeln!(
+                    pgn,
+                    "{}",
+                    played.to_pgn_game(&white.name, &black.name, round)
+                )
+                .context("Failed to write the game")?;
+            }
+        }
+        pentanomial.0[points] += 1;
+
+        let llr = pentanomial.llr(settings.elo0, settings.elo1);
+        let elo = match pentanomial.elo() {
+            Some((elo, margin)) => format!("{:.1} +- {:.1}", elo, margin),
+            None => String::from("?"),
+        };
+        println!(
+            "Games: {}, W: {}, L: {}, D: {}, Elo: {}, LLR: {:.2}",
+            wins + losses + draws,
+            wins,
+            losses,
+            draws,
+            elo,
+            llr
+        );
+
+        if llr >= upper {
+            println!("H1 accepted: the new version is stronger");
+            break;
+        }
+        if llr <= lower {
+            println!("H0 accepted: the new version isn't stronger");
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn llr() {
+        // Even results favor neither of two hypotheses as far from 0 Elo
+        let pentanomial = Pentanomial([10, 20, 40, 20, 10]);
+        assert!(pentanomial.llr(0.0, 5.0) < 0.0);
+        assert!(pentanomial.llr(-5.0, 5.0).abs() < 1e-9);
+        let (measured, margin) = pentanomial.elo().unwrap();
+        assert!(measured.abs() < 1e-9);
+        assert!(margin > 0.0);
+
+        // Many more won pairs than lost ones
+        let pentanomial = Pentanomial([50, 200, 400, 300, 100]);
+        assert!(pentanomial.llr(0.0, 5.0) > 2.94);
+        assert!(pentanomial.elo().unwrap().0 > 20.0);
+
+        assert_eq!(Pentanomial::default().llr(0.0, 5.0), 0.0);
+        assert!(Pentanomial([0, 0, 0, 0, 3]).elo().is_none());
+        assert!((elo(expected_score(30.0)) - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn settings() {
+        let settings = || Settings {
+            new: EngineConfig::this_program(Some("New")).unwrap(),
+            base: EngineConfig::this_program(Some("Base")).unwrap(),
+            elo0: -3.0,
+            elo1: 2.0,
+            alpha: 0.05,
+            beta: 0.05,
+            time: 1000,
+            increment: 10,
+            openings: None,
+            max_games: Some(100),
+            pgn: None,
+            adjudication: Adjudication::default(),
+        };
+        assert!(settings().check().is_ok());
+
+        for settings in [
+            Settings {
+                elo0: 5.0,
+                elo1: 0.0,
+                ..settings()
+            },
+            Settings {
+                alpha: 0.7,
+                ..settings()
+            },
+            Settings {
+                beta: 0.0,
+                ..settings()
+            },
+        ] {
+            assert!(settings.check().is_err());
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{
+    chess::{params::EvalParams, pgn::GameResult, scores::*, Player, Score},
+    engine_match::{self, Adjudication, Engine, EngineConfig},
+    openings::Random,
+    search::SearchParams,
+};
+use anyhow::{bail, Context};
+
+// Simultaneous perturbation stochastic approximation, with the gains of Fishtest:
+// https://www.chessprogramming.org/SPSA
+
+/// Exponent of the decay of the learning rate
+const ALPHA: f64 = 0.602;
+/// Exponent of the decay of the perturbations
+const GAMMA: f64 = 0.101;
+/// Part of the iterations delaying the decay of the learning rate, which keeps the
+/// first steps small
+const STABILITY: f64 = 0.1;
+/// Transposition table of the engines, which only think a moment on each move
+const HASH_MB: &str = "16";
+/// Iterations between two reports of the values
+const REPORT_INTERVAL: usize = 10;
+
+/// A constant of the search or of the evaluation which can be tuned, set through the UCI
+/// option of its name
+pub struct Tunable {
+    pub name: &'static str,
+    pub default: Score,
+    pub min: Score,
+    pub max: Score,
+    /// Perturbation of the value at the end of the tuning, `c_end` of Fishtest, it
+    /// should change the strength of the engine by a few Elo
+    pub step: f64,
+    set: fn(&mut SearchParams, &mut EvalParams, Score),
+}
+
+impl Tunable {
+    /// Sets the constant to `value` in the parameters of an engine
+    pub fn apply(
+        &self,
+        search: &mut SearchParams,
+        eval: &mut EvalParams,
+        value: Score,
+    ) -> anyhow::Result<()> {
+        if !(self.min..=self.max).contains(&value) {
+            bail!(
+                "{} must be between {} and {}",
+                self.name,
+                self.min,
+                self.max
+            );
+        }
+        (self.set)(search, eval, value);
+        Ok(())
+    }
+}
+
+/// The constants which can be tuned, each one is a UCI option of the engine
+pub const TUNABLES: [Tunable; 16] = [
+    Tunable {
+        name: "NullMoveReduction",
+        default: SearchParams::DEFAULT.null_move_reduction as Score,
+        min: 1,
+        max: 4,
+        step: 0.5,
+        set: |search, _, value| search.null_move_reduction = value as u8,
+    },
+    Tunable {
+        name: "NullMoveDepth",
+        default: SearchParams::DEFAULT.null_move_depth as Score,
+        min: 2,
+        max: 6,
+        step: 0.5,
+        set: |search, _, value| search.null_move_depth = value as u8,
+    },
+    Tunable {
+        name: "FullWindowMoves",
+        default: SearchParams::DEFAULT.full_window_moves as Score,
+        min: 1,
+        max: 8,
+        step: 0.5,
+        set: |search, _, value| search.full_window_moves = value as usize,
+    },
+    Tunable {
+        name: "HistoryLimit",
+        default: SearchParams::DEFAULT.history_limit as Score,
+        min: 2000,
+        max: 30000,
+        step: 1000.0,
+        set: |search, _, value| search.history_limit = value as u16,
+    },
+    Tunable {
+        name: "DoubledPawnPenalty",
+        default: DOUBLED_PAWN_PENALTY,
+        min: 0,
+        max: 50,
+        step: 2.0,
+        set: |_, eval, value| eval.doubled_pawn_penalty = value,
+    },
+    Tunable {
+        name: "IsolatedPawnPenalty",
+        default: ISOLATED_PAWN_PENALTY,
+        min: 0,
+        max: 50,
+        step: 2.0,
+        set: |_, eval, value| eval.isolated_pawn_penalty = value,
+    },
+    Tunable {
+        name: "SupportedPawnBonus",
+        default: SUPPORTED_PAWN_BONUS,
+        min: 0,
+        max: 30,
+        step: 1.0,
+        set: |_, eval, value| eval.supported_pawn_bonus = value,
+    },
+    Tunable {
+        name: "BadBishopPenalty",
+        default: BAD_BISHOP_PENALTY,
+        min: 0,
+        max: 20,
+        step: 1.0,
+        set: |_, eval, value| eval.bad_bishop_penalty = value,
+    },
+    Tunable {
+        name: "PinnedPiecePenalty",
+        default: PINNED_PIECE_PENALTY,
+        min: 0,
+        max: 50,
+        step: 2.0,
+        set: |_, eval, value| eval.pinned_piece_penalty = value,
+    },
+    Tunable {
+        name: "MissingShieldPawnPenalty",
+        default: MISSING_SHIELD_PAWN_PENALTY,
+        min: 0,
+        max: 50,
+        step: 2.0,
+        set: |_, eval, value| eval.missing_shield_pawn_penalty = value,
+    },
+    Tunable {
+        name: "OpenFileNearKingPenalty",
+        default: OPEN_FILE_NEAR_KING_PENALTY,
+        min: 0,
+        max: 50,
+        step: 2.0,
+        set: |_, eval, value| eval.open_file_near_king_penalty = value,
+    },
+    Tunable {
+        name: "SpaceBonus",
+        default: SPACE_BONUS,
+        min: 0,
+        max: 20,
+        step: 1.0,
+        set: |_, eval, value| eval.space_bonus = value,
+    },
+    Tunable {
+        name: "ThreatByPawnBonus",
+        default: THREAT_BY_PAWN_BONUS,
+        min: 0,
+        max: 150,
+        step: 5.0,
+        set: |_, eval, value| eval.threat_by_pawn_bonus = value,
+    },
+    Tunable {
+        name: "ThreatByLesserPieceBonus",
+        default: THREAT_BY_LESSER_PIECE_BONUS,
+        min: 0,
+        max: 100,
+        step: 3.0,
+        set: |_, eval, value| eval.threat_by_lesser_piece_bonus = value,
+    },
+    Tunable {
+        name: "HangingPieceBonus",
+        default: HANGING_PIECE_BONUS,
+        min: 0,
+        max: 100,
+        step: 3.0,
+        set: |_, eval, value| eval.hanging_piece_bonus = value,
+    },
+    Tunable {
+        name: "UnderdefendedPieceBonus",
+        default: UNDERDEFENDED_PIECE_BONUS,
+        min: 0,
+        max: 60,
+        step: 2.0,
+        set: |_, eval, value| eval.underdefended_piece_bonus = value,
+    },
+];
+
+/// The tunable constant of a UCI option, whose name is case insensitive
+pub fn tunable(name: &str) -> Option<&'static Tunable> {
+    TUNABLES
+        .iter()
+        .find(|tunable| tunable.name.eq_ignore_ascii_case(name))
+}
+
+/// Settings of the tuning of some constants of an engine
+pub struct Settings {
+    /// Engine whose constants are tuned, with the options set in every game
+    pub engine: EngineConfig,
+    pub tunables: Vec<&'static Tunable>,
+    /// Game pairs played, the steps get smaller until the last one
+    pub iterations: usize,
+    /// Time of each engine at the start of a game and added after each move, in ms
+    pub time: u64,
+    pub increment: u64,
+    /// Learning rate at the end of the tuning, `r_end` of Fishtest
+    pub learning_rate: f64,
+    /// EPD or FEN file of the openings, the bundled ones if there is none
+    pub openings: Option<String>,
+    /// Seed of the perturbations and of the choice of the openings
+    pub seed: Option<u64>,
+    pub adjudication: Adjudication,
+}
+
+/// Perturbation and learning rate of a constant at an iteration, from 1 to `iterations`.
+/// They reach `step` and `learning_rate` at the last iteration, the changes of the value
+/// which they lead to decrease until then.
+fn gains(step: f64, learning_rate: f64, iteration: usize, iterations: usize) -> (f64, f64) {
+    let (k, n) = (iteration as f64, iterations as f64);
+    let stability = STABILITY * n;
+
+    let c = step * n.powf(GAMMA);
+    let a = learning_rate * step * step * (stability + n).powf(ALPHA);
+    let c_k = c / k.powf(GAMMA);
+    let a_k = a / (stability + k).powf(ALPHA);
+
+    (c_k, a_k / (c_k * c_k))
+}
+
+/// Tunes the constants by playing the engine against itself with the values perturbed in
+/// opposite random directions, a pair of games from the same opening per iteration. The
+/// values move towards the perturbation which scored more, and the final ones are printed
+/// as `<name>=<value>` to be checked with `sprt --new-option`.
+pub fn run(settings: &Settings) -> anyhow::Result<()> {
+    if settings.tunables.is_empty() {
+        bail!("No constants to tune");
+    }
+    let openings = engine_match::load_openings(settings.openings.as_deref())?;
+    let mut random = Random::new(settings.seed);
+
+    let mut values: Vec<f64> = settings
+        .tunables
+        .iter()
+        .map(|tunable| tunable.default as f64)
+        .collect();
+    // Total of the results of the perturbations towards higher values
+    let mut score = 0;
+
+    for iteration in 1..=settings.iterations {
+        let gains: Vec<(f64, f64)> = settings
+            .tunables
+            .iter()
+            .map(|tunable| {
+                gains(
+                    tunable.step,
+                    settings.learning_rate,
+                    iteration,
+                    settings.iterations,
+                )
+            })
+            .collect();
+        let directions: Vec<f64> = settings
+            .tunables
+            .iter()
+            .map(|_| if random.next() & 1 == 0 { 1.0 } else { -1.0 })
+            .collect();
+
+        let engine = |name: &str, sign: f64| {
+            let mut options = settings.engine.options.clone();
+            options.push((String::from("Hash"), String::from(HASH_MB)));
+            for (index, tunable) in settings.tunables.iter().enumerate() {
+                let value = values[index] + sign * gains[index].0 * directions[index];
+                let value = value.clamp(tunable.min as f64, tunable.max as f64);
+                options.push((tunable.name.to_string(), format!("{:.0}", value)));
+            }
+            EngineConfig {
+                name: Some(name.to_string()),
+                command: settings.engine.command.clone(),
+                options,
+            }
+        };
+        let mut plus = Engine::start(&engine("Plus", 1.0))?;
+        let mut minus = Engine::start(&engine("Minus", -1.0))?;
+
+        // Wins minus losses of the engine with the values moved along the directions
+        let mut result = 0;
+        let opening = &openings[random.below(openings.len())];
+        for plus_player in [Player::White, Player::Black] {
+            let (white, black) = match plus_player {
+                Player::White => (&mut plus, &mut minus),
+                Player::Black => (&mut minus, &mut plus),
+            };
+            let played = engine_match::play_game(
+                white,
+                black,
+                opening,
+                settings.time,
+                settings.increment,
+                settings.adjudication,
+            )
+            .with_context(|| format!("Iteration {}", iteration))?;
+
+            result += match played.result {
+                GameResult::Draw | GameResult::Unfinished => 0,
+                result if result == GameResult::win(plus_player) => 1,
+                _ => -1,
+            };
+        }
+        score += result;
+
+        for (index, tunable) in settings.tunables.iter().enumerate() {
+            let (perturbation, rate) = gains[index];
+            values[index] += rate * perturbation * result as f64 * directions[index];
+            values[index] = values[index].clamp(tunable.min as f64, tunable.max as f64);
+        }
+
+        println!("Iteration {}: {:+}, total {:+}", iteration, result, score);
+        if iteration % REPORT_INTERVAL == 0 || iteration == settings.iterations {
+            let report: Vec<String> = settings
+                .tunables
+                .iter()
+                .zip(&values)
+                .map(|(tunable, value)| format!("{} {:.2}", tunable.name, value))
+                .collect();
+            println!("{}", report.join(", "));
+        }
+    }
+
+    println!("Tuned values:");
+    for (tunable, value) in settings.tunables.iter().zip(&values) {
+        println!("{}={:.0}", tunable.name, value);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tunables() {
+        let (mut search, mut eval) = (SearchParams::DEFAULT, EvalParams::DEFAULT);
+        for tunable in &TUNABLES {
+            assert!((tunable.min..=tunable.max).contains(&tunable.default));
+            assert!(tunable.step > 0.0);
+            tunable
+                .apply(&mut search, &mut eval, tunable.default)
+                .unwrap();
+        }
+        // The defaults are the values in use
+        assert_eq!(search, SearchParams::DEFAULT);
+        assert_eq!(eval, EvalParams::DEFAULT);
+
+        let tunable = tunable("spacebonus").unwrap();
+        tunable.apply(&mut search, &mut eval, 7).unwrap();
+        assert_eq!(eval.space_bonus, 7);
+        assert!(tunable.apply(&mut search, &mut eval, 21).is_err());
+        assert!(self::tunable("Contempt").is_none());
+    }
+
+    #[test]
+    fn gain_schedule() {
+        let (first_step, first_rate) = gains(2.0, 0.002, 1, 1000);
+        let (last_step, last_rate) = gains(2.0, 0.002, 1000, 1000);
+        assert!((last_step - 2.0).abs() < 1e-9);
+        assert!((last_rate - 0.002).abs() < 1e-9);
+        assert!(first_step > last_step);
+
+        // The steps of the values shrink from the first iteration to the last one
+        assert!(first_rate * first_step > last_rate * last_step);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    chess::{move_struct::Move, score::Score},
+    openings::Random,
+    search::{SearchInfo, SearchLimits},
+};
+use std::ops::RangeInclusive;
+
+/// Levels of the `SkillLevel` option and of `play --level`, the highest one is the full
+/// strength of the engine
+pub const LEVEL_RANGE: RangeInclusive<u8> = 1..=20;
+
+/// Handicaps of a playing strength below the full one, for a practice opponent: a search
+/// capped in depth and nodes, whose move is chosen at random among its best ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Strength {
+    /// Deepest depth searched
+    pub depth: u8,
+    /// Positions visited after which the search stops
+    pub nodes: u64,
+    /// Best moves of the search among which the move played is chosen
+    pub candidates: u8,
+    /// Most centipawns added at random to the score of each candidate, the one with the
+    /// highest sum is played
+    pub noise: Score,
+}
+
+impl Strength {
+    /// Handicaps of `level` of `LEVEL_RANGE`, None for the highest one which has none
+    ///
+    /// Level 1 searches 40 nodes and plays any of its 5 best moves which is within
+    /// 285 centipawns of the best one, level 19 searches about 190000 nodes and picks
+    /// between its 2 best moves within 15 centipawns.
+    pub fn of_level(level: u8) -> Option<Self> {
+        let max_level = *LEVEL_RANGE.end();
+        if level >= max_level {
+            return None;
+        }
+        let level = level.max(*LEVEL_RANGE.start());
+
+        Some(Self {
+            depth: 1 + level / 2,
+            nodes: (25.0 * 1.6f64.powi(level as i32)) as u64,
+            candidates: 2 + (max_level - 1 - level) / 5,
+            noise: 15 * (max_level - level) as Score,
+        })
+    }
+
+    /// `limits` capped by the handicaps, with enough lines for the candidates
+    pub fn limit(&self, limits: SearchLimits) -> SearchLimits {
+        let lines = limits.lines().max(self.candidates);
+        limits.at_most(self.depth, self.nodes).multi_pv(lines)
+    }
+
+    /// The move played among the candidates, `lines` being the last report of every line
+    /// of the search by rank
+    pub(crate) fn choose(&self, lines: &[SearchInfo], random: &mut Random) -> Option<Move> {
+        lines
+            .iter()
+            .take(self.candidates as usize)
+            .filter_map(|info| {
+                let noise = random.below(self.noise as usize + 1) as Score;
+                Some((*info.pv.first()?, info.score + noise))
+            })
+            .max_by_key(|&(_, score)| score)
+            .map(|(_move, _)| _move)
+    }
+}
+
+/// Keeps the last report of every line of a search, by rank
+pub fn record_line(lines: &mut Vec<SearchInfo>, info: &SearchInfo) {
+    let rank = info.multipv.max(1) as usize - 1;
+    if rank < lines.len() {
+        lines[rank] = info.clone();
+    } else {
+        lines.push(info.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chess::Game, search::search, wdl::Wdl};
+    use std::sync::atomic::AtomicBool;
+
+    #[test]
+    fn levels() {
+        assert_eq!(Strength::of_level(20), None);
+        let weakest = Strength::of_level(1).unwrap();
+        assert_eq!(
+            (weakest.nodes, weakest.candidates, weakest.noise),
+            (40, 5, 285)
+        );
+        assert_eq!(Strength::of_level(0), Some(weakest));
+
+        let levels: Vec<Strength> = (1..20).filter_map(Strength::of_level).collect();
+        assert_eq!(levels.len(), 19);
+        for pair in levels.windows(2) {
+            assert!(pair[0].depth <= pair[1].depth);
+            assert!(pair[0].nodes < pair[1].nodes);
+            assert!(pair[0].candidates >= pair[1].candidates);
+            assert!(pair[0].noise > pair[1].noise);
+        }
+        assert_eq!(levels[18].candidates, 2);
+
+        let limits = weakest.limit(SearchLimits::new().depth(20));
+        assert_eq!(limits.max_depth(), Some(weakest.depth));
+        assert_eq!(limits.lines(), 5);
+    }
+
+    #[test]
+    fn random_choice() {
+        let game = Game::default();
+        let line = |multipv, score, uci| SearchInfo {
+            depth: 1,
+            multipv,
+            score,
+            wdl: Wdl {
+                win: 0,
+                draw: 1000,
+                loss: 0,
+            },
+            nodes: 0,
+            pv: vec![Move::from_uci_notation(uci, &game).unwrap()],
+        };
+        let mut lines = Vec::new();
+        for info in [
+            line(1, 30, "e2e4"),
+            line(2, 20, "d2d4"),
+            line(1, 40, "g1f3"),
+            line(3, -500, "g2g4"),
+        ] {
+            record_line(&mut lines, &info);
+        }
+        assert_eq!(lines.len(), 3);
+
+        let mut random = Random::new(Some(1));
+        let strength = Strength::of_level(10).unwrap();
+        let mut chosen = Vec::new();
+        for _ in 0..100 {
+            chosen.push(
+                strength
+                    .choose(&lines, &mut random)
+                    .unwrap()
+                    .uci_notation(&game),
+            );
+        }
+        // The blunder is far worse than the noise
+        assert!(chosen.contains(&String::from("g1f3")));
+        assert!(chosen.contains(&String::from("d2d4")));
+        assert!(!chosen.contains(&String::from("g2g4")));
+
+        // A weak search still finds a legal move
+        let strength = Strength::of_level(1).unwrap();
+        let mut lines = Vec::new();
+        let limits = strength.limit(SearchLimits::new());
+        let mut table = Default::default();
+        let best = search(
+            &game,
+            &limits,
+            &mut table,
+            &AtomicBool::new(true),
+            0,
+            |info| record_line(&mut lines, info),
+        );
+        assert!(best.is_some());
+        assert_eq!(lines.len(), 5);
+        assert!(game
+            .legal_moves()
+            .contains(&strength.choose(&lines, &mut random).unwrap()));
+    }
+}
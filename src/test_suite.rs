@@ -0,0 +1,144 @@
+use crate::{
+    chess::{epd::Epd, move_struct::Move, score},
+    constants::{DEFAULT_CONTEMPT, TT_CAPACITY},
+    search::{search, SearchLimits, TranspositionTable},
+    uci,
+};
+use anyhow::{bail, Context};
+use nohash_hasher::BuildNoHashHasher;
+use std::{collections::HashMap, fs, sync::atomic::AtomicBool, time::Duration};
+
+/// Points of the moves of a position of the Strategic Test Suite, written in its `c0`
+/// operation like `c0 "Rxb7=10, Qd2=5, a4=3";`
+///
+/// Returns `None` when the operation is missing or is an ordinary comment.
+fn move_points(epd: &Epd) -> Option<Vec<(Move, u32)>> {
+    let comment = epd.operation("c0")?;
+    comment
+        .split(',')
+        .map(|item| {
+            // Promotions also contain an equal sign, the points are after the last one
+            let (san, points) = item.trim().rsplit_once('=')?;
+            Some((Move::from_san(san, &epd.game).ok()?, points.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Searches every position of an EPD test suite for `millis` and counts the ones where
+/// the move found is one of the `bm` moves and none of the `am` moves
+///
+/// Suites scoring several moves of each position, like the Strategic Test Suite, are
+/// also scored by the sum of the points of the moves found.
+pub fn solve(path: &str, millis: u64) -> anyhow::Result<()> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+
+    let mut positions = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let epd = Epd::parse(line).with_context(|| format!("Line {}", line_number + 1))?;
+        let points = move_points(&epd);
+        if epd.best_moves.is_empty() && epd.avoid_moves.is_empty() && points.is_none() {
+            bail!("Line {}: no bm, am or c0 points operation", line_number + 1);
+        }
+        positions.push((epd, points));
+    }
+
+    let mut cache: TranspositionTable =
+        HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildNoHashHasher::default());
+    let output = uci::Output::stdout();
+    let mut solved = 0;
+    let (mut points, mut max_points) = (0, 0);
+
+    for (index, (epd, move_points)) in positions.iter().enumerate() {
+        // Positions are searched independently
+        cache.clear();
+
+        let name = epd.id.clone().unwrap_or_else(|| format!("#{}", index + 1));
+        let Some((found, score, depth)) = search(
+            &epd.game,
+            &SearchLimits::new().move_time(Duration::from_millis(millis)),
+            &mut cache,
+            &AtomicBool::new(true),
+            DEFAULT_CONTEMPT,
+            |info| uci::print_info(&output, info, &epd.game, false),
+        ) else {
+            println!("{}: no legal move", name);
+            continue;
+        };
+
+        let is_solved = (epd.best_moves.is_empty() || epd.best_moves.contains(&found))
+            && !epd.avoid_moves.contains(&found);
+        if is_solved {
+            solved += 1;
+        }
+
+        let mut line = format!(
+            "{}: {} ({}, depth {}) {}",
+            name,
+            found.to_san(&epd.game),
+            score::to_pgn(score),
+            depth,
+            if is_solved { "solved" } else { "failed" }
+        );
+        if let Some(move_points) = move_points {
+            let found_points = move_points
+                .iter()
+                .find(|(_move, _)| *_move == found)
+                .map_or(0, |&(_, points)| points);
+            points += found_points;
+            max_points += move_points
+                .iter()
+                .map(|&(_, points)| points)
+                .max()
+                .unwrap_or(0);
+            line += &format!(", {} points", found_points);
+        }
+        println!("{}", line);
+    }
+
+    let total = positions.len();
+    println!(
+        "Solved {} of {} ({:.1}%)",
+        solved,
+        total,
+        100.0 * solved as f64 / total.max(1) as f64
+    );
+    if max_points > 0 {
+        println!("Points: {} of {}", points, max_points);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points() {
+        let epd = Epd::parse("1k6/1P5R/8/8/8/8/6K1/8 w - - bm Rh8+; c0 \"Rh8+=10, Kf3=2, Kg3=2\";")
+            .unwrap();
+        let points: Vec<_> = move_points(&epd)
+            .unwrap()
+            .into_iter()
+            .map(|(_move, points)| (_move.to_san(&epd.game), points))
+            .collect();
+        assert_eq!(
+            points,
+            [
+                (String::from("Rh8+"), 10),
+                (String::from("Kf3"), 2),
+                (String::from("Kg3"), 2)
+            ]
+        );
+
+        let epd =
+            Epd::parse("8/4P1k1/8/8/8/8/6K1/8 w - - bm e8=Q; c0 \"e8=Q=10, e8=N=1\";").unwrap();
+        assert_eq!(move_points(&epd).unwrap().len(), 2);
+
+        let epd = Epd::parse("8/4P1k1/8/8/8/8/6K1/8 w - - bm e8=Q; c0 \"Queening wins\";").unwrap();
+        assert!(move_points(&epd).is_none());
+    }
+}
@@ -0,0 +1,477 @@
+use crate::{
+    chess::{
+        move_struct::Move,
+        pgn::GameResult,
+        position::Position,
+        score::{self, Score},
+        Game, Player,
+    },
+    engine::{Engine, EngineEvent},
+    search::{SearchInfo, SearchLimits},
+    uci,
+};
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    symbols::Marker,
+    text::{Line, Span},
+    widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::{
+    sync::mpsc::Receiver,
+    time::{Duration, Instant},
+};
+
+/// Time between two redraws when nothing happens, which keeps the clocks running
+const REFRESH: Duration = Duration::from_millis(100);
+/// Scores of the graph are cut at this many pawns, mates included
+const GRAPH_PAWNS: f64 = 5.0;
+const LIGHT_SQUARE: Color = Color::Rgb(240, 217, 181);
+const DARK_SQUARE: Color = Color::Rgb(181, 136, 99);
+
+const HELP: &str = "Enter a move in SAN or UCI notation, or nothing to play the best one. \
+Commands: undo, flip, new, quit (or Esc)";
+
+/// Analyzes a game in the terminal: the engine searches every position reached without
+/// limits, showing its best `lines` next to the board and the score of every position of
+/// the game in a graph. With a time control of `(time, increment)` in ms the clocks of
+/// both sides run like in a game. The game is printed as PGN at the end.
+pub fn run(
+    game: Game,
+    lines: u8,
+    time_control: Option<(u64, u64)>,
+    hash_megabytes: usize,
+) -> anyhow::Result<()> {
+    let mut analysis = Analysis::new(game, lines, time_control, hash_megabytes)?;
+
+    let mut terminal = ratatui::init();
+    let result = analysis.run(&mut terminal);
+    ratatui::restore();
+    result?;
+
+    analysis.engine.stop();
+    let result = match analysis.game.outcome() {
+        outcome if outcome.is_over() => outcome.result(),
+        _ => GameResult::Unfinished,
+    };
+    println!("{}", analysis.game.get_pgn("?", "?", result, &[]));
+
+    Ok(())
+}
+
+/// Clocks of both sides, the one of the player to move runs
+struct Clocks {
+    /// Time of white and black when the last move was made
+    remaining: [Duration; 2],
+    increment: Duration,
+    /// When the player to move started thinking
+    turn_start: Instant,
+}
+
+impl Clocks {
+    fn new(time: u64, increment: u64) -> Self {
+        Self {
+            remaining: [Duration::from_millis(time); 2],
+            increment: Duration::from_millis(increment),
+            turn_start: Instant::now(),
+        }
+    }
+
+    /// Time left to `player`, which is to move if it is `to_move`
+    fn left(&self, player: Player, to_move: Player) -> Duration {
+        let remaining = self.remaining[player.as_index()];
+        if player == to_move {
+            remaining.saturating_sub(self.turn_start.elapsed())
+        } else {
+            remaining
+        }
+    }
+
+    /// Stops the clock of `player` after their move and starts the one of the opponent
+    fn moved(&mut self, player: Player) {
+        let left = self.left(player, player);
+        self.remaining[player.as_index()] = left + self.increment;
+        self.turn_start = Instant::now();
+    }
+}
+
+struct Analysis {
+    game: Game,
+    engine: Engine,
+    events: Receiver<EngineEvent>,
+    /// Last line reported for each rank of the current position
+    lines: Vec<SearchInfo>,
+    /// Score of every position of the game which was analyzed, indexed by ply, in
+    /// centipawns from the point of view of white
+    scores: Vec<Option<Score>>,
+    clocks: Option<Clocks>,
+    /// Time and increment of the clocks in ms, with which they start again in a new game
+    time_control: Option<(u64, u64)>,
+    /// When the analysis of the current position started
+    analysis_start: Instant,
+    /// Text typed by the user
+    input: String,
+    /// Answer to the last input
+    message: String,
+    flipped: bool,
+    quit: bool,
+}
+
+impl Analysis {
+    fn new(
+        game: Game,
+        lines: u8,
+        time_control: Option<(u64, u64)>,
+        hash_megabytes: usize,
+    ) -> anyhow::Result<Self> {
+        let (mut engine, events) = Engine::with_hash(hash_megabytes)?;
+        engine.set_option("MultiPV", &lines.to_string())?;
+
+        let mut analysis = Self {
+            flipped: game.player() == Player::Black,
+            game,
+            engine,
+            events,
+            lines: Vec::new(),
+            scores: Vec::new(),
+            clocks: time_control.map(|(time, increment)| Clocks::new(time, increment)),
+            time_control,
+            analysis_start: Instant::now(),
+            input: String::new(),
+            message: String::from(HELP),
+            quit: false,
+        };
+        analysis.restart()?;
+        Ok(analysis)
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        while !self.quit {
+            self.receive();
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if event::poll(REFRESH)? {
+                if let Event::Key(key) = event::read()? {
+                    if key.kind == KeyEventKind::Press {
+                        self.on_key(key)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Analyzes the current position from scratch
+    fn restart(&mut self) -> anyhow::Result<()> {
+        self.engine.stop();
+        // Lines of the previous position which were sent before it stopped
+        self.events.try_iter().for_each(drop);
+        self.lines.clear();
+        self.analysis_start = Instant::now();
+
+        self.engine.set_position(self.game.clone())?;
+        if !self.game.outcome().is_over() {
+            self.engine.start_search(SearchLimits::new().infinite())?;
+        }
+        Ok(())
+    }
+
+    /// Takes the lines reported by the engine since the last call
+    fn receive(&mut self) {
+        for event in self.events.try_iter() {
+            let EngineEvent::Info(info) = event else {
+                continue;
+            };
+
+            if info.multipv == 1 {
+                let ply = self.game.history().len();
+                if self.scores.len() <= ply {
+                    self.scores.resize(ply + 1, None);
+                }
+                self.scores[ply] = Some(info.score * self.game.player() as Score);
+            }
+            let index = info.multipv as usize - 1;
+            if self.lines.len() <= index {
+                self.lines.resize(index + 1, info.clone());
+            }
+            self.lines[index] = info;
+        }
+    }
+
+    fn on_key(&mut self, key: KeyEvent) -> anyhow::Result<()> {
+        match key.code {
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => self.quit = true,
+            KeyCode::Char(c) => self.input.push(c),
+            KeyCode::Backspace => {
+                self.input.pop();
+            }
+            KeyCode::Enter => {
+                let input = std::mem::take(&mut self.input);
+                self.submit(input.trim())?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Runs a command or plays a move typed by the user
+    fn submit(&mut self, input: &str) -> anyhow::Result<()> {
+        self.message.clear();
+        match input {
+            "quit" | "exit" => self.quit = true,
+            "flip" => self.flipped = !self.flipped,
+            "help" => self.message = String::from(HELP),
+            "new" => {
+                self.game = Game::default();
+                self.scores.clear();
+                self.clocks = self
+                    .time_control
+                    .map(|(time, increment)| Clocks::new(time, increment));
+                self.restart()?;
+            }
+            "undo" => {
+                let history = self.game.history();
+                if history.is_empty() {
+                    self.message = String::from("No move to take back");
+                } else {
+                    let mut undone = self.game.initial_position();
+                    for &_move in &history[..history.len() - 1] {
+                        undone.push_history(_move);
+                    }
+                    self.game = undone;
+                    self.scores.truncate(self.game.history().len() + 1);
+                    self.restart()?;
+                }
+            }
+            "" => match self.lines.first().and_then(|line| line.pv.first()) {
+                Some(&best_move) => self.play(best_move)?,
+                None => self.message = String::from("No move found yet"),
+            },
+            text => match uci::parse_move(text, &self.game) {
+                Ok(_move) => self.play(_move)?,
+                Err(err) => self.message = err.to_string(),
+            },
+        }
+        Ok(())
+    }
+
+    fn play(&mut self, _move: Move) -> anyhow::Result<()> {
+        if let Some(clocks) = &mut self.clocks {
+            clocks.moved(self.game.player());
+        }
+        self.message = format!("Played {}", _move.to_san(&self.game));
+        self.game.push_history(_move);
+        self.restart()
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [main, input] =
+            Layout::vertical([Constraint::Min(12), Constraint::Length(3)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Length(30), Constraint::Min(30)]).areas(main);
+        let [board, clocks] =
+            Layout::vertical([Constraint::Length(12), Constraint::Min(4)]).areas(left);
+        let [lines, graph] =
+            Layout::vertical([Constraint::Percentage(50), Constraint::Percentage(50)]).areas(right);
+
+        frame.render_widget(self.board(), board);
+        frame.render_widget(self.clocks(), clocks);
+        frame.render_widget(self.lines(), lines);
+
+        let points: Vec<(f64, f64)> = self
+            .scores
+            .iter()
+            .enumerate()
+            .filter_map(|(ply, score)| {
+                score.map(|score| {
+                    let pawns = if score::is_mate(score) {
+                        GRAPH_PAWNS * score.signum() as f64
+                    } else {
+                        score as f64 / 100.0
+                    };
+                    (ply as f64, pawns.clamp(-GRAPH_PAWNS, GRAPH_PAWNS))
+                })
+            })
+            .collect();
+        frame.render_widget(self.graph(&points), graph);
+
+        let prompt = Paragraph::new(Line::from(vec![
+            Span::raw("> "),
+            Span::raw(self.input.as_str()).bold(),
+            Span::raw("  "),
+            Span::raw(self.message.as_str()).dim(),
+        ]))
+        .block(Block::bordered());
+        frame.render_widget(prompt, input);
+    }
+
+    fn board(&self) -> Paragraph<'_> {
+        let (rows, files) = if self.flipped {
+            ([0, 1, 2, 3, 4, 5, 6, 7], "  h  g  f  e  d  c  b  a")
+        } else {
+            ([7, 6, 5, 4, 3, 2, 1, 0], "  a  b  c  d  e  f  g  h")
+        };
+
+        let mut text: Vec<Line> = rows
+            .iter()
+            .map(|&row| {
+                let mut spans = vec![Span::raw(format!("{}", row + 1))];
+                for col in 0..8 {
+                    let col = if self.flipped { 7 - col } else { col };
+                    let piece = self.game.get_position(Position::new_assert(row, col));
+                    let symbol = piece.map_or(' ', |piece| piece.as_char());
+                    let square = if (row + col) % 2 == 0 {
+                        DARK_SQUARE
+                    } else {
+                        LIGHT_SQUARE
+                    };
+                    spans.push(Span::styled(
+                        format!(" {} ", symbol),
+                        Style::new().bg(square).fg(Color::Black),
+                    ));
+                }
+                Line::from(spans)
+            })
+            .collect();
+        text.push(Line::raw(files));
+
+        let title = match self.game.outcome() {
+            outcome if outcome.is_over() => format!("Board, {}", outcome.description()),
+            _ => format!("Board, {:?} to move", self.game.player()),
+        };
+        Paragraph::new(text).block(Block::bordered().title(title))
+    }
+
+    fn clocks(&self) -> Paragraph<'_> {
+        let player = self.game.player();
+        let mut text = Vec::new();
+        if let Some(clocks) = &self.clocks {
+            for side in [Player::White, Player::Black] {
+                let left = clocks.left(side, player);
+                let mut line = Line::raw(format!("{:?}: {}", side, format_time(left)));
+                if side == player {
+                    line = line.bold();
+                }
+                if left.is_zero() {
+                    line = line.red();
+                }
+                text.push(line);
+            }
+        }
+        text.push(Line::raw(format!(
+            "Analysis: {}",
+            format_time(self.analysis_start.elapsed())
+        )));
+        if let Some(line) = self.lines.first() {
+            text.push(Line::raw(format!("Nodes: {}", line.nodes)));
+        }
+
+        Paragraph::new(text).block(Block::bordered().title("Clocks"))
+    }
+
+    fn lines(&self) -> Paragraph<'_> {
+        let text: Vec<Line> = self
+            .lines
+            .iter()
+            .map(|info| {
+                let white_score = info.score * self.game.player() as Score;
+                let score = match score::to_pgn(white_score) {
+                    pawns if white_score > 0 && !score::is_mate(white_score) => {
+                        format!("+{}", pawns)
+                    }
+                    text => text,
+                };
+
+                let mut game = self.game.clone();
+                let mut moves = Vec::new();
+                for &_move in &info.pv {
+                    moves.push(_move.to_san(&game));
+                    game.push(_move);
+                }
+                Line::from(vec![
+                    Span::raw(format!("{:>6} ", score)).bold(),
+                    Span::raw(format!("d{:<3}", info.depth)).dim(),
+                    Span::raw(moves.join(" ")),
+                ])
+            })
+            .collect();
+
+        Paragraph::new(text)
+            .wrap(Wrap { trim: true })
+            .block(Block::bordered().title("Lines"))
+    }
+
+    fn graph<'a>(&self, points: &'a [(f64, f64)]) -> Chart<'a> {
+        let plies = (self.scores.len().max(2) - 1) as f64;
+        let dataset = Dataset::default()
+            .marker(Marker::Braille)
+            .graph_type(GraphType::Line)
+            .cyan()
+            .data(points);
+
+        Chart::new(vec![dataset])
+            .block(Block::bordered().title("Evaluation"))
+            .x_axis(Axis::default().bounds([0.0, plies]))
+            .y_axis(
+                Axis::default()
+                    .bounds([-GRAPH_PAWNS, GRAPH_PAWNS])
+                    .labels(["-5", "0", "+5"]),
+            )
+    }
+}
+
+/// A duration as minutes, seconds and tenths
+fn format_time(time: Duration) -> String {
+    let tenths = time.as_millis() / 100;
+    format!("{}:{:02}.{}", tenths / 600, tenths / 10 % 60, tenths % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::{backend::TestBackend, Terminal};
+
+    #[test]
+    fn analysis() {
+        let mut analysis = Analysis::new(Game::default(), 2, Some((60000, 1000)), 1).unwrap();
+        analysis.submit("e4").unwrap();
+        analysis.submit("Nf6").unwrap();
+        assert_eq!(analysis.game.history().len(), 2);
+        analysis.submit("Ke2").unwrap();
+        assert_eq!(analysis.message, "Played Ke2");
+        analysis.submit("O-O").unwrap();
+        assert_eq!(analysis.game.history().len(), 3);
+        analysis.submit("undo").unwrap();
+        assert_eq!(analysis.game.history().len(), 2);
+
+        // The best move is played once the engine found one
+        while analysis.lines.is_empty() {
+            std::thread::sleep(Duration::from_millis(10));
+            analysis.receive();
+        }
+        analysis.submit("").unwrap();
+        assert_eq!(analysis.game.history().len(), 3);
+        // White moved three times with an increment of a second, taking a move back
+        // doesn't give back the time
+        let white_time = analysis.clocks.as_ref().unwrap().remaining[0];
+        assert!(white_time > Duration::from_secs(62) && white_time <= Duration::from_secs(63));
+
+        let mut terminal = Terminal::new(TestBackend::new(100, 30)).unwrap();
+        terminal.draw(|frame| analysis.draw(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        assert!(screen.contains("a  b  c  d  e  f  g  h"));
+        assert!(screen.contains("Black to move"));
+        assert!(screen.contains("White: 1:02."));
+
+        assert_eq!(format_time(Duration::from_millis(754_321)), "12:34.3");
+    }
+}
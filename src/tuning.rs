@@ -0,0 +1,516 @@
+use crate::chess::{
+    epd::Epd,
+    marlinformat::{self, RECORD_SIZE},
+    move_struct::MoveKind,
+    params::{self, EvalParams},
+    pgn::{self, GameResult, PgnMove},
+    piece::{Piece, PieceType},
+    position::Position,
+    Game, GamePhase, Player,
+};
+use anyhow::{bail, Context};
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{BufRead, BufReader},
+};
+
+// Documentation of the method: https://www.chessprogramming.org/Texel%27s_Tuning_Method
+
+/// Every piece-square table is tuned, the king has two of them
+const TABLE_COUNT: usize = 7;
+const PARAMETER_COUNT: usize = TABLE_COUNT * 64;
+
+/// Index of the endgame king table, the other ones follow the order of PieceType
+const KING_END_TABLE: usize = 6;
+
+/// Adam optimizer hyperparameters, the learning rate is measured in centipawns
+const LEARNING_RATE: f64 = 1.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+const EPSILON: f64 = 1e-8;
+
+/// Plies at the start of a game from which no position is extracted, since they are
+/// shared by many games
+const SKIPPED_OPENING_PLIES: usize = 16;
+
+/// A labeled position reduced to the parameters which contribute to its score
+struct Entry {
+    /// Parameter indices paired with the sign of their contribution (1 for white, -1 for black)
+    features: Vec<(u16, i8)>,
+    /// Evaluation terms which aren't tuned, they are constant for this position
+    offset: f64,
+    /// Share of the evaluation kept by the endgame scale factors and the fifty-move rule,
+    /// taken as constant while the parameters change
+    scale: f64,
+    /// Game result from white's perspective: 1.0 win, 0.5 draw, 0.0 loss
+    result: f64,
+}
+
+impl Entry {
+    fn new(game: &Game, result: f64) -> Self {
+        let evaluation = game.evaluation();
+        // The tables are tuned against the evaluation the search uses
+        debug_assert_eq!(evaluation.total(), game.evaluate());
+        Self {
+            features: extract_features(game),
+            offset: (evaluation.sum() - game.score()) as f64,
+            scale: evaluation.scale_ratio(),
+            result,
+        }
+    }
+
+    fn evaluate(&self, parameters: &[f64]) -> f64 {
+        let tables: f64 = self
+            .features
+            .iter()
+            .map(|&(index, sign)| parameters[index as usize] * sign as f64)
+            .sum();
+        self.scale * (self.offset + tables)
+    }
+}
+
+/// Tunes the piece-square tables on a file of positions labeled with game results,
+/// then writes the evaluation parameters with the new tables to `output`, in the format
+/// loaded by the `EvalFile` option
+///
+/// The other evaluation terms are left untouched.
+///
+/// Each line of the file should contain an EPD with the result of the game in its `c9`
+/// operation, e.g. `c9 "1-0";`, or a FEN followed by the result, e.g. `[1.0]`, `[0.5]`.
+/// The positions are expected to be quiet, since they are scored by the static evaluation
+/// and not by a quiescence search.
+pub fn tune(path: &str, epochs: u32, output: &str) -> anyhow::Result<()> {
+    let entries = load_entries(path)?;
+
+    if entries.is_empty() {
+        bail!("No positions found in {}", path);
+    }
+
+    println!("Loaded {} positions", entries.len());
+
+    let mut parameters = initial_parameters();
+
+    let k = find_scaling_constant(&entries, &parameters);
+    println!("Scaling constant: {:.4}", k);
+    println!("Initial error: {:.8}", mean_error(&entries, &parameters, k));
+
+    let mut momentum = vec![0.0; PARAMETER_COUNT];
+    let mut velocity = vec![0.0; PARAMETER_COUNT];
+
+    for epoch in 1..=epochs {
+        let gradient = gradient(&entries, &parameters, k);
+
+        for index in 0..PARAMETER_COUNT {
+            momentum[index] = BETA1 * momentum[index] + (1.0 - BETA1) * gradient[index];
+            velocity[index] = BETA2 * velocity[index] + (1.0 - BETA2) * gradient[index].powi(2);
+
+            let momentum_hat = momentum[index] / (1.0 - BETA1.powi(epoch as i32));
+            let velocity_hat = velocity[index] / (1.0 - BETA2.powi(epoch as i32));
+
+            parameters[index] -= LEARNING_RATE * momentum_hat / (velocity_hat.sqrt() + EPSILON);
+        }
+
+        if epoch % 50 == 0 || epoch == epochs {
+            println!(
+                "Epoch {}: error {:.8}",
+                epoch,
+                mean_error(&entries, &parameters, k)
+            );
+        }
+    }
+
+    fs::write(output, tuned_params(&parameters).to_string())
+        .with_context(|| format!("Failed to write tuned tables to {}", output))?;
+
+    println!("Tuned tables written to {}", output);
+
+    Ok(())
+}
+
+/// Writes the positions of the games of a PGN file, labeled with their results,
+/// to `output` in the format read by `tune`
+///
+/// Only quiet positions are kept: the player to move isn't in check and the move
+/// played doesn't capture or promote. Unfinished games are skipped.
+///
+/// Outputs ending in `.bin` are written in Marlinformat instead of EPD, much smaller and
+/// faster to read, with the static evaluation of every position as its score.
+pub fn extract(path: &str, output: &str) -> anyhow::Result<()> {
+    let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}", path))?;
+    let games = pgn::parse_pgn(&text).with_context(|| format!("Failed to parse {}", path))?;
+
+    let binary = output.ends_with(".bin");
+    let mut positions = String::new();
+    let mut records = Vec::new();
+    let mut count = 0;
+
+    for pgn_game in &games {
+        if pgn_game.result == GameResult::Unfinished {
+            println!(
+                "Skipped unfinished game {} - {}",
+                pgn_game.tag("White").unwrap_or("?"),
+                pgn_game.tag("Black").unwrap_or("?")
+            );
+            continue;
+        }
+
+        let mut game = pgn_game.start.clone();
+        for (ply, &PgnMove { _move, .. }) in pgn_game.moves.iter().enumerate() {
+            let is_quiet = !game.is_check()
+                && _move.captured_piece(&game).is_none()
+                && !matches!(_move.kind(), MoveKind::Promotion(_));
+
+            if ply >= SKIPPED_OPENING_PLIES && is_quiet {
+                if binary {
+                    records.extend(marlinformat::pack(&game, game.evaluate(), pgn_game.result));
+                } else {
+                    let mut epd = Epd::new(game.clone());
+                    let label = pgn_game.result.as_str().to_string();
+                    epd.operations.push((String::from("c9"), vec![label]));
+                    writeln!(positions, "{}", epd)?;
+                }
+                count += 1;
+            }
+            game.push(_move);
+        }
+    }
+
+    if binary {
+        fs::write(output, records)
+    } else {
+        fs::write(output, positions)
+    }
+    .with_context(|| format!("Failed to write {}", output))?;
+
+    println!(
+        "Extracted {} positions from {} games to {}",
+        count,
+        games.len(),
+        output
+    );
+
+    Ok(())
+}
+
+fn load_entries(path: &str) -> anyhow::Result<Vec<Entry>> {
+    if path.ends_with(".bin") {
+        return load_records(path);
+    }
+
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {}", path))?;
+
+    let mut entries = Vec::new();
+
+    for (line_number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {}", path))?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry = parse_entry(&line).with_context(|| format!("Line {}", line_number + 1))?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+/// Reads a file of Marlinformat records, as written by `extract`
+fn load_records(path: &str) -> anyhow::Result<Vec<Entry>> {
+    let bytes = fs::read(path).with_context(|| format!("Failed to read {}", path))?;
+    if bytes.len() % RECORD_SIZE != 0 {
+        bail!("{} isn't made of {} byte records", path, RECORD_SIZE);
+    }
+
+    let mut entries = Vec::with_capacity(bytes.len() / RECORD_SIZE);
+    for (index, record) in bytes.chunks_exact(RECORD_SIZE).enumerate() {
+        let (game, _, result) = marlinformat::unpack(record.try_into().unwrap())
+            .with_context(|| format!("Record {}", index + 1))?;
+        let result = match result {
+            GameResult::WhiteWins => 1.0,
+            GameResult::Draw => 0.5,
+            _ => 0.0,
+        };
+        entries.push(Entry::new(&game, result));
+    }
+
+    Ok(entries)
+}
+
+fn parse_entry(line: &str) -> anyhow::Result<Entry> {
+    let epd = Epd::parse(line)?;
+
+    // The result is in the c9 operation, or written on its own after the position
+    let result_term = match epd.operation("c9") {
+        Some(result) => result,
+        None => line
+            .split_ascii_whitespace()
+            .last()
+            .context("Missing game result")?,
+    }
+    .trim_matches(|c| matches!(c, '[' | ']' | '"' | ';'));
+
+    let result = match result_term {
+        "1-0" | "1.0" | "1" => 1.0,
+        "1/2-1/2" | "0.5" => 0.5,
+        "0-1" | "0.0" | "0" => 0.0,
+        _ => bail!("Invalid game result"),
+    };
+
+    Ok(Entry::new(&epd.game, result))
+}
+
+/// Lists the table entries which are summed up by the evaluation of the game
+fn extract_features(game: &Game) -> Vec<(u16, i8)> {
+    let mut features = Vec::with_capacity(32);
+
+    for row in 0..8 {
+        for col in 0..8 {
+            let position = Position::new_assert(row, col);
+            if let Some(piece) = game.get_position(position) {
+                features.push((
+                    parameter_index(piece, position, game.phase()) as u16,
+                    piece.owner as i8,
+                ));
+            }
+        }
+    }
+
+    features
+}
+
+/// Mirrors the lookup done by Piece::score
+fn parameter_index(piece: Piece, position: Position, phase: GamePhase) -> usize {
+    let table = match phase {
+        GamePhase::Endgame if piece.piece_type == PieceType::King => KING_END_TABLE,
+        _ => piece.piece_type as usize,
+    };
+
+    let row = match piece.owner {
+        Player::White => 7 - position.row(),
+        Player::Black => position.row(),
+    };
+
+    table * 64 + Position::new_assert(row, position.col()).as_usize()
+}
+
+fn initial_parameters() -> Vec<f64> {
+    let params = params::default();
+    [
+        &params.queen_scores,
+        &params.rook_scores,
+        &params.bishop_scores,
+        &params.knight_scores,
+        &params.pawn_scores,
+        &params.king_scores_middle,
+        &params.king_scores_end,
+    ]
+    .iter()
+    .flat_map(|table| table.iter().map(|&score| score as f64))
+    .collect()
+}
+
+/// Maps a centipawn score to the expected game result
+fn sigmoid(score: f64, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * score / 400.0))
+}
+
+fn mean_error(entries: &[Entry], parameters: &[f64], k: f64) -> f64 {
+    let total: f64 = entries
+        .iter()
+        .map(|entry| (entry.result - sigmoid(entry.evaluate(parameters), k)).powi(2))
+        .sum();
+
+    total / entries.len() as f64
+}
+
+/// Gradient of the mean error with respect to every parameter
+fn gradient(entries: &[Entry], parameters: &[f64], k: f64) -> Vec<f64> {
+    let mut gradient = vec![0.0; PARAMETER_COUNT];
+
+    for entry in entries {
+        let prediction = sigmoid(entry.evaluate(parameters), k);
+
+        // Derivative of (result - sigmoid(score))^2 with respect to score
+        let derivative = -2.0
+            * (entry.result - prediction)
+            * prediction
+            * (1.0 - prediction)
+            * k
+            * std::f64::consts::LN_10
+            / 400.0;
+
+        for &(index, sign) in &entry.features {
+            gradient[index as usize] += derivative * entry.scale * sign as f64;
+        }
+    }
+
+    for value in &mut gradient {
+        *value /= entries.len() as f64;
+    }
+
+    gradient
+}
+
+/// Finds the constant K which minimizes the error of the current evaluation,
+/// by searching with finer and finer steps around the best value found so far
+fn find_scaling_constant(entries: &[Entry], parameters: &[f64]) -> f64 {
+    let mut best_k = 1.0;
+    let mut best_error = mean_error(entries, parameters, best_k);
+    let mut step = 1.0;
+
+    for _ in 0..6 {
+        let start = best_k;
+        for i in -10..=10 {
+            let k = start + i as f64 * step / 10.0;
+            if k <= 0.0 {
+                continue;
+            }
+
+            let error = mean_error(entries, parameters, k);
+            if error < best_error {
+                best_error = error;
+                best_k = k;
+            }
+        }
+        step /= 10.0;
+    }
+
+    best_k
+}
+
+/// The default parameters with the tables replaced by the tuned ones
+fn tuned_params(parameters: &[f64]) -> EvalParams {
+    let mut params = params::default().as_ref().clone();
+    for (table, values) in [
+        &mut params.queen_scores,
+        &mut params.rook_scores,
+        &mut params.bishop_scores,
+        &mut params.knight_scores,
+        &mut params.pawn_scores,
+        &mut params.king_scores_middle,
+        &mut params.king_scores_end,
+    ]
+    .into_iter()
+    .zip(parameters.chunks_exact(64))
+    {
+        for (score, value) in table.iter_mut().zip(values) {
+            *score = value.round() as i16;
+        }
+    }
+
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chess::move_struct::Move, constants::TESTING_GAME};
+
+    #[test]
+    fn parse_result_formats() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+        for (suffix, result) in [
+            ("[1.0]", 1.0),
+            ("[0.5]", 0.5),
+            ("[0.0]", 0.0),
+            ("c9 \"1-0\";", 1.0),
+            ("c9 \"1/2-1/2\";", 0.5),
+            ("c9 \"0-1\";", 0.0),
+        ] {
+            let entry = parse_entry(&format!("{} {}", fen, suffix)).unwrap();
+            assert_eq!(entry.result, result);
+        }
+
+        assert!(parse_entry(&format!("{} [2.0]", fen)).is_err());
+
+        // The usual EPD lines, where c9 is not a halfmove clock
+        let epd = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - c9 \"1-0\";";
+        let entry = parse_entry(epd).unwrap();
+        assert_eq!(entry.result, 1.0);
+        assert_eq!(entry.features, extract_features(&Game::default()));
+
+        // Positions written by extract have no FEN counters
+        let epd =
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - hmvc 0; fmvn 1; c9 \"0-1\";";
+        assert_eq!(parse_entry(epd).unwrap().result, 0.0);
+    }
+
+    #[test]
+    fn features_match_evaluation() {
+        let mut game = Game::default();
+        let parameters = initial_parameters();
+
+        for _move in TESTING_GAME.split_ascii_whitespace().take(30) {
+            let _move = Move::from_uci_notation(_move, &game).unwrap();
+            game.push(_move);
+
+            // The evaluation rounds after each scaling
+            let score = Entry::new(&game, 0.0).evaluate(&parameters);
+            assert!((score - game.evaluate() as f64).abs() < 2.0);
+        }
+
+        // Drawish endgames and positions close to the fifty-move rule are scaled down, along
+        // with the change of the tables
+        let mut changed = params::default().as_ref().clone();
+        changed
+            .pawn_scores
+            .iter_mut()
+            .for_each(|score| *score += 40);
+        let changed = std::sync::Arc::new(changed);
+        let mut changed_parameters = parameters.clone();
+        let pawns = PieceType::Pawn as usize * 64;
+        changed_parameters[pawns..pawns + 64]
+            .iter_mut()
+            .for_each(|score| *score += 40.0);
+        for fen in [
+            "8/8/4k3/8/3B4/5b2/4P3/4K3 w - - 0 60",
+            "4k3/8/8/8/8/8/3PP3/3QK3 w - - 80 90",
+        ] {
+            let game = Game::new(fen).unwrap();
+            let entry = Entry::new(&game, 0.0);
+            assert!(entry.scale < 1.0);
+            assert!((entry.evaluate(&parameters) - game.evaluate() as f64).abs() < 2.0);
+
+            let changed_game = Game::with_params(fen, changed.clone()).unwrap();
+            let score = entry.evaluate(&changed_parameters);
+            assert!((score - changed_game.evaluate() as f64).abs() < 2.0);
+        }
+    }
+
+    #[test]
+    fn tuned_params_round_trip() {
+        let directory = std::env::temp_dir();
+        let path = directory.join(format!("rustybait-tune-{}.epd", std::process::id()));
+        let output = directory.join(format!("rustybait-tuned-{}.txt", std::process::id()));
+
+        let mut game = Game::default();
+        let mut positions = String::new();
+        for (ply, _move) in TESTING_GAME.split_ascii_whitespace().take(40).enumerate() {
+            game.push(Move::from_uci_notation(_move, &game).unwrap());
+            let result = ["[1.0]", "[0.5]", "[0.0]"][ply % 3];
+            writeln!(positions, "{} {}", game.to_fen(), result).unwrap();
+        }
+        fs::write(&path, positions).unwrap();
+
+        tune(path.to_str().unwrap(), 10, output.to_str().unwrap()).unwrap();
+        let text = fs::read_to_string(&output).unwrap();
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&output).unwrap();
+
+        // Only the tables change, and the file loads back as the same parameters
+        let tuned = EvalParams::parse(&text).unwrap();
+        let defaults = params::default();
+        assert_eq!(tuned.doubled_pawn_penalty, defaults.doubled_pawn_penalty);
+        assert_eq!(tuned.mobility_bonus, defaults.mobility_bonus);
+        assert_ne!(tuned, *defaults);
+
+        let mut parameters = initial_parameters();
+        parameters[KING_END_TABLE * 64] += 12.4;
+        let params = tuned_params(&parameters);
+        assert_eq!(params.king_scores_end[0], defaults.king_scores_end[0] + 12);
+        assert_eq!(EvalParams::parse(&params.to_string()).unwrap(), params);
+    }
+}
@@ -1,329 +1,906 @@
 use crate::{
-    chess::{move_struct::Move, Game, Player},
-    constants::TT_CAPACITY,
-    search::{get_best_move_until_stop, TranspositionTable},
+    analysis::JsonReport,
+    chess::{
+        move_struct::{Move, UciMoveError},
+        score, Game, Player, SanMoveError, START_FEN,
+    },
+    config,
+    constants::{DEFAULT_CONTEMPT, DEFAULT_HASH_MB},
+    engine::{
+        self, Engine, EngineEvent, BOOK_DEPTH_RANGE, BOOK_RANDOMNESS_RANGE, CONTEMPT_RANGE,
+        HASH_RANGE, MOVE_TIME_RANGE, MULTI_PV_RANGE,
+    },
+    json::{self, Message},
+    log::{self, Level},
+    search::{SearchInfo, SearchLimits},
+    spsa,
+    strength::LEVEL_RANGE,
 };
 use anyhow::{bail, Context};
-use arrayvec::ArrayVec;
-use nohash_hasher::BuildNoHashHasher;
 use std::{
-    collections::HashMap,
-    io::stdin,
+    io::{self, stdin, BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
     str::SplitAsciiWhitespace,
-    sync::{
-        atomic::{AtomicBool, Ordering::Relaxed},
-        Arc, Mutex,
-    },
+    sync::{mpsc::Receiver, Arc, Mutex},
     thread::{self, JoinHandle},
     time::Duration,
 };
 
+/// Prints a line for the GUI on `output`, like `println!`, and writes it to the log after
+/// `<< `, where the commands received are written after `>> `
+macro_rules! send {
+    ($output:expr, $($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        if json::is_enabled() {
+            $output.write_json(&Message::Line { text: &line });
+        } else {
+            $output.write_line(&line);
+            log::info!("<< {}", line);
+        }
+    }};
+}
+
+/// Prints `error: ` and the message for the GUI on `output`, which is also an error of the log
+macro_rules! send_error {
+    ($output:expr, $($arg:tt)*) => {{
+        let line = format!($($arg)*);
+        if json::is_enabled() {
+            $output.write_json(&Message::Error { message: &line });
+        } else {
+            $output.write_line(&format!("error: {}", line));
+            log::error!("<< error: {}", line);
+        }
+    }};
+}
+
+/// Where the answers for the GUI go, stdout or its TCP connection, shared with the thread
+/// printing the events of the searches
+#[derive(Clone)]
+pub struct Output(Arc<Mutex<dyn Write + Send>>);
+
+impl Output {
+    pub fn stdout() -> Self {
+        Self::new(io::stdout())
+    }
+
+    fn new(writer: impl Write + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(writer)))
+    }
+
+    fn write_line(&self, line: &str) {
+        // A GUI which is gone is noticed when its next command can't be read
+        let _ = writeln!(self.0.lock().unwrap(), "{}", line);
+    }
+
+    fn write_json(&self, message: &Message) {
+        json::write(&mut *self.0.lock().unwrap(), message);
+    }
+}
+
 struct Data {
-    current_game: Option<Game>,
-    cache: TranspositionTable,
+    engine: Engine,
+    output: Output,
+    /// Whether a position was set since the last search, which is needed by `go`
+    has_position: bool,
+    /// Whether to report win, draw and loss probabilities with the score
+    show_wdl: bool,
+    /// Whether to name the opening of the game with `info string` before searching
+    show_opening: bool,
+    /// Whether the positions are searched until `stop` as soon as they are set, since the
+    /// `analyze` command
+    analyzing: bool,
 }
 
 impl Data {
-    fn mut_refs(&mut self) -> (&mut Option<Game>, &mut TranspositionTable) {
-        (&mut self.current_game, &mut self.cache)
+    fn new(output: Output) -> (Self, Receiver<EngineEvent>) {
+        let (engine, events) = Engine::new();
+        let data = Self {
+            engine,
+            output,
+            has_position: false,
+            show_wdl: false,
+            show_opening: false,
+            analyzing: false,
+        };
+        (data, events)
+    }
+
+    fn game(&self) -> Option<&Game> {
+        self.has_position.then(|| self.engine.position())
     }
 }
 
-/// Enter uci mode and wait for commands
+/// Enter uci mode and wait for commands, after setting the options of the configuration
+/// file at `config_path`, or of `rustybait.ini` if there is one, and then `options`, those
+/// of the command line
 ///
 /// Specification of UCI standard source
-/// https://gist.github.com/DOBRO/2592c6dad754ba67e6dcaec8c90165bf
-pub fn uci_talk() -> anyhow::Result<()> {
-    let data = Arc::new(Mutex::new(Data {
-        current_game: None,
-        cache: HashMap::with_capacity_and_hasher(TT_CAPACITY, BuildNoHashHasher::default()),
-    }));
+/// <https://gist.github.com/DOBRO/2592c6dad754ba67e6dcaec8c90165bf>
+pub fn uci_talk(config_path: Option<&str>, options: &[(String, String)]) -> anyhow::Result<()> {
+    let (data, events) = setup(config_path, options, Output::stdout())?;
+    talk(data, events, stdin().lock())
+}
 
-    let mut search_thread: Option<JoinHandle<()>> = None;
-    let mut search_is_running = Arc::new(AtomicBool::new(false));
+/// Speaks UCI over the TCP connections to `address`, e.g. `0.0.0.0:5000`, instead of stdin
+/// and stdout, for GUIs on other machines or engines in containers
+///
+/// GUIs are served one at a time, each with an engine of its own set up like in `uci_talk`,
+/// the next one is accepted after the previous one quits or disconnects.
+pub fn uci_listen(
+    address: &str,
+    config_path: Option<&str>,
+    options: &[(String, String)],
+) -> anyhow::Result<()> {
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("Failed to listen on {}", address))?;
+    println!("Listening on {}", listener.local_addr()?);
+    accept(listener.incoming(), config_path, options)
+}
 
-    'main_loop: for line in stdin().lines() {
-        let line = line.context("Failed to read line from stdin")?;
+/// Serves the `connections` one after the other. An invalid option ends the server, since
+/// it is the same for every GUI, but an error of a connection only ends that connection.
+fn accept(
+    connections: impl Iterator<Item = io::Result<TcpStream>>,
+    config_path: Option<&str>,
+    options: &[(String, String)],
+) -> anyhow::Result<()> {
+    for stream in connections {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                log::warning!("Failed to accept connection: {}", err);
+                continue;
+            }
+        };
+        let peer = stream
+            .peer_addr()
+            .map_or_else(|_| String::from("unknown address"), |peer| peer.to_string());
+        log::info!("Connection from {}", peer);
+
+        let writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(err) => {
+                log::warning!("Connection from {} failed: {}", peer, err);
+                continue;
+            }
+        };
+        let (data, events) = setup(config_path, options, Output::new(writer))?;
+        match talk(data, events, BufReader::new(stream)) {
+            Ok(()) => log::info!("Connection from {} closed", peer),
+            Err(err) => log::warning!("Connection from {} ended: {:#}", peer, err),
+        }
+    }
+    Ok(())
+}
+
+/// Creates the engine of a GUI answering on `output`, with the options of the configuration
+/// file and then those of the command line
+fn setup(
+    config_path: Option<&str>,
+    options: &[(String, String)],
+    output: Output,
+) -> anyhow::Result<(Data, Receiver<EngineEvent>)> {
+    let (mut data, events) = Data::new(output);
+    let config_path = config_path.unwrap_or(config::DEFAULT_PATH);
+    let file_options = config::load(config_path)?;
+    for (name, value) in &file_options {
+        set_option(&mut data, name, value)
+            .with_context(|| format!("Invalid option {} in {}", name, config_path))?;
+    }
+    if !file_options.is_empty() {
+        log::info!("Read {} options from {}", file_options.len(), config_path);
+    }
+    for (name, value) in options {
+        set_option(&mut data, name, value).with_context(|| format!("Invalid --{}", name))?;
+    }
+    Ok((data, events))
+}
+
+/// Answers the commands of `input` until `quit` or its end
+fn talk(mut data: Data, events: Receiver<EngineEvent>, input: impl BufRead) -> anyhow::Result<()> {
+    // Taken by the thread printing the events of every search
+    let events = Arc::new(Mutex::new(events));
+    let mut printer: Option<JoinHandle<()>> = None;
+
+    let mut result = Ok(());
+
+    'main_loop: for line in input.lines() {
+        let line = match line {
+            Ok(line) => line,
+            // The GUI closed its connection without `quit`
+            Err(err) if is_disconnect(&err) => break,
+            Err(err) => {
+                result = Err(err).context("Failed to read command");
+                break;
+            }
+        };
+        log::info!(">> {}", line);
 
         let mut terms = line.split_ascii_whitespace();
 
         while let Some(term) = terms.next() {
             match term {
                 "uci" => {
-                    command_uci();
+                    command_uci(&data.output);
                 }
                 "ucinewgame" => {
-                    if search_is_running.load(Relaxed) {
-                        let thread =
-                            search_thread.context("There should a search thread running")?;
-                        search_is_running.store(false, Relaxed);
-                        thread.join().unwrap();
-                        search_thread = None;
-                    }
-                    let mut data = data.lock().unwrap();
-                    command_ucinewgame(&mut data);
+                    data.engine.new_game();
+                    join(&mut printer);
+                    data.has_position = false;
+                    data.analyzing = false;
                 }
                 "isready" => {
-                    command_isready();
+                    command_isready(&data.output);
+                }
+                "setoption" => {
+                    if data.engine.is_searching() {
+                        send_error!(
+                            data.output,
+                            "search is still running, enter 'stop' to stop it"
+                        );
+                    } else if let Err(err) = command_setoption(&mut data, &mut terms) {
+                        send_error!(data.output, "{}", err);
+                    }
                 }
                 "position" => {
-                    if search_is_running.load(Relaxed) {
-                        println!("error: search is still running, enter 'stop' to stop it");
+                    if data.analyzing {
+                        // The analysis goes on with the new position
+                        data.engine.stop();
+                        join(&mut printer);
+                    }
+
+                    if data.engine.is_searching() {
+                        send_error!(
+                            data.output,
+                            "search is still running, enter 'stop' to stop it"
+                        );
+                    } else if let Err(err) = command_position(&mut data, &mut terms) {
+                        data.analyzing = false;
+                        send_error!(data.output, "{}", err);
+                    } else if data.analyzing {
+                        start_analysis(&mut data, &events, &mut printer);
+                    }
+                }
+                "analyze" => {
+                    if data.engine.is_searching() {
+                        send_error!(
+                            data.output,
+                            "search is still running, enter 'stop' to stop it"
+                        );
                     } else {
-                        let mut data = data.lock().unwrap();
-                        if let Err(err) = command_position(&mut data, &mut terms) {
-                            println!("error: {}", err);
-                        };
+                        join(&mut printer);
+                        data.analyzing = true;
+                        start_analysis(&mut data, &events, &mut printer);
                     }
                 }
                 "go" => {
-                    if search_is_running.load(Relaxed) {
-                        println!("error: search is still running, enter 'stop' to stop it");
+                    if data.engine.is_searching() {
+                        send_error!(
+                            data.output,
+                            "search is still running, enter 'stop' to stop it"
+                        );
                     } else {
-                        // Create new bool such that if the old sleep threaed is still runnning
-                        // it won't affect this new search
-                        search_is_running = Arc::new(AtomicBool::new(false));
-                        match command_go(&data, &mut terms, &search_is_running) {
-                            Ok(thread) => search_thread = Some(thread),
-                            Err(err) => println!("error: {}", err),
+                        join(&mut printer);
+                        match command_go(&mut data, &mut terms, &events) {
+                            Ok(thread) => printer = Some(thread),
+                            Err(err) => send_error!(data.output, "{}", err),
                         }
                     }
                 }
-                "show" | "d" => {
-                    if search_is_running.load(Relaxed) {
-                        println!("error: search is still running, enter 'stop' to stop it");
-                    } else {
-                        let data = data.lock().unwrap();
-                        if let Err(err) = command_show(&data) {
-                            println!("error: {}", err);
-                        };
+                "show" | "d" | "display" => {
+                    if data.engine.is_searching() {
+                        send_error!(
+                            data.output,
+                            "search is still running, enter 'stop' to stop it"
+                        );
+                    } else if let Err(err) = command_show(&data) {
+                        send_error!(data.output, "{}", err);
                     }
                 }
-                "stop" => {
-                    search_is_running.store(false, Relaxed);
-                    if let Some(thread) = search_thread {
-                        thread.join().unwrap();
-                        search_thread = None;
+                "eval" => {
+                    if data.engine.is_searching() {
+                        send_error!(
+                            data.output,
+                            "search is still running, enter 'stop' to stop it"
+                        );
+                    } else if let Err(err) = command_eval(&data) {
+                        send_error!(data.output, "{}", err);
                     }
                 }
-                "wait" => {
-                    if let Some(thread) = search_thread {
-                        thread.join().unwrap();
-                        search_thread = None;
-                        search_is_running.store(false, Relaxed);
+                "params" => {
+                    for line in data.engine.params().to_string().lines() {
+                        send!(data.output, "{}", line);
                     }
                 }
+                "stop" => {
+                    data.analyzing = false;
+                    data.engine.stop();
+                    join(&mut printer);
+                }
+                "wait" => {
+                    data.engine.wait();
+                    join(&mut printer);
+                }
                 "quit" => {
                     break 'main_loop;
                 }
                 _ => continue,
             }
 
-            break;
+            continue 'main_loop;
+        }
+
+        if !line.trim().is_empty() {
+            log::warning!("Unknown command {}", line);
         }
     }
 
-    Ok(())
+    // The best move of a running search goes to this GUI and not to the next connection
+    data.engine.stop();
+    join(&mut printer);
+    result
+}
+
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+    )
+}
+
+/// Searches the position until the next `stop` or `position` command, like `go infinite`
+fn start_analysis(
+    data: &mut Data,
+    events: &Arc<Mutex<Receiver<EngineEvent>>>,
+    printer: &mut Option<JoinHandle<()>>,
+) {
+    match command_go(data, &mut "infinite".split_ascii_whitespace(), events) {
+        Ok(thread) => *printer = Some(thread),
+        Err(err) => {
+            data.analyzing = false;
+            send_error!(data.output, "{}", err);
+        }
+    }
+}
+
+/// Waits until the printer has written the best move of the last search
+fn join(printer: &mut Option<JoinHandle<()>>) {
+    if let Some(printer) = printer.take() {
+        printer.join().unwrap();
+    }
 }
 
-fn command_uci() {
-    println!("id name rustybait");
-    println!("id author Malanca Daniel");
-    println!("uciok");
+/// Runs the `position` and `setoption` commands of `text`, one per line, and returns the
+/// game they set up. Errors are ignored like in `uci_talk`.
+///
+/// Options naming files aren't set, the fuzzing harness shouldn't read or write arbitrary
+/// files, nor the size of the hash table, which could take all the memory.
+#[cfg(any(test, feature = "fuzzing"))]
+pub fn apply_commands(text: &str) -> Option<Game> {
+    let (mut data, _) = Data::new(Output::new(io::sink()));
+
+    for line in text.lines() {
+        let mut terms = line.split_ascii_whitespace();
+        let lowercase = line.to_ascii_lowercase();
+        let _ = match terms.next() {
+            Some("position") => command_position(&mut data, &mut terms),
+            Some("setoption") if !lowercase.contains("file") && !lowercase.contains("hash") => {
+                command_setoption(&mut data, &mut terms)
+            }
+            _ => Ok(()),
+        };
+    }
+
+    data.game().cloned()
 }
 
-fn command_ucinewgame(data: &mut Data) {
-    data.cache.clear();
-    data.current_game = None;
+fn command_uci(output: &Output) {
+    send!(output, "id name rustybait");
+    send!(output, "id author Malanca Daniel");
+    send!(
+        output,
+        "option name Contempt type spin default {} min {} max {}",
+        DEFAULT_CONTEMPT,
+        CONTEMPT_RANGE.start(),
+        CONTEMPT_RANGE.end()
+    );
+    send!(output, "option name UCI_ShowWDL type check default false");
+    send!(output, "option name UCI_Chess960 type check default false");
+    send!(output, "option name ShowOpening type check default false");
+    send!(output, "option name EvalFile type string default <empty>");
+    send!(output, "option name BookFile type string default <empty>");
+    send!(
+        output,
+        "option name BookRandomness type spin default {} min {} max {}",
+        BOOK_RANDOMNESS_RANGE.end(),
+        BOOK_RANDOMNESS_RANGE.start(),
+        BOOK_RANDOMNESS_RANGE.end()
+    );
+    send!(
+        output,
+        "option name BookDepth type spin default 0 min {} max {}",
+        BOOK_DEPTH_RANGE.start(),
+        BOOK_DEPTH_RANGE.end()
+    );
+    send!(
+        output,
+        "option name Hash type spin default {} min {} max {}",
+        DEFAULT_HASH_MB,
+        HASH_RANGE.start(),
+        HASH_RANGE.end()
+    );
+    send!(
+        output,
+        "option name Threads type spin default 1 min 1 max 1"
+    );
+    send!(
+        output,
+        "option name MoveTime type spin default 0 min {} max {}",
+        MOVE_TIME_RANGE.start(),
+        MOVE_TIME_RANGE.end()
+    );
+    send!(
+        output,
+        "option name MultiPV type spin default 1 min {} max {}",
+        MULTI_PV_RANGE.start(),
+        MULTI_PV_RANGE.end()
+    );
+    send!(
+        output,
+        "option name SkillLevel type spin default {} min {} max {}",
+        LEVEL_RANGE.end(),
+        LEVEL_RANGE.start(),
+        LEVEL_RANGE.end()
+    );
+    for tunable in &spsa::TUNABLES {
+        send!(
+            output,
+            "option name {} type spin default {} min {} max {}",
+            tunable.name,
+            tunable.default,
+            tunable.min,
+            tunable.max
+        );
+    }
+    send!(output, "option name LogFile type string default <empty>");
+    send!(
+        output,
+        "option name LogLevel type combo default info var error var warn var info var debug"
+    );
+    send!(output, "uciok");
 }
 
-fn command_isready() {
-    println!("readyok");
+/// Parses `setoption name <name> value <value>`, the name may contain spaces
+fn command_setoption(data: &mut Data, terms: &mut SplitAsciiWhitespace<'_>) -> anyhow::Result<()> {
+    if terms.next() != Some("name") {
+        bail!("Invalid setoption command");
+    }
+
+    let name = terms
+        .by_ref()
+        .take_while(|&term| term != "value")
+        .collect::<Vec<_>>()
+        .join(" ");
+    let value = terms.collect::<Vec<_>>().join(" ");
+
+    set_option(data, &name, &value)
 }
 
+/// Sets an option given by `setoption` or the configuration file
+fn set_option(data: &mut Data, name: &str, value: &str) -> anyhow::Result<()> {
+    // The options of the output are handled here, the others by the engine
+    match name.to_ascii_lowercase().as_str() {
+        "uci_showwdl" => data.show_wdl = engine::parse_check("UCI_ShowWDL", value)?,
+        "showopening" => data.show_opening = engine::parse_check("ShowOpening", value)?,
+        "logfile" => {
+            let path = (!value.is_empty() && value != "<empty>").then_some(value);
+            log::set_file(path)?;
+        }
+        "loglevel" => log::set_level(value.parse::<Level>()?),
+        _ => data.engine.set_option(name, value)?,
+    }
+
+    Ok(())
+}
+
+fn command_isready(output: &Output) {
+    send!(output, "readyok");
+}
+
+/// Prints the board and the state of the position, for debugging
 fn command_show(data: &Data) -> anyhow::Result<()> {
-    if let Some(game) = data.current_game.as_ref() {
-        println!("{}", game);
-    } else {
+    let Some(game) = data.game() else {
         bail!("No game to show, please set a position first");
+    };
+
+    send!(data.output, "{}", game);
+    // The castling rights and the en passant square are written as in the FEN
+    let fen = game.to_fen();
+    let fields: Vec<&str> = fen.split(' ').collect();
+    send!(data.output, "Side to move: {:?}", game.player());
+    send!(data.output, "Castling rights: {}", fields[2]);
+    send!(data.output, "En passant: {}", fields[3]);
+    send!(
+        data.output,
+        "Static evaluation: {} cp (white's point of view)",
+        game.evaluate()
+    );
+    // The key of opening books, to look the position up with other tools
+    send!(data.output, "Polyglot key: {:016X}", game.polyglot_key());
+
+    Ok(())
+}
+
+fn command_eval(data: &Data) -> anyhow::Result<()> {
+    if let Some(game) = data.game() {
+        send!(data.output, "{}", game.evaluation());
+    } else {
+        bail!("No game to evaluate, please set a position first");
     }
 
     Ok(())
 }
 
 fn command_go(
-    data_mutex: &Arc<Mutex<Data>>,
+    data: &mut Data,
     terms: &mut SplitAsciiWhitespace<'_>,
-    search_is_running: &Arc<AtomicBool>,
+    events: &Arc<Mutex<Receiver<EngineEvent>>>,
 ) -> anyhow::Result<JoinHandle<()>> {
-    let mut data = data_mutex.lock().unwrap();
-    let Some(game) = data.current_game.as_mut() else {
+    let Some(game) = data.game() else {
         bail!("No game to play, please set a position first");
     };
+    let game = game.clone();
+
+    if data.show_opening {
+        if let Some(opening) = game.opening() {
+            send!(
+                data.output,
+                "info string Opening {} {}",
+                opening.eco,
+                opening.name
+            );
+        }
+    }
 
-    let mut wtime: Option<u64> = None;
-    let mut btime: Option<u64> = None;
-    let mut winc: Option<u64> = None;
-    let mut binc: Option<u64> = None;
-    let mut depth: Option<u8> = None;
-    let mut move_time: Option<u64> = None;
-    let mut infinite = false;
+    let mut limits = SearchLimits::new();
+    let mut clocks = [(None, Duration::ZERO); 2];
+    let mut terms = terms.peekable();
+    let millis = |term: Option<&str>| term.and_then(|s| s.parse().ok()).map(Duration::from_millis);
+    // Depths and move counts beyond what a search can reach are clamped instead of ignored
+    let count = |term: Option<&str>| {
+        term.and_then(|s| s.parse::<u64>().ok())
+            .map(|count| u8::try_from(count).unwrap_or(u8::MAX))
+    };
 
     while let Some(term) = terms.next() {
         match term {
-            "wtime" => wtime = terms.next().and_then(|s| s.parse().ok()),
-            "btime" => btime = terms.next().and_then(|s| s.parse().ok()),
-            "winc" => winc = terms.next().and_then(|s| s.parse().ok()),
-            "binc" => binc = terms.next().and_then(|s| s.parse().ok()),
-            "depth" => depth = terms.next().and_then(|s| s.parse().ok()),
-            "movetime" => move_time = terms.next().and_then(|s| s.parse().ok()),
-            "infinite" => infinite = true,
+            "wtime" => clocks[0].0 = millis(terms.next()),
+            "btime" => clocks[1].0 = millis(terms.next()),
+            "winc" => clocks[0].1 = millis(terms.next()).unwrap_or_default(),
+            "binc" => clocks[1].1 = millis(terms.next()).unwrap_or_default(),
+            "movetime" => {
+                if let Some(move_time) = millis(terms.next()) {
+                    limits = limits.move_time(move_time);
+                }
+            }
+            "depth" => {
+                if let Some(depth) = count(terms.next()) {
+                    limits = limits.depth(depth);
+                }
+            }
+            "nodes" => {
+                if let Some(nodes) = terms.next().and_then(|s| s.parse().ok()) {
+                    limits = limits.nodes(nodes);
+                }
+            }
+            "mate" => {
+                if let Some(moves) = count(terms.next()) {
+                    limits = limits.mate(moves);
+                }
+            }
+            "infinite" => limits = limits.infinite(),
+            "searchmoves" => {
+                // The moves go on until the next parameter
+                let mut moves = Vec::new();
+                while let Some(_move) = terms
+                    .peek()
+                    .and_then(|term| Move::from_uci_notation(term, &game).ok())
+                {
+                    moves.push(_move);
+                    terms.next();
+                }
+                limits = limits.search_moves(moves);
+            }
             _ => continue,
         }
     }
+    for (player, (time, increment)) in [Player::White, Player::Black].into_iter().zip(clocks) {
+        if let Some(time) = time {
+            limits = limits.clock(player, time, increment);
+        }
+    }
 
-    const FRACTION_OF_TOTAL_TIME: f64 = 0.02;
-    const LATENCY_MS_COMPENSATE: u64 = 150;
+    if let Some(time) = limits.time_for_move(game.player()) {
+        send!(data.output, "info time {:?}", time.as_millis());
+    }
 
-    let mut time = None;
+    data.engine.start_search(limits)?;
+    data.has_position = false;
+
+    let events = events.clone();
+    let output = data.output.clone();
+    let show_wdl = data.show_wdl;
+    let multi_pv = data.engine.multi_pv() > 1;
+    Ok(thread::spawn(move || {
+        for event in events.lock().unwrap().iter() {
+            match event {
+                EngineEvent::Info(info) if multi_pv => print_line(&output, &info, &game, show_wdl),
+                EngineEvent::Info(info) => print_info(&output, &info, &game, show_wdl),
+                EngineEvent::BestMove(best_move) => {
+                    print_best_move(&output, best_move, &game);
+                    break;
+                }
+            }
+        }
+    }))
+}
 
-    if wtime.is_some() && btime.is_some() && winc.is_some() && binc.is_some() {
-        let wtime = wtime.unwrap();
-        let btime = btime.unwrap();
-        let winc = winc.unwrap();
-        let binc = binc.unwrap();
+/// Prints the progress of the search as `info` lines, with the win, draw and loss
+/// probabilities after the score if `show_wdl` is set
+pub fn print_info(output: &Output, info: &SearchInfo, game: &Game, show_wdl: bool) {
+    if json::is_enabled() {
+        output.write_json(&Message::Info(JsonReport::new(info, game)));
+        return;
+    }
+    send!(output, "info depth {}", info.depth);
+    if show_wdl {
+        send!(
+            output,
+            "info score {} wdl {}",
+            score::to_uci(info.score),
+            info.wdl
+        );
+    } else {
+        send!(output, "info score {}", score::to_uci(info.score));
+    }
+    send!(output, "info nodes {}", info.nodes);
+    // There is no line when the game is over
+    if !info.pv.is_empty() {
+        let pv: Vec<String> = info
+            .pv
+            .iter()
+            .map(|_move| _move.uci_notation(game))
+            .collect();
+        send!(output, "info pv {}", pv.join(" "));
+    }
+}
 
-        // We decrease the time to make sure we never run out
-        let white_time =
-            (wtime as f64 * FRACTION_OF_TOTAL_TIME) as u64 + winc - LATENCY_MS_COMPENSATE;
-        let black_time =
-            (btime as f64 * FRACTION_OF_TOTAL_TIME) as u64 + binc - LATENCY_MS_COMPENSATE;
+/// Prints a line of the search as a single `info` line with its rank, the format of GUIs
+/// showing several lines
+pub fn print_line(output: &Output, info: &SearchInfo, game: &Game, show_wdl: bool) {
+    if json::is_enabled() {
+        output.write_json(&Message::Info(JsonReport::new(info, game)));
+        return;
+    }
+    let wdl = if show_wdl {
+        format!(" wdl {}", info.wdl)
+    } else {
+        String::new()
+    };
+    let pv: Vec<String> = info
+        .pv
+        .iter()
+        .map(|_move| _move.uci_notation(game))
+        .collect();
+    let pv = if pv.is_empty() {
+        String::new()
+    } else {
+        format!(" pv {}", pv.join(" "))
+    };
+    send!(
+        output,
+        "info depth {} multipv {} score {}{} nodes {}{}",
+        info.depth,
+        info.multipv,
+        score::to_uci(info.score),
+        wdl,
+        info.nodes,
+        pv
+    );
+}
 
-        time = if game.player() == Player::White {
-            Some(Duration::from_millis(white_time))
-        } else {
-            Some(Duration::from_millis(black_time))
-        };
+/// Prints the result of the search, `bestmove none` when the game is over
+fn print_best_move(output: &Output, best_move: Option<Move>, game: &Game) {
+    let best_move = best_move.map(|best_move| best_move.uci_notation(game));
+    if json::is_enabled() {
+        output.write_json(&Message::BestMove {
+            bestmove: best_move,
+        });
+    } else {
+        send!(
+            output,
+            "bestmove {}",
+            best_move.as_deref().unwrap_or("none")
+        );
     }
+}
 
-    if let Some(move_time) = move_time {
-        time = Some(Duration::from_millis(move_time));
+/// Reads a legal move of `game` in UCI notation or in SAN
+pub fn parse_move(s: &str, game: &Game) -> anyhow::Result<Move> {
+    match Move::from_uci_notation(s, game) {
+        Ok(_move) => Ok(_move),
+        Err(err @ UciMoveError::Unparseable(_)) => match Move::from_san(s, game) {
+            Ok(_move) => Ok(_move),
+            // Reported as a UCI move unless it is written in SAN
+            Err(SanMoveError::Unparseable(_)) => bail!("{}", err),
+            Err(san_err) => bail!("{}", san_err),
+        },
+        Err(err) => bail!("{}", err),
     }
+}
 
-    if let Some(time) = time {
-        if !infinite {
-            // Cut 5 ms from the time because sleep always takes more than given
-            let time = time.saturating_sub(Duration::from_millis(5));
+fn command_position(data: &mut Data, terms: &mut SplitAsciiWhitespace<'_>) -> anyhow::Result<()> {
+    // The previous position is gone even if this one is invalid
+    data.has_position = false;
+    let game = parse_position(terms, &data.engine)?;
+    data.engine.set_position(game)?;
+    data.has_position = true;
 
-            println!("info time {:?}", time.as_millis());
+    Ok(())
+}
 
-            // This thread might stop a future search if the current one stops by itself
-            // Thus when a new search is started, a new atomic bool is created
-            thread::spawn({
-                let search_is_running = search_is_running.clone();
-                move || {
-                    thread::sleep(time);
-                    search_is_running.store(false, Relaxed);
-                }
-            });
-        }
-    }
+/// Reads the arguments of the `position` command, `startpos` or `fen <FEN>` optionally
+/// followed by `moves` and the moves played since, for `engine`
+pub fn parse_position(
+    terms: &mut SplitAsciiWhitespace<'_>,
+    engine: &Engine,
+) -> anyhow::Result<Game> {
+    let mut add_moves = false;
 
-    let thread = thread::spawn({
-        search_is_running.store(true, Relaxed);
-        let data_mutex = data_mutex.clone();
-        let search_is_running = search_is_running.clone();
-        move || {
-            let mut data = data_mutex.lock().unwrap();
-            let (current_game, cache) = data.mut_refs();
-            let best_move = get_best_move_until_stop(
-                current_game.as_mut().unwrap(),
-                cache,
-                &search_is_running,
-                depth,
-            );
+    let Some(term) = terms.next() else {
+        bail!("Invalid position command");
+    };
 
-            if let Some(best_move) = best_move {
-                println!("bestmove {}", best_move.uci_notation());
-            } else {
-                println!("bestmove none");
+    let mut game = match term {
+        "startpos" => {
+            if let Some(term) = terms.next() {
+                if term == "moves" {
+                    add_moves = true;
+                }
             }
 
-            search_is_running.store(false, Relaxed);
-            *current_game = None;
+            Game::with_params(START_FEN, engine.params()).unwrap()
         }
-    });
+        "fen" => {
+            let fen: String = terms
+                .by_ref()
+                .take_while(|&term| {
+                    if term == "moves" {
+                        add_moves = true;
+                        false
+                    } else {
+                        true
+                    }
+                })
+                .flat_map(|term| [term, " "].into_iter())
+                .collect();
 
-    Ok(thread)
-}
+            Game::with_params(&fen, engine.params())
+                .map_err(|err| anyhow::anyhow!("Invalid FEN string: {}", err))?
+        }
+        _ => bail!("Invalid position command"),
+    };
 
-fn command_position(data: &mut Data, terms: &mut SplitAsciiWhitespace<'_>) -> anyhow::Result<()> {
-    let mut add_moves = false;
+    game.set_chess960(engine.is_chess960());
 
-    if let Some(term) = terms.next() {
-        let game = match term {
-            "startpos" => {
-                data.current_game = Some(Game::default());
+    if add_moves {
+        for move_str in terms.by_ref() {
+            // SAN is accepted too, which makes it easier to set up positions by hand
+            game.push_history(parse_move(move_str, &game)?);
+        }
+    }
 
-                if let Some(term) = terms.next() {
-                    if term == "moves" {
-                        add_moves = true;
-                    }
-                }
+    Ok(game)
+}
 
-                data.current_game.as_mut().unwrap()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reads the lines of `connection` until the best move
+    fn until_best_move(connection: &TcpStream) -> Vec<String> {
+        let mut answers = Vec::new();
+        for line in BufReader::new(connection).lines() {
+            let line = line.unwrap();
+            answers.push(line.clone());
+            if line.starts_with("bestmove") {
+                break;
             }
-            "fen" => {
-                let fen: String = terms
-                    .by_ref()
-                    .take_while(|&term| {
-                        if term == "moves" {
-                            add_moves = true;
-                            false
-                        } else {
-                            true
-                        }
-                    })
-                    .flat_map(|term| [term, " "].into_iter())
-                    .collect();
-
-                match Game::new(&fen) {
-                    Ok(fen_game) => {
-                        data.current_game = Some(fen_game);
-                        data.current_game.as_mut().unwrap()
-                    }
-                    Err(err) => {
-                        data.current_game = None;
-                        bail!("{:?}", err.context("Invalid FEN string"));
-                    }
-                }
-            }
-            _ => bail!("Invalid position command"),
-        };
+        }
+        answers
+    }
 
-        if add_moves {
-            for move_str in terms.by_ref() {
-                let Some(_move) = Move::from_uci_notation(move_str, game) else {
-                    data.current_game = None;
-                    bail!("Invalid move: {}", move_str);
-                };
-
-                let mut moves = ArrayVec::new();
-                game.get_moves(&mut moves, true);
-                if moves.iter().any(|&allowed_move| _move == allowed_move) {
-                    game.push_history(_move);
-                    if game.len() >= 400 {
-                        data.current_game = None;
-                        bail!("Game became too long, please try again");
-                    }
-                } else {
-                    bail!("Invalid move: {}", move_str);
-                }
-            }
+    #[test]
+    fn tcp_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || accept(listener.incoming().take(2), None, &[]));
+
+        for _ in 0..2 {
+            let mut connection = TcpStream::connect(address).unwrap();
+            writeln!(
+                connection,
+                "uci\nisready\nposition startpos moves e2e4\ngo depth 3"
+            )
+            .unwrap();
+            let answers = until_best_move(&connection);
+            assert!(answers.contains(&String::from("uciok")));
+            assert!(answers.contains(&String::from("readyok")));
+            assert!(answers.iter().any(|line| line == "info depth 3"));
+
+            // The next GUI is served once this one is gone
+            writeln!(connection, "quit").unwrap();
+            let mut rest = String::new();
+            BufReader::new(&connection).read_line(&mut rest).unwrap();
+            assert!(rest.is_empty());
         }
-    } else {
-        bail!("Invalid position command");
+        server.join().unwrap().unwrap();
     }
 
-    Ok(())
+    #[test]
+    fn invalid_utf8() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = thread::spawn(move || accept(listener.incoming().take(2), None, &[]));
+
+        // The command can't be read, which ends this connection but not the server
+        let mut connection = TcpStream::connect(address).unwrap();
+        connection.write_all(b"uci\n\xff\xfe\n").unwrap();
+        let mut lines = BufReader::new(&connection).lines();
+        assert!(lines.any(|line| line.unwrap() == "uciok"));
+        assert!(lines.next().is_none());
+
+        let mut connection = TcpStream::connect(address).unwrap();
+        writeln!(connection, "position startpos\ngo depth 1").unwrap();
+        assert!(until_best_move(&connection)
+            .last()
+            .unwrap()
+            .starts_with("bestmove "));
+        writeln!(connection, "quit").unwrap();
+        server.join().unwrap().unwrap();
+    }
+
+    /// Answers to `commands`, written to a buffer instead of stdout
+    fn answers(commands: &str) -> Vec<String> {
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let (data, events) = Data::new(Output(buffer.clone()));
+        talk(data, events, commands.as_bytes()).unwrap();
+        let buffer = buffer.lock().unwrap();
+        String::from_utf8_lossy(&buffer)
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn go_limits() {
+        // A depth of 0 still searches the first one to find a move
+        for command in ["go depth 0", "go mate 0"] {
+            let answers = answers(&format!("position startpos\n{}\nwait", command));
+            let best_move = answers.last().unwrap();
+            assert!(best_move.starts_with("bestmove "));
+            assert_ne!(best_move, "bestmove none");
+        }
+
+        // Depths and mates too large for a byte are clamped to the deepest search
+        for command in ["go depth 300", "go mate 300"] {
+            let answers = answers(&format!(
+                "position fen 6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1\n{}\nwait",
+                command
+            ));
+            assert_eq!(answers.last().unwrap(), "bestmove e1e8");
+        }
+    }
 }
@@ -0,0 +1,125 @@
+use crate::chess::{
+    score::{self, Score},
+    Game, Player,
+};
+use std::fmt;
+
+/// Material of both players at the start of the game, without the kings
+const STARTING_MATERIAL: Score = 8000;
+
+/// Coefficients of the win rate model, to be refitted on self-play results whenever
+/// the evaluation changes significantly. The first value applies to a bare board,
+/// the second is added in proportion to the remaining material.
+///
+/// The win rate of a score `s` is `1 / (1 + exp((a - s) / b))`,
+/// `a` is the score which wins half of the games and `b` how spread out the results are.
+const WIN_RATE_A: [f64; 2] = [110.0, 140.0];
+const WIN_RATE_B: [f64; 2] = [45.0, 35.0];
+
+/// Win, draw and loss probabilities of the side to move, in permille
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Wdl {
+    pub win: u32,
+    pub draw: u32,
+    pub loss: u32,
+}
+
+impl Wdl {
+    /// Converts a score of the side to move, mates are certain results
+    pub fn new(score: Score, game: &Game) -> Self {
+        if score::is_mate(score) {
+            return if score > 0 {
+                Self {
+                    win: 1000,
+                    draw: 0,
+                    loss: 0,
+                }
+            } else {
+                Self {
+                    win: 0,
+                    draw: 0,
+                    loss: 1000,
+                }
+            };
+        }
+
+        let (a, b) = model_parameters(game);
+        let win = (1000.0 * win_rate(score as f64, a, b)).round() as u32;
+        let loss = (1000.0 * win_rate(-score as f64, a, b)).round() as u32;
+
+        Self {
+            win,
+            draw: 1000 - win - loss,
+            loss,
+        }
+    }
+}
+
+/// Formatted as expected after `wdl` in the `info` UCI output
+impl fmt::Display for Wdl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.win, self.draw, self.loss)
+    }
+}
+
+/// Rescales a score such that 100 centipawns always mean a 50% chance of winning,
+/// whatever the material left on the board. Mates are left unchanged.
+pub fn normalize(score: Score, game: &Game) -> Score {
+    if score::is_mate(score) {
+        return score;
+    }
+
+    let (a, _) = model_parameters(game);
+    (score as f64 * 100.0 / a).round() as Score
+}
+
+fn model_parameters(game: &Game) -> (f64, f64) {
+    let material = game.material(Player::White) + game.material(Player::Black);
+    let fraction = material.clamp(0, STARTING_MATERIAL) as f64 / STARTING_MATERIAL as f64;
+
+    (
+        WIN_RATE_A[0] + WIN_RATE_A[1] * fraction,
+        WIN_RATE_B[0] + WIN_RATE_B[1] * fraction,
+    )
+}
+
+fn win_rate(score: f64, a: f64, b: f64) -> f64 {
+    1.0 / (1.0 + f64::exp((a - score) / b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probabilities() {
+        let game = Game::default();
+
+        let equal = Wdl::new(0, &game);
+        assert_eq!(equal.win, equal.loss);
+        assert_eq!(equal.win + equal.draw + equal.loss, 1000);
+        assert!(equal.draw > 900);
+
+        let ahead = Wdl::new(300, &game);
+        let behind = Wdl::new(-300, &game);
+        assert_eq!((ahead.win, ahead.loss), (behind.loss, behind.win));
+        assert!(ahead.win > equal.win && ahead.loss < equal.loss);
+
+        assert_eq!(Wdl::new(score::mate_in(3), &game).win, 1000);
+        assert_eq!(Wdl::new(score::mated_in(2), &game).loss, 1000);
+        assert_eq!(equal.to_string(), "42 916 42");
+    }
+
+    #[test]
+    fn material_dependence() {
+        // The same advantage is easier to convert with less material on the board
+        let opening = Game::default();
+        let endgame = Game::new("8/4k3/8/8/8/8/4K3/R7 w - - 0 1").unwrap();
+
+        assert!(Wdl::new(150, &endgame).win > Wdl::new(150, &opening).win);
+        assert_eq!(normalize(250, &opening), 100);
+        assert!(normalize(150, &endgame) > 100);
+        assert_eq!(normalize(score::mate_in(5), &endgame), score::mate_in(5));
+    }
+}
@@ -0,0 +1,114 @@
+use rustybait::{
+    chess::{epd::Epd, move_struct::Move, Game, Score},
+    constants::DEFAULT_CONTEMPT,
+    search::{search, SearchLimits, TranspositionTable},
+};
+use std::{env, fmt::Write, fs, sync::atomic::AtomicBool};
+
+// Golden tests of the search and the evaluation: the positions of golden.epd are searched
+// to the depth of their `acd` operation, which must give the best move of `bm`, a score
+// close to `ce` for the player to move, and a static evaluation close to `eval` for white.
+// Changes of the engine which change either of them are flagged here, and once they are
+// intended the expectations are recorded again with
+//
+//     GOLDEN_UPDATE=1 cargo test --test golden
+
+const GOLDEN_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden.epd");
+
+/// Difference of the scores, in centipawns, small enough to come from retuned parameters
+/// rather than from a change of behavior
+const SCORE_TOLERANCE: Score = 15;
+
+/// Best move and score of an iterative deepening search from an empty table
+fn search_to_depth(game: &Game, depth: u8) -> (Move, Score) {
+    let (best_move, score, _) = search(
+        game,
+        &SearchLimits::new().depth(depth),
+        &mut TranspositionTable::default(),
+        &AtomicBool::new(true),
+        DEFAULT_CONTEMPT,
+        |_| (),
+    )
+    .expect("No legal move");
+    (best_move, score)
+}
+
+fn operand<'a>(epd: &'a Epd, opcode: &str) -> Option<&'a str> {
+    epd.operations
+        .iter()
+        .find(|(name, _)| name == opcode)
+        .and_then(|(_, operands)| operands.first())
+        .map(String::as_str)
+}
+
+#[test]
+fn golden_positions() {
+    let text = fs::read_to_string(GOLDEN_PATH).unwrap();
+    let update = env::var_os("GOLDEN_UPDATE").is_some();
+    let mut updated = String::new();
+    let mut failures = Vec::new();
+
+    for line in text.lines().filter(|line| !line.trim().is_empty()) {
+        let mut epd = Epd::parse(line).unwrap();
+        let name = epd.id.clone().unwrap_or_else(|| epd.game.to_fen());
+        let depth = operand(&epd, "acd")
+            .and_then(|depth| depth.parse().ok())
+            .unwrap_or_else(|| panic!("{}: missing acd operation", name));
+
+        let (best_move, score) = search_to_depth(&epd.game, depth);
+        let eval = epd.game.evaluate();
+
+        if update {
+            epd.best_moves = vec![best_move];
+            epd.centipawn_evaluation = Some(score);
+            epd.operations.retain(|(opcode, _)| opcode != "eval");
+            epd.operations
+                .push((String::from("eval"), vec![eval.to_string()]));
+            writeln!(updated, "{}", epd).unwrap();
+            continue;
+        }
+
+        if !epd.best_moves.contains(&best_move) {
+            failures.push(format!(
+                "{}: best move {} instead of {}",
+                name,
+                best_move.to_san(&epd.game),
+                epd.best_moves
+                    .iter()
+                    .map(|_move| _move.to_san(&epd.game))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+        }
+
+        let expected_score = epd
+            .centipawn_evaluation
+            .unwrap_or_else(|| panic!("{}: missing ce operation", name));
+        if (score - expected_score).abs() > SCORE_TOLERANCE {
+            failures.push(format!(
+                "{}: score {} instead of {}",
+                name, score, expected_score
+            ));
+        }
+
+        let expected_eval: Score = operand(&epd, "eval")
+            .and_then(|eval| eval.parse().ok())
+            .unwrap_or_else(|| panic!("{}: missing eval operation", name));
+        if (eval - expected_eval).abs() > SCORE_TOLERANCE {
+            failures.push(format!(
+                "{}: evaluation {} instead of {}",
+                name, eval, expected_eval
+            ));
+        }
+    }
+
+    if update {
+        fs::write(GOLDEN_PATH, updated).unwrap();
+        return;
+    }
+    assert!(
+        failures.is_empty(),
+        "Search or evaluation changed, run with GOLDEN_UPDATE=1 if it is intended:\n{}",
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,165 @@
+use nohash_hasher::BuildNoHashHasher;
+use rustybait::{
+    analysis::JsonReport,
+    chess::{move_struct::UciMoveError, Score},
+    constants::DEFAULT_CONTEMPT,
+    search::{self, SearchLimits, TranspositionTable},
+    Game, Move,
+};
+use std::{collections::HashMap, sync::atomic::AtomicBool, time::Duration};
+use wasm_bindgen::prelude::*;
+
+// The engine for JavaScript, e.g. for analysis boards in browsers, built with
+//
+//     cargo build --release --target wasm32-unknown-unknown
+//     wasm-bindgen --target web target/wasm32-unknown-unknown/release/rustybait_wasm.wasm --out-dir pkg
+//
+// Searches run on the thread calling them, which should be a web worker so that the page
+// stays responsive.
+
+/// Entries reserved in the transposition table, far fewer than natively since pages
+/// don't get much memory
+const TABLE_CAPACITY: usize = 1 << 20;
+
+#[wasm_bindgen]
+pub struct Engine {
+    game: Game,
+    table: TranspositionTable,
+    contempt: Score,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Engine {
+    /// The engine in the start position
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            game: Game::default(),
+            table: HashMap::with_capacity_and_hasher(TABLE_CAPACITY, BuildNoHashHasher::default()),
+            contempt: DEFAULT_CONTEMPT,
+        }
+    }
+
+    /// Sets the position from a FEN, or `startpos`, and the moves played since, separated
+    /// by spaces, in UCI notation or SAN
+    #[wasm_bindgen(js_name = setPosition)]
+    pub fn set_position(&mut self, fen: &str, moves: &str) -> Result<(), String> {
+        let mut game = match fen {
+            "startpos" => Game::default(),
+            fen => Game::new(fen).map_err(|err| format!("Invalid FEN string: {}", err))?,
+        };
+        game.set_chess960(self.game.is_chess960());
+
+        for move_str in moves.split_ascii_whitespace() {
+            let _move = match Move::from_uci_notation(move_str, &game) {
+                Err(UciMoveError::Unparseable(_)) => {
+                    Move::from_san(move_str, &game).map_err(|err| err.to_string())?
+                }
+                _move => _move.map_err(|err| err.to_string())?,
+            };
+            game.push_history(_move);
+        }
+
+        self.game = game;
+        Ok(())
+    }
+
+    /// Whether castling moves are written as the king taking its own rook
+    #[wasm_bindgen(js_name = setChess960)]
+    pub fn set_chess960(&mut self, chess960: bool) {
+        self.game.set_chess960(chess960);
+    }
+
+    /// How much worse than an equal position the engine considers a draw, in centipawns
+    #[wasm_bindgen(js_name = setContempt)]
+    pub fn set_contempt(&mut self, contempt: Score) {
+        self.contempt = contempt;
+    }
+
+    /// The position as JSON, like the first line of the `analyze` command: the FEN,
+    /// the legal moves, whether the game is over and how the game got there
+    pub fn position(&self) -> String {
+        serde_json::to_string(&self.game).unwrap()
+    }
+
+    /// Forgets what was learned from the previous positions
+    #[wasm_bindgen(js_name = newGame)]
+    pub fn new_game(&mut self) {
+        self.table.clear();
+    }
+
+    /// Searches the position for at most `move_time` ms, `nodes` positions or `depth`
+    /// half-moves, at least one of which must be given
+    ///
+    /// The result is JSON with the score, win/draw/loss probabilities and best line of
+    /// every depth, like the `analyze` command, and the best move, which is null when the
+    /// game is over: `{"depths":[{"depth":1,"score":{"cp":45},...}],"bestmove":"e2e4"}`
+    pub fn search(
+        &mut self,
+        move_time: Option<u32>,
+        nodes: Option<u32>,
+        depth: Option<u8>,
+    ) -> Result<String, String> {
+        if move_time.is_none() && nodes.is_none() && depth.is_none() {
+            return Err(String::from(
+                "The search must be limited by time, nodes or depth",
+            ));
+        }
+
+        let mut limits = SearchLimits::new();
+        if let Some(move_time) = move_time {
+            limits = limits.move_time(Duration::from_millis(move_time as u64));
+        }
+        if let Some(nodes) = nodes {
+            limits = limits.nodes(nodes as u64);
+        }
+        if let Some(depth) = depth {
+            limits = limits.depth(depth);
+        }
+
+        let game = &self.game;
+        let mut depths = Vec::new();
+        let best_move = search::search(
+            game,
+            &limits,
+            &mut self.table,
+            &AtomicBool::new(true),
+            self.contempt,
+            |info| depths.push(JsonReport::new(info, game)),
+        )
+        .map(|(best_move, _, _)| best_move.uci_notation(game));
+
+        Ok(serde_json::json!({ "depths": depths, "bestmove": best_move }).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn search() {
+        let mut engine = Engine::new();
+        engine.set_position("startpos", "e4 e7e5 Nf3").unwrap();
+        assert!(engine
+            .position()
+            .contains(r#""history":["e2e4","e7e5","g1f3"]"#));
+        assert!(engine.set_position("startpos", "e2e5").is_err());
+        assert!(engine.search(None, None, None).is_err());
+
+        engine
+            .set_position("6k1/5ppp/8/8/8/8/8/4R1K1 w - - 0 1", "")
+            .unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(&engine.search(None, Some(100_000), Some(4)).unwrap()).unwrap();
+        assert_eq!(result["bestmove"], "e1e8");
+        let depths = result["depths"].as_array().unwrap();
+        assert_eq!(depths.last().unwrap()["pv"][0], "e1e8");
+    }
+}